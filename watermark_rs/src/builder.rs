@@ -1,61 +1,456 @@
-use crate::watermark::Quality;
-use anyhow::Result;
+use crate::error::WatermarkError;
+use crate::metadata::{self, Metadata};
+use crate::{linearize, links, parallel, pdfa};
+use crate::pdf::{self, Page};
+use crate::progress::{ProgressSink, Silent, Tally};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::sign::Signer;
+use crate::watermark::{Quality, Subsampling};
+use anyhow::{anyhow, Result};
 use flate2::write::ZlibEncoder;
 use flate2::Compression;
-use ::image::DynamicImage;
-use lopdf::{dictionary, Document, Object, Stream};
+use ::image::{DynamicImage, RgbaImage};
+use lopdf::encryption::crypt_filters::{Aes128CryptFilter, Aes256CryptFilter, CryptFilter};
+use lopdf::xref::XrefType;
+use lopdf::{dictionary, Dictionary, Document, EncryptionState, IncrementalDocument, EncryptionVersion, Object, ObjectId, Permissions, Stream, StringFormat};
+use std::collections::{BTreeMap, HashMap};
 use std::io::Write;
+use std::sync::Arc;
+
+/// Algoritmo de cifrado del PDF generado.
+#[derive(Clone, Copy, PartialEq)]
+pub enum EncryptionLevel {
+    Rc4_128,
+    Aes128,
+    Aes256,
+}
+
+pub fn parse_encryption_level(s: &str) -> Result<EncryptionLevel, WatermarkError> {
+    match s {
+        "rc4-128" => Ok(EncryptionLevel::Rc4_128),
+        "aes-128" => Ok(EncryptionLevel::Aes128),
+        "aes-256" => Ok(EncryptionLevel::Aes256),
+        other => Err(WatermarkError::InvalidOption(format!(
+            "Cifrado inválido: {} (usar rc4-128, aes-128 o aes-256)",
+            other
+        ))),
+    }
+}
+
+/// Tamaño en bytes escrito como "10MB", "500 KB", "1.5G" o "2048" (múltiplos
+/// de 1024).
+pub fn parse_size(s: &str) -> Result<usize, WatermarkError> {
+    let lower = s.trim().to_lowercase();
+    let number = lower.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let unit = match lower[number.len()..].trim_end_matches('b') {
+        "" => 1.0,
+        "k" => 1024.0,
+        "m" => 1_048_576.0,
+        "g" => 1_073_741_824.0,
+        _ => return Err(WatermarkError::InvalidOption(format!("Tamaño inválido: {} (ej. 10MB o 500KB)", s))),
+    };
+    match number.trim().parse::<f64>() {
+        Ok(n) if n > 0.0 && n.is_finite() => Ok((n * unit) as usize),
+        _ => Err(WatermarkError::InvalidOption(format!("Tamaño inválido: {} (ej. 10MB o 500KB)", s))),
+    }
+}
+
+/// Contraseñas del PDF generado. Sin contraseña de propietario se usa la de
+/// usuario; con solo la de propietario el PDF abre sin contraseña pero con
+/// permisos limitados a imprimir.
+#[derive(Clone)]
+pub struct Encryption {
+    pub user_password: String,
+    pub owner_password: String,
+    pub level: EncryptionLevel,
+}
+
+impl Encryption {
+    /// `None` si no se indicó ninguna contraseña.
+    pub fn new(user_password: &str, owner_password: &str, level: EncryptionLevel) -> Option<Self> {
+        if user_password.is_empty() && owner_password.is_empty() {
+            return None;
+        }
+        let owner_password = if owner_password.is_empty() { user_password } else { owner_password };
+        Some(Self {
+            user_password: user_password.to_string(),
+            owner_password: owner_password.to_string(),
+            level,
+        })
+    }
+}
+
+/// Opciones de escritura del PDF generado.
+#[derive(Clone, Default)]
+pub struct OutputOptions {
+    pub encryption: Option<Encryption>,
+    /// PDF/A-2b (solo en modo raster).
+    pub pdfa: bool,
+    /// Linealizado ("vista rápida en la web").
+    pub linearize: bool,
+    /// Actualización incremental del PDF original (solo en modo stamp).
+    pub incremental: bool,
+    /// Flujos de objetos y xref comprimida (PDF 1.5). Se ignoran si el PDF
+    /// va cifrado.
+    pub object_streams: bool,
+    /// Sin diccionario Info, XMP ni miniaturas del original.
+    pub strip_metadata: bool,
+    /// Título, autor, asunto y palabras clave del PDF generado.
+    pub metadata: Metadata,
+    /// Firma digital del PDF generado.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub signer: Option<Signer>,
+}
+
+impl OutputOptions {
+    fn validate(&self) -> Result<(), WatermarkError> {
+        let invalid = |message: &str| Err(WatermarkError::InvalidOption(message.to_string()));
+        if self.encryption.is_some() && self.pdfa {
+            return invalid("PDF/A no admite cifrado");
+        }
+        if self.encryption.is_some() && self.linearize {
+            return invalid("La salida linealizada no admite cifrado");
+        }
+        if self.encryption.is_some() && self.incremental {
+            return invalid("La actualización incremental conserva el cifrado del original y no admite contraseñas nuevas");
+        }
+        if self.linearize && self.incremental {
+            return invalid("La salida linealizada no admite actualización incremental");
+        }
+        if self.object_streams && self.linearize {
+            return invalid("La salida linealizada no admite flujos de objetos");
+        }
+        if self.object_streams && self.incremental {
+            return invalid("La actualización incremental no admite flujos de objetos");
+        }
+        if self.strip_metadata && self.incremental {
+            return invalid("La actualización incremental conserva los metadatos del original");
+        }
+        if self.strip_metadata && self.pdfa {
+            return invalid("PDF/A requiere metadatos XMP");
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.encryption.is_some() && self.signer.is_some() {
+            return invalid("La firma digital no admite cifrado");
+        }
+        Ok(())
+    }
 
-const PAGE_W: f64 = 1376.0;
-const PAGE_H: f64 = 768.0;
+    /// Sufijo para el resumen que se imprime al generar el PDF.
+    pub(crate) fn describe(&self) -> String {
+        let mut out = String::new();
+        if self.pdfa {
+            out += ", PDF/A-2b";
+        }
+        if self.linearize {
+            out += ", linealizado";
+        }
+        if self.incremental {
+            out += ", incremental";
+        }
+        if self.object_streams {
+            out += ", flujos de objetos";
+        }
+        if self.strip_metadata {
+            out += ", sin metadatos";
+        }
+        if self.encryption.is_some() {
+            out += ", cifrado";
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.signer.is_some() {
+            out += ", firmado";
+        }
+        out
+    }
+}
 
-pub fn build_pdf_bytes(images: &[DynamicImage], quality: &Quality) -> Result<Vec<u8>> {
-    let mut doc = build_document(images, quality)?;
+/// Construye el PDF con una imagen por página. Si se indica `source`, copia
+/// sus metadatos (diccionario Info y XMP), sus marcadores y sus anotaciones.
+pub fn build_pdf_bytes(
+    pages: &[Page],
+    source: Option<&Document>,
+    quality: &Quality,
+    options: &OutputOptions,
+) -> Result<Vec<u8>, WatermarkError> {
     let mut buf = Vec::new();
-    doc.save_to(&mut buf)?;
+    write_pdf(pages, source, quality, options, &mut buf, &Silent)?;
     Ok(buf)
 }
 
-#[cfg(not(target_arch = "wasm32"))]
-pub fn build_pdf(images: &[DynamicImage], output: &str, quality: &Quality) -> Result<()> {
-    let buf = build_pdf_bytes(images, quality)?;
-    std::fs::write(output, &buf)?;
+/// Como [`build_pdf_bytes`], escribiendo el PDF en `out` y reportando cada
+/// página codificada a `progress`.
+pub fn write_pdf(
+    pages: &[Page],
+    source: Option<&Document>,
+    quality: &Quality,
+    options: &OutputOptions,
+    out: &mut dyn Write,
+    progress: &dyn ProgressSink,
+) -> Result<(), WatermarkError> {
+    if options.incremental {
+        return Err(WatermarkError::InvalidOption(
+            "La actualización incremental solo está disponible en modo stamp".to_string(),
+        ));
+    }
+    options.validate()?;
+    // PDF/A restringe filtros y espacios de color: las imágenes se recodifican.
+    let mut doc = build_document(pages, source.filter(|_| !options.pdfa), quality, progress)?;
+    if let Some(source) = source {
+        copy_metadata(&mut doc, source)?;
+        links::copy_outlines(&mut doc, source, pages)?;
+        links::copy_annotations(&mut doc, source, pages)?;
+    }
+    if options.pdfa {
+        pdfa::convert(&mut doc)?;
+    }
+    Ok(save(&mut doc, options, out)?)
+}
 
-    let mode = match quality {
-        Quality::Lossless => "Flate lossless".to_string(),
-        Quality::Jpeg(q) => format!("JPEG q={}", q),
+/// Cifra y serializa el documento en `out` según `options`.
+pub(crate) fn save(doc: &mut Document, options: &OutputOptions, mut out: &mut dyn Write) -> Result<()> {
+    options.validate()?;
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(signer) = &options.signer {
+        let mut buf = Vec::new();
+        save(doc, &OutputOptions { signer: None, ..options.clone() }, &mut buf)?;
+        return Ok(out.write_all(&signer.sign(buf)?)?);
+    }
+    if options.strip_metadata {
+        strip_metadata(doc);
+    }
+    if !options.metadata.is_empty() {
+        metadata::apply(doc, &options.metadata, options.pdfa)?;
+    }
+    if let Some(encryption) = &options.encryption {
+        encrypt(doc, encryption)?;
+    }
+    if options.linearize {
+        // Las tablas de pistas necesitan las posiciones finales de los objetos.
+        out.write_all(&linearize::save(doc)?)?;
+    } else if options.object_streams {
+        doc.save_modern(&mut out)?;
+    } else {
+        doc.save_to(&mut out)?;
+    }
+    Ok(())
+}
+
+/// Guarda `doc` como actualización incremental de `original`, que se cargó
+/// de `data`: copia los bytes originales y añade solo los objetos nuevos o
+/// modificados, con una sección xref cuyo `/Prev` apunta a la anterior. Los
+/// objetos que ya no se usan siguen en la revisión original.
+pub(crate) fn save_incremental(
+    data: &[u8],
+    original: Document,
+    doc: &Document,
+    options: &OutputOptions,
+    mut out: &mut dyn Write,
+) -> Result<()> {
+    options.validate()?;
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(signer) = &options.signer {
+        let mut buf = Vec::new();
+        save_incremental(data, original, doc, &OutputOptions { signer: None, ..options.clone() }, &mut buf)?;
+        return Ok(out.write_all(&signer.sign(buf)?)?);
+    }
+    if !options.metadata.is_empty() {
+        let mut doc = doc.clone();
+        metadata::apply(&mut doc, &options.metadata, false)?;
+        let options = OutputOptions { metadata: Metadata::default(), ..options.clone() };
+        return save_incremental(data, original, &doc, &options, out);
+    }
+    if original.xref_start == 0 {
+        return Err(anyhow!("El PDF está dañado y no admite actualización incremental"));
+    }
+    let changed: BTreeMap<ObjectId, Object> = doc
+        .objects
+        .iter()
+        .filter(|&(id, object)| original.objects.get(id) != Some(object))
+        .map(|(&id, object)| (id, object.clone()))
+        .collect();
+
+    let mut update = IncrementalDocument::create_from(data.to_vec(), original);
+    update.new_document.objects = changed;
+    update.new_document.max_id = doc.max_id;
+    // `/XRefStm` solo vale para la sección en la que aparece.
+    update.new_document.trailer.remove(b"XRefStm");
+    if let Ok(info) = doc.trailer.get(b"Info") {
+        update.new_document.trailer.set("Info", info.clone());
+    }
+    update.save_to(&mut out)?;
+    Ok(())
+}
+
+/// Cifra el documento con las contraseñas indicadas. Añade un `/ID` al
+/// trailer si no lo tiene, porque la clave de RC4 y AES-128 depende de él.
+pub(crate) fn encrypt(doc: &mut Document, encryption: &Encryption) -> Result<()> {
+    ensure_id(doc)?;
+
+    // AES requiere PDF 1.6 (128 bits) o 2.0 (256 bits).
+    let min_version = match encryption.level {
+        EncryptionLevel::Rc4_128 => "1.4",
+        EncryptionLevel::Aes128 => "1.6",
+        EncryptionLevel::Aes256 => "2.0",
     };
-    println!(
-        "  PDF generado: {} ({:.1} MB, {})",
-        output,
-        buf.len() as f64 / 1_048_576.0,
-        mode
-    );
+    if doc.version.as_str() < min_version {
+        doc.version = min_version.to_string();
+    }
+
+    let permissions = Permissions::PRINTABLE
+        | Permissions::PRINTABLE_IN_HIGH_QUALITY
+        | Permissions::COPYABLE_FOR_ACCESSIBILITY;
+    let owner_password = encryption.owner_password.as_str();
+    let user_password = encryption.user_password.as_str();
+    let mut file_key = [0u8; 32];
+    let version = match encryption.level {
+        EncryptionLevel::Rc4_128 => EncryptionVersion::V2 {
+            document: doc,
+            owner_password,
+            user_password,
+            key_length: 128,
+            permissions,
+        },
+        EncryptionLevel::Aes128 => EncryptionVersion::V4 {
+            document: doc,
+            encrypt_metadata: true,
+            crypt_filters: std_crypt_filter(Arc::new(Aes128CryptFilter)),
+            stream_filter: b"StdCF".to_vec(),
+            string_filter: b"StdCF".to_vec(),
+            owner_password,
+            user_password,
+            permissions,
+        },
+        EncryptionLevel::Aes256 => {
+            getrandom::fill(&mut file_key).map_err(|e| anyhow!("No se pudo generar la clave: {}", e))?;
+            EncryptionVersion::V5 {
+                encrypt_metadata: true,
+                crypt_filters: std_crypt_filter(Arc::new(Aes256CryptFilter)),
+                file_encryption_key: &file_key,
+                stream_filter: b"StdCF".to_vec(),
+                string_filter: b"StdCF".to_vec(),
+                owner_password,
+                user_password,
+                permissions,
+            }
+        }
+    };
+    let state = EncryptionState::try_from(version).map_err(|e| anyhow!("Error preparando el cifrado: {}", e))?;
+    doc.encrypt(&state).map_err(|e| anyhow!("Error cifrando el PDF: {}", e))?;
+    Ok(())
+}
+
+/// Añade al trailer un `/ID` aleatorio si no lo tiene.
+pub(crate) fn ensure_id(doc: &mut Document) -> Result<()> {
+    if doc.trailer.get(b"ID").is_err() {
+        let mut id = [0u8; 16];
+        getrandom::fill(&mut id).map_err(|e| anyhow!("No se pudo generar el ID del PDF: {}", e))?;
+        let id = Object::String(id.to_vec(), StringFormat::Hexadecimal);
+        doc.trailer.set("ID", vec![id.clone(), id]);
+    }
     Ok(())
 }
 
-fn build_document(images: &[DynamicImage], quality: &Quality) -> Result<Document> {
+fn std_crypt_filter(filter: Arc<dyn CryptFilter>) -> BTreeMap<Vec<u8>, Arc<dyn CryptFilter>> {
+    BTreeMap::from([(b"StdCF".to_vec(), filter)])
+}
+
+/// Crea un documento con una página por imagen. Las páginas cuya imagen sale
+/// sin cambios de un XObject de `source` lo copian tal cual.
+fn build_document(
+    pages: &[Page],
+    source: Option<&Document>,
+    quality: &Quality,
+    progress: &dyn ProgressSink,
+) -> Result<Document> {
     let mut doc = Document::with_version("1.4");
+    // lopdf escribe por defecto un xref stream, que no existe en PDF 1.4.
+    doc.reference_table.cross_reference_type = XrefType::CrossReferenceTable;
     let pages_id = doc.new_object_id();
     let mut page_ids: Vec<Object> = Vec::new();
+    let mut cache = ImageCache::new();
+    let mut imported = HashMap::new();
+    let source_pages = source.map(Document::get_pages).unwrap_or_default();
+    let original = |page: &Page| source.zip(page.original).zip(source_pages.get(&page.number).copied());
+    // Codificar las imágenes es lo más costoso: se hace en paralelo.
+    let tally = Tally::new(progress, pages.len());
+    let encoded = parallel::map(pages, |page| {
+        let encoded = original(page)
+            .is_none()
+            .then(|| encode_image_stream(&page.image, quality));
+        tally.tick();
+        encoded
+    });
 
-    for img in images {
-        let image_stream = encode_image_stream(img, quality)?;
-        let img_id = doc.add_object(image_stream);
+    for (page, encoded) in pages.iter().zip(encoded) {
+        let (w, h) = (page.width, page.height);
+        let (image, matrix) = match original(page) {
+            // La imagen original está sin girar: el giro de la página va en la matriz.
+            Some(((source, image_id), page_id)) => {
+                log::debug!("Página {}: se copia la imagen original sin recodificar", page.number);
+                (
+                    import_object(&mut doc, source, &Object::Reference(image_id), &mut imported),
+                    match pdf::rotation(source, page_id) {
+                        90 => [0.0, -h, w, 0.0, 0.0, h],
+                        180 => [-w, 0.0, 0.0, -h, w, h],
+                        270 => [0.0, h, -w, 0.0, w, 0.0],
+                        _ => [w, 0.0, 0.0, h, 0.0, 0.0],
+                    },
+                )
+            }
+            None => (
+                Object::Reference(doc.add_object(
+                    encoded.unwrap_or_else(|| encode_image_stream(&page.image, quality))?,
+                )),
+                [w, 0.0, 0.0, h, 0.0, 0.0],
+            ),
+        };
+        let mut xobjects = dictionary! { "Im0" => image };
 
-        let content = format!("q\n{} 0 0 {} 0 0 cm\n/Im0 Do\nQ\n", PAGE_W, PAGE_H);
+        let [a, b, c, d, e, f] = matrix;
+        let mut content = format!("q\n{} {} {} {} {} {} cm\n/Im0 Do\nQ\n", a, b, c, d, e, f);
+        // Los watermarks van en píxeles de la imagen de la página.
+        let sx = page.width / page.image.width().max(1) as f64;
+        let sy = page.height / page.image.height().max(1) as f64;
+        for (n, overlay) in page.overlays.iter().enumerate() {
+            let name = format!("Wm{}", n);
+            let id = match original(page) {
+                // La zona cambiada de la imagen original, opaca: con su misma calidad.
+                Some(((source, image_id), _)) if n == 0 && page.patched => {
+                    let patch = DynamicImage::ImageRgba8(overlay.image.clone().into_owned());
+                    let patch = match page.image {
+                        DynamicImage::ImageLuma8(_) => DynamicImage::ImageLuma8(patch.to_luma8()),
+                        _ => DynamicImage::ImageRgb8(patch.to_rgb8()),
+                    };
+                    let jpeg = jpeg_quality(source, image_id);
+                    doc.add_object(encode_image_stream(&patch, jpeg.as_ref().unwrap_or(quality))?)
+                }
+                _ => cached_image(&mut doc, &mut cache, &overlay.image)?,
+            };
+            xobjects.set(name.as_bytes(), Object::Reference(id));
+            let (ow, oh) = (overlay.image.width() as f64, overlay.image.height() as f64);
+            for &(x, y) in &overlay.origins {
+                let bottom = page.image.height() as f64 - y as f64 - oh;
+                content += &format!(
+                    "q {} 0 0 {} {} {} cm /{} Do Q\n",
+                    ow * sx,
+                    oh * sy,
+                    x as f64 * sx,
+                    bottom * sy,
+                    name
+                );
+            }
+        }
         let content_stream = Stream::new(dictionary! {}, content.into_bytes());
         let content_id = doc.add_object(content_stream);
 
         let page = dictionary! {
             "Type" => "Page",
             "Parent" => Object::Reference(pages_id),
-            "MediaBox" => vec![0.into(), 0.into(), PAGE_W.into(), PAGE_H.into()],
+            "MediaBox" => vec![0.into(), 0.into(), page.width.into(), page.height.into()],
             "Contents" => Object::Reference(content_id),
             "Resources" => dictionary! {
-                "XObject" => dictionary! {
-                    "Im0" => Object::Reference(img_id),
-                },
+                "XObject" => xobjects,
             },
         };
         let page_id = doc.add_object(page);
@@ -65,7 +460,7 @@ fn build_document(images: &[DynamicImage], quality: &Quality) -> Result<Document
     let pages = dictionary! {
         "Type" => "Pages",
         "Kids" => page_ids,
-        "Count" => images.len() as i64,
+        "Count" => pages.len() as i64,
     };
     doc.objects.insert(pages_id, Object::Dictionary(pages));
 
@@ -79,13 +474,293 @@ fn build_document(images: &[DynamicImage], quality: &Quality) -> Result<Document
     Ok(doc)
 }
 
+/// Copia el diccionario Info del trailer y el stream XMP del catálogo.
+fn copy_metadata(doc: &mut Document, source: &Document) -> Result<()> {
+    let mut imported = HashMap::new();
+    if let Ok(info) = source.trailer.get(b"Info") {
+        let info = match import_object(doc, source, info, &mut imported) {
+            Object::Dictionary(info) => Object::Reference(doc.add_object(info)),
+            info => info,
+        };
+        doc.trailer.set("Info", info);
+    }
+
+    let metadata = source
+        .catalog()
+        .ok()
+        .and_then(|catalog| catalog.get(b"Metadata").ok())
+        .cloned();
+    if let Some(metadata @ Object::Reference(_)) = metadata {
+        let metadata = import_object(doc, source, &metadata, &mut imported);
+        let catalog_id = doc.trailer.get(b"Root")?.as_reference()?;
+        doc.get_dictionary_mut(catalog_id)?.set("Metadata", metadata);
+    }
+    Ok(())
+}
+
+/// Quita el diccionario Info del trailer, los streams XMP (`/Metadata` del
+/// catálogo, de las páginas o de cualquier otro objeto), las miniaturas
+/// `/Thumb` y los datos privados `/PieceInfo`, y borra los objetos que dejan
+/// de usarse.
+fn strip_metadata(doc: &mut Document) {
+    fn strip(obj: &mut Object) {
+        let dict = match obj {
+            Object::Dictionary(dict) => dict,
+            Object::Stream(stream) => &mut stream.dict,
+            Object::Array(items) => return items.iter_mut().for_each(strip),
+            _ => return,
+        };
+        for key in [b"Metadata".as_slice(), b"Thumb", b"PieceInfo"] {
+            dict.remove(key);
+        }
+        dict.iter_mut().for_each(|(_, value)| strip(value));
+    }
+    doc.trailer.remove(b"Info");
+    doc.objects.values_mut().for_each(strip);
+    doc.prune_objects();
+}
+
+/// Copia `obj` de `source` a `doc`, incluidos los objetos a los que hace
+/// referencia. `imported` asocia los ids de `source` con los nuevos, así que
+/// cada objeto se copia una sola vez y los ciclos terminan. Las referencias a
+/// páginas que no estén en `imported` se copian como `null`.
+pub(crate) fn import_object(
+    doc: &mut Document,
+    source: &Document,
+    obj: &Object,
+    imported: &mut HashMap<ObjectId, ObjectId>,
+) -> Object {
+    match obj {
+        Object::Reference(id) => {
+            if let Some(new_id) = imported.get(id) {
+                return Object::Reference(*new_id);
+            }
+            let Ok(target) = source.get_object(*id) else {
+                return Object::Null;
+            };
+            // Las páginas solo se enlazan si ya tienen equivalente en `doc`.
+            if target.as_dict().is_ok_and(|d| d.has_type(b"Page")) {
+                return Object::Null;
+            }
+            let new_id = doc.new_object_id();
+            imported.insert(*id, new_id);
+            let copy = import_object(doc, source, target, imported);
+            doc.objects.insert(new_id, copy);
+            Object::Reference(new_id)
+        }
+        Object::Array(items) => {
+            Object::Array(items.iter().map(|item| import_object(doc, source, item, imported)).collect())
+        }
+        Object::Dictionary(dict) => Object::Dictionary(import_dict(doc, source, dict, imported)),
+        Object::Stream(stream) => {
+            let dict = import_dict(doc, source, &stream.dict, imported);
+            Object::Stream(Stream::new(dict, stream.content.clone()).with_compression(false))
+        }
+        other => other.clone(),
+    }
+}
+
+fn import_dict(
+    doc: &mut Document,
+    source: &Document,
+    dict: &Dictionary,
+    imported: &mut HashMap<ObjectId, ObjectId>,
+) -> Dictionary {
+    let mut copy = Dictionary::new();
+    for (key, value) in dict.iter() {
+        copy.set(key.clone(), import_object(doc, source, value, imported));
+    }
+    copy
+}
+
+/// Escribe en `out` un TIFF multipágina con la imagen de cada página, en
+/// RGB y comprimida con LZW, que admiten los sistemas de archivo y fax. Cada
+/// página lleva su resolución para conservar su tamaño al imprimirla.
+pub fn write_tiff(pages: &[Page], out: &mut dyn Write, progress: &dyn ProgressSink) -> Result<(), WatermarkError> {
+    Ok(encode_tiff(pages, out, progress)?)
+}
+
+fn encode_tiff(pages: &[Page], out: &mut dyn Write, progress: &dyn ProgressSink) -> Result<()> {
+    use tiff::encoder::{colortype, Compression, Predictor, Rational, TiffEncoder};
+    use tiff::tags::ResolutionUnit;
+
+    // El encoder necesita volver atrás para enlazar cada página con la siguiente.
+    let mut buf = std::io::Cursor::new(Vec::new());
+    let mut encoder = TiffEncoder::new(&mut buf)?
+        .with_compression(Compression::Lzw)
+        .with_predictor(Predictor::Horizontal);
+    let tally = Tally::new(progress, pages.len());
+    for page in pages {
+        let image = DynamicImage::ImageRgba8(page.flattened()).to_rgb8();
+        let mut tiff = encoder.new_image::<colortype::RGB8>(image.width(), image.height())?;
+        let dpi = image.width() as f64 * 72.0 / page.width;
+        tiff.resolution(ResolutionUnit::Inch, Rational { n: (dpi * 100.0).round() as u32, d: 100 });
+        tiff.write_data(image.as_raw())?;
+        tally.tick();
+    }
+    out.write_all(&buf.into_inner())?;
+    Ok(())
+}
+
+/// Formato de las imágenes exportadas en vez de generar un PDF.
+#[derive(Clone, Copy, PartialEq)]
+pub enum ImageFormat {
+    Png,
+    /// Con la calidad de `--quality` (90 si es lossless).
+    Jpeg,
+    /// Sin pérdida.
+    Webp,
+}
+
+pub fn parse_image_format(s: &str) -> Result<ImageFormat, WatermarkError> {
+    match s {
+        "png" => Ok(ImageFormat::Png),
+        "jpeg" | "jpg" => Ok(ImageFormat::Jpeg),
+        "webp" => Ok(ImageFormat::Webp),
+        other => Err(WatermarkError::InvalidOption(format!("Formato de imagen inválido: {} (usar png, jpeg o webp)", other))),
+    }
+}
+
+impl ImageFormat {
+    /// Extensión de los archivos, sin punto.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Jpeg => "jpg",
+            ImageFormat::Webp => "webp",
+        }
+    }
+
+    /// Descripción de la codificación, ej. "JPEG q=80".
+    pub fn describe(&self, quality: &Quality) -> String {
+        match (self, quality) {
+            (ImageFormat::Png, _) => "PNG".to_string(),
+            (ImageFormat::Jpeg, Quality::Jpeg(q, subsampling)) => format!("JPEG q={}{}", q, subsampling.suffix()),
+            (ImageFormat::Jpeg, Quality::Lossless) => format!("JPEG q={}", EXPORT_JPEG_QUALITY),
+            (ImageFormat::Webp, _) => "WebP lossless".to_string(),
+        }
+    }
+}
+
+/// Calidad de las imágenes JPEG exportadas con `--quality lossless`.
+const EXPORT_JPEG_QUALITY: u8 = 90;
+
+/// Codifica la imagen de una página en `format`. JPEG no admite
+/// transparencia, así que se descarta el canal alfa.
+pub fn encode_page_image(img: &RgbaImage, format: ImageFormat, quality: &Quality) -> Result<Vec<u8>, WatermarkError> {
+    let mut buf = Vec::new();
+    let result = match format {
+        ImageFormat::Png => img.write_to(&mut std::io::Cursor::new(&mut buf), ::image::ImageFormat::Png),
+        ImageFormat::Webp => img.write_to(&mut std::io::Cursor::new(&mut buf), ::image::ImageFormat::WebP),
+        ImageFormat::Jpeg => {
+            let (q, subsampling) = match quality {
+                Quality::Jpeg(q, subsampling) => (*q, *subsampling),
+                Quality::Lossless => (EXPORT_JPEG_QUALITY, Subsampling::default()),
+            };
+            return encode_jpeg(&DynamicImage::ImageRgba8(img.clone()), q, subsampling)
+                .map_err(|e| WatermarkError::Other(format!("No se pudo codificar la imagen: {}", e)));
+        }
+    };
+    result.map_err(|e| WatermarkError::Other(format!("No se pudo codificar la imagen: {}", e)))?;
+    Ok(buf)
+}
+
+/// Codifica `img` como JPEG con calidad `q` (1-100), en gris si es
+/// `ImageLuma8` y si no en RGB.
+fn encode_jpeg(img: &DynamicImage, q: u8, subsampling: Subsampling) -> Result<Vec<u8>> {
+    use jpeg_encoder::{ColorType, Encoder, SamplingFactor};
+    let (w, h) = ::image::GenericImageView::dimensions(img);
+    let (Ok(w), Ok(h)) = (u16::try_from(w), u16::try_from(h)) else {
+        return Err(anyhow!("JPEG no admite imágenes de más de 65535 píxeles de lado ({}x{})", w, h));
+    };
+    let mut buf = Vec::new();
+    let mut encoder = Encoder::new(&mut buf, q);
+    encoder.set_sampling_factor(match subsampling {
+        Subsampling::S444 => SamplingFactor::R_4_4_4,
+        Subsampling::S422 => SamplingFactor::R_4_2_2,
+        Subsampling::S420 => SamplingFactor::R_4_2_0,
+    });
+    let rgb;
+    let (pixels, color_type) = match img {
+        DynamicImage::ImageLuma8(gray) => (gray.as_raw(), ColorType::Luma),
+        _ => {
+            rgb = img.to_rgb8();
+            (rgb.as_raw(), ColorType::Rgb)
+        }
+    };
+    encoder.encode(pixels, w, h, color_type)?;
+    Ok(buf)
+}
+
+/// Calidad de la imagen `image_id` de `source` si es un JPEG, estimada por
+/// su tabla de cuantización de luminancia (las de libjpeg son la del anexo K
+/// escalada).
+fn jpeg_quality(source: &Document, image_id: ObjectId) -> Option<Quality> {
+    // Suma de la tabla de luminancia del anexo K de JPEG, la de calidad 50.
+    const BASE_SUM: u32 = 3688;
+    let stream = source.get_object(image_id).and_then(Object::as_stream).ok()?;
+    if stream.filters().ok()? != [&b"DCTDecode"[..]] {
+        return None;
+    }
+    let data = &stream.content;
+    let (mut table, mut subsampling) = (None, Subsampling::S444);
+    let mut pos = 2;
+    while pos + 4 <= data.len() && data[pos] == 0xFF {
+        let marker = data[pos + 1];
+        let end = (pos + 2 + u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize).min(data.len());
+        // La longitud incluye sus 2 bytes: menos es un JPEG roto.
+        let segment = data.get(pos + 4..end)?;
+        match marker {
+            // DQT: precisión y número de tabla, y 64 valores de 8 o 16 bits.
+            0xDB => {
+                let mut i = 0;
+                while i < segment.len() {
+                    let (wide, id) = (segment[i] >> 4 == 1, segment[i] & 0x0F);
+                    let len = if wide { 128 } else { 64 };
+                    let values = segment.get(i + 1..i + 1 + len).unwrap_or_default();
+                    if id == 0 {
+                        table = Some(match wide {
+                            true => values.chunks(2).map(|v| u16::from_be_bytes([v[0], v[1]]) as u32).sum::<u32>(),
+                            false => values.iter().map(|&v| v as u32).sum(),
+                        });
+                    }
+                    i += 1 + len;
+                }
+            }
+            // SOF: el muestreo del primer componente respecto a los demás.
+            0xC0..=0xC2 if segment.len() >= 9 && segment[5] > 1 => {
+                subsampling = match (segment[7] >> 4, segment[7] & 0x0F) {
+                    (2, 2) => Subsampling::S420,
+                    (2, 1) => Subsampling::S422,
+                    _ => Subsampling::S444,
+                };
+            }
+            0xDA => break,
+            _ => {}
+        }
+        pos = end;
+    }
+    let sum = table?;
+    // libjpeg escala la tabla base por 5000/q por debajo de 50 y por 200-2q encima.
+    let scale = (sum * 100 + BASE_SUM / 2) / BASE_SUM;
+    let q = if scale <= 100 { (200 - scale) / 2 } else { 5000 / scale };
+    Some(Quality::Jpeg(q.clamp(1, 100) as u8, subsampling))
+}
+
+/// Bytes que ocupa `img` codificada con `quality`, para estimar el tamaño del PDF.
+pub(crate) fn encoded_size(img: &DynamicImage, quality: &Quality) -> Result<usize> {
+    Ok(encode_image_stream(img, quality)?.content.len())
+}
+
 fn encode_image_stream(img: &DynamicImage, quality: &Quality) -> Result<Stream> {
-    let rgb = img.to_rgb8();
-    let (w, h) = ::image::GenericImageView::dimensions(&rgb);
+    let (w, h) = ::image::GenericImageView::dimensions(img);
+    // Las páginas en escala de grises van en DeviceGray: un tercio de los datos.
+    let gray = matches!(img, DynamicImage::ImageLuma8(_));
+    let color_space = if gray { "DeviceGray" } else { "DeviceRGB" };
 
     match quality {
         Quality::Lossless => {
-            let raw = rgb.into_raw();
+            let raw = if gray { img.to_luma8().into_raw() } else { img.to_rgb8().into_raw() };
             let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
             encoder.write_all(&raw)?;
             let compressed = encoder.finish()?;
@@ -95,24 +770,21 @@ fn encode_image_stream(img: &DynamicImage, quality: &Quality) -> Result<Stream>
                 "Subtype" => "Image",
                 "Width" => w as i64,
                 "Height" => h as i64,
-                "ColorSpace" => "DeviceRGB",
+                "ColorSpace" => color_space,
                 "BitsPerComponent" => 8_i64,
                 "Filter" => "FlateDecode",
             };
             Ok(Stream::new(dict, compressed))
         }
-        Quality::Jpeg(q) => {
-            let mut buf: Vec<u8> = Vec::new();
-            let encoder =
-                ::image::codecs::jpeg::JpegEncoder::new_with_quality(&mut buf, *q);
-            img.write_with_encoder(encoder)?;
+        Quality::Jpeg(q, subsampling) => {
+            let buf = encode_jpeg(img, *q, *subsampling)?;
 
             let dict = dictionary! {
                 "Type" => "XObject",
                 "Subtype" => "Image",
                 "Width" => w as i64,
                 "Height" => h as i64,
-                "ColorSpace" => "DeviceRGB",
+                "ColorSpace" => color_space,
                 "BitsPerComponent" => 8_i64,
                 "Filter" => "DCTDecode",
             };
@@ -120,3 +792,62 @@ fn encode_image_stream(img: &DynamicImage, quality: &Quality) -> Result<Stream>
         }
     }
 }
+
+/// Añade `img` al documento como XObject RGB con su canal alfa como SMask.
+/// Imágenes ya incrustadas, para reutilizarlas entre páginas.
+pub(crate) type ImageCache = Vec<(RgbaImage, ObjectId)>;
+
+/// Incrusta `img` con [`add_rgba_image`] salvo que ya esté en `cache`.
+pub(crate) fn cached_image(doc: &mut Document, cache: &mut ImageCache, img: &RgbaImage) -> Result<ObjectId> {
+    if let Some((_, id)) = cache.iter().find(|(cached, _)| cached == img) {
+        return Ok(*id);
+    }
+    let id = add_rgba_image(doc, img)?;
+    cache.push((img.clone(), id));
+    Ok(id)
+}
+
+pub(crate) fn add_rgba_image(doc: &mut Document, img: &RgbaImage) -> Result<ObjectId> {
+    let (w, h) = img.dimensions();
+    let mut rgb = Vec::with_capacity((w * h * 3) as usize);
+    let mut alpha = Vec::with_capacity((w * h) as usize);
+    for p in img.pixels() {
+        rgb.extend_from_slice(&p.0[..3]);
+        alpha.push(p.0[3]);
+    }
+
+    let mask = Stream::new(
+        dictionary! {
+            "Type" => "XObject",
+            "Subtype" => "Image",
+            "Width" => w as i64,
+            "Height" => h as i64,
+            "ColorSpace" => "DeviceGray",
+            "BitsPerComponent" => 8_i64,
+            "Filter" => "FlateDecode",
+        },
+        deflate(&alpha)?,
+    );
+    let mask_id = doc.add_object(mask);
+
+    let image = Stream::new(
+        dictionary! {
+            "Type" => "XObject",
+            "Subtype" => "Image",
+            "Width" => w as i64,
+            "Height" => h as i64,
+            "ColorSpace" => "DeviceRGB",
+            "BitsPerComponent" => 8_i64,
+            "Filter" => "FlateDecode",
+            "SMask" => Object::Reference(mask_id),
+        },
+        deflate(&rgb)?,
+    );
+    Ok(doc.add_object(image))
+}
+
+fn deflate(data: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
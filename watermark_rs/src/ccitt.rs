@@ -0,0 +1,302 @@
+//! Decodificador CCITT Group 3/4 (`CCITTFaxDecode`).
+
+use anyhow::{anyhow, Result};
+
+/// Parámetros de `DecodeParms` para `CCITTFaxDecode`.
+pub struct Params {
+    /// <0: Group 4, 0: Group 3 1D, >0: Group 3 mixto 1D/2D
+    pub k: i64,
+    pub columns: u32,
+    pub rows: u32,
+    pub black_is_1: bool,
+    pub byte_align: bool,
+}
+
+/// Devuelve las filas empaquetadas a 1 bit por píxel, como espera el resto
+/// del pipeline (0 = negro salvo con `BlackIs1`).
+pub fn decode(data: &[u8], params: &Params) -> Result<Vec<u8>> {
+    let columns = params.columns;
+    let stride = columns.div_ceil(8) as usize;
+    let mut reader = BitReader::new(data);
+    let mut out = Vec::with_capacity(stride * params.rows as usize);
+    let mut reference: Vec<u32> = Vec::new();
+
+    for _ in 0..params.rows {
+        if params.byte_align {
+            reader.align();
+        }
+        if reader.skip_eols() && params.k < 0 {
+            break; // EOFB
+        }
+        let two_d = match params.k {
+            k if k < 0 => true,
+            0 => false,
+            _ => reader.read(1) == Some(0),
+        };
+        let line = if two_d {
+            decode_2d(&mut reader, &reference, columns)
+        } else {
+            decode_1d(&mut reader, columns)
+        };
+        let Some(line) = line else {
+            break;
+        };
+        pack_row(&line, columns, params.black_is_1, &mut out);
+        reference = line;
+    }
+
+    if out.is_empty() {
+        return Err(anyhow!("Datos CCITTFaxDecode inválidos"));
+    }
+    // Filas que falten (datos truncados) se completan en blanco.
+    let white = if params.black_is_1 { 0x00 } else { 0xFF };
+    out.resize(stride * params.rows as usize, white);
+    Ok(out)
+}
+
+const WHITE: bool = false;
+const BLACK: bool = true;
+
+fn decode_1d(reader: &mut BitReader, columns: u32) -> Option<Vec<u32>> {
+    let mut line = Vec::new();
+    let mut pos = 0;
+    let mut color = WHITE;
+    while pos < columns {
+        pos = (pos + read_run(reader, color)?).min(columns);
+        line.push(pos);
+        color = !color;
+    }
+    Some(line)
+}
+
+fn decode_2d(reader: &mut BitReader, reference: &[u32], columns: u32) -> Option<Vec<u32>> {
+    let mut line = Vec::new();
+    // a0 empieza en un píxel blanco imaginario antes del primero.
+    let mut a0: i64 = -1;
+    let mut color = WHITE;
+    while a0 < columns as i64 {
+        let (b1, b2) = find_b1_b2(reference, a0, color, columns);
+        match read_mode(reader)? {
+            Mode::Pass => a0 = b2 as i64,
+            Mode::Horizontal => {
+                let start = a0.max(0) as u32;
+                let a1 = (start + read_run(reader, color)?).min(columns);
+                let a2 = (a1 + read_run(reader, !color)?).min(columns);
+                line.push(a1);
+                line.push(a2);
+                a0 = a2 as i64;
+            }
+            Mode::Vertical(delta) => {
+                let a1 = (b1 as i64 + delta as i64).clamp(a0.max(0), columns as i64);
+                line.push(a1 as u32);
+                a0 = a1;
+                color = !color;
+            }
+        }
+    }
+    Some(line)
+}
+
+/// b1: primer cambio de la línea de referencia a la derecha de a0 hacia el
+/// color opuesto al actual; b2: el cambio siguiente.
+fn find_b1_b2(reference: &[u32], a0: i64, color: bool, columns: u32) -> (u32, u32) {
+    // Los cambios en posiciones pares pasan a negro y en impares a blanco.
+    let first = reference
+        .iter()
+        .enumerate()
+        .position(|(i, &p)| p as i64 > a0 && (i % 2 == 0) != color);
+    match first {
+        Some(i) => (
+            reference[i],
+            reference.get(i + 1).copied().unwrap_or(columns),
+        ),
+        None => (columns, columns),
+    }
+}
+
+fn pack_row(line: &[u32], columns: u32, black_is_1: bool, out: &mut Vec<u8>) {
+    let start = out.len();
+    out.resize(start + columns.div_ceil(8) as usize, 0);
+    let row = &mut out[start..];
+    let mut color = WHITE;
+    let mut last = 0;
+    for &p in line.iter().chain(std::iter::once(&columns)) {
+        let p = p.min(columns);
+        if (color == BLACK) == black_is_1 {
+            for x in last..p {
+                row[(x / 8) as usize] |= 0x80 >> (x % 8);
+            }
+        }
+        last = p.max(last);
+        color = !color;
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Mode {
+    Pass,
+    Horizontal,
+    Vertical(i8),
+}
+
+const MODES: &[(u8, u16, Mode)] = &[
+    (1, 0b1, Mode::Vertical(0)),
+    (3, 0b011, Mode::Vertical(1)),
+    (3, 0b010, Mode::Vertical(-1)),
+    (3, 0b001, Mode::Horizontal),
+    (4, 0b0001, Mode::Pass),
+    (6, 0b000011, Mode::Vertical(2)),
+    (6, 0b000010, Mode::Vertical(-2)),
+    (7, 0b0000011, Mode::Vertical(3)),
+    (7, 0b0000010, Mode::Vertical(-3)),
+];
+
+fn read_mode(reader: &mut BitReader) -> Option<Mode> {
+    MODES.iter().find_map(|(len, code, mode)| {
+        (reader.peek(*len as u32) == Some(*code as u32)).then(|| {
+            reader.consume(*len as u32);
+            *mode
+        })
+    })
+}
+
+/// Lee una racha completa: códigos make-up (≥64) seguidos de uno terminal.
+fn read_run(reader: &mut BitReader, color: bool) -> Option<u32> {
+    let table = if color == BLACK { BLACK_CODES } else { WHITE_CODES };
+    let mut total = 0;
+    loop {
+        let run = table.iter().find_map(|&(len, code, run)| {
+            (reader.peek(len as u32) == Some(code as u32)).then(|| {
+                reader.consume(len as u32);
+                run
+            })
+        })?;
+        total += run as u32;
+        if run < 64 {
+            return Some(total);
+        }
+    }
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    /// Los siguientes `n` bits (≤ 16); al final de los datos se rellena con ceros.
+    fn peek(&self, n: u32) -> Option<u32> {
+        if self.pos >= self.data.len() * 8 {
+            return None;
+        }
+        let mut value = 0;
+        for i in 0..n as usize {
+            let bit = self.pos + i;
+            let byte = self.data.get(bit / 8).copied().unwrap_or(0);
+            value = (value << 1) | ((byte >> (7 - bit % 8)) & 1) as u32;
+        }
+        Some(value)
+    }
+
+    fn consume(&mut self, n: u32) {
+        self.pos += n as usize;
+    }
+
+    fn read(&mut self, n: u32) -> Option<u32> {
+        let value = self.peek(n)?;
+        self.consume(n);
+        Some(value)
+    }
+
+    fn align(&mut self) {
+        self.pos = self.pos.div_ceil(8) * 8;
+    }
+
+    /// Salta códigos EOL (con relleno de ceros previo). Devuelve true si había
+    /// dos seguidos (fin de bloque en Group 4).
+    fn skip_eols(&mut self) -> bool {
+        let mut count = 0;
+        while self.peek(12) == Some(0) || self.peek(12) == Some(1) {
+            while self.peek(12) == Some(0) {
+                self.consume(1);
+            }
+            if self.peek(12) != Some(1) {
+                break;
+            }
+            self.consume(12);
+            count += 1;
+        }
+        count >= 2
+    }
+}
+
+const WHITE_CODES: &[(u8, u16, u16)] = &[
+    (8, 0b00110101, 0), (6, 0b000111, 1), (4, 0b0111, 2), (4, 0b1000, 3), (4, 0b1011, 4),
+    (4, 0b1100, 5), (4, 0b1110, 6), (4, 0b1111, 7), (5, 0b10011, 8), (5, 0b10100, 9),
+    (5, 0b00111, 10), (5, 0b01000, 11), (6, 0b001000, 12), (6, 0b000011, 13),
+    (6, 0b110100, 14), (6, 0b110101, 15), (6, 0b101010, 16), (6, 0b101011, 17),
+    (7, 0b0100111, 18), (7, 0b0001100, 19), (7, 0b0001000, 20), (7, 0b0010111, 21),
+    (7, 0b0000011, 22), (7, 0b0000100, 23), (7, 0b0101000, 24), (7, 0b0101011, 25),
+    (7, 0b0010011, 26), (7, 0b0100100, 27), (7, 0b0011000, 28), (8, 0b00000010, 29),
+    (8, 0b00000011, 30), (8, 0b00011010, 31), (8, 0b00011011, 32), (8, 0b00010010, 33),
+    (8, 0b00010011, 34), (8, 0b00010100, 35), (8, 0b00010101, 36), (8, 0b00010110, 37),
+    (8, 0b00010111, 38), (8, 0b00101000, 39), (8, 0b00101001, 40), (8, 0b00101010, 41),
+    (8, 0b00101011, 42), (8, 0b00101100, 43), (8, 0b00101101, 44), (8, 0b00000100, 45),
+    (8, 0b00000101, 46), (8, 0b00001010, 47), (8, 0b00001011, 48), (8, 0b01010010, 49),
+    (8, 0b01010011, 50), (8, 0b01010100, 51), (8, 0b01010101, 52), (8, 0b00100100, 53),
+    (8, 0b00100101, 54), (8, 0b01011000, 55), (8, 0b01011001, 56), (8, 0b01011010, 57),
+    (8, 0b01011011, 58), (8, 0b01001010, 59), (8, 0b01001011, 60), (8, 0b00110010, 61),
+    (8, 0b00110011, 62), (8, 0b00110100, 63), (5, 0b11011, 64), (5, 0b10010, 128),
+    (6, 0b010111, 192), (7, 0b0110111, 256), (8, 0b00110110, 320), (8, 0b00110111, 384),
+    (8, 0b01100100, 448), (8, 0b01100101, 512), (8, 0b01101000, 576), (8, 0b01100111, 640),
+    (9, 0b011001100, 704), (9, 0b011001101, 768), (9, 0b011010010, 832), (9, 0b011010011, 896),
+    (9, 0b011010100, 960), (9, 0b011010101, 1024), (9, 0b011010110, 1088),
+    (9, 0b011010111, 1152), (9, 0b011011000, 1216), (9, 0b011011001, 1280),
+    (9, 0b011011010, 1344), (9, 0b011011011, 1408), (9, 0b010011000, 1472),
+    (9, 0b010011001, 1536), (9, 0b010011010, 1600), (6, 0b011000, 1664),
+    (9, 0b010011011, 1728), (11, 0b00000001000, 1792), (11, 0b00000001100, 1856),
+    (11, 0b00000001101, 1920), (12, 0b000000010010, 1984), (12, 0b000000010011, 2048),
+    (12, 0b000000010100, 2112), (12, 0b000000010101, 2176), (12, 0b000000010110, 2240),
+    (12, 0b000000010111, 2304), (12, 0b000000011100, 2368), (12, 0b000000011101, 2432),
+    (12, 0b000000011110, 2496), (12, 0b000000011111, 2560),
+];
+
+const BLACK_CODES: &[(u8, u16, u16)] = &[
+    (10, 0b0000110111, 0), (3, 0b010, 1), (2, 0b11, 2), (2, 0b10, 3), (3, 0b011, 4),
+    (4, 0b0011, 5), (4, 0b0010, 6), (5, 0b00011, 7), (6, 0b000101, 8), (6, 0b000100, 9),
+    (7, 0b0000100, 10), (7, 0b0000101, 11), (7, 0b0000111, 12), (8, 0b00000100, 13),
+    (8, 0b00000111, 14), (9, 0b000011000, 15), (10, 0b0000010111, 16), (10, 0b0000011000, 17),
+    (10, 0b0000001000, 18), (11, 0b00001100111, 19), (11, 0b00001101000, 20),
+    (11, 0b00001101100, 21), (11, 0b00000110111, 22), (11, 0b00000101000, 23),
+    (11, 0b00000010111, 24), (11, 0b00000011000, 25), (12, 0b000011001010, 26),
+    (12, 0b000011001011, 27), (12, 0b000011001100, 28), (12, 0b000011001101, 29),
+    (12, 0b000001101000, 30), (12, 0b000001101001, 31), (12, 0b000001101010, 32),
+    (12, 0b000001101011, 33), (12, 0b000011010010, 34), (12, 0b000011010011, 35),
+    (12, 0b000011010100, 36), (12, 0b000011010101, 37), (12, 0b000011010110, 38),
+    (12, 0b000011010111, 39), (12, 0b000001101100, 40), (12, 0b000001101101, 41),
+    (12, 0b000011011010, 42), (12, 0b000011011011, 43), (12, 0b000001010100, 44),
+    (12, 0b000001010101, 45), (12, 0b000001010110, 46), (12, 0b000001010111, 47),
+    (12, 0b000001100100, 48), (12, 0b000001100101, 49), (12, 0b000001010010, 50),
+    (12, 0b000001010011, 51), (12, 0b000000100100, 52), (12, 0b000000110111, 53),
+    (12, 0b000000111000, 54), (12, 0b000000100111, 55), (12, 0b000000101000, 56),
+    (12, 0b000001011000, 57), (12, 0b000001011001, 58), (12, 0b000000101011, 59),
+    (12, 0b000000101100, 60), (12, 0b000001011010, 61), (12, 0b000001100110, 62),
+    (12, 0b000001100111, 63), (10, 0b0000001111, 64), (12, 0b000011001000, 128),
+    (12, 0b000011001001, 192), (12, 0b000001011011, 256), (12, 0b000000110011, 320),
+    (12, 0b000000110100, 384), (12, 0b000000110101, 448), (13, 0b0000001101100, 512),
+    (13, 0b0000001101101, 576), (13, 0b0000001001010, 640), (13, 0b0000001001011, 704),
+    (13, 0b0000001001100, 768), (13, 0b0000001001101, 832), (13, 0b0000001110010, 896),
+    (13, 0b0000001110011, 960), (13, 0b0000001110100, 1024), (13, 0b0000001110101, 1088),
+    (13, 0b0000001110110, 1152), (13, 0b0000001110111, 1216), (13, 0b0000001010010, 1280),
+    (13, 0b0000001010011, 1344), (13, 0b0000001010100, 1408), (13, 0b0000001010101, 1472),
+    (13, 0b0000001011010, 1536), (13, 0b0000001011011, 1600), (13, 0b0000001100100, 1664),
+    (13, 0b0000001100101, 1728), (11, 0b00000001000, 1792), (11, 0b00000001100, 1856),
+    (11, 0b00000001101, 1920), (12, 0b000000010010, 1984), (12, 0b000000010011, 2048),
+    (12, 0b000000010100, 2112), (12, 0b000000010101, 2176), (12, 0b000000010110, 2240),
+    (12, 0b000000010111, 2304), (12, 0b000000011100, 2368), (12, 0b000000011101, 2432),
+    (12, 0b000000011110, 2496), (12, 0b000000011111, 2560),
+];
@@ -0,0 +1,145 @@
+use std::fmt;
+
+/// Error de la API pública, para distinguir el motivo sin interpretar el
+/// mensaje. `Display` da el mismo mensaje que muestra la CLI.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum WatermarkError {
+    /// El archivo no es un PDF válido; lleva el detalle del parser.
+    InvalidPdf(String),
+    /// El PDF está cifrado y no se indicó contraseña.
+    EncryptedPdf,
+    /// La contraseña indicada no abre el PDF.
+    WrongPassword,
+    /// Una imagen usa un filtro de compresión que no se sabe decodificar.
+    UnsupportedFilter(String),
+    /// Una página solo tiene imágenes en un espacio de color que no se sabe
+    /// convertir (y el crate se compiló sin la feature `render`).
+    UnsupportedColorSpace(String),
+    /// Una imagen del PDF tiene datos inválidos o un formato no soportado.
+    InvalidImage(String),
+    /// La página no tiene imágenes y el crate se compiló sin la feature `render`.
+    RenderUnavailable,
+    /// No se pudo cargar la imagen del watermark.
+    InvalidLogo(String),
+    /// Un parámetro (posición, calidad, cifrado...) no es válido.
+    InvalidOption(String),
+    /// Se pidió una página que el PDF no tiene (numeradas desde 1).
+    PageNotFound { page: usize, total: usize },
+    /// Ninguna de las páginas elegidas existe en el PDF.
+    NoPagesSelected,
+    /// El PDF no cabe en `max_bytes`; el más pequeño que se generó ocupaba
+    /// `smallest`.
+    TooLarge { max_bytes: usize, smallest: usize },
+    /// Falló la página `page` (numerada desde 1) por el motivo `source`.
+    PageExtractionFailed { page: u32, source: Box<WatermarkError> },
+    /// No se pudo leer o escribir un archivo.
+    Io(std::io::Error),
+    /// Cualquier otro error, con su mensaje.
+    Other(String),
+}
+
+impl WatermarkError {
+    /// Atribuye `err` a la página `page`.
+    pub(crate) fn page(page: u32, err: impl Into<WatermarkError>) -> Self {
+        WatermarkError::PageExtractionFailed { page, source: Box::new(err.into()) }
+    }
+
+    /// Identificador estable del motivo, ej. "unsupported_filter". Los
+    /// errores de una página usan el de su causa.
+    pub fn code(&self) -> &'static str {
+        match self {
+            WatermarkError::InvalidPdf(_) => "invalid_pdf",
+            WatermarkError::EncryptedPdf => "encrypted_pdf",
+            WatermarkError::WrongPassword => "wrong_password",
+            WatermarkError::UnsupportedFilter(_) => "unsupported_filter",
+            WatermarkError::UnsupportedColorSpace(_) => "unsupported_color_space",
+            WatermarkError::InvalidImage(_) => "invalid_image",
+            WatermarkError::RenderUnavailable => "render_unavailable",
+            WatermarkError::InvalidLogo(_) => "invalid_logo",
+            WatermarkError::InvalidOption(_) => "invalid_option",
+            WatermarkError::PageNotFound { .. } => "page_not_found",
+            WatermarkError::NoPagesSelected => "no_pages_selected",
+            WatermarkError::TooLarge { .. } => "too_large",
+            WatermarkError::PageExtractionFailed { source, .. } => source.code(),
+            WatermarkError::Io(_) => "io",
+            WatermarkError::Other(_) => "other",
+        }
+    }
+
+    /// Página a la que se refiere el error (numerada desde 1), si la hay.
+    pub fn page_number(&self) -> Option<usize> {
+        match self {
+            WatermarkError::PageExtractionFailed { page, .. } => Some(*page as usize),
+            WatermarkError::PageNotFound { page, .. } => Some(*page),
+            _ => None,
+        }
+    }
+
+    /// Filtro o espacio de color no soportado, para mensajes propios.
+    pub fn detail(&self) -> Option<&str> {
+        match self {
+            WatermarkError::UnsupportedFilter(name) | WatermarkError::UnsupportedColorSpace(name) => Some(name),
+            WatermarkError::PageExtractionFailed { source, .. } => source.detail(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for WatermarkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WatermarkError::InvalidPdf(detail) => write!(f, "No se pudo parsear el PDF: {}", detail),
+            WatermarkError::EncryptedPdf => write!(f, "El PDF está protegido con contraseña"),
+            WatermarkError::WrongPassword => write!(f, "Contraseña del PDF incorrecta"),
+            WatermarkError::UnsupportedFilter(name) if name == "JPXDecode" && !cfg!(feature = "jpeg2000") => {
+                write!(f, "Filtro no soportado: JPXDecode (compilar con la feature jpeg2000)")
+            }
+            WatermarkError::UnsupportedFilter(name) => write!(f, "Filtro no soportado: {}", name),
+            WatermarkError::UnsupportedColorSpace(name) => write!(f, "Espacio de color no soportado: {}", name),
+            WatermarkError::RenderUnavailable => write!(
+                f,
+                "La página no contiene imágenes (compilar con la feature render para rasterizarla)"
+            ),
+            WatermarkError::InvalidLogo(detail) => write!(f, "Error preparando logo: {}", detail),
+            WatermarkError::PageNotFound { page, total } => {
+                write!(f, "La página {} no existe (el PDF tiene {} páginas)", page, total)
+            }
+            WatermarkError::NoPagesSelected => write!(f, "No se seleccionaron páginas válidas"),
+            WatermarkError::TooLarge { max_bytes, smallest } => write!(
+                f,
+                "El PDF no cabe en {} bytes: lo más pequeño que se consiguió fue {} bytes",
+                max_bytes, smallest
+            ),
+            WatermarkError::PageExtractionFailed { page, source } => write!(f, "Error en página {}: {}", page, source),
+            WatermarkError::Io(err) => write!(f, "No se pudo acceder al archivo: {}", err),
+            WatermarkError::InvalidImage(message)
+            | WatermarkError::InvalidOption(message)
+            | WatermarkError::Other(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+// El mensaje ya incluye la causa, así que no se expone como `source`.
+impl std::error::Error for WatermarkError {}
+
+/// Los errores internos se propagan con `anyhow`; si su origen es un
+/// `WatermarkError` se recupera tal cual.
+impl From<anyhow::Error> for WatermarkError {
+    fn from(err: anyhow::Error) -> Self {
+        let err = match err.downcast::<WatermarkError>() {
+            Ok(err) => return err,
+            Err(err) => err,
+        };
+        match err.downcast::<std::io::Error>() {
+            Ok(err) => WatermarkError::Io(err),
+            Err(err) => WatermarkError::Other(format!("{:#}", err)),
+        }
+    }
+}
+
+impl From<std::io::Error> for WatermarkError {
+    fn from(err: std::io::Error) -> Self {
+        WatermarkError::Io(err)
+    }
+}
@@ -0,0 +1,391 @@
+//! Watermark forense: un identificador corto repartido por toda la página
+//! en las frecuencias medias de la DCT, con un patrón que sale de una clave.
+//! A diferencia del de [`stego`](crate::stego) sobrevive a JPEG, a cambios
+//! de tamaño y a capturas de pantalla de la página entera, pero no a
+//! recortes; sin la clave no se puede leer.
+//!
+//! La luminancia de la página se reduce a una rejilla fija de
+//! [`GRID`]×[`GRID`], en bloques de 8×8; cada bit del identificador se suma
+//! (o se resta) con signos pseudoaleatorios a los coeficientes de unos
+//! cuantos bloques, y se lee por la correlación con esos signos. Las páginas
+//! (y las capturas) deben medir al menos eso de lado.
+
+use crate::error::WatermarkError;
+use crate::pdf::{self, PageReader};
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, ImageBuffer, Luma};
+
+/// Lado de la rejilla a la que se reduce la página, en píxeles.
+const GRID: u32 = 256;
+const BLOCK: usize = 8;
+/// Bytes del identificador, sin contar la suma de comprobación.
+pub const MAX_PAYLOAD: usize = 10;
+/// Bits que se guardan: el identificador y 16 de comprobación.
+const BITS: usize = (MAX_PAYLOAD + 2) * 8;
+/// Intensidad por defecto, en niveles de gris de cada coeficiente.
+pub const DEFAULT_STRENGTH: f32 = 8.0;
+/// Intensidad hasta la que se sube si el watermark no se lee bien.
+const MAX_STRENGTH: f32 = 32.0;
+/// Factor por el que se sube la intensidad en cada intento.
+const RAISE: f32 = 1.5;
+/// Confianza con la que se tiene que leer el watermark recién guardado: con
+/// menos no suele sobrevivir a JPEG ni a reducir la página.
+const MIN_CONFIDENCE: f64 = 0.99;
+/// Límite de lo que cuenta cada coeficiente al leer, en niveles de gris:
+/// los bordes del texto dan coeficientes grandes que si no taparían al
+/// watermark.
+const CLIP: f32 = 12.0;
+/// Pasadas con las que se ajusta la amplitud de cada bit al guardarlo.
+const ROUNDS: usize = 5;
+/// Amplitud máxima de un bit respecto a la intensidad, para que una página
+/// que se opone mucho a un bit no acabe con un patrón visible.
+const MAX_GAIN: f32 = 4.0;
+
+type Plane = ImageBuffer<Luma<f32>, Vec<f32>>;
+
+/// Identificador leído con [`Forensic::detect`].
+pub struct Detection {
+    pub payload: Vec<u8>,
+    /// Probabilidad (0-1) de haber leído bien todos los bits
+    pub confidence: f64,
+}
+
+/// Clave e intensidad con las que se guarda y se lee el watermark.
+#[derive(Clone)]
+pub struct Forensic {
+    seed: u64,
+    strength: f32,
+}
+
+impl Forensic {
+    pub fn new(key: &str) -> Self {
+        Forensic {
+            seed: fnv1a(key.as_bytes()),
+            strength: DEFAULT_STRENGTH,
+        }
+    }
+
+    /// Más intensidad resiste más compresión, pero empieza a notarse como
+    /// un grano suave en las zonas lisas. Es la de partida: [`Forensic::embed`]
+    /// la sube en las páginas en las que no basta, como las de texto.
+    pub fn with_strength(mut self, strength: f32) -> Self {
+        self.strength = strength.max(0.0);
+        self
+    }
+
+    /// Guarda `payload` (como mucho [`MAX_PAYLOAD`] bytes, sin ceros: se
+    /// rellena con ellos) en `image`, que pasa a RGB si no es RGB ni gris.
+    /// Falla si no se puede leer con confianza ni con la intensidad máxima.
+    pub fn embed(&self, image: &mut DynamicImage, payload: &[u8]) -> Result<(), WatermarkError> {
+        if payload.len() > MAX_PAYLOAD {
+            return Err(WatermarkError::InvalidOption(format!(
+                "El watermark forense admite como mucho {} bytes: '{}' ocupa {}",
+                MAX_PAYLOAD,
+                String::from_utf8_lossy(payload),
+                payload.len()
+            )));
+        }
+        if payload.contains(&0) {
+            return Err(WatermarkError::InvalidOption("El watermark forense no admite bytes nulos".to_string()));
+        }
+        let mut strength = self.strength;
+        loop {
+            let marked = self.marked(image, payload, strength);
+            match self.detect(&marked) {
+                Some(detection) if detection.payload == payload && detection.confidence >= MIN_CONFIDENCE => {
+                    *image = marked;
+                    return Ok(());
+                }
+                _ if strength < MAX_STRENGTH => {
+                    strength = (strength * RAISE).clamp(1.0, MAX_STRENGTH);
+                    log::debug!("Watermark forense poco legible: se sube la intensidad a {:.1}", strength);
+                }
+                _ => {
+                    return Err(WatermarkError::InvalidOption(format!(
+                        "El watermark forense no se puede leer en esta página ni con intensidad {}",
+                        MAX_STRENGTH
+                    )))
+                }
+            }
+        }
+    }
+
+    /// `image` con `payload` guardado con intensidad `strength`.
+    fn marked(&self, image: &DynamicImage, payload: &[u8], strength: f32) -> DynamicImage {
+        let blocks = self.blocks();
+        let basis = basis();
+        let target: Vec<f32> = self.bits(payload).iter().map(|&bit| if bit { strength } else { -strength }).collect();
+        // La propia página ya correla algo con cada bit, y al ampliar el
+        // patrón, redondear y recortar en blanco y negro se pierde parte:
+        // se corrige la amplitud de cada bit midiendo el resultado.
+        let mut amplitudes = target.clone();
+        for _ in 0..ROUNDS {
+            let marked = add_pattern(image, &pattern(&blocks, &basis, &amplitudes));
+            let measured = measure(&marked, &blocks, &basis);
+            for ((amplitude, target), measured) in amplitudes.iter_mut().zip(&target).zip(measured) {
+                let limit = MAX_GAIN * strength;
+                *amplitude = (*amplitude + target - measured.mean).clamp(-limit, limit);
+            }
+        }
+        add_pattern(image, &pattern(&blocks, &basis, &amplitudes))
+    }
+
+    /// Identificador guardado con esta clave en `image`, si lo hay.
+    pub fn detect(&self, image: &DynamicImage) -> Option<Detection> {
+        let readings = measure(image, &self.blocks(), &basis());
+        let mut bytes = [0u8; BITS / 8];
+        for (bit, reading) in readings.iter().enumerate() {
+            if reading.mean > 0.0 {
+                bytes[bit / 8] |= 0x80 >> (bit % 8);
+            }
+        }
+        let (payload, check) = bytes.split_at(MAX_PAYLOAD);
+        let payload = payload.iter().copied().take_while(|&b| b != 0).collect::<Vec<_>>();
+        if check != self.check(&payload) {
+            return None;
+        }
+        // Sin watermark cada `z` sería una normal estándar: la probabilidad
+        // de que un bit tenga el signo cambiado es la de la cola más allá.
+        let confidence = readings.iter().map(|r| (-normal_tail(r.z.abs() as f64)).ln_1p()).sum::<f64>().exp();
+        Some(Detection { payload, confidence })
+    }
+
+    /// Identificadores guardados con esta clave en las páginas del PDF
+    /// `data`, con el número de cada página que tiene uno.
+    pub fn detect_pdf(&self, data: &[u8], password: &str) -> Result<Vec<(u32, Detection)>, WatermarkError> {
+        let mut reader = PageReader::new(data.to_vec(), password, pdf::DEFAULT_DPI)?;
+        let indices: Vec<usize> = (0..reader.len()).collect();
+        let pages = reader.pages(&indices)?;
+        Ok(pages.iter().filter_map(|page| Some((page.number, self.detect(&page.image)?))).collect())
+    }
+
+    /// `payload` rellenado con ceros hasta [`MAX_PAYLOAD`] y su comprobación,
+    /// bit a bit.
+    fn bits(&self, payload: &[u8]) -> Vec<bool> {
+        let mut bytes = payload.to_vec();
+        bytes.resize(MAX_PAYLOAD, 0);
+        bytes.extend(self.check(payload));
+        bytes.iter().flat_map(|byte| (0..8).rev().map(move |i| byte >> i & 1 == 1)).collect()
+    }
+
+    /// Comprobación de 16 bits de `payload`, que depende de la clave para
+    /// que con otra clave no se lea un identificador por casualidad.
+    fn check(&self, payload: &[u8]) -> [u8; 2] {
+        let hash = fnv1a(&[&self.seed.to_le_bytes()[..], payload].concat());
+        [(hash >> 8) as u8, hash as u8]
+    }
+
+    /// Cada bloque de la rejilla con el bit que lleva y el signo de cada
+    /// coeficiente de [`MID_BAND`], sacados de la clave.
+    fn blocks(&self) -> Vec<(Block, Vec<f32>)> {
+        let side = GRID as usize / BLOCK;
+        let mut rng = SplitMix(self.seed);
+        let mut order: Vec<usize> = (0..side * side).collect();
+        // Fisher-Yates, para que los bloques de un mismo bit queden repartidos.
+        for i in (1..order.len()).rev() {
+            order.swap(i, (rng.next() % (i as u64 + 1)) as usize);
+        }
+        order
+            .iter()
+            .enumerate()
+            .map(|(n, &cell)| {
+                let block = Block { x: cell % side * BLOCK, y: cell / side * BLOCK, bit: n % BITS };
+                let chips = MID_BAND.iter().map(|_| if rng.next() & 1 == 1 { 1.0 } else { -1.0 }).collect();
+                (block, chips)
+            })
+            .collect()
+    }
+}
+
+struct Block {
+    x: usize,
+    y: usize,
+    bit: usize,
+}
+
+/// Patrón en la rejilla que suma `amplitudes[bit]` (en niveles de gris) a
+/// los coeficientes de los bloques de cada bit, con su signo.
+fn pattern(blocks: &[(Block, Vec<f32>)], basis: &[[f32; BLOCK * BLOCK]], amplitudes: &[f32]) -> Plane {
+    let mut pattern = Plane::new(GRID, GRID);
+    for (block, chips) in blocks {
+        for (&coefficient, chip) in MID_BAND.iter().zip(chips) {
+            let amplitude = amplitudes[block.bit] * chip;
+            for (i, &value) in basis[coefficient].iter().enumerate() {
+                let (x, y) = (block.x + i % BLOCK, block.y + i / BLOCK);
+                pattern.get_pixel_mut(x as u32, y as u32)[0] += amplitude * value;
+            }
+        }
+    }
+    pattern
+}
+
+/// `image` con `pattern` ampliado a su tamaño sumado a la luminancia.
+fn add_pattern(image: &DynamicImage, pattern: &Plane) -> DynamicImage {
+    // `resize` recorta las imágenes f32 a 0-1: el patrón se lleva a ese rango.
+    let mut shifted = pattern.clone();
+    shifted.pixels_mut().for_each(|p| p[0] = p[0] / 512.0 + 0.5);
+    let pattern = imageops::resize(&shifted, image.width(), image.height(), FilterType::Triangle);
+    let add = |sample: &mut u8, delta: f32| {
+        *sample = (*sample as f32 + (delta - 0.5) * 512.0).round().clamp(0.0, 255.0) as u8;
+    };
+    match image {
+        DynamicImage::ImageLuma8(gray) => {
+            let mut gray = gray.clone();
+            for (pixel, delta) in gray.pixels_mut().zip(pattern.pixels()) {
+                add(&mut pixel[0], delta[0]);
+            }
+            DynamicImage::ImageLuma8(gray)
+        }
+        _ => {
+            let mut rgb = image.to_rgb8();
+            for (pixel, delta) in rgb.pixels_mut().zip(pattern.pixels()) {
+                for sample in pixel.0.iter_mut() {
+                    add(sample, delta[0]);
+                }
+            }
+            DynamicImage::ImageRgb8(rgb)
+        }
+    }
+}
+
+/// Correlación de una página con el patrón de un bit.
+struct Reading {
+    /// Media por coeficiente en niveles de gris: cerca de ± la intensidad si
+    /// la página lleva el watermark
+    mean: f32,
+    /// Correlación normalizada por la energía de la página
+    z: f32,
+}
+
+fn measure(image: &DynamicImage, blocks: &[(Block, Vec<f32>)], basis: &[[f32; BLOCK * BLOCK]]) -> Vec<Reading> {
+    let luma = imageops::resize(&image.to_luma32f(), GRID, GRID, FilterType::Triangle);
+    let mut sums = vec![0.0; BITS];
+    let mut energies = vec![0.0; BITS];
+    let mut counts = vec![0; BITS];
+    for (block, chips) in blocks {
+        for (&coefficient, chip) in MID_BAND.iter().zip(chips) {
+            let value: f32 = basis[coefficient]
+                .iter()
+                .enumerate()
+                .map(|(i, &b)| b * luma.get_pixel((block.x + i % BLOCK) as u32, (block.y + i / BLOCK) as u32)[0])
+                .sum();
+            let value = (value * 255.0).clamp(-CLIP, CLIP);
+            sums[block.bit] += value * chip;
+            energies[block.bit] += value * value;
+            counts[block.bit] += 1;
+        }
+    }
+    (0..BITS)
+        .map(|bit| Reading {
+            mean: sums[bit] / counts[bit].max(1) as f32,
+            z: sums[bit] / energies[bit].sqrt().max(f32::EPSILON),
+        })
+        .collect()
+}
+
+/// Probabilidad de que una normal estándar pase de `x` (Abramowitz y
+/// Stegun 7.1.26).
+fn normal_tail(x: f64) -> f64 {
+    let x = x / std::f64::consts::SQRT_2;
+    let t = 1.0 / (1.0 + 0.327_591_1 * x);
+    let poly = t
+        * (0.254_829_592 + t * (-0.284_496_736 + t * (1.421_413_741 + t * (-1.453_152_027 + t * 1.061_405_429))));
+    0.5 * poly * (-x * x).exp()
+}
+
+/// Coeficientes (u + 8·v) de frecuencias medias: resisten la compresión
+/// mejor que los altos y se notan menos que los bajos.
+const MID_BAND: [usize; 15] = [3, 10, 17, 24, 4, 11, 18, 25, 32, 5, 12, 19, 26, 33, 40];
+
+/// Funciones base de la DCT 8×8 ortonormal: `basis()[u + 8·v][x + 8·y]`.
+fn basis() -> Vec<[f32; BLOCK * BLOCK]> {
+    let n = BLOCK as f32;
+    let c = |k: usize| if k == 0 { (1.0 / n).sqrt() } else { (2.0 / n).sqrt() };
+    let cos = |k: usize, x: usize| ((2 * x + 1) as f32 * k as f32 * std::f32::consts::PI / (2.0 * n)).cos();
+    (0..BLOCK * BLOCK)
+        .map(|uv| {
+            let (u, v) = (uv % BLOCK, uv / BLOCK);
+            std::array::from_fn(|xy| {
+                let (x, y) = (xy % BLOCK, xy / BLOCK);
+                c(u) * c(v) * cos(u, x) * cos(v, y)
+            })
+        })
+        .collect()
+}
+
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+/// Generador pseudoaleatorio SplitMix64.
+struct SplitMix(u64);
+
+impl SplitMix {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::codecs::jpeg::JpegEncoder;
+    use image::{GrayImage, ImageFormat};
+
+    /// Página de texto de mentira: A4 a 150 ppp con renglones de "letras" de
+    /// trazos negros de 2-3 px sobre blanco, a `leading` px entre renglones.
+    fn text_page(leading: u32, seed: u64) -> DynamicImage {
+        let (w, h) = (1240, 1754);
+        let mut page = GrayImage::from_pixel(w, h, Luma([255]));
+        let mut rng = SplitMix(seed);
+        let rect = |page: &mut GrayImage, x: u32, y: u32, rw: u32, rh: u32| {
+            for py in y..(y + rh).min(h) {
+                for px in x..(x + rw).min(w) {
+                    page.put_pixel(px, py, Luma([0]));
+                }
+            }
+        };
+        for line in (120..h - 120).step_by(leading as usize) {
+            let mut x = 120;
+            while x < w - 140 {
+                let (glyph, stroke) = (10 + rng.next() % 8, 2 + rng.next() % 2);
+                for _ in 0..2 + rng.next() % 2 {
+                    match rng.next() % 3 {
+                        0 => rect(&mut page, x + (rng.next() % glyph) as u32, line, stroke as u32, 18),
+                        1 => rect(&mut page, x, line + (rng.next() % 18) as u32, glyph as u32, stroke as u32),
+                        _ => rect(&mut page, x + (rng.next() % glyph) as u32, line + 9, stroke as u32, 9),
+                    }
+                }
+                x += glyph as u32 + 4 + if rng.next().is_multiple_of(6) { 12 } else { 0 };
+            }
+        }
+        DynamicImage::ImageLuma8(page)
+    }
+
+    /// `image` guardada como JPEG de calidad `quality` y leída de nuevo.
+    fn jpeg(image: &DynamicImage, quality: u8) -> DynamicImage {
+        let mut buf = Vec::new();
+        image.write_with_encoder(JpegEncoder::new_with_quality(&mut buf, quality)).unwrap();
+        image::load_from_memory_with_format(&buf, ImageFormat::Jpeg).unwrap()
+    }
+
+    #[test]
+    fn text_page_survives_jpeg_and_resize() {
+        let forensic = Forensic::new("clave");
+        for (leading, seed) in [(30, 1), (30, 2), (22, 3)] {
+            let mut page = text_page(leading, seed);
+            forensic.embed(&mut page, b"ID-0042").unwrap();
+            let direct = forensic.detect(&page).expect("sin recodificar");
+            assert_eq!(direct.payload, b"ID-0042");
+            assert!(direct.confidence > 0.99, "sin recodificar: {}", direct.confidence);
+            let copy = jpeg(&page, 75).resize_exact(620, 877, FilterType::Triangle);
+            let copied = forensic.detect(&copy).expect("tras JPEG y reducir");
+            assert_eq!(copied.payload, b"ID-0042");
+            assert!(copied.confidence > 0.9, "tras JPEG y reducir: {}", copied.confidence);
+        }
+    }
+}
@@ -1,54 +1,330 @@
+//! Aplica una marca de agua (logo o texto) a las páginas de un PDF.
+//!
+//! [`Pipeline`] procesa PDFs completos con los [`watermark::Stamp`] y las
+//! opciones indicadas; [`pdf`], [`watermark`] y [`builder`] dan acceso a cada
+//! paso por separado. Los errores son [`WatermarkError`]. La CLI y la versión
+//! web (funciones con `#[wasm_bindgen]`) se construyen sobre esta API.
+
 pub mod pdf;
 pub mod watermark;
 pub mod builder;
+pub mod error;
+pub mod forensic;
+pub mod metadata;
+pub mod options;
+pub mod pages;
+pub mod progress;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod sign;
+pub mod text;
+pub mod stamp;
+pub mod stego;
+mod ccitt;
+mod links;
+mod linearize;
+mod parallel;
+mod pdfa;
+mod pipeline;
+mod render;
 
+pub use error::WatermarkError;
+pub use options::Options;
+pub use pipeline::{Estimate, PageSize, Pipeline};
 use wasm_bindgen::prelude::*;
 
+/// Aplica el watermark descrito en `options` al PDF `pdf_bytes`.
+#[wasm_bindgen]
+pub fn process_pdf(pdf_bytes: &[u8], options: &Options) -> Result<Vec<u8>, ProcessError> {
+    Processor::new(pdf_bytes, options)?.finish()
+}
+
+/// Procesa un PDF por partes para no bloquear el navegador: cada llamada a
+/// `step` extrae y marca unas pocas páginas, devolviendo el control al bucle
+/// de eventos entre llamadas, y `finish` genera el PDF. En modo stamp todo el
+/// trabajo se hace en `finish`, que es rápido.
+#[wasm_bindgen]
+pub struct Processor {
+    pipeline: Pipeline,
+    input: Input,
+    /// Páginas elegidas que faltan por procesar, en orden inverso.
+    pending: Vec<usize>,
+    skip: Vec<usize>,
+    pages: Vec<pdf::Page>,
+}
+
+enum Input {
+    Raster(Box<pdf::PageReader>),
+    Stamp(Vec<u8>),
+}
+
 #[wasm_bindgen]
-pub fn process_pdf(
-    pdf_bytes: &[u8],
-    logo_bytes: &[u8],
-    quality_str: &str,
-    page_indices: &[u32],
-    position: &str,
-    min_w: u32,
-    min_h: u32,
-) -> Result<Vec<u8>, JsValue> {
-    let quality = watermark::parse_quality(quality_str)
-        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+impl Processor {
+    #[wasm_bindgen(constructor)]
+    pub fn new(pdf_bytes: &[u8], options: &Options) -> Result<Processor, ProcessError> {
+        let pipeline = options.pipeline()?;
+        if pipeline.mode() == stamp::Mode::Stamp {
+            return Ok(Processor {
+                pipeline,
+                input: Input::Stamp(pdf_bytes.to_vec()),
+                pending: Vec::new(),
+                skip: Vec::new(),
+                pages: Vec::new(),
+            });
+        }
+        let reader = pipeline.reader(pdf_bytes.to_vec())?;
+        let (mut pending, skip) = pipeline.selection(reader.len())?;
+        if pending.is_empty() {
+            return Err(WatermarkError::NoPagesSelected.into());
+        }
+        pending.reverse();
+        Ok(Processor {
+            pipeline,
+            input: Input::Raster(Box::new(reader)),
+            pending,
+            skip,
+            pages: Vec::new(),
+        })
+    }
+
+    /// Páginas que faltan por procesar.
+    pub fn remaining(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Procesa hasta `count` páginas y devuelve cuántas faltan.
+    pub fn step(&mut self, count: usize) -> Result<usize, ProcessError> {
+        let Input::Raster(reader) = &mut self.input else {
+            return Ok(0);
+        };
+        let split = self.pending.len().saturating_sub(count.max(1));
+        let mut chunk = self.pending.split_off(split);
+        chunk.reverse();
+        let pages = reader.pages(&chunk)?;
+        let bates = self.pipeline.bates();
+        let pages = self.pipeline.watermark(pages, &chunk, reader.len(), &self.skip, "", bates, &progress::Silent)?;
+        self.pages.extend(pages);
+        Ok(self.pending.len())
+    }
+
+    /// Procesa las páginas que falten y genera el PDF.
+    pub fn finish(mut self) -> Result<Vec<u8>, ProcessError> {
+        self.step(self.pending.len())?;
+        let mut pdf = Vec::new();
+        match &self.input {
+            Input::Stamp(data) => self.pipeline.process_to(data, "", &mut pdf, &progress::Silent)?,
+            Input::Raster(reader) => self.pipeline.build(&self.pages, reader.document(), &mut pdf, &progress::Silent)?,
+        }
+        Ok(pdf)
+    }
+}
+
+/// Índices (desde 0) de las páginas de `spec` (ej. "1-5,8,12-") en un PDF de
+/// `total` páginas; vacío si `spec` lo está.
+#[wasm_bindgen]
+pub fn parse_page_spec(spec: &str, total: usize) -> Result<Vec<u32>, ProcessError> {
+    let indices = pages::parse_pages(spec)
+        .and_then(|selection| selection.listed(total))?;
+    Ok(indices.into_iter().map(|i| i as u32).collect())
+}
+
+#[wasm_bindgen]
+pub fn get_page_count(pdf_bytes: &[u8], password: &str) -> Result<usize, ProcessError> {
+    Ok(pdf::page_count(pdf_bytes, password)?)
+}
+
+/// Informe del PDF en JSON (ver [`pdf::PdfInfo`]): versión, cifrado, número
+/// de páginas y, por página, tamaño en puntos, giro y las imágenes con sus
+/// filtros, espacio de color y si se saben decodificar. No extrae las
+/// páginas, así que es rápido. Si el PDF está cifrado y `password` no lo
+/// abre devuelve `needs_password: true` sin páginas en vez de un error.
+#[wasm_bindgen]
+pub fn get_pdf_info(pdf_bytes: &[u8], password: &str) -> Result<String, ProcessError> {
+    Ok(pdf::pdf_info(pdf_bytes, password)?.to_json())
+}
+
+/// Tamaño de cada página del PDF, para avisar de tamaños mezclados o
+/// calcular la escala del watermark antes de procesarlo. Extrae todas las
+/// páginas; las que fallan llevan el motivo en `error` y solo su tamaño en
+/// puntos.
+#[wasm_bindgen]
+pub fn get_page_dimensions(pdf_bytes: &[u8], password: &str) -> Result<Vec<PageDimensions>, ProcessError> {
+    let mut reader = pdf::PageReader::new(pdf_bytes.to_vec(), password, pdf::DEFAULT_DPI)?;
+    let dimensions = (0..reader.len())
+        .map(|index| {
+            let (width_pt, height_pt) = reader.page_size(index).unwrap_or_default();
+            let (width, height, error) = match reader.dimensions(index) {
+                Ok((width, height)) => (width, height, None),
+                Err(e) => (0, 0, Some(e.to_string())),
+            };
+            PageDimensions { width, height, width_pt, height_pt, error }
+        })
+        .collect();
+    Ok(dimensions)
+}
+
+/// Tamaño de una página (ver [`get_page_dimensions`]).
+#[wasm_bindgen]
+pub struct PageDimensions {
+    width: u32,
+    height: u32,
+    width_pt: f64,
+    height_pt: f64,
+    error: Option<String>,
+}
+
+#[wasm_bindgen]
+impl PageDimensions {
+    /// Ancho en píxeles de la imagen de la página; 0 si no se pudo extraer.
+    #[wasm_bindgen(getter)]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
 
-    let all_pages = pdf::extract_pages_from_bytes(pdf_bytes)
-        .map_err(|e| JsValue::from_str(&format!("Error extrayendo páginas: {}", e)))?;
+    /// Alto en píxeles de la imagen de la página; 0 si no se pudo extraer.
+    #[wasm_bindgen(getter)]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
 
-    let pages: Vec<_> = if page_indices.is_empty() {
-        all_pages
-    } else {
-        page_indices
-            .iter()
-            .filter_map(|&i| all_pages.get(i as usize).cloned())
-            .collect()
-    };
+    /// Ancho en puntos, ya girada según /Rotate.
+    #[wasm_bindgen(getter)]
+    pub fn width_pt(&self) -> f64 {
+        self.width_pt
+    }
 
-    if pages.is_empty() {
-        return Err(JsValue::from_str("No se seleccionaron páginas válidas"));
+    /// Alto en puntos, ya girada según /Rotate.
+    #[wasm_bindgen(getter)]
+    pub fn height_pt(&self) -> f64 {
+        self.height_pt
     }
 
-    let pos = if position.is_empty() { "br" } else { position };
+    /// Ancho entre alto, de los píxeles o, si no hay, de los puntos.
+    #[wasm_bindgen(getter)]
+    pub fn aspect_ratio(&self) -> f64 {
+        match (self.width, self.height) {
+            (w, h) if h > 0 => w as f64 / h as f64,
+            _ if self.height_pt > 0.0 => self.width_pt / self.height_pt,
+            _ => 0.0,
+        }
+    }
 
-    let wm = watermark::prepare_from_bytes(logo_bytes, min_w, min_h)
-        .map_err(|e| JsValue::from_str(&format!("Error preparando logo: {}", e)))?;
+    /// Motivo por el que no se pudo extraer la página, si es el caso.
+    #[wasm_bindgen(getter)]
+    pub fn error(&self) -> Option<String> {
+        self.error.clone()
+    }
+}
 
-    let result: Vec<_> = pages.iter().map(|page| watermark::apply(page, &wm, pos)).collect();
+/// Miniaturas de las páginas del PDF, de como mucho `max_width` píxeles de
+/// ancho, para elegir páginas antes de procesarlo. Las páginas que no se
+/// pueden extraer devuelven una miniatura vacía con el motivo en `error`.
+#[wasm_bindgen]
+pub fn render_thumbnails(pdf_bytes: &[u8], password: &str, max_width: u32) -> Result<Vec<Thumbnail>, ProcessError> {
+    if max_width == 0 {
+        return Err(WatermarkError::InvalidOption("El ancho de las miniaturas debe ser mayor que 0".to_string()).into());
+    }
+    let mut reader = pdf::PageReader::new(pdf_bytes.to_vec(), password, pdf::DEFAULT_DPI)?;
+    let thumbnails = (0..reader.len())
+        .map(|index| match reader.thumbnail(index, max_width).and_then(encode_png) {
+            Ok((png, width, height)) => Thumbnail { png, width, height, error: None },
+            Err(e) => Thumbnail { png: Vec::new(), width: 0, height: 0, error: Some(e.to_string()) },
+        })
+        .collect();
+    Ok(thumbnails)
+}
 
-    let pdf_out = builder::build_pdf_bytes(&result, &quality)
-        .map_err(|e| JsValue::from_str(&format!("Error generando PDF: {}", e)))?;
+fn encode_png(image: image::RgbaImage) -> Result<(Vec<u8>, u32, u32), WatermarkError> {
+    let mut png = Vec::new();
+    image
+        .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+        .map_err(|e| WatermarkError::Other(e.to_string()))?;
+    Ok((png, image.width(), image.height()))
+}
 
-    Ok(pdf_out)
+/// Miniatura PNG de una página (ver [`render_thumbnails`]).
+#[wasm_bindgen]
+pub struct Thumbnail {
+    png: Vec<u8>,
+    width: u32,
+    height: u32,
+    error: Option<String>,
 }
 
 #[wasm_bindgen]
-pub fn get_page_count(pdf_bytes: &[u8]) -> Result<usize, JsValue> {
-    let pages = pdf::extract_pages_from_bytes(pdf_bytes)
-        .map_err(|e| JsValue::from_str(&e.to_string()))?;
-    Ok(pages.len())
+impl Thumbnail {
+    /// PNG de la página; vacío si no se pudo extraer.
+    #[wasm_bindgen(getter)]
+    pub fn png(&self) -> Vec<u8> {
+        self.png.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Motivo por el que no hay miniatura, si es el caso.
+    #[wasm_bindgen(getter)]
+    pub fn error(&self) -> Option<String> {
+        self.error.clone()
+    }
+}
+
+/// Error devuelto a JavaScript, para distinguir el motivo sin interpretar el
+/// mensaje (ver [`WatermarkError`]).
+#[wasm_bindgen]
+#[derive(Debug)]
+pub struct ProcessError {
+    code: &'static str,
+    page: Option<usize>,
+    detail: Option<String>,
+    message: String,
+}
+
+#[wasm_bindgen]
+impl ProcessError {
+    /// Motivo, ej. "unsupported_filter" o "wrong_password".
+    #[wasm_bindgen(getter)]
+    pub fn code(&self) -> String {
+        self.code.to_string()
+    }
+
+    /// Índice (desde 0) de la página que falló, si la hay.
+    #[wasm_bindgen(getter)]
+    pub fn page(&self) -> Option<usize> {
+        self.page
+    }
+
+    /// Filtro o espacio de color no soportado, si es el motivo.
+    #[wasm_bindgen(getter)]
+    pub fn detail(&self) -> Option<String> {
+        self.detail.clone()
+    }
+
+    /// Mensaje en español, el mismo que muestra la CLI.
+    #[wasm_bindgen(getter)]
+    pub fn message(&self) -> String {
+        self.message.clone()
+    }
+
+    #[wasm_bindgen(js_name = toString)]
+    pub fn to_js_string(&self) -> String {
+        self.message.clone()
+    }
+}
+
+impl From<WatermarkError> for ProcessError {
+    fn from(err: WatermarkError) -> Self {
+        ProcessError {
+            code: err.code(),
+            page: err.page_number().map(|n| n - 1),
+            detail: err.detail().map(str::to_string),
+            message: err.to_string(),
+        }
+    }
 }
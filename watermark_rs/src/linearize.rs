@@ -0,0 +1,495 @@
+use anyhow::{anyhow, Result};
+use lopdf::{Dictionary, Document, Object, ObjectId, StringFormat};
+use std::collections::{HashMap, HashSet};
+
+/// Entradas del catálogo que el visor necesita al abrir el documento; van
+/// junto al catálogo, antes de la primera página.
+const OPEN_KEYS: &[&[u8]] = &[b"ViewerPreferences", b"OpenAction", b"Threads", b"AcroForm"];
+
+/// Ancho fijo del diccionario de linealización y del primer trailer, que se
+/// escriben antes de conocer los offsets que contienen.
+const LINEARIZATION_WIDTH: usize = 128;
+
+/// Objetos del documento agrupados en el orden en que se escriben (anexo F
+/// de ISO 32000-1).
+struct Layout {
+    /// Catálogo y entradas necesarias al abrir (parte 4).
+    open: Vec<ObjectId>,
+    /// Primera página con todo lo que usa, compartido o no (parte 6).
+    first_page: Vec<ObjectId>,
+    /// Resto de páginas: cada objeto `Page` seguido de sus objetos exclusivos
+    /// (parte 7).
+    pages: Vec<Vec<ObjectId>>,
+    /// Objetos que usan varias páginas y no están en la primera (parte 8).
+    shared: Vec<ObjectId>,
+    /// Árbol de páginas, Info, marcadores, metadatos... (parte 9).
+    other: Vec<ObjectId>,
+    /// Objetos compartidos (de la primera página o de la parte 8) que usa
+    /// cada página a partir de la segunda.
+    shared_refs: Vec<Vec<ObjectId>>,
+}
+
+/// Serializa `doc` linealizado ("vista rápida en la web"): el catálogo y la
+/// primera página van al principio con su propia tabla xref, y las tablas de
+/// pistas permiten al visor pedir cada página por rangos de bytes.
+pub(crate) fn save(doc: &Document) -> Result<Vec<u8>> {
+    let layout = layout(doc)?;
+
+    // La parte principal (páginas 2..n, compartidos y resto) se numera desde 1;
+    // la sección de la primera página, a continuación, en orden de escritura.
+    let mut numbers = HashMap::new();
+    let main: Vec<ObjectId> = layout
+        .pages
+        .iter()
+        .flatten()
+        .chain(&layout.shared)
+        .chain(&layout.other)
+        .copied()
+        .collect();
+    for (i, id) in main.iter().enumerate() {
+        numbers.insert(*id, i as u32 + 1);
+    }
+    let linearization_number = main.len() as u32 + 1;
+    let mut next = linearization_number + 1;
+    for id in &layout.open {
+        numbers.insert(*id, next);
+        next += 1;
+    }
+    let hint_number = next;
+    next += 1;
+    for id in &layout.first_page {
+        numbers.insert(*id, next);
+        next += 1;
+    }
+    let size = next;
+
+    let encode = |ids: &[ObjectId]| -> Vec<Vec<u8>> {
+        ids.iter()
+            .map(|id| indirect_object(numbers[id], doc.get_object(*id).unwrap_or(&Object::Null), &numbers))
+            .collect()
+    };
+    let open = encode(&layout.open);
+    let first_page = encode(&layout.first_page);
+    let main_objects = encode(&main);
+
+    let header = [b"%PDF-".as_slice(), doc.version.as_bytes(), b"\n%\xBB\xAD\xC0\xDE\n"].concat();
+    let first_count = size - linearization_number;
+    let trailer = |prev: u64| -> Vec<u8> {
+        let mut trailer = Dictionary::new();
+        trailer.set("Size", size as i64);
+        for key in [b"Root".as_slice(), b"Info", b"ID"] {
+            if let Ok(value) = doc.trailer.get(key) {
+                trailer.set(key, value.clone());
+            }
+        }
+        trailer.set("Prev", prev as i64);
+        let mut out = Vec::new();
+        write_object(&mut out, &Object::Dictionary(trailer), &numbers);
+        out
+    };
+    let trailer_width = trailer(9_999_999_999).len();
+    let first_xref_len = format!("xref\n{} {}\n", linearization_number, first_count).len()
+        + 20 * first_count as usize
+        + "trailer\n".len()
+        + trailer_width
+        + "\nstartxref\n0\n%%EOF\n".len();
+    let linearization_len = format!("{} 0 obj\n", linearization_number).len() + LINEARIZATION_WIDTH + "\nendobj\n".len();
+
+    // Offsets sin el stream de pistas: así se expresan en las propias tablas.
+    let open_start = header.len() + linearization_len + first_xref_len;
+    let hint_start = open_start + open.iter().map(Vec::len).sum::<usize>();
+    let offsets = |start: usize, objects: &[Vec<u8>]| -> Vec<usize> {
+        objects
+            .iter()
+            .scan(start, |pos, object| {
+                let offset = *pos;
+                *pos += object.len();
+                Some(offset)
+            })
+            .collect()
+    };
+    let first_page_offsets = offsets(hint_start, &first_page);
+    let main_start = hint_start + first_page.iter().map(Vec::len).sum::<usize>();
+    let main_offsets = offsets(main_start, &main_objects);
+    let offset_of: HashMap<ObjectId, usize> = layout
+        .first_page
+        .iter()
+        .zip(&first_page_offsets)
+        .chain(main.iter().zip(&main_offsets))
+        .map(|(id, offset)| (*id, *offset))
+        .collect();
+    let length_of: HashMap<ObjectId, usize> = layout
+        .first_page
+        .iter()
+        .zip(&first_page)
+        .chain(main.iter().zip(&main_objects))
+        .map(|(id, bytes)| (*id, bytes.len()))
+        .collect();
+
+    let (hints, shared_table) = hint_tables(&layout, &numbers, &offset_of, &length_of);
+    let mut hint_dict = Dictionary::new();
+    hint_dict.set("S", shared_table as i64);
+    let hint = indirect_object(
+        hint_number,
+        &Object::Stream(lopdf::Stream::new(hint_dict, hints)),
+        &numbers,
+    );
+    let shift = hint.len();
+
+    let first_page_end = main_start + shift;
+    let main_xref = first_page_end + main_objects.iter().map(Vec::len).sum::<usize>();
+    let main_xref_head = format!("xref\n0 {}", main.len() + 1);
+    let mut main_xref_text = format!("{}\n0000000000 65535 f \n", main_xref_head);
+    for offset in &main_offsets {
+        main_xref_text += &format!("{:010} 00000 n \n", offset + shift);
+    }
+    let tail = format!("trailer\n<</Size {}>>\nstartxref\n{}\n%%EOF\n", main.len() + 1, header.len() + linearization_len);
+    let file_len = main_xref + main_xref_text.len() + tail.len();
+
+    let linearization = format!(
+        "<</Linearized 1/L {}/H[{} {}]/O {}/N {}/E {}/T {}>>",
+        file_len,
+        hint_start,
+        shift,
+        numbers[&layout.first_page[0]],
+        layout.pages.len() + 1,
+        first_page_end,
+        main_xref + main_xref_head.len(),
+    );
+
+    let mut out = Vec::with_capacity(file_len);
+    out.extend(&header);
+    out.extend(format!("{} 0 obj\n{:<width$}\nendobj\n", linearization_number, linearization, width = LINEARIZATION_WIDTH).as_bytes());
+    out.extend(format!("xref\n{} {}\n", linearization_number, first_count).as_bytes());
+    let mut first_offsets = vec![header.len()];
+    first_offsets.extend(offsets(open_start, &open));
+    first_offsets.push(hint_start);
+    first_offsets.extend(first_page_offsets.iter().map(|offset| offset + shift));
+    for offset in first_offsets {
+        out.extend(format!("{:010} 00000 n \n", offset).as_bytes());
+    }
+    out.extend(b"trailer\n");
+    let first_trailer = trailer(main_xref as u64);
+    out.extend(&first_trailer);
+    out.resize(out.len() + trailer_width - first_trailer.len(), b' ');
+    out.extend(b"\nstartxref\n0\n%%EOF\n");
+    for object in open.iter().chain([&hint]).chain(&first_page).chain(&main_objects) {
+        out.extend(object);
+    }
+    out.extend(main_xref_text.as_bytes());
+    out.extend(tail.as_bytes());
+    debug_assert_eq!(out.len(), file_len);
+    Ok(out)
+}
+
+fn layout(doc: &Document) -> Result<Layout> {
+    let catalog_id = doc.trailer.get(b"Root")?.as_reference()?;
+    let catalog = doc.get_dictionary(catalog_id)?;
+    let page_ids: Vec<ObjectId> = doc.get_pages().into_values().collect();
+    if page_ids.is_empty() {
+        return Err(anyhow!("El PDF no tiene páginas"));
+    }
+
+    let mut open_roots = Vec::new();
+    let uses_outlines = catalog.get(b"PageMode").and_then(Object::as_name).is_ok_and(|m| m == b"UseOutlines");
+    for key in OPEN_KEYS.iter().chain(uses_outlines.then_some(&b"Outlines".as_slice())) {
+        if let Ok(value) = catalog.get(key) {
+            references(value, &mut open_roots);
+        }
+    }
+    let mut placed = HashSet::from([catalog_id]);
+    let mut open = vec![catalog_id];
+    open.extend(reachable(doc, &open_roots, &placed));
+    placed.extend(&open);
+
+    let page_objects: Vec<Vec<ObjectId>> = page_ids.iter().map(|id| reachable(doc, &[*id], &placed)).collect();
+    let mut users: HashMap<ObjectId, usize> = HashMap::new();
+    for id in page_objects.iter().flatten() {
+        *users.entry(*id).or_default() += 1;
+    }
+
+    let first_page = page_objects[0].clone();
+    placed.extend(&first_page);
+    let mut pages = Vec::new();
+    let mut shared = Vec::new();
+    let mut shared_refs = Vec::new();
+    for objects in &page_objects[1..] {
+        let (private, common): (Vec<ObjectId>, Vec<ObjectId>) =
+            objects.iter().partition(|id| users[id] == 1 && !placed.contains(id));
+        placed.extend(&private);
+        pages.push(private);
+        for id in &common {
+            if placed.insert(*id) {
+                shared.push(*id);
+            }
+        }
+        shared_refs.push(common);
+    }
+    let other = doc.objects.keys().filter(|id| !placed.contains(id)).copied().collect();
+
+    Ok(Layout { open, first_page, pages, shared, other, shared_refs })
+}
+
+/// Objetos alcanzables desde `roots` en preorden, sin seguir `/Parent` ni
+/// entrar en otras páginas o nodos del árbol de páginas.
+fn reachable(doc: &Document, roots: &[ObjectId], exclude: &HashSet<ObjectId>) -> Vec<ObjectId> {
+    let mut seen = HashSet::new();
+    let mut order = Vec::new();
+    let mut stack: Vec<ObjectId> = roots.iter().rev().copied().collect();
+    while let Some(id) = stack.pop() {
+        if exclude.contains(&id) || !seen.insert(id) {
+            continue;
+        }
+        let Ok(object) = doc.get_object(id) else {
+            continue;
+        };
+        let page_node = object.as_dict().is_ok_and(|d| d.has_type(b"Page") || d.has_type(b"Pages"));
+        if page_node && !roots.contains(&id) {
+            continue;
+        }
+        order.push(id);
+        let mut refs = Vec::new();
+        references(object, &mut refs);
+        stack.extend(refs.into_iter().rev());
+    }
+    order
+}
+
+fn references(object: &Object, out: &mut Vec<ObjectId>) {
+    let dict = match object {
+        Object::Reference(id) => return out.push(*id),
+        Object::Array(items) => return items.iter().for_each(|item| references(item, out)),
+        Object::Dictionary(dict) => dict,
+        Object::Stream(stream) => &stream.dict,
+        _ => return,
+    };
+    for (key, value) in dict.iter() {
+        if key != b"Parent" {
+            references(value, out);
+        }
+    }
+}
+
+/// Tablas de pistas de desplazamiento de páginas y de objetos compartidos
+/// (anexo F.4). Devuelve el contenido del stream y el offset de la segunda
+/// tabla dentro de él.
+fn hint_tables(
+    layout: &Layout,
+    numbers: &HashMap<ObjectId, u32>,
+    offset_of: &HashMap<ObjectId, usize>,
+    length_of: &HashMap<ObjectId, usize>,
+) -> (Vec<u8>, usize) {
+    let group_length = |ids: &[ObjectId]| ids.iter().map(|id| length_of[id] as u64).sum::<u64>();
+    let object_counts: Vec<u64> = std::iter::once(layout.first_page.len() as u64)
+        .chain(layout.pages.iter().map(|p| p.len() as u64))
+        .collect();
+    let page_lengths: Vec<u64> = std::iter::once(group_length(&layout.first_page))
+        .chain(layout.pages.iter().map(|p| group_length(p)))
+        .collect();
+
+    // Identificadores en la tabla de compartidos: primero cada objeto de la
+    // primera página y luego los de la parte 8.
+    let shared_index: HashMap<ObjectId, u64> = layout
+        .first_page
+        .iter()
+        .chain(&layout.shared)
+        .enumerate()
+        .map(|(i, id)| (*id, i as u64))
+        .collect();
+    let page_shared: Vec<Vec<u64>> = std::iter::once(Vec::new())
+        .chain(layout.shared_refs.iter().map(|refs| refs.iter().map(|id| shared_index[id]).collect()))
+        .collect();
+
+    let min_objects = *object_counts.iter().min().unwrap_or(&0);
+    let max_objects = *object_counts.iter().max().unwrap_or(&0);
+    let min_length = *page_lengths.iter().min().unwrap_or(&0);
+    let max_length = *page_lengths.iter().max().unwrap_or(&0);
+    let objects_bits = bits(max_objects - min_objects);
+    let length_bits = bits(max_length - min_length);
+    let refs_bits = bits(page_shared.iter().map(|s| s.len() as u64).max().unwrap_or(0));
+    let id_bits = bits(page_shared.iter().flatten().copied().max().unwrap_or(0));
+
+    let mut w = BitWriter::default();
+    w.write(min_objects, 32);
+    w.write(offset_of[&layout.first_page[0]] as u64, 32);
+    w.write(objects_bits as u64, 16);
+    w.write(min_length, 32);
+    w.write(length_bits as u64, 16);
+    // Sin offsets de contenido: se declara la página entera como contenido.
+    w.write(0, 32);
+    w.write(0, 16);
+    w.write(min_length, 32);
+    w.write(length_bits as u64, 16);
+    w.write(refs_bits as u64, 16);
+    w.write(id_bits as u64, 16);
+    w.write(0, 16);
+    w.write(4, 16);
+    for count in &object_counts {
+        w.write(count - min_objects, objects_bits);
+    }
+    w.flush();
+    for length in &page_lengths {
+        w.write(length - min_length, length_bits);
+    }
+    w.flush();
+    for refs in &page_shared {
+        w.write(refs.len() as u64, refs_bits);
+    }
+    w.flush();
+    for id in page_shared.iter().flatten() {
+        w.write(*id, id_bits);
+    }
+    w.flush();
+    // Numeradores (0 bits) y offsets de contenido (0 bits): secciones vacías.
+    for length in &page_lengths {
+        w.write(length - min_length, length_bits);
+    }
+    w.flush();
+    let shared_table = w.data.len();
+
+    let groups: Vec<u64> = layout.first_page.iter().chain(&layout.shared).map(|id| length_of[id] as u64).collect();
+    let min_group = *groups.iter().min().unwrap_or(&0);
+    let max_group = *groups.iter().max().unwrap_or(&0);
+    let group_bits = bits(max_group - min_group);
+    let (first_shared, first_shared_offset) = match layout.shared.first() {
+        Some(id) => (numbers[id] as u64, offset_of[id] as u64),
+        None => (0, 0),
+    };
+    w.write(first_shared, 32);
+    w.write(first_shared_offset, 32);
+    w.write(layout.first_page.len() as u64, 32);
+    w.write(groups.len() as u64, 32);
+    w.write(0, 16);
+    w.write(min_group, 32);
+    w.write(group_bits as u64, 16);
+    for length in &groups {
+        w.write(length - min_group, group_bits);
+    }
+    w.flush();
+    // Sin firmas MD5 y un objeto por grupo.
+    for _ in &groups {
+        w.write(0, 1);
+    }
+    w.flush();
+    (w.data, shared_table)
+}
+
+/// Bits necesarios para representar `n`.
+fn bits(n: u64) -> u32 {
+    u64::BITS - n.leading_zeros()
+}
+
+#[derive(Default)]
+struct BitWriter {
+    data: Vec<u8>,
+    byte: u8,
+    used: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u64, bits: u32) {
+        for i in (0..bits).rev() {
+            self.byte = (self.byte << 1) | ((value >> i) & 1) as u8;
+            self.used += 1;
+            if self.used == 8 {
+                self.data.push(self.byte);
+                self.byte = 0;
+                self.used = 0;
+            }
+        }
+    }
+
+    /// Completa el byte en curso con ceros.
+    fn flush(&mut self) {
+        if self.used > 0 {
+            self.data.push(self.byte << (8 - self.used));
+            self.byte = 0;
+            self.used = 0;
+        }
+    }
+}
+
+fn indirect_object(number: u32, object: &Object, numbers: &HashMap<ObjectId, u32>) -> Vec<u8> {
+    let mut out = format!("{} 0 obj\n", number).into_bytes();
+    write_object(&mut out, object, numbers);
+    out.extend(b"\nendobj\n");
+    out
+}
+
+/// Escribe `object` con las referencias renumeradas según `numbers`; las que
+/// no aparecen se escriben como `null`.
+fn write_object(out: &mut Vec<u8>, object: &Object, numbers: &HashMap<ObjectId, u32>) {
+    match object {
+        Object::Null => out.extend(b"null"),
+        Object::Boolean(value) => out.extend(if *value { b"true".as_slice() } else { b"false" }),
+        Object::Integer(value) => out.extend(value.to_string().as_bytes()),
+        Object::Real(value) => out.extend(value.to_string().as_bytes()),
+        Object::Name(name) => write_name(out, name),
+        Object::String(text, StringFormat::Literal) => {
+            out.push(b'(');
+            for &byte in text {
+                match byte {
+                    b'(' | b')' | b'\\' => out.extend([b'\\', byte]),
+                    b'\r' => out.extend(b"\\r"),
+                    _ => out.push(byte),
+                }
+            }
+            out.push(b')');
+        }
+        Object::String(text, StringFormat::Hexadecimal) => {
+            out.push(b'<');
+            for byte in text {
+                out.extend(format!("{:02X}", byte).as_bytes());
+            }
+            out.push(b'>');
+        }
+        Object::Array(items) => {
+            out.push(b'[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(b' ');
+                }
+                write_object(out, item, numbers);
+            }
+            out.push(b']');
+        }
+        Object::Dictionary(dict) => write_dictionary(out, dict, None, numbers),
+        Object::Stream(stream) => {
+            write_dictionary(out, &stream.dict, Some(stream.content.len()), numbers);
+            out.extend(b"\nstream\n");
+            out.extend(&stream.content);
+            out.extend(b"\nendstream");
+        }
+        Object::Reference(id) => match numbers.get(id) {
+            Some(number) => out.extend(format!("{} 0 R", number).as_bytes()),
+            None => out.extend(b"null"),
+        },
+    }
+}
+
+fn write_dictionary(out: &mut Vec<u8>, dict: &Dictionary, length: Option<usize>, numbers: &HashMap<ObjectId, u32>) {
+    out.extend(b"<<");
+    for (key, value) in dict.iter() {
+        if length.is_some() && key == b"Length" {
+            continue;
+        }
+        write_name(out, key);
+        out.push(b' ');
+        write_object(out, value, numbers);
+    }
+    if let Some(length) = length {
+        out.extend(format!("/Length {}", length).as_bytes());
+    }
+    out.extend(b">>");
+}
+
+fn write_name(out: &mut Vec<u8>, name: &[u8]) {
+    out.push(b'/');
+    for &byte in name {
+        if b" \t\n\r\x0C()<>[]{}/%#".contains(&byte) || !(33..=126).contains(&byte) {
+            out.extend(format!("#{:02X}", byte).as_bytes());
+        } else {
+            out.push(byte);
+        }
+    }
+}
@@ -0,0 +1,416 @@
+use crate::builder::import_object;
+use crate::pdf::{self, Page};
+use crate::render::{multiply, Matrix, IDENTITY};
+use anyhow::Result;
+use lopdf::{Dictionary, Document, Object, ObjectId};
+use std::collections::{HashMap, HashSet};
+
+/// Profundidad máxima al recorrer árboles de nombres, por si tienen ciclos.
+const MAX_DEPTH: usize = 32;
+
+/// Lleva coordenadas del espacio de usuario de una página de origen al de la
+/// página reconstruida, que empieza en (0, 0) y ya está girada.
+pub(crate) struct PageTransform {
+    origin: (f64, f64),
+    size: (f64, f64),
+    rotation: u32,
+    scale: (f64, f64),
+}
+
+impl PageTransform {
+    pub(crate) fn new(source: &Document, page_id: ObjectId, page: &Page) -> Self {
+        let rotation = pdf::rotation(source, page_id);
+        let Ok([llx, lly, urx, ury]) = pdf::crop_box(source, page_id) else {
+            return Self { origin: (0.0, 0.0), size: (page.width, page.height), rotation: 0, scale: (1.0, 1.0) };
+        };
+        let (w, h) = (urx - llx, ury - lly);
+        let (shown_w, shown_h) = if rotation.is_multiple_of(180) { (w, h) } else { (h, w) };
+        Self {
+            origin: (llx, lly),
+            size: (w, h),
+            rotation,
+            scale: (page.width / shown_w, page.height / shown_h),
+        }
+    }
+
+    /// Transforma un punto; cada coordenada puede faltar (`null` en los
+    /// destinos). Con giros de 90° o 270° las coordenadas se intercambian.
+    pub(crate) fn point(&self, x: Option<f64>, y: Option<f64>) -> (Option<f64>, Option<f64>) {
+        let (w, h) = self.size;
+        let u = x.map(|x| x - self.origin.0);
+        let v = y.map(|y| y - self.origin.1);
+        let (x, y) = match self.rotation {
+            90 => (v, u.map(|u| w - u)),
+            180 => (u.map(|u| w - u), v.map(|v| h - v)),
+            270 => (v.map(|v| h - v), u),
+            _ => (u, v),
+        };
+        (x.map(|x| x * self.scale.0), y.map(|y| y * self.scale.1))
+    }
+}
+
+/// Copia los marcadores (`/Outlines`) de `source` sobre las páginas
+/// reconstruidas. Los destinos se redirigen a las páginas nuevas y se
+/// eliminan los que apuntan a páginas descartadas.
+pub(crate) fn copy_outlines(doc: &mut Document, source: &Document, pages: &[Page]) -> Result<()> {
+    let Ok(catalog) = source.catalog() else {
+        return Ok(());
+    };
+    let Ok(outlines @ Object::Reference(_)) = catalog.get(b"Outlines").cloned() else {
+        return Ok(());
+    };
+    let page_mode = catalog.get(b"PageMode").ok().cloned();
+
+    let (mut imported, transforms) = page_map(doc, source, pages);
+    let Object::Reference(root) = import_object(doc, source, &outlines, &mut imported) else {
+        return Ok(());
+    };
+
+    let mut pending = vec![root];
+    let mut visited = HashSet::new();
+    while let Some(id) = pending.pop() {
+        if !visited.insert(id) {
+            continue;
+        }
+        let Ok(item) = doc.get_dictionary(id).cloned() else {
+            continue;
+        };
+        for key in [b"First".as_slice(), b"Next"] {
+            if let Ok(Object::Reference(next)) = item.get(key) {
+                pending.push(*next);
+            }
+        }
+        if id != root {
+            let item = retarget(doc, source, item, &mut imported, &transforms);
+            doc.objects.insert(id, Object::Dictionary(item));
+        }
+    }
+
+    let catalog_id = doc.trailer.get(b"Root")?.as_reference()?;
+    let catalog = doc.get_dictionary_mut(catalog_id)?;
+    catalog.set("Outlines", Object::Reference(root));
+    if let Some(page_mode) = page_mode {
+        catalog.set("PageMode", page_mode);
+    }
+    Ok(())
+}
+
+/// Copia las anotaciones (enlaces, notas...) de cada página de origen sobre la
+/// reconstruida, con sus coordenadas transformadas. Los campos de formulario
+/// (`/Widget`) no se copian porque dependen del `/AcroForm` del original.
+pub(crate) fn copy_annotations(doc: &mut Document, source: &Document, pages: &[Page]) -> Result<()> {
+    let (mut imported, transforms) = page_map(doc, source, pages);
+    let source_pages = source.get_pages();
+    let new_pages: Vec<_> = doc.get_pages().into_values().collect();
+    let mut done = HashSet::new();
+    let mut rotated = HashSet::new();
+
+    for (page, new_id) in pages.iter().zip(new_pages) {
+        let Some(&source_id) = source_pages.get(&page.number) else {
+            continue;
+        };
+        let annots = source
+            .get_dictionary(source_id)
+            .ok()
+            .and_then(|p| p.get(b"Annots").ok())
+            .and_then(|a| pdf::resolve(source, a).ok());
+        let Some(Object::Array(annots)) = annots else {
+            continue;
+        };
+        let transform = &transforms[&new_id];
+
+        let mut copied = Vec::new();
+        for annot in &annots {
+            let Ok(dict) = pdf::resolve_to_dict(source, annot) else {
+                continue;
+            };
+            if dict.get(b"Subtype").and_then(Object::as_name).is_ok_and(|s| s == b"Widget") {
+                continue;
+            }
+            let (id, dict) = match import_object(doc, source, annot, &mut imported) {
+                Object::Reference(id) if !done.insert(id) => {
+                    copied.push(Object::Reference(id));
+                    continue;
+                }
+                Object::Reference(id) => match doc.get_dictionary(id) {
+                    Ok(dict) => (Some(id), dict.clone()),
+                    Err(_) => continue,
+                },
+                Object::Dictionary(dict) => (None, dict),
+                _ => continue,
+            };
+            let mut dict = retarget(doc, source, dict, &mut imported, &transforms);
+            transform_annotation(&mut dict, transform);
+            dict.set("P", Object::Reference(new_id));
+            rotate_appearance(doc, &dict, transform, &mut rotated);
+            copied.push(match id {
+                Some(id) => {
+                    doc.objects.insert(id, Object::Dictionary(dict));
+                    Object::Reference(id)
+                }
+                None => Object::Dictionary(dict),
+            });
+        }
+        if !copied.is_empty() {
+            doc.get_dictionary_mut(new_id)?.set("Annots", copied);
+        }
+    }
+    Ok(())
+}
+
+/// Transforma las coordenadas de página de una anotación: su `/Rect` y las
+/// listas de puntos de subrayados, líneas, polígonos y tinta.
+fn transform_annotation(annot: &mut Dictionary, transform: &PageTransform) {
+    let points = |values: &[Object]| -> Vec<Object> {
+        values
+            .chunks(2)
+            .flat_map(|pair| {
+                let num = |i: usize| pair.get(i).and_then(|v| v.as_float().ok()).map(f64::from);
+                let (x, y) = transform.point(num(0), num(1));
+                [x, y].map(|v| Object::from(v.unwrap_or(0.0)))
+            })
+            .collect()
+    };
+
+    if let Ok(rect) = annot.get(b"Rect").and_then(Object::as_array) {
+        let corners = points(rect);
+        let num = |i: usize| corners.get(i).and_then(|v| v.as_float().ok()).unwrap_or(0.0);
+        let (x1, y1, x2, y2) = (num(0), num(1), num(2), num(3));
+        annot.set("Rect", [x1.min(x2), y1.min(y2), x1.max(x2), y1.max(y2)].map(Object::from).to_vec());
+    }
+    for key in [b"QuadPoints".as_slice(), b"Vertices", b"L", b"CL"] {
+        if let Ok(values) = annot.get(key).and_then(Object::as_array) {
+            let values = points(values);
+            annot.set(key, values);
+        }
+    }
+    if let Ok(ink) = annot.get(b"InkList").and_then(Object::as_array) {
+        let ink: Vec<Object> = ink
+            .iter()
+            .map(|path| path.as_array().map(|p| Object::Array(points(p))).unwrap_or(Object::Null))
+            .collect();
+        annot.set("InkList", ink);
+    }
+}
+
+/// En páginas giradas, gira también las apariencias (`/AP`) de la anotación,
+/// que el visor dibuja dentro de `/Rect` con la orientación de la página.
+fn rotate_appearance(
+    doc: &mut Document,
+    annot: &Dictionary,
+    transform: &PageTransform,
+    rotated: &mut HashSet<ObjectId>,
+) {
+    let rotation: Matrix = match transform.rotation {
+        90 => [0.0, -1.0, 1.0, 0.0, 0.0, 0.0],
+        180 => [-1.0, 0.0, 0.0, -1.0, 0.0, 0.0],
+        270 => [0.0, 1.0, -1.0, 0.0, 0.0, 0.0],
+        _ => return,
+    };
+    let Some(Object::Dictionary(appearance)) = annot.get(b"AP").ok().and_then(|a| pdf::resolve(doc, a).ok()) else {
+        return;
+    };
+
+    let mut streams = Vec::new();
+    for (_, entry) in appearance.iter() {
+        match entry {
+            Object::Reference(id) => match doc.get_object(*id) {
+                Ok(Object::Stream(_)) => streams.push(*id),
+                Ok(Object::Dictionary(states)) => {
+                    streams.extend(states.iter().filter_map(|(_, s)| s.as_reference().ok()));
+                }
+                _ => {}
+            },
+            Object::Dictionary(states) => {
+                streams.extend(states.iter().filter_map(|(_, s)| s.as_reference().ok()));
+            }
+            _ => {}
+        }
+    }
+
+    for id in streams {
+        if !rotated.insert(id) {
+            continue;
+        }
+        let Ok(Object::Stream(stream)) = doc.get_object_mut(id) else {
+            continue;
+        };
+        let matrix = stream
+            .dict
+            .get(b"Matrix")
+            .and_then(Object::as_array)
+            .ok()
+            .and_then(|m| {
+                let values: Vec<f64> = m.iter().filter_map(|v| v.as_float().ok()).map(f64::from).collect();
+                values.try_into().ok()
+            })
+            .unwrap_or(IDENTITY);
+        let matrix = multiply(&matrix, &rotation);
+        stream.dict.set("Matrix", matrix.map(Object::from).to_vec());
+    }
+}
+
+/// Asocia cada página de origen con la reconstruida a partir de ella, como
+/// semilla de `import_object`, y guarda cómo transformar sus coordenadas.
+fn page_map(
+    doc: &Document,
+    source: &Document,
+    pages: &[Page],
+) -> (HashMap<ObjectId, ObjectId>, HashMap<ObjectId, PageTransform>) {
+    let source_pages = source.get_pages();
+    let mut imported = HashMap::new();
+    let mut transforms = HashMap::new();
+    for (page, new_id) in pages.iter().zip(doc.get_pages().into_values()) {
+        if let Some(&id) = source_pages.get(&page.number) {
+            imported.entry(id).or_insert(new_id);
+            transforms.insert(new_id, PageTransform::new(source, id, page));
+        }
+    }
+    (imported, transforms)
+}
+
+/// Reescribe el destino de un marcador o un enlace (`/Dest` o la acción
+/// `/GoTo`). Si la página de destino no está en la salida, lo quita.
+fn retarget(
+    doc: &mut Document,
+    source: &Document,
+    mut item: Dictionary,
+    imported: &mut HashMap<ObjectId, ObjectId>,
+    transforms: &HashMap<ObjectId, PageTransform>,
+) -> Dictionary {
+    if let Ok(dest) = item.get(b"Dest").cloned() {
+        match map_destination(doc, source, &dest, imported, transforms) {
+            Some(dest) => item.set("Dest", dest),
+            None => {
+                item.remove(b"Dest");
+            }
+        }
+    }
+
+    let action = item.get(b"A").ok().and_then(|a| pdf::resolve(doc, a).ok());
+    if let Some(Object::Dictionary(mut action)) = action {
+        let is_goto = action.get(b"S").and_then(Object::as_name).is_ok_and(|s| s == b"GoTo");
+        if let (true, Ok(dest)) = (is_goto, action.get(b"D").cloned()) {
+            match map_destination(doc, source, &dest, imported, transforms) {
+                Some(dest) => {
+                    action.set("D", dest);
+                    item.set("A", action);
+                }
+                None => {
+                    item.remove(b"A");
+                }
+            }
+        }
+    }
+    item
+}
+
+/// Convierte un destino (explícito o con nombre) en uno explícito sobre la
+/// página reconstruida. `None` si la página no está en la salida.
+fn map_destination(
+    doc: &mut Document,
+    source: &Document,
+    dest: &Object,
+    imported: &mut HashMap<ObjectId, ObjectId>,
+    transforms: &HashMap<ObjectId, PageTransform>,
+) -> Option<Object> {
+    let dest = match pdf::resolve(doc, dest).ok()? {
+        Object::Name(name) => named_destination(source, &name, true)
+            .map(|d| import_object(doc, source, &d, imported))?,
+        Object::String(name, _) => named_destination(source, &name, false)
+            .map(|d| import_object(doc, source, &d, imported))?,
+        dest => dest,
+    };
+    let dest = match pdf::resolve(doc, &dest).ok()? {
+        Object::Dictionary(d) => pdf::resolve(doc, d.get(b"D").ok()?).ok()?,
+        dest => dest,
+    };
+    let dest = dest.as_array().ok()?;
+    let page = dest.first()?.as_reference().ok()?;
+    let transform = transforms.get(&page)?;
+    Some(Object::Array(transform_destination(dest, transform)))
+}
+
+/// Busca un destino con nombre: los de tipo nombre en el `/Dests` del
+/// catálogo (PDF 1.1) y los de tipo cadena en el árbol `/Names /Dests`.
+fn named_destination(source: &Document, name: &[u8], legacy: bool) -> Option<Object> {
+    let catalog = source.catalog().ok()?;
+    if legacy {
+        let dests = pdf::resolve_to_dict(source, catalog.get(b"Dests").ok()?).ok()?;
+        return dests.get(name).ok().cloned();
+    }
+    let names = pdf::resolve_to_dict(source, catalog.get(b"Names").ok()?).ok()?;
+    let tree = pdf::resolve_to_dict(source, names.get(b"Dests").ok()?).ok()?;
+    name_tree_lookup(source, &tree, name, 0)
+}
+
+fn name_tree_lookup(source: &Document, node: &Dictionary, key: &[u8], depth: usize) -> Option<Object> {
+    if depth > MAX_DEPTH {
+        return None;
+    }
+    if let Ok(names) = node.get(b"Names").and_then(Object::as_array) {
+        for pair in names.chunks(2) {
+            if let [name, value] = pair {
+                let name = pdf::resolve(source, name).ok();
+                if name.as_ref().and_then(|n| n.as_str().ok()) == Some(key) {
+                    return Some(value.clone());
+                }
+            }
+        }
+    }
+    let kids = node.get(b"Kids").and_then(Object::as_array).ok()?;
+    kids.iter().find_map(|kid| {
+        let kid = pdf::resolve_to_dict(source, kid).ok()?;
+        name_tree_lookup(source, &kid, key, depth + 1)
+    })
+}
+
+/// Transforma las coordenadas de un destino explícito `[página /Tipo ...]`.
+/// En páginas giradas 90° o 270° `/FitH` pasa a ser `/FitV` y viceversa.
+fn transform_destination(dest: &[Object], transform: &PageTransform) -> Vec<Object> {
+    let num = |i: usize| dest.get(i).and_then(|v| v.as_float().ok()).map(f64::from);
+    let value = |v: Option<f64>| v.map(Object::from).unwrap_or(Object::Null);
+    let kind = dest.get(1).and_then(|k| k.as_name().ok()).unwrap_or(b"Fit");
+
+    let mut out = vec![dest[0].clone()];
+    match kind {
+        b"XYZ" => {
+            let (left, top) = transform.point(num(2), num(3));
+            out.push(Object::Name(b"XYZ".to_vec()));
+            out.push(value(left));
+            out.push(value(top));
+            out.push(dest.get(4).cloned().unwrap_or(Object::Null));
+        }
+        b"FitH" | b"FitBH" | b"FitV" | b"FitBV" => {
+            let bounded = kind.starts_with(b"FitB");
+            let horizontal = kind.ends_with(b"H");
+            let (x, y) = if horizontal {
+                transform.point(None, num(2))
+            } else {
+                transform.point(num(2), None)
+            };
+            let (kind, coord) = match (x, y) {
+                (Some(x), _) => ("V", Some(x)),
+                (_, Some(y)) => ("H", Some(y)),
+                // Sin coordenada se conserva la orientación, girada si hace falta.
+                _ if transform.rotation.is_multiple_of(180) == horizontal => ("H", None),
+                _ => ("V", None),
+            };
+            let prefix = if bounded { "FitB" } else { "Fit" };
+            out.push(Object::Name(format!("{}{}", prefix, kind).into_bytes()));
+            out.push(value(coord));
+        }
+        b"FitR" => {
+            let (x1, y1) = transform.point(num(2), num(3));
+            let (x2, y2) = transform.point(num(4), num(5));
+            let (Some(x1), Some(y1), Some(x2), Some(y2)) = (x1, y1, x2, y2) else {
+                out.push(Object::Name(b"Fit".to_vec()));
+                return out;
+            };
+            out.push(Object::Name(b"FitR".to_vec()));
+            out.extend([x1.min(x2), y1.min(y2), x1.max(x2), y1.max(y2)].map(Object::from));
+        }
+        kind => out.push(Object::Name(kind.to_vec())),
+    }
+    out
+}
@@ -1,83 +1,1731 @@
 #[cfg(not(target_arch = "wasm32"))]
-mod pdf;
+use watermark::{builder, pdf, stamp, stego, Options, Pipeline, WatermarkError};
 #[cfg(not(target_arch = "wasm32"))]
-mod watermark;
+use watermark::forensic::Forensic;
 #[cfg(not(target_arch = "wasm32"))]
-mod builder;
+use watermark::progress::{Progress, ProgressSink, Stage};
+#[cfg(not(target_arch = "wasm32"))]
+use clap::{CommandFactory, FromArgMatches, Parser};
+#[cfg(not(target_arch = "wasm32"))]
+use anyhow::{anyhow, Result};
+#[cfg(not(target_arch = "wasm32"))]
+use rayon::prelude::*;
 
+/// Muestra los mensajes de `log`: los informativos en la salida estándar
+/// (o en stderr si por ella sale el PDF), el resto en stderr. Los de las
+/// dependencias (ej. avisos de lopdf sobre el PDF) solo con -vv.
 #[cfg(not(target_arch = "wasm32"))]
-use clap::Parser;
+struct Console {
+    level: log::LevelFilter,
+    dependencies: bool,
+    /// Con --json solo se escribe el JSON final en la salida estándar.
+    json: bool,
+    pdf_to_stdout: bool,
+}
+
 #[cfg(not(target_arch = "wasm32"))]
-use anyhow::Result;
+impl log::Log for Console {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= self.level && (self.dependencies || metadata.target().starts_with("watermark"))
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        match record.level() {
+            log::Level::Info if self.json => {}
+            log::Level::Info if !self.pdf_to_stdout => println!("{}", record.args()),
+            log::Level::Info | log::Level::Warn | log::Level::Error => eprintln!("{}", record.args()),
+            _ => eprintln!("[{}] {}", record.target(), record.args()),
+        }
+    }
+
+    fn flush(&self) {}
+}
 
 #[cfg(not(target_arch = "wasm32"))]
 #[derive(Parser)]
 #[command(name = "watermark", about = "Aplica marca de agua a un PDF de presentación")]
+#[command(subcommand_negates_reqs = true)]
 struct Args {
-    /// PDF de entrada
-    input: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Archivo TOML con valores por defecto de las opciones, ej.
+    /// `position = "tr"`, y presets propios; las opciones indicadas en la
+    /// línea de comandos tienen prioridad
+    #[arg(long, global = true)]
+    config: Option<String>,
+
+    /// Conjunto de opciones con nombre: confidential, draft, internal o uno
+    /// definido en [presets.<nombre>] de --config
+    #[arg(long, global = true)]
+    preset: Option<String>,
+
+    /// PDFs de entrada; admite patrones como *.pdf (con varios hace falta
+    /// --out-dir), - para leer un solo PDF de la entrada estándar y carpetas
+    /// de imágenes PNG, JPEG o WebP, que dan un PDF con una página por imagen
+    /// en el orden de sus nombres
+    #[arg(required_unless_present = "recursive")]
+    inputs: Vec<String>,
+
+    /// Procesa todos los PDFs de esta carpeta y sus subcarpetas, replicando
+    /// la estructura en --out-dir
+    #[arg(long, conflicts_with = "inputs")]
+    recursive: Option<String>,
+
+    /// Une los PDFs de entrada, en el orden indicado, en un solo PDF de salida
+    #[arg(long)]
+    merge: bool,
+
+    /// Escribe cada página en su propio PDF, con el número de página en
+    /// {page} de --output o, si no aparece, al final del nombre
+    #[arg(long, conflicts_with = "merge")]
+    split: bool,
+
+    /// Exporta cada página como imagen (png, jpeg o webp) en vez de generar
+    /// un PDF, con los nombres de --split; jpeg usa la calidad de --quality
+    #[arg(long, value_name = "FORMATO", conflicts_with = "split")]
+    export_images: Option<String>,
+
+    /// Carpeta donde se escribe cada PDF procesado, con su mismo nombre
+    #[arg(long, visible_alias = "output-dir")]
+    out_dir: Option<String>,
+
+    /// CSV de destinatarios con cabecera (ej. name,email,id): genera una
+    /// copia por fila, con sus columnas como variables de --text,
+    /// --invisible, --forensic y --output, ej. {id}_{stem}.pdf
+    #[arg(long, value_name = "CSV")]
+    recipients: Option<String>,
+
+    /// Sobrescribe los archivos de salida que ya existan
+    #[arg(long, global = true)]
+    force: bool,
+
+    /// PDFs que se procesan a la vez con --out-dir (0 = uno por núcleo)
+    #[arg(long, default_value = "1")]
+    jobs: usize,
+
+    /// Contraseña para abrir el PDF de entrada si está protegido
+    #[arg(long, global = true, default_value = "")]
+    password: String,
+
+    /// Páginas a procesar, ej. "1-5,8,12-", first, last, odd o even (por
+    /// defecto todas; el resto se descarta)
+    #[arg(long, default_value = "")]
+    pages: String,
+
+    /// Procesa solo una de cada N de las páginas elegidas, empezando por la primera
+    #[arg(long, default_value = "1")]
+    every_nth: usize,
+
+    /// Páginas que se conservan sin marca de agua, ej. "1,20-" (con la misma sintaxis que --pages)
+    #[arg(long, global = true, default_value = "")]
+    skip_pages: String,
+
+    /// Imagen de marca de agua (PNG, JPG o SVG); por defecto logo.png si no hay --text
+    #[arg(long, global = true)]
+    logo: Option<String>,
+
+    /// Texto de marca de agua (se aplica además del logo si se indica --logo).
+    /// Admite {page}, {total}, {date} y {filename}
+    #[arg(long, global = true)]
+    text: Option<String>,
 
-    /// Imagen de marca de agua (PNG o JPG)
-    #[arg(long, default_value = "logo.png")]
-    logo: String,
+    /// Posición del texto cuando se combina con un logo (por defecto --position)
+    #[arg(long, global = true)]
+    text_position: Option<String>,
 
-    /// Calidad: "lossless" o 1-100 (JPEG)
+    /// Tamaño de fuente del texto en píxeles
+    #[arg(long, global = true, default_value = "32")]
+    font_size: f32,
+
+    /// Color del texto: #RRGGBB o #RRGGBBAA
+    #[arg(long, global = true, default_value = "#000000")]
+    color: String,
+
+    /// Tiñe el logo de este color (#RRGGBB o #RRGGBBAA): el blanco pasa a
+    /// ser este color y lo oscuro se conserva, para usar un solo logo blanco
+    /// en varios colores
+    #[arg(long, global = true, value_name = "COLOR")]
+    tint: Option<String>,
+
+    /// Pasa el logo a escala de grises, para documentos que no admiten
+    /// sellos de color. Con --tint se tiñe después
+    #[arg(long, global = true)]
+    gray_logo: bool,
+
+    /// Fuente TTF/OTF para el texto (por defecto DejaVu Sans Bold)
+    #[arg(long, global = true)]
+    font: Option<String>,
+
+    /// Modo: raster (rasteriza y reconstruye cada página) o stamp
+    /// (conserva texto, enlaces y vectores del PDF original)
+    #[arg(long, global = true, default_value = "raster")]
+    mode: String,
+
+    /// Formato de salida: pdf o tiff (multipágina y sin pérdida, para
+    /// sistemas de archivo o fax; las páginas se rasterizan en cualquier modo)
+    #[arg(long, default_value = "pdf")]
+    format: String,
+
+    /// Resolución al rasterizar páginas sin imágenes (requiere la feature
+    /// render), de las imágenes de una carpeta de entrada y de las páginas
+    /// de --page-size
+    #[arg(long, global = true, default_value = "150")]
+    dpi: f32,
+
+    /// Calidad: "lossless" o 1-100 (JPEG, solo en modo raster)
     #[arg(long, default_value = "lossless")]
     quality: String,
 
-    /// Archivo PDF de salida
+    /// Submuestreo de color con --quality JPEG: 4:4:4 (por defecto), 4:2:2 o
+    /// 4:2:0, que ocupa menos pero emborrona el texto pequeño de color
+    #[arg(long, value_name = "RATIO")]
+    subsampling: Option<String>,
+
+    /// Tamaño máximo del PDF de salida, ej. 10MB: si no cabe con --quality
+    /// se usa la mayor calidad JPEG con la que cabe (solo en modo raster)
+    #[arg(long, value_name = "TAMAÑO")]
+    max_size: Option<String>,
+
+    /// Con --max-size, reduce también la resolución de las páginas si no
+    /// basta con bajar la calidad
+    #[arg(long, requires = "max_size")]
+    downscale: bool,
+
+    /// Reduce las imágenes de las páginas que pasan de esta resolución antes
+    /// de aplicar la marca de agua (solo en modo raster)
+    #[arg(long, value_name = "DPI")]
+    max_dpi: Option<f32>,
+
+    /// Reduce las imágenes de las páginas más anchas que estos píxeles (solo
+    /// en modo raster)
+    #[arg(long, value_name = "PX")]
+    max_width: Option<u32>,
+
+    /// Tamaño de las páginas de salida: 1920x1080 (píxeles, a --dpi) o a3,
+    /// a4, a5, letter o legal en la orientación de cada página; el contenido
+    /// se escala sin deformarse y se centra (solo en modo raster)
+    #[arg(long, value_name = "TAMAÑO")]
+    page_size: Option<String>,
+
+    /// Convierte las páginas y la marca de agua a escala de grises, para
+    /// PDFs que solo se van a imprimir (solo en modo raster)
+    #[arg(long)]
+    grayscale: bool,
+
+    /// Guarda este texto de forma invisible en cada página, ej. el
+    /// destinatario, para rastrear copias filtradas (admite {page},
+    /// {filename}, etc.). Sin --logo ni --text no se añade marca visible.
+    /// Solo en modo raster y sin pérdida; se lee con el subcomando detect
+    #[arg(long, value_name = "TEXTO")]
+    invisible: Option<String>,
+
+    /// Guarda este identificador (hasta 10 bytes, ej. un código de
+    /// destinatario) con un watermark forense que resiste JPEG, cambios de
+    /// tamaño y capturas de la página entera. Requiere --forensic-key; se lee
+    /// con detect --key. Solo en modo raster
+    #[arg(long, value_name = "ID")]
+    forensic: Option<String>,
+
+    /// Clave del watermark forense: sin ella no se puede leer
+    #[arg(long, value_name = "CLAVE")]
+    forensic_key: Option<String>,
+
+    /// Intensidad del watermark forense (por defecto 8): más resiste más
+    /// compresión, pero se nota como un grano suave. Se sube sola en las
+    /// páginas en las que no se podría leer, como las de texto
+    #[arg(long)]
+    forensic_strength: Option<f32>,
+
+    /// Contraseña de usuario para cifrar el PDF de salida
+    #[arg(long, default_value = "")]
+    user_password: String,
+
+    /// Contraseña de propietario del PDF de salida (por defecto la de usuario)
+    #[arg(long, default_value = "")]
+    owner_password: String,
+
+    /// Cifrado del PDF de salida si se indica alguna contraseña: rc4-128, aes-128 o aes-256
+    #[arg(long, default_value = "aes-256")]
+    encryption: String,
+
+    /// Genera PDF/A-2b para archivo (solo en modo raster, sin cifrado)
+    #[arg(long)]
+    pdfa: bool,
+
+    /// Linealiza el PDF ("vista rápida en la web") para que el navegador
+    /// muestre la primera página antes de descargarlo entero (sin cifrado)
+    #[arg(long)]
+    linearize: bool,
+
+    /// Añade el watermark como actualización incremental del PDF original,
+    /// conservando sus objetos y firmas (solo en modo stamp, sin cifrado nuevo)
+    #[arg(long)]
+    incremental: bool,
+
+    /// Comprime el PDF con flujos de objetos y tabla xref comprimida (PDF 1.5+)
+    #[arg(long)]
+    object_streams: bool,
+
+    /// Quita del PDF generado el diccionario Info, el XMP y las miniaturas
+    /// del original, para distribuirlo sin datos del autor
+    #[arg(long, conflicts_with_all = ["pdfa", "incremental"])]
+    strip_metadata: bool,
+
+    /// Guarda en el XMP un registro de cómo y cuándo se marcó el PDF:
+    /// versión, fecha, SHA-256 del logo y opciones usadas
+    #[arg(long)]
+    provenance: bool,
+
+    /// Título del PDF generado, en el diccionario Info y en el XMP
+    #[arg(long, value_name = "TEXTO")]
+    title: Option<String>,
+
+    /// Autor del PDF generado
+    #[arg(long, value_name = "TEXTO")]
+    author: Option<String>,
+
+    /// Asunto del PDF generado
+    #[arg(long, value_name = "TEXTO")]
+    subject: Option<String>,
+
+    /// Palabras clave del PDF generado, ej. "contrato, 2026"
+    #[arg(long, value_name = "TEXTO")]
+    keywords: Option<String>,
+
+    /// Firma el PDF generado con el certificado PKCS#12 (.p12 o .pfx) de
+    /// este archivo; no se puede combinar con el cifrado
+    #[arg(long, value_name = "P12")]
+    sign: Option<String>,
+
+    /// Contraseña del certificado de --sign
+    #[arg(long, default_value = "")]
+    sign_password: String,
+
+    /// Línea fija arriba de cada página marcada, ej. el título o el número
+    /// de expediente, aparte del watermark; admite las variables de --text
+    #[arg(long, global = true, value_name = "TEXTO")]
+    header: Option<String>,
+
+    /// Línea fija abajo de cada página marcada, como --header
+    #[arg(long, global = true, value_name = "TEXTO")]
+    footer: Option<String>,
+
+    /// Tamaño de fuente de --header y --footer
+    #[arg(long, global = true, default_value = "16")]
+    running_size: f32,
+
+    /// Alineación de --header y --footer: left, center o right
+    #[arg(long, global = true, default_value = "center")]
+    running_align: String,
+
+    /// Distancia en px de --header y --footer al borde de la página
+    #[arg(long, global = true, default_value = "20")]
+    running_margin: u32,
+
+    /// Sella la fecha y hora de proceso en cada página marcada, con este
+    /// formato de strftime (por defecto "%Y-%m-%d %H:%M:%S")
+    #[arg(long, value_name = "FORMATO", num_args = 0..=1, default_missing_value = "%Y-%m-%d %H:%M:%S")]
+    timestamp: Option<String>,
+
+    /// Zona horaria de --timestamp: local, utc o un desfase como +02:00
+    #[arg(long, default_value = "local")]
+    timestamp_zone: String,
+
+    /// Sella este texto tal cual en lugar de la hora actual, ej. la fecha
+    /// de una exportación anterior
+    #[arg(long, value_name = "TEXTO")]
+    timestamp_text: Option<String>,
+
+    /// Posición del sello de fecha, como --position
+    #[arg(long, default_value = "bl")]
+    timestamp_position: String,
+
+    /// Numeración Bates: este prefijo seguido de un número con ceros delante
+    /// (ej. ACME000001) en cada página marcada, que sigue de un PDF al
+    /// siguiente en el orden de entrada
+    #[arg(long, value_name = "PREFIJO")]
+    bates: Option<String>,
+
+    /// Primer número de --bates
+    #[arg(long, default_value = "1")]
+    bates_start: u32,
+
+    /// Cifras del número de --bates
+    #[arg(long, default_value = "6")]
+    bates_digits: u32,
+
+    /// Esquina de --bates: tl, tr, bl o br
+    #[arg(long, default_value = "br")]
+    bates_position: String,
+
+    /// Archivo con el siguiente número de --bates: si existe se empieza por
+    /// él en vez de por --bates-start, y al terminar se guarda el siguiente
+    /// para continuar la numeración en otra ejecución
+    #[arg(long, value_name = "ARCHIVO", requires = "bates")]
+    bates_counter: Option<String>,
+
+    /// Muestra las páginas, el tamaño de sus imágenes y el tamaño estimado
+    /// del PDF de salida sin escribir nada
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Muestra detalles de depuración, ej. por qué una página se rasteriza
+    /// (-vv incluye los de las librerías usadas)
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Solo muestra avisos y errores
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// En vez de los mensajes, escribe en la salida estándar un JSON con el
+    /// resultado de cada PDF: páginas procesadas y omitidas, tamaño y tiempo
+    #[arg(long)]
+    json: bool,
+
+    /// Archivo PDF de salida (con un solo PDF de entrada y sin --out-dir); "-"
+    /// lo escribe en la salida estándar. Puede ser una plantilla con {stem},
+    /// {name}, {dir} y {date}, ej. "{dir}/{stem}-{date}.pdf", que admite
+    /// varios PDFs de entrada, y con --split también {page}; con --out-dir
+    /// da el nombre dentro de la carpeta
     #[arg(short, long, default_value = "output_watermarked.pdf")]
     output: String,
 
-    /// Posición del watermark: tl,tc,tr,ml,mc,mr,bl,bc,br
-    #[arg(long, default_value = "br")]
+    /// Posición del watermark: tl,tc,tr,ml,mc,mr,bl,bc,br, tile, diagonal o
+    /// auto (de esas nueve, la de la zona con menos detalle de cada página;
+    /// solo en modo raster)
+    #[arg(long, global = true, default_value = "br")]
     position: String,
 
+    /// Coordenada x del watermark en px o % del ancho (reemplaza a --position, requiere --pos-y)
+    #[arg(long, global = true)]
+    pos_x: Option<String>,
+
+    /// Coordenada y del watermark en px o % del alto (reemplaza a --position, requiere --pos-x)
+    #[arg(long, global = true)]
+    pos_y: Option<String>,
+
+    /// Posiciones por página del logo (o del texto si no hay logo): un
+    /// archivo JSON o el JSON directamente, ej. {"1": "tc", "3": {"x": "85%", "y": 40}}
+    #[arg(long, global = true, value_name = "JSON")]
+    page_positions: Option<String>,
+
+    /// Capa del watermark: over (encima) o under (debajo del contenido)
+    #[arg(long, global = true, default_value = "over")]
+    layer: String,
+
+    /// Zona que el watermark no debe tapar, como un pie legal: x,y,ancho,alto
+    /// en px o %, con las páginas delante si no son todas (1-3:0,90%,100%,10%).
+    /// Se puede repetir; si el watermark la toca se mueve a la posición libre
+    /// más cercana
+    #[arg(long, global = true, value_name = "ZONA")]
+    avoid: Vec<String>,
+
+    /// Distancia del watermark al borde de la página (px)
+    #[arg(long, global = true, default_value = "0")]
+    margin: u32,
+
+    /// Separación horizontal entre copias en modo tile (px)
+    #[arg(long, global = true, default_value = "120")]
+    spacing_x: u32,
+
+    /// Separación vertical entre copias en modo tile (px)
+    #[arg(long, global = true, default_value = "80")]
+    spacing_y: u32,
+
+    /// Opacidad del watermark: 0-1 o porcentaje (ej. 30%). Por defecto 1, o
+    /// 0.3 en diagonal para que se lea lo que hay debajo
+    #[arg(long, global = true)]
+    opacity: Option<String>,
+
+    /// Ancho del watermark relativo a cada página, ej. 10% (ignora --min-w/--min-h)
+    #[arg(long, global = true)]
+    scale: Option<String>,
+
+    /// Sombra difuminada debajo del watermark, para que se lea sobre fondos
+    /// recargados
+    #[arg(long, global = true)]
+    shadow: bool,
+
+    /// Contorno de este grosor (1-4 px) en negro o blanco, el que más
+    /// contraste con el watermark
+    #[arg(long, global = true, value_name = "PX")]
+    outline: Option<u32>,
+
+    /// Invierte los colores del watermark donde se pierde en el fondo, como
+    /// un logo negro en una diapositiva oscura (solo en modo raster)
+    #[arg(long, global = true)]
+    adaptive: bool,
+
+    /// Rectángulo redondeado de este color (#RRGGBB o #RRGGBBAA) detrás del
+    /// watermark, para que se lea sobre fotos
+    #[arg(long, global = true, value_name = "COLOR")]
+    plate: Option<String>,
+
+    /// Opacidad del rectángulo de --plate: 0-1 o porcentaje
+    #[arg(long, global = true, default_value = "60%")]
+    plate_opacity: String,
+
+    /// Margen entre el watermark y el borde del rectángulo de --plate (px)
+    #[arg(long, global = true, default_value = "8")]
+    plate_padding: u32,
+
     /// Ancho mínimo del watermark
-    #[arg(long, default_value = "107")]
+    #[arg(long, global = true, default_value = "107")]
     min_w: u32,
 
     /// Alto mínimo del watermark
-    #[arg(long, default_value = "21")]
+    #[arg(long, global = true, default_value = "21")]
     min_h: u32,
 }
 
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Genera un PNG de una página con la marca de agua, para comprobar su
+    /// posición y tamaño sin generar el PDF
+    Preview {
+        /// PDF de entrada (- para la entrada estándar)
+        input: String,
+
+        /// Página a mostrar (desde 1)
+        #[arg(long, default_value = "1")]
+        page: usize,
+
+        /// Archivo PNG de salida
+        #[arg(long, default_value = "preview.png")]
+        out: String,
+    },
+    /// Busca en cada página de un PDF o imagen el texto de --invisible y,
+    /// con --key, el identificador de --forensic con su confianza
+    Detect {
+        /// PDF o imagen de entrada (- para la entrada estándar)
+        input: String,
+
+        /// Clave con la que se guardó el watermark forense
+        #[arg(long)]
+        key: Option<String>,
+    },
+}
+
 #[cfg(not(target_arch = "wasm32"))]
 fn main() -> Result<()> {
-    let args = Args::parse();
-    let quality = watermark::parse_quality(&args.quality)?;
+    let args = parse_args()?;
+    let pdf_to_stdout = args.command.is_none() && args.out_dir.is_none() && args.output == "-";
+    if args.json && pdf_to_stdout {
+        return Err(anyhow!("--json no se puede usar con -o - (los dos van a la salida estándar)"));
+    }
+    let level = match (args.quiet, args.verbose) {
+        (true, _) => log::LevelFilter::Warn,
+        (false, 0) => log::LevelFilter::Info,
+        (false, 1) => log::LevelFilter::Debug,
+        (false, _) => log::LevelFilter::Trace,
+    };
+    let console = Console { level, dependencies: args.verbose > 1, json: args.json, pdf_to_stdout };
+    log::set_boxed_logger(Box::new(console))?;
+    log::set_max_level(level);
+    let mode = stamp::parse_mode(&args.mode)?;
+    if args.every_nth == 0 {
+        return Err(anyhow!("--every-nth debe ser al menos 1"));
+    }
+    let mut options = Options::new()
+        .with_min_size(args.min_w, args.min_h)
+        .with_font_size(args.font_size)
+        .with_color(&args.color)
+        .with_mode(&args.mode)
+        .with_quality(&args.quality)
+        .with_subsampling(args.subsampling.as_deref().unwrap_or_default())
+        .with_max_size(args.max_size.as_deref().unwrap_or_default(), args.downscale)
+        .with_max_resolution(args.max_dpi.unwrap_or_default(), args.max_width.unwrap_or_default())
+        .with_page_size(args.page_size.as_deref().unwrap_or_default())
+        .with_grayscale(args.grayscale)
+        .with_dpi(args.dpi)
+        .with_password(&args.password)
+        .with_pages(&args.pages)
+        .with_every_nth(args.every_nth)
+        .with_skip_pages(&args.skip_pages)
+        .with_position(&args.position)
+        .with_layer(&args.layer)
+        .with_avoid(&args.avoid.join(";"))
+        .with_margin(args.margin)
+        .with_spacing(args.spacing_x, args.spacing_y)
+        .with_effects(args.shadow, args.outline.unwrap_or_default())
+        .with_adaptive(args.adaptive)
+        .with_gray_logo(args.gray_logo)
+        .with_encryption(&args.user_password, &args.owner_password, &args.encryption)
+        .with_pdfa(args.pdfa)
+        .with_linearize(args.linearize)
+        .with_incremental(args.incremental)
+        .with_object_streams(args.object_streams)
+        .with_strip_metadata(args.strip_metadata)
+        .with_provenance(args.provenance);
+    match (&args.pos_x, &args.pos_y) {
+        (Some(x), Some(y)) => options = options.with_point(x, y),
+        (None, None) => {}
+        _ => return Err(anyhow!("--pos-x y --pos-y deben indicarse juntos")),
+    }
+    if let Some(opacity) = &args.opacity {
+        options = options.with_opacity(opacity);
+    }
+    if let Some(positions) = &args.page_positions {
+        // Si no empieza por '{' es la ruta de un archivo JSON.
+        let json = match positions.trim_start().starts_with('{') {
+            true => positions.clone(),
+            false => std::fs::read_to_string(positions)
+                .map_err(|e| anyhow!("No se pudo leer {}: {}", positions, e))?,
+        };
+        options = options.with_page_positions(&json);
+    }
+    if let Some(scale) = &args.scale {
+        options = options.with_scale(scale);
+    }
+    if let Some(text) = &args.text {
+        options = options.with_text(text);
+    }
+    if let Some(position) = &args.text_position {
+        options = options.with_text_position(position);
+    }
+    if let Some(color) = &args.plate {
+        options = options.with_plate(color, &args.plate_opacity, args.plate_padding);
+    }
+    if let Some(tint) = &args.tint {
+        options = options.with_tint(tint);
+    }
+    if let Some(font) = &args.font {
+        options = options.with_font(&std::fs::read(font)?);
+    }
+    if let Some(title) = &args.title {
+        options = options.with_title(title);
+    }
+    if let Some(author) = &args.author {
+        options = options.with_author(author);
+    }
+    if let Some(subject) = &args.subject {
+        options = options.with_subject(subject);
+    }
+    if let Some(keywords) = &args.keywords {
+        options = options.with_keywords(keywords);
+    }
+    if let Some(header) = &args.header {
+        options = options.with_header(header);
+    }
+    if let Some(footer) = &args.footer {
+        options = options.with_footer(footer);
+    }
+    options = options.with_running_style(args.running_size, &args.running_align, args.running_margin);
+    if let Some(format) = &args.timestamp {
+        options = options.with_timestamp(format, &args.timestamp_zone);
+    }
+    if let Some(text) = &args.timestamp_text {
+        options = options.with_timestamp_text(text);
+    }
+    options = options.with_timestamp_position(&args.timestamp_position);
+    if let Some(prefix) = &args.bates {
+        let start = match &args.bates_counter {
+            Some(path) if std::path::Path::new(path).exists() => std::fs::read_to_string(path)?
+                .trim()
+                .parse()
+                .map_err(|_| anyhow!("{} no tiene un número Bates válido", path))?,
+            _ => args.bates_start,
+        };
+        options = options.with_bates(prefix, &args.bates_position, start, args.bates_digits);
+    }
+    if let Some(path) = &args.sign {
+        let p12 = std::fs::read(path).map_err(|e| anyhow!("No se pudo leer {}: {}", path, e))?;
+        options = options.with_signature(&p12, &args.sign_password);
+    }
 
-    println!("  Input:   {}", args.input);
-    println!("  Logo:    {}", args.logo);
-    println!("  Calidad: {}", args.quality);
-    println!("  Salida:  {}", args.output);
-    println!();
+    if let Some(Command::Detect { input, key }) = &args.command {
+        return detect(input, key.as_deref(), &args);
+    }
+    if let Some(payload) = &args.invisible {
+        options = options.with_invisible(payload);
+    }
+    if let Some(payload) = &args.forensic {
+        let key = args.forensic_key.as_deref().ok_or_else(|| anyhow!("--forensic requiere --forensic-key"))?;
+        options = options.with_forensic(payload, key, args.forensic_strength.unwrap_or_default());
+    }
 
-    println!("[1/4] Extrayendo páginas del PDF...");
-    let pages = pdf::extract_pages(&args.input)?;
-    println!("  Extraídas {} páginas", pages.len());
+    let logo = match (&args.logo, &args.text) {
+        (None, None)
+            if [
+                &args.invisible,
+                &args.forensic,
+                &args.bates,
+                &args.header,
+                &args.footer,
+                &args.timestamp,
+                &args.timestamp_text,
+            ]
+            .iter()
+            .any(|option| option.is_some()) =>
+        {
+            None
+        }
+        (None, None) => Some("logo.png"),
+        (logo, _) => logo.as_deref(),
+    };
+    if let Some(logo) = logo {
+        let data = std::fs::read(logo).map_err(|e| WatermarkError::InvalidLogo(e.to_string()))?;
+        options = options.with_logo(&data);
+    }
 
-    println!("[2/4] Preparando marca de agua...");
-    let wm = watermark::prepare(&args.logo, args.min_w, args.min_h)?;
+    if let Some(Command::Preview { input, page, out }) = &args.command {
+        if *page == 0 {
+            return Err(anyhow!("--page empieza en 1"));
+        }
+        check_overwrite(out, args.force)?;
+        let (data, filename) = read_input(input, args.dpi)?;
+        let image = options.pipeline()?.preview(&data, &filename, page - 1)?;
+        image.save(out).map_err(|e| anyhow!("No se pudo escribir {}: {}", out, e))?;
+        log::info!("Vista previa de la página {} en {} ({}x{} px)", page, out, image.width(), image.height());
+        return Ok(());
+    }
 
-    println!("[3/4] Aplicando marca de agua...");
-    let total = pages.len();
-    let result: Vec<_> = pages
+    let inputs = match (&args.recursive, &args.out_dir) {
+        (Some(_), None) => return Err(anyhow!("--recursive requiere --out-dir")),
+        (Some(root), Some(out_dir)) => find_pdfs(std::path::Path::new(root), std::path::Path::new(out_dir))?,
+        (None, _) => expand_inputs(&args.inputs)?,
+    };
+    let recipients = match &args.recipients {
+        Some(path) => read_recipients(path)?,
+        None => Vec::new(),
+    };
+    // Las columnas del CSV van antes que las variables de --output: {name}
+    // es el del destinatario si el CSV tiene esa columna.
+    let personal = recipients
+        .first()
+        .is_some_and(|recipient| recipient.iter().any(|(column, _)| args.output.contains(&format!("{{{}}}", column))));
+    if !recipients.is_empty() && !personal {
+        return Err(anyhow!("Con --recipients, --output debe llevar alguna columna del CSV, ej. {{id}}_{{stem}}.pdf"));
+    }
+    let template = personal || OUTPUT_VARIABLES.iter().any(|variable| args.output.contains(variable));
+    if args.merge && (args.out_dir.is_some() || template) {
+        return Err(anyhow!("--merge genera un solo PDF: no se puede usar con --out-dir ni con plantillas en --output"));
+    }
+    if inputs.len() > 1 && args.out_dir.is_none() && !template && !args.merge {
+        return Err(anyhow!(
+            "Con varios PDFs de entrada hace falta --out-dir, --merge o una plantilla en --output, ej. {{stem}}_marcado.pdf"
+        ));
+    }
+    let export = args.export_images.as_deref().map(builder::parse_image_format).transpose()?;
+    let per_page = args.split || export.is_some();
+    let tiff = match args.format.as_str() {
+        "pdf" => false,
+        "tiff" if per_page => return Err(anyhow!("--format tiff no se puede usar con --split ni con --export-images")),
+        "tiff" => true,
+        other => return Err(anyhow!("Formato inválido: {} (usar pdf o tiff)", other)),
+    };
+    if per_page && args.output == "-" {
+        return Err(anyhow!("--split y --export-images escriben varios archivos: no se pueden usar con la salida estándar"));
+    }
+    if !per_page && args.output.contains("{page}") {
+        return Err(anyhow!("{{page}} en --output solo se puede usar con --split o --export-images"));
+    }
+    if (args.out_dir.is_some() || template) && inputs.iter().any(|input| input == "-") {
+        return Err(anyhow!("La entrada estándar no se puede usar con --out-dir ni con plantillas en --output"));
+    }
+    // Cada grupo de entradas da un PDF de salida (con --merge hay uno solo),
+    // o uno por destinatario con --recipients.
+    let merged: Vec<Vec<String>> = if args.merge {
+        vec![inputs.clone()]
+    } else {
+        inputs.iter().map(|input| vec![input.clone()]).collect()
+    };
+    let copies: Vec<Option<&Recipient>> = match recipients.is_empty() {
+        true => vec![None],
+        false => recipients.iter().map(Some).collect(),
+    };
+    let groups: Vec<(Vec<String>, usize)> = merged
+        .iter()
+        .flat_map(|group| (0..copies.len()).map(move |copy| (group.clone(), copy)))
+        .collect();
+    let outputs: Vec<String> = groups
+        .iter()
+        .map(|(group, copy)| {
+            let path = std::path::Path::new(&group[0]);
+            let output = match copies[*copy] {
+                Some(recipient) => {
+                    // Una barra en un valor crearía carpetas.
+                    let safe = recipient
+                        .iter()
+                        .map(|(column, value)| (column.clone(), value.replace(['/', '\\'], "_")));
+                    fill_recipient(&args.output, &safe.collect())
+                }
+                None => args.output.clone(),
+            };
+            let name = if template { output_name(&output, path) } else { output };
+            let Some(out_dir) = &args.out_dir else {
+                return name;
+            };
+            // Con --recursive se conserva la ruta relativa a la carpeta de entrada.
+            let relative = match &args.recursive {
+                Some(root) => path.strip_prefix(root).unwrap_or(path),
+                None => std::path::Path::new(path.file_name().unwrap_or_default()),
+            };
+            let relative = if template {
+                relative.with_file_name(name)
+            } else if path.is_dir() {
+                // Una carpeta de imágenes da un PDF con su nombre.
+                relative.with_file_name(format!("{}.pdf", relative.display()))
+            } else {
+                relative.to_path_buf()
+            };
+            std::path::Path::new(out_dir).join(relative).to_string_lossy().into_owned()
+        })
+        .collect();
+    let mut seen = std::collections::HashSet::new();
+    if let Some(output) = outputs.iter().find(|output| !seen.insert(*output)) {
+        return Err(anyhow!("Varios PDFs de entrada se escribirían en {}", output));
+    }
+    let started = std::time::Instant::now();
+
+    match &inputs[..] {
+        [input] => log::info!("  Input:   {}", input),
+        _ if args.merge => log::info!("  Input:   {} PDFs unidos", inputs.len()),
+        _ => log::info!("  Input:   {} PDFs", inputs.len()),
+    }
+    if !recipients.is_empty() {
+        log::info!("  Copias:  {} destinatarios", recipients.len());
+    }
+    if let Some(logo) = logo {
+        log::info!("  Logo:    {}", logo);
+    }
+    if let Some(text) = &args.text {
+        log::info!("  Texto:   {}", text);
+    }
+    log::info!("  Calidad: {}", args.quality);
+    log::info!("  Salida:  {}", args.out_dir.as_deref().unwrap_or(&args.output));
+    log::info!("");
+
+    let steps = match mode {
+        // Las imágenes y el TIFF salen de las páginas rasterizadas en cualquier modo.
+        _ if export.is_some() || tiff => 4,
+        // Con --split las páginas se separan en un paso más.
+        stamp::Mode::Stamp if args.split => 3,
+        stamp::Mode::Stamp => 2,
+        stamp::Mode::Raster => 4,
+    };
+    log::info!("[1/{}] Preparando marca de agua...", steps);
+    // En paralelo los pasos de cada PDF se mezclarían, y la numeración
+    // Bates no seguiría el orden de entrada.
+    let verbose = args.jobs == 1 || groups.len() == 1 || args.bates.is_some();
+    let jobs = copies
+        .iter()
+        .map(|recipient| {
+            let options = match recipient {
+                Some(recipient) => personalize(&options, &args, recipient),
+                None => options.clone(),
+            };
+            Ok(Job {
+                pipeline: options.pipeline()?,
+                steps,
+                verbose,
+                force: args.force,
+                split: args.split,
+                export,
+                tiff,
+                // Solo en una terminal y sin los mensajes de depuración, que la romperían.
+                bar: !args.json
+                    && !args.quiet
+                    && args.verbose == 0
+                    && std::io::IsTerminal::is_terminal(&std::io::stderr()),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    // Entradas de la copia, con el destinatario (su primera columna).
+    let label = |group: &[String], copy: usize| match copies[copy] {
+        Some(recipient) => format!("{} ({})", group.join(" + "), recipient[0].1),
+        None => group.join(" + "),
+    };
+    let process = |group: &[String], copy: usize, output: &String| -> Result<serde_json::Value> {
+        let job = &jobs[copy];
+        let (data, filename) = match group {
+            [input] => read_input(input, args.dpi)?,
+            _ => {
+                let inputs = group.iter().map(|input| Ok(read_input(input, args.dpi)?.0)).collect::<Result<Vec<_>>>()?;
+                let filename = std::path::Path::new(output).file_name().unwrap_or_default();
+                (pdf::merge(&inputs, &args.password)?, filename.to_string_lossy().into_owned())
+            }
+        };
+        if args.dry_run {
+            return job.estimate(&group.join(" + "), &data, &filename);
+        }
+        if args.out_dir.is_some() || template {
+            if let Some(parent) = std::path::Path::new(output).parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+        }
+        if group.iter().any(|input| same_file(input, std::path::Path::new(output))) {
+            return Err(anyhow!("La salida sobrescribiría el PDF de entrada"));
+        }
+        job.run(&data, &filename, output)
+    };
+    if let ([(group, copy)], [output], None) = (&groups[..], &outputs[..], &args.out_dir) {
+        let outcome = Outcome::timed(&label(group, *copy), || process(group, *copy, output));
+        if args.json {
+            print_json(std::slice::from_ref(&outcome), args.dry_run, started);
+        }
+        outcome.result?;
+        save_bates(&args, &jobs)?;
+        log::info!("Listo.");
+        return Ok(());
+    }
+
+    let outcomes: Vec<Outcome> = if verbose {
+        groups
+            .iter()
+            .zip(&outputs)
+            .enumerate()
+            .map(|(n, ((group, copy), output))| {
+                let input = label(group, *copy);
+                log::info!("\n=== [{}/{}] {} ===", n + 1, groups.len(), input);
+                let outcome = Outcome::timed(&input, || process(group, *copy, output));
+                if let Err(e) = &outcome.result {
+                    log::error!("  ✗ Error: {:#}", e);
+                }
+                outcome
+            })
+            .collect()
+    } else {
+        // Las páginas de cada PDF se reparten en el mismo grupo de hilos.
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(args.jobs).build()?;
+        pool.install(|| {
+            groups
+                .par_iter()
+                .zip(&outputs)
+                .map(|((group, copy), output)| {
+                    let input = label(group, *copy);
+                    let outcome = Outcome::timed(&input, || process(group, *copy, output));
+                    if let Err(e) = &outcome.result {
+                        log::error!("  ✗ {}: {:#}", input, e);
+                    }
+                    outcome
+                })
+                .collect()
+        })
+    };
+    if args.json {
+        print_json(&outcomes, args.dry_run, started);
+    }
+    let failed: Vec<_> = outcomes
+        .iter()
+        .filter_map(|outcome| outcome.result.as_ref().err().map(|e| (&outcome.input, e)))
+        .collect();
+
+    log::info!("");
+    log::info!("Resumen: {} correctos, {} con errores", groups.len() - failed.len(), failed.len());
+    for (input, e) in &failed {
+        log::error!("  ✗ {}: {:#}", input, e);
+    }
+    if !failed.is_empty() {
+        return Err(anyhow!("{} de {} PDFs fallaron", failed.len(), groups.len()));
+    }
+    save_bates(&args, &jobs)?;
+    log::info!("Listo.");
+    Ok(())
+}
+
+/// Configuración común a todos los PDFs, con la forma de mostrar el avance.
+#[cfg(not(target_arch = "wasm32"))]
+struct Job {
+    pipeline: Pipeline,
+    steps: usize,
+    /// Muestra el avance paso a paso.
+    verbose: bool,
+    /// Muestra el avance con una barra en vez de una línea por página.
+    bar: bool,
+    /// Sobrescribe las salidas que ya existan.
+    force: bool,
+    /// Escribe un PDF por página.
+    split: bool,
+    /// Exporta las páginas como imágenes en vez de generar un PDF.
+    export: Option<builder::ImageFormat>,
+    /// Genera un TIFF multipágina en vez de un PDF.
+    tiff: bool,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Job {
+    /// Procesa el PDF `data` y devuelve el resultado para --json.
+    fn run(&self, data: &[u8], filename: &str, output: &str) -> Result<serde_json::Value> {
+        let per_page = self.split || self.export.is_some();
+        // Con --export-images o --format tiff el nombre lleva la extensión del formato.
+        let extension = match self.export {
+            Some(format) => Some(format.extension()),
+            None => self.tiff.then_some("tif"),
+        };
+        let output = match extension {
+            Some(extension) if output.to_lowercase().ends_with(".pdf") => {
+                std::path::Path::new(output).with_extension(extension).to_string_lossy().into_owned()
+            }
+            _ => output.to_string(),
+        };
+        if !per_page {
+            check_overwrite(&output, self.force)?;
+        }
+        let pages = std::sync::Mutex::new((0, Vec::new(), Vec::new()));
+        let written = std::sync::Mutex::new(None);
+        let fitted = std::sync::Mutex::new(None);
+        // Cada fase dura muy distinto según el PDF: la barra empieza de nuevo
+        // en cada una, con su propia estimación.
+        let bar = self.progress_bar();
+        let stage = |n: usize, message: &str| {
+            let message = format!("[{}/{}] {}", n, self.steps, message);
+            if bar.is_hidden() {
+                self.step(&format!("{}...", message));
+            }
+            bar.set_message(message);
+            bar.reset();
+        };
+        let progress = |progress: Progress| match progress {
+            Progress::Stage(Stage::Extracting) => stage(2, "Extrayendo páginas del PDF"),
+            Progress::Stage(Stage::Watermarking) if !self.rasterizes() => {
+                stage(2, "Aplicando marca de agua sobre el PDF original")
+            }
+            Progress::Stage(Stage::Watermarking) => stage(3, "Aplicando marca de agua"),
+            Progress::Stage(Stage::Building) if self.export.is_some() => stage(self.steps, "Codificando imágenes"),
+            Progress::Stage(Stage::Building) if self.tiff => stage(self.steps, "Generando TIFF"),
+            Progress::Stage(Stage::Building) if self.split => stage(self.steps, "Generando un PDF por página"),
+            Progress::Stage(Stage::Building) => stage(self.steps, "Reconstruyendo PDF"),
+            Progress::Page { index, total, stamped } => {
+                let mut pages = pages.lock().unwrap();
+                pages.0 = total;
+                if stamped {
+                    pages.1.push(index + 1);
+                } else {
+                    pages.2.push(index + 1);
+                }
+                if bar.is_hidden() {
+                    let mark = if stamped { "✓" } else { "(sin marca)" };
+                    self.step(&format!("  Página {}/{} {}", index + 1, total, mark));
+                }
+            }
+            Progress::Fitted { quality, scale } => {
+                let resolution = match scale {
+                    1.0 => String::new(),
+                    _ => format!(" al {:.0}% de resolución", scale * 100.0),
+                };
+                bar.suspend(|| log::info!("  Para no pasar de --max-size: JPEG q={}{}", quality, resolution));
+                *fitted.lock().unwrap() = Some((quality, scale));
+            }
+            Progress::Advance { total, .. } => {
+                bar.set_length(total as u64);
+                bar.inc(1);
+            }
+            Progress::Written { path, bytes, summary } => {
+                bar.finish_and_clear();
+                let kind = if self.tiff { "TIFF" } else { "PDF" };
+                log::info!("  {} generado: {} ({:.1} MB, {})", kind, path, bytes as f64 / 1_048_576.0, summary);
+                *written.lock().unwrap() = Some((path.to_string(), bytes, summary.to_string()));
+            }
+        };
+        let mut files = Vec::new();
+        let mut write = |page, bytes: Vec<u8>| {
+            let path = page_output(&output, page);
+            check_overwrite(&path, self.force)?;
+            std::fs::write(&path, &bytes)?;
+            files.push((page, path, bytes.len()));
+            Ok(())
+        };
+        let result = match self.export {
+            Some(format) => self.pipeline.export_images(data, filename, format, &progress, &mut write),
+            None if self.split => self.pipeline.process_split(data, filename, &progress, &mut write),
+            None if self.tiff => self.write_tiff(data, filename, &output, &progress),
+            None => self.pipeline.process_to_file(data, filename, &output, &progress),
+        };
+        bar.finish_and_clear();
+        result?;
+        if per_page {
+            let kind = if self.export.is_some() { "Imagen generada" } else { "PDF generado" };
+            for (_, path, bytes) in &files {
+                log::info!("  {}: {} ({})", kind, path, human_size(*bytes));
+            }
+            let bytes = files.iter().map(|(_, _, bytes)| bytes).sum();
+            let summary = match self.export {
+                Some(format) => format.describe(self.pipeline.quality()),
+                None => self.pipeline.describe(),
+            };
+            *written.lock().unwrap() = Some((output.clone(), bytes, summary));
+        }
+
+        let (total, mut stamped, mut unstamped) = pages.into_inner().unwrap();
+        stamped.sort_unstable();
+        unstamped.sort_unstable();
+        // Las páginas que no aparecen no estaban en --pages y se descartaron.
+        let skipped: Vec<_> = (1..=total)
+            .filter(|page| !stamped.contains(page))
+            .map(|page| {
+                let reason = if unstamped.contains(&page) { "skip_pages" } else { "not_selected" };
+                serde_json::json!({ "page": page, "reason": reason })
+            })
+            .collect();
+        let (path, bytes, summary) = written.into_inner().unwrap().unwrap_or_default();
+        let mut json = serde_json::json!({
+            "output": path,
+            "bytes": bytes,
+            "encoding": summary,
+            "pages_total": total,
+            "pages_processed": stamped,
+            "pages_skipped": skipped,
+        });
+        if let Some((quality, scale)) = fitted.into_inner().unwrap() {
+            json["fitted"] = serde_json::json!({ "quality": quality, "scale": scale });
+        }
+        if per_page {
+            json["files"] = files
+                .iter()
+                .map(|(page, path, bytes)| serde_json::json!({ "page": page, "path": path, "bytes": bytes }))
+                .collect();
+        }
+        Ok(json)
+    }
+
+    /// Genera el TIFF de `data` y lo escribe en `output` ("-" es la salida
+    /// estándar).
+    fn write_tiff(&self, data: &[u8], filename: &str, output: &str, progress: &dyn ProgressSink) -> Result<(), WatermarkError> {
+        let mut buf = Vec::new();
+        self.pipeline.process_tiff(data, filename, &mut buf, progress)?;
+        if output == "-" {
+            std::io::Write::write_all(&mut std::io::stdout().lock(), &buf)?;
+        } else {
+            std::fs::write(output, &buf)?;
+        }
+        progress.report(Progress::Written { path: output, bytes: buf.len(), summary: "TIFF LZW" });
+        Ok(())
+    }
+
+    /// Las páginas se rasterizan aunque el modo sea stamp.
+    fn rasterizes(&self) -> bool {
+        self.pipeline.mode() == stamp::Mode::Raster || self.export.is_some() || self.tiff
+    }
+
+    /// Muestra el tamaño de las páginas de `data`, leído de `input`, y el
+    /// estimado del PDF que se generaría.
+    fn estimate(&self, input: &str, data: &[u8], filename: &str) -> Result<serde_json::Value> {
+        let estimate = self.pipeline.estimate(data, filename)?;
+        for page in &estimate.pages {
+            self.step(&format!("  Página {}/{}: {}x{} px", page.index + 1, estimate.total, page.width, page.height));
+        }
+        log::info!(
+            "  {}: {} de {} páginas, {} → ~{} ({})",
+            input,
+            estimate.selected,
+            estimate.total,
+            human_size(data.len()),
+            human_size(estimate.bytes),
+            self.pipeline.describe()
+        );
+        let pages: Vec<_> = estimate
+            .pages
+            .iter()
+            .map(|page| serde_json::json!({ "page": page.index + 1, "width": page.width, "height": page.height }))
+            .collect();
+        Ok(serde_json::json!({
+            "input_bytes": data.len(),
+            "estimated_bytes": estimate.bytes,
+            "encoding": self.pipeline.describe(),
+            "pages_total": estimate.total,
+            "pages_selected": estimate.selected,
+            "pages": pages,
+        }))
+    }
+
+    /// Barra de avance en stderr, oculta si no corresponde mostrarla.
+    fn progress_bar(&self) -> indicatif::ProgressBar {
+        if !(self.verbose && self.bar) {
+            return indicatif::ProgressBar::hidden();
+        }
+        let style = indicatif::ProgressStyle::with_template("{msg:<52} [{bar:30}] {pos}/{len} páginas (quedan {eta})")
+            .expect("plantilla válida")
+            .progress_chars("=> ");
+        indicatif::ProgressBar::no_length().with_style(style)
+    }
+
+    fn step(&self, message: &str) {
+        if self.verbose {
+            log::info!("{}", message);
+        }
+    }
+}
+
+/// Guarda en --bates-counter el número Bates por el que seguir (el mayor si
+/// cada destinatario lleva su numeración).
+#[cfg(not(target_arch = "wasm32"))]
+fn save_bates(args: &Args, jobs: &[Job]) -> Result<()> {
+    let Some(path) = args.bates_counter.as_ref().filter(|_| !args.dry_run) else {
+        return Ok(());
+    };
+    let next = jobs.iter().filter_map(|job| job.pipeline.bates()).map(|bates| bates.next()).max().unwrap_or_default();
+    std::fs::write(path, format!("{}\n", next)).map_err(|e| anyhow!("No se pudo escribir {}: {}", path, e))?;
+    log::info!("  Siguiente número Bates: {} (en {})", next, path);
+    Ok(())
+}
+
+/// Resultado de un PDF de entrada, con lo que tardó.
+#[cfg(not(target_arch = "wasm32"))]
+struct Outcome {
+    input: String,
+    result: Result<serde_json::Value>,
+    seconds: f64,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Outcome {
+    fn timed(input: &str, process: impl FnOnce() -> Result<serde_json::Value>) -> Outcome {
+        let started = std::time::Instant::now();
+        let result = process();
+        Outcome { input: input.to_string(), result, seconds: started.elapsed().as_secs_f64() }
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        let mut json = match &self.result {
+            Ok(report) => report.clone(),
+            Err(e) => {
+                let error = e.downcast_ref::<WatermarkError>();
+                serde_json::json!({
+                    "error": format!("{:#}", e),
+                    "code": error.map(WatermarkError::code),
+                    "page": error.and_then(WatermarkError::page_number),
+                })
+            }
+        };
+        json["input"] = self.input.clone().into();
+        json["ok"] = self.result.is_ok().into();
+        json["seconds"] = self.seconds.into();
+        json
+    }
+}
+
+/// Escribe el JSON de --json con el resultado de cada PDF.
+#[cfg(not(target_arch = "wasm32"))]
+fn print_json(outcomes: &[Outcome], dry_run: bool, started: std::time::Instant) {
+    let failed = outcomes.iter().filter(|outcome| outcome.result.is_err()).count();
+    let json = serde_json::json!({
+        "ok": failed == 0,
+        "dry_run": dry_run,
+        "processed": outcomes.len() - failed,
+        "failed": failed,
+        "seconds": started.elapsed().as_secs_f64(),
+        "files": outcomes.iter().map(Outcome::to_json).collect::<Vec<_>>(),
+    });
+    println!("{:#}", json);
+}
+
+/// Presets incluidos; los de `[presets.<nombre>]` en `--config` pueden
+/// reemplazarlos.
+#[cfg(not(target_arch = "wasm32"))]
+const BUILTIN_PRESETS: &str = r##"
+[confidential]
+text = "CONFIDENCIAL"
+position = "diagonal"
+color = "#C00000"
+opacity = "30%"
+
+[draft]
+text = "BORRADOR"
+position = "diagonal"
+color = "#808080"
+opacity = "25%"
+
+[internal]
+text = "USO INTERNO"
+position = "tc"
+margin = 20
+color = "#C00000"
+opacity = "60%"
+"##;
+
+/// Contenido de `input` ("-" es la entrada estándar) y su nombre de archivo.
+/// Si es una carpeta, el PDF que arman sus imágenes a `dpi`.
+#[cfg(not(target_arch = "wasm32"))]
+fn read_input(input: &str, dpi: f32) -> Result<(Vec<u8>, String)> {
+    if input == "-" {
+        let mut data = Vec::new();
+        std::io::Read::read_to_end(&mut std::io::stdin().lock(), &mut data)?;
+        return Ok((data, String::new()));
+    }
+    let path = std::path::Path::new(input);
+    let filename = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+    if path.is_dir() {
+        let images = find_images(path)?;
+        log::debug!("{} imágenes en {}", images.len(), input);
+        let images = images.iter().map(std::fs::read).collect::<std::io::Result<Vec<_>>>()?;
+        return Ok((pdf::from_images(&images, dpi)?, filename));
+    }
+    Ok((std::fs::read(input)?, filename))
+}
+
+/// Fila del CSV de --recipients: el valor de cada columna, con su nombre.
+#[cfg(not(target_arch = "wasm32"))]
+type Recipient = Vec<(String, String)>;
+
+/// Destinatarios del CSV `path`, cuya primera fila da el nombre de las
+/// columnas. Los campos van separados por comas o, si la cabecera no tiene
+/// ninguna, por punto y coma (como los exporta Excel en español).
+#[cfg(not(target_arch = "wasm32"))]
+fn read_recipients(path: &str) -> Result<Vec<Recipient>> {
+    let text = std::fs::read_to_string(path).map_err(|e| anyhow!("No se pudo leer {}: {}", path, e))?;
+    let text = text.trim_start_matches('\u{feff}');
+    let first = text.lines().next().unwrap_or_default();
+    let separator = if !first.contains(',') && first.contains(';') { ';' } else { ',' };
+    let mut rows = parse_csv(text, separator).map_err(|e| anyhow!("{}: {}", path, e))?.into_iter();
+    let Some((_, header)) = rows.next() else {
+        return Err(anyhow!("{} está vacío", path));
+    };
+    let mut seen = std::collections::HashSet::new();
+    if let Some(column) = header.iter().find(|column| column.is_empty() || !seen.insert(*column)) {
+        return Err(anyhow!("Columna vacía o repetida en la cabecera de {}: '{}'", path, column));
+    }
+    let recipients = rows
+        .map(|(line, fields)| {
+            if fields.len() != header.len() {
+                return Err(anyhow!("{}:{}: {} campos en vez de {}", path, line, fields.len(), header.len()));
+            }
+            Ok(header.iter().cloned().zip(fields).collect())
+        })
+        .collect::<Result<Vec<Recipient>>>()?;
+    if recipients.is_empty() {
+        return Err(anyhow!("{} no tiene ningún destinatario", path));
+    }
+    Ok(recipients)
+}
+
+/// Filas de `text` con el número de línea donde empieza cada una y sus
+/// campos sin espacios alrededor. Un campo entre comillas puede llevar
+/// separadores, saltos de línea y comillas dobles ("").
+#[cfg(not(target_arch = "wasm32"))]
+fn parse_csv(text: &str, separator: char) -> Result<Vec<(usize, Vec<String>)>> {
+    let mut rows = Vec::new();
+    let (mut fields, mut field) = (Vec::new(), String::new());
+    let (mut line, mut start, mut quoted) = (1, 1, false);
+    let mut end_row = |start: usize, fields: &mut Vec<String>, field: &mut String| {
+        fields.push(std::mem::take(field));
+        let row: Vec<String> = fields.drain(..).map(|field| field.trim().to_string()).collect();
+        // Las líneas en blanco no son destinatarios.
+        if row.iter().any(|field| !field.is_empty()) {
+            rows.push((start, row));
+        }
+    };
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\n' {
+            line += 1;
+        }
+        match c {
+            '"' if quoted && chars.next_if_eq(&'"').is_some() => field.push('"'),
+            '"' => quoted = !quoted,
+            c if quoted => field.push(c),
+            c if c == separator => fields.push(std::mem::take(&mut field)),
+            '\n' => {
+                end_row(start, &mut fields, &mut field);
+                start = line;
+            }
+            '\r' => {}
+            c => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(anyhow!("comillas sin cerrar en la línea {}", start));
+    }
+    end_row(start, &mut fields, &mut field);
+    Ok(rows)
+}
+
+/// Sustituye en `template` cada columna de `recipient` entre llaves, ej.
+/// `{email}`, por su valor.
+#[cfg(not(target_arch = "wasm32"))]
+fn fill_recipient(template: &str, recipient: &Recipient) -> String {
+    recipient
+        .iter()
+        .fold(template.to_string(), |text, (column, value)| text.replace(&format!("{{{}}}", column), value))
+}
+
+/// `options` con las columnas de `recipient` en --text, --invisible y
+/// --forensic.
+#[cfg(not(target_arch = "wasm32"))]
+fn personalize(options: &Options, args: &Args, recipient: &Recipient) -> Options {
+    let mut options = options.clone();
+    if let Some(text) = &args.text {
+        options = options.with_text(&fill_recipient(text, recipient));
+    }
+    if let Some(payload) = &args.invisible {
+        options = options.with_invisible(&fill_recipient(payload, recipient));
+    }
+    if let (Some(payload), Some(key)) = (&args.forensic, &args.forensic_key) {
+        let strength = args.forensic_strength.unwrap_or_default();
+        options = options.with_forensic(&fill_recipient(payload, recipient), key, strength);
+    }
+    options
+}
+
+/// Subcomando detect: informa de los watermarks invisibles y forenses (si
+/// hay `key`) de cada página de `input`, o con --json los escribe como JSON.
+#[cfg(not(target_arch = "wasm32"))]
+fn detect(input: &str, key: Option<&str>, args: &Args) -> Result<()> {
+    let (mut data, _) = read_input(input, args.dpi)?;
+    // Una captura de pantalla se lee como un PDF de una página.
+    if image::guess_format(&data).is_ok() {
+        data = pdf::from_images(&[data], args.dpi)?;
+    }
+    let mut found: Vec<serde_json::Value> = stego::extract_pdf(&data, &args.password)?
         .into_iter()
-        .enumerate()
-        .map(|(i, page)| {
-            let img = watermark::apply(&page, &wm, &args.position);
-            println!("  Página {}/{} ✓", i + 1, total);
-            img
+        .map(|(page, payload)| {
+            log::info!("Página {}: '{}' (invisible)", page, String::from_utf8_lossy(&payload));
+            serde_json::json!({ "page": page, "kind": "invisible", "payload": String::from_utf8_lossy(&payload) })
         })
         .collect();
+    if let Some(key) = key {
+        for (page, detection) in Forensic::new(key).detect_pdf(&data, &args.password)? {
+            let payload = String::from_utf8_lossy(&detection.payload);
+            log::info!("Página {}: '{}' (forense, confianza {:.1}%)", page, payload, detection.confidence * 100.0);
+            found.push(serde_json::json!({
+                "page": page,
+                "kind": "forensic",
+                "payload": payload,
+                "confidence": detection.confidence,
+            }));
+        }
+    }
+    if args.json {
+        println!("{:#}", serde_json::json!({ "input": input, "watermarks": found }));
+    }
+    if found.is_empty() {
+        return Err(anyhow!("No se ha encontrado ningún watermark en {}", input));
+    }
+    Ok(())
+}
 
-    println!("[4/4] Reconstruyendo PDF...");
-    builder::build_pdf(&result, &args.output, &quality)?;
+/// Imágenes PNG, JPEG o WebP de la carpeta `dir` (sin subcarpetas),
+/// ordenadas por nombre con los números por su valor, para que Slide2.png
+/// vaya antes que Slide10.png.
+#[cfg(not(target_arch = "wasm32"))]
+fn find_images(dir: &std::path::Path) -> Result<Vec<std::path::PathBuf>> {
+    let entries = std::fs::read_dir(dir).map_err(|e| anyhow!("No se pudo leer {}: {}", dir.display(), e))?;
+    let mut images = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        let image = path.extension().is_some_and(|ext| {
+            ["png", "jpg", "jpeg", "webp"].iter().any(|known| ext.eq_ignore_ascii_case(known))
+        });
+        if image && path.is_file() {
+            images.push(path);
+        }
+    }
+    if images.is_empty() {
+        return Err(anyhow!("No hay imágenes PNG, JPEG ni WebP en {}", dir.display()));
+    }
+    let name = |path: &std::path::PathBuf| path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+    images.sort_by(|a, b| natural_cmp(&name(a), &name(b)));
+    Ok(images)
+}
+
+/// Compara `a` y `b` carácter a carácter, salvo las cifras seguidas, que
+/// se comparan como números.
+#[cfg(not(target_arch = "wasm32"))]
+fn natural_cmp(a: &str, b: &str) -> std::cmp::Ordering {
+    use std::cmp::Ordering;
+    let (mut a, mut b) = (a.chars().peekable(), b.chars().peekable());
+    let number = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+        let mut digits = String::new();
+        while let Some(c) = chars.next_if(char::is_ascii_digit) {
+            digits.push(c);
+        }
+        digits.trim_start_matches('0').to_string()
+    };
+    loop {
+        let order = match (a.peek(), b.peek()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let (x, y) = (number(&mut a), number(&mut b));
+                x.len().cmp(&y.len()).then(x.cmp(&y))
+            }
+            (Some(x), Some(y)) => {
+                let order = x.cmp(y);
+                a.next();
+                b.next();
+                order
+            }
+        };
+        if order != Ordering::Equal {
+            return order;
+        }
+    }
+}
+
+/// Variables de las plantillas de --output.
+#[cfg(not(target_arch = "wasm32"))]
+const OUTPUT_VARIABLES: [&str; 4] = ["{stem}", "{name}", "{dir}", "{date}"];
+
+/// Sustituye en `template` el nombre de `input` sin extensión (`{stem}`) y
+/// con ella (`{name}`), su carpeta (`{dir}`) y la fecha (`{date}`).
+#[cfg(not(target_arch = "wasm32"))]
+fn output_name(template: &str, input: &std::path::Path) -> String {
+    let part = |part: Option<&std::ffi::OsStr>| part.unwrap_or_default().to_string_lossy().into_owned();
+    let dir = match input.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_string_lossy().into_owned(),
+        _ => ".".to_string(),
+    };
+    template
+        .replace("{stem}", &part(input.file_stem()))
+        .replace("{name}", &part(input.file_name()))
+        .replace("{dir}", &dir)
+        .replace("{date}", &watermark::text::today())
+}
+
+/// Archivo de la página `page` con --split o --export-images: sustituye `{page}` en `output`
+/// o, si no aparece, añade el número al nombre, ej. informe-3.pdf.
+#[cfg(not(target_arch = "wasm32"))]
+fn page_output(output: &str, page: u32) -> String {
+    if output.contains("{page}") {
+        return output.replace("{page}", &page.to_string());
+    }
+    let path = std::path::Path::new(output);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{}-{}.{}", stem, page, extension.to_string_lossy()),
+        None => format!("{}-{}", stem, page),
+    };
+    path.with_file_name(name).to_string_lossy().into_owned()
+}
 
-    println!("Listo.");
+/// Falla si `path` ya existe, salvo con --force ("-" es la salida estándar).
+#[cfg(not(target_arch = "wasm32"))]
+fn check_overwrite(path: &str, force: bool) -> Result<()> {
+    if !force && path != "-" && std::path::Path::new(path).exists() {
+        return Err(anyhow!("{} ya existe (usa --force para sobrescribirlo)", path));
+    }
+    Ok(())
+}
+
+/// Tamaño en KB o MB.
+#[cfg(not(target_arch = "wasm32"))]
+fn human_size(bytes: usize) -> String {
+    if bytes < 1_048_576 {
+        format!("{:.0} KB", bytes as f64 / 1024.0)
+    } else {
+        format!("{:.1} MB", bytes as f64 / 1_048_576.0)
+    }
+}
+
+/// Lee los argumentos y completa las opciones que no se indicaron en la
+/// línea de comandos con las del preset y, después, con las de `--config`.
+#[cfg(not(target_arch = "wasm32"))]
+fn parse_args() -> Result<Args> {
+    let argv: Vec<std::ffi::OsString> = std::env::args_os().collect();
+    let matches = Args::command().get_matches_from(&argv);
+
+    let (mut config, base) = match matches.get_one::<String>("config") {
+        Some(path) => {
+            let config: toml::Table = std::fs::read_to_string(path)
+                .map_err(|e| anyhow!("No se pudo leer {}: {}", path, e))?
+                .parse()
+                .map_err(|e| anyhow!("Configuración inválida en {}: {}", path, e))?;
+            // Las rutas del archivo son relativas a su carpeta.
+            let base = std::path::Path::new(path).parent().unwrap_or(std::path::Path::new("")).to_path_buf();
+            (config, base)
+        }
+        None => (toml::Table::new(), std::path::PathBuf::new()),
+    };
+    let mut presets: toml::Table = BUILTIN_PRESETS.parse()?;
+    match config.remove("presets") {
+        Some(toml::Value::Table(own)) => presets.extend(own),
+        Some(_) => return Err(anyhow!("[presets] debe ser una tabla de presets")),
+        None => {}
+    }
+    let name = match (matches.get_one::<String>("preset"), config.remove("preset")) {
+        (Some(name), _) => Some(name.clone()),
+        (None, Some(toml::Value::String(name))) => Some(name),
+        (None, Some(_)) => return Err(anyhow!("preset debe ser el nombre de un preset")),
+        (None, None) => None,
+    };
+
+    let mut extra = Vec::new();
+    let mut filled = std::collections::HashSet::new();
+    if let Some(name) = name {
+        let preset = match presets.get(&name) {
+            Some(toml::Value::Table(preset)) => preset,
+            _ => {
+                let names: Vec<_> = presets.keys().map(String::as_str).collect();
+                return Err(anyhow!("Preset desconocido: {} (disponibles: {})", name, names.join(", ")));
+            }
+        };
+        let origin = format!("el preset {}", name);
+        fill_args(&matches, preset, &base, &origin, &mut filled, &mut extra)?;
+    }
+    if let Some(path) = matches.get_one::<String>("config") {
+        fill_args(&matches, &config, &base, path, &mut filled, &mut extra)?;
+    }
+    if extra.is_empty() {
+        return Ok(Args::from_arg_matches(&matches)?);
+    }
+    // Justo detrás del nombre del programa, por si la línea de comandos usa `--`.
+    let argv = argv[..1].iter().cloned().chain(extra).chain(argv[1..].iter().cloned());
+    Ok(Args::parse_from(argv))
+}
+
+/// Añade a `extra` como argumentos las opciones de `table` que no se
+/// indicaron en la línea de comandos ni están ya en `filled`. `origin`
+/// identifica la tabla en los errores.
+#[cfg(not(target_arch = "wasm32"))]
+fn fill_args(
+    matches: &clap::ArgMatches,
+    table: &toml::Table,
+    base: &std::path::Path,
+    origin: &str,
+    filled: &mut std::collections::HashSet<String>,
+    extra: &mut Vec<std::ffi::OsString>,
+) -> Result<()> {
+    let command = Args::command();
+    for (key, value) in table {
+        let id = key.replace('-', "_");
+        let known = command.get_arguments().any(|arg| {
+            arg.get_id() == id.as_str() && arg.get_long().is_some() && !matches!(id.as_str(), "config" | "preset")
+        });
+        if !known {
+            return Err(anyhow!("Opción desconocida en {}: {}", origin, key));
+        }
+        if matches.value_source(&id) == Some(clap::parser::ValueSource::CommandLine) || !filled.insert(id.clone()) {
+            continue;
+        }
+        let flag = format!("--{}", id.replace('_', "-"));
+        let value = match value {
+            toml::Value::Boolean(true) => {
+                extra.push(flag.into());
+                continue;
+            }
+            toml::Value::Boolean(false) => continue,
+            // Opciones que se repiten, como verbose = 2.
+            toml::Value::Integer(n) if matches!(id.as_str(), "verbose") => {
+                extra.extend((0..*n).map(|_| flag.clone().into()));
+                continue;
+            }
+            // Opciones que se repiten con un valor cada vez, como avoid = [...].
+            toml::Value::Array(values) if matches!(id.as_str(), "avoid") => {
+                for value in values {
+                    let toml::Value::String(s) = value else {
+                        return Err(anyhow!(
+                            "Valor inválido en {} para {}: se esperaba una lista de textos",
+                            origin,
+                            key
+                        ));
+                    };
+                    extra.push(format!("{}={}", flag, s).into());
+                }
+                continue;
+            }
+            toml::Value::String(s) if id == "page_positions" && s.trim_start().starts_with('{') => s.clone(),
+            toml::Value::String(s)
+                if matches!(id.as_str(), "logo" | "font" | "page_positions" | "recipients" | "sign") =>
+            {
+                base.join(s).to_string_lossy().into_owned()
+            }
+            toml::Value::String(s) => s.clone(),
+            toml::Value::Integer(n) => n.to_string(),
+            toml::Value::Float(n) => n.to_string(),
+            _ => return Err(anyhow!("Valor inválido en {} para {}: se esperaba texto, número o booleano", origin, key)),
+        };
+        extra.push(format!("{}={}", flag, value).into());
+    }
     Ok(())
 }
 
+/// Expande los patrones con `*` y `?` en el nombre de archivo (la carpeta va
+/// tal cual), por si la shell no lo ha hecho. Cada patrón debe coincidir con
+/// algún archivo.
+#[cfg(not(target_arch = "wasm32"))]
+fn expand_inputs(patterns: &[String]) -> Result<Vec<String>> {
+    let mut inputs = Vec::new();
+    for pattern in patterns {
+        let path = std::path::Path::new(pattern);
+        let name = path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+        if !name.contains(['*', '?']) {
+            inputs.push(pattern.clone());
+            continue;
+        }
+        let dir = path.parent().filter(|d| !d.as_os_str().is_empty());
+        let entries = std::fs::read_dir(dir.unwrap_or(std::path::Path::new(".")))
+            .map_err(|e| anyhow!("No se pudo leer la carpeta de {}: {}", pattern, e))?;
+        let pattern_chars: Vec<char> = name.chars().collect();
+        let mut matches: Vec<String> = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().is_file())
+            .filter_map(|entry| {
+                let file = entry.file_name().to_string_lossy().into_owned();
+                // Como en la shell, `*` no incluye archivos ocultos.
+                let hidden = file.starts_with('.') && !name.starts_with('.');
+                let chars: Vec<char> = file.chars().collect();
+                (!hidden && wildcard_match(&pattern_chars, &chars)).then(|| match dir {
+                    Some(dir) => dir.join(&file).to_string_lossy().into_owned(),
+                    None => file,
+                })
+            })
+            .collect();
+        if matches.is_empty() {
+            return Err(anyhow!("Ningún archivo coincide con {}", pattern));
+        }
+        matches.sort();
+        inputs.extend(matches);
+    }
+    Ok(inputs)
+}
+
+/// PDFs de `root` y sus subcarpetas, ordenados, sin entrar en `out_dir` ni
+/// seguir enlaces simbólicos a carpetas.
+#[cfg(not(target_arch = "wasm32"))]
+fn find_pdfs(root: &std::path::Path, out_dir: &std::path::Path) -> Result<Vec<String>> {
+    let out_dir = std::fs::canonicalize(out_dir).ok();
+    let mut pdfs = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        if out_dir.is_some() && std::fs::canonicalize(&dir).ok() == out_dir {
+            continue;
+        }
+        let entries = std::fs::read_dir(&dir).map_err(|e| anyhow!("No se pudo leer {}: {}", dir.display(), e))?;
+        for entry in entries {
+            let entry = entry?;
+            let path = entry.path();
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                pending.push(path);
+            } else if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("pdf")) && path.is_file() {
+                pdfs.push(path.to_string_lossy().into_owned());
+            }
+        }
+    }
+    if pdfs.is_empty() {
+        return Err(anyhow!("No hay PDFs en {}", root.display()));
+    }
+    pdfs.sort();
+    Ok(pdfs)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn wildcard_match(pattern: &[char], name: &[char]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some('*'), _) => {
+            wildcard_match(&pattern[1..], name) || (!name.is_empty() && wildcard_match(pattern, &name[1..]))
+        }
+        (Some('?'), Some(_)) => wildcard_match(&pattern[1..], &name[1..]),
+        (Some(p), Some(n)) if p == n => wildcard_match(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn same_file(a: &str, b: &std::path::Path) -> bool {
+    match (std::fs::canonicalize(a), std::fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
 #[cfg(target_arch = "wasm32")]
 fn main() {}
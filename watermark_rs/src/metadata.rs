@@ -0,0 +1,270 @@
+use crate::pdf;
+use anyhow::Result;
+use lopdf::{dictionary, Dictionary, Document, Object, Stream};
+use sha2::{Digest, Sha256};
+
+/// Espacio de nombres XMP del registro de procedencia.
+const PROVENANCE_NS: &str = "https://github.com/colosal-ai/pdf-watermark/xmp/1.0/";
+
+/// Metadatos del PDF generado. Los campos indicados sustituyen a los del
+/// original en el diccionario Info y en el XMP.
+#[derive(Clone, Default)]
+pub struct Metadata {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub subject: Option<String>,
+    pub keywords: Option<String>,
+    pub provenance: Option<Provenance>,
+}
+
+impl Metadata {
+    pub fn is_empty(&self) -> bool {
+        [&self.title, &self.author, &self.subject, &self.keywords].iter().all(|field| field.is_none())
+            && self.provenance.is_none()
+    }
+}
+
+/// Registro de cómo y cuándo se marcó el PDF, que se guarda en el XMP con el
+/// prefijo `wm` para que otros sistemas puedan comprobarlo.
+#[derive(Clone)]
+pub struct Provenance {
+    /// Nombre y versión de la herramienta.
+    pub tool: String,
+    /// Fecha y hora del marcado en ISO 8601.
+    pub date: String,
+    /// SHA-256 en hexadecimal del logo, si lo hay.
+    pub logo_sha256: Option<String>,
+    /// Opciones usadas, en JSON.
+    pub options: String,
+}
+
+impl Provenance {
+    /// Registro con la versión de esta herramienta y la hora actual.
+    pub fn new(logo: Option<&[u8]>, options: &str) -> Self {
+        Self {
+            tool: format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+            date: chrono::Local::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, false),
+            logo_sha256: logo.map(|logo| Sha256::digest(logo).iter().map(|b| format!("{:02x}", b)).collect()),
+            options: options.to_string(),
+        }
+    }
+
+    /// Propiedades XMP: nombre, tipo de valor de PDF/A, descripción y valor.
+    fn properties(&self) -> Vec<(&str, &str, &str, &str)> {
+        let mut properties = vec![
+            ("Tool", "Text", "Herramienta y versión", self.tool.as_str()),
+            ("Date", "Date", "Fecha del marcado", self.date.as_str()),
+        ];
+        if let Some(hash) = &self.logo_sha256 {
+            properties.push(("LogoSHA256", "Text", "SHA-256 del logo", hash));
+        }
+        properties.push(("Options", "Text", "Opciones usadas, en JSON", &self.options));
+        properties
+    }
+}
+
+/// Escribe `metadata` en el diccionario Info, que se crea si no existe, y
+/// regenera el XMP a partir de él para que ambos coincidan.
+pub(crate) fn apply(doc: &mut Document, metadata: &Metadata, pdfa: bool) -> Result<()> {
+    let info_id = match doc.trailer.get(b"Info") {
+        Ok(Object::Reference(id)) => *id,
+        Ok(Object::Dictionary(info)) => doc.add_object(info.clone()),
+        _ => doc.add_object(Dictionary::new()),
+    };
+    doc.trailer.set("Info", Object::Reference(info_id));
+    let info = doc.get_dictionary_mut(info_id)?;
+    for (key, value) in [
+        ("Title", &metadata.title),
+        ("Author", &metadata.author),
+        ("Subject", &metadata.subject),
+        ("Keywords", &metadata.keywords),
+    ] {
+        if let Some(value) = value {
+            info.set(key, lopdf::text_string(value));
+        }
+    }
+    write_xmp(doc, pdfa, metadata.provenance.as_ref())
+}
+
+/// Sustituye el stream XMP del catálogo por uno generado a partir del
+/// diccionario Info (ver [`info_properties`]), con `pdfaid` si `pdfa` y con
+/// el registro de procedencia si se indica.
+pub(crate) fn write_xmp(doc: &mut Document, pdfa: bool, provenance: Option<&Provenance>) -> Result<()> {
+    let catalog_id = doc.trailer.get(b"Root")?.as_reference()?;
+    let mut properties = info_properties(doc);
+    if let Some(provenance) = provenance {
+        properties += &provenance_properties(provenance, pdfa);
+    }
+    let xmp = xmp_packet(&properties, pdfa);
+    let metadata = Stream::new(
+        dictionary! { "Type" => "Metadata", "Subtype" => "XML" },
+        xmp.into_bytes(),
+    )
+    .with_compression(false);
+    let metadata_id = doc.add_object(metadata);
+
+    let catalog = doc.get_dictionary_mut(catalog_id)?;
+    let old_metadata = catalog.get(b"Metadata").and_then(Object::as_reference).ok();
+    catalog.set("Metadata", Object::Reference(metadata_id));
+    if let Some(old) = old_metadata {
+        doc.objects.remove(&old);
+    }
+    Ok(())
+}
+
+/// Propiedades XMP con las mismas entradas que el diccionario Info. Las
+/// fechas de Info que no se pueden convertir se eliminan, porque PDF/A exige
+/// que ambos coincidan.
+fn info_properties(doc: &mut Document) -> String {
+    let info_id = doc.trailer.get(b"Info").and_then(Object::as_reference).ok();
+    let Some((info_id, info)) = info_id.and_then(|id| Some((id, doc.get_dictionary(id).ok()?.clone()))) else {
+        return String::new();
+    };
+    let text = |key: &[u8]| {
+        let value = pdf::resolve(doc, info.get(key).ok()?).ok()?;
+        lopdf::decode_text_string(&value).ok().map(|v| escape(&v))
+    };
+
+    let mut properties = String::new();
+    let mut invalid = Vec::new();
+    if let Some(title) = text(b"Title") {
+        properties += &format!("<dc:title><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:title>\n", title);
+    }
+    if let Some(author) = text(b"Author") {
+        properties += &format!("<dc:creator><rdf:Seq><rdf:li>{}</rdf:li></rdf:Seq></dc:creator>\n", author);
+    }
+    if let Some(subject) = text(b"Subject") {
+        properties += &format!("<dc:description><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:description>\n", subject);
+    }
+    for (key, property) in [
+        (b"Keywords".as_slice(), "pdf:Keywords"),
+        (b"Creator", "xmp:CreatorTool"),
+        (b"Producer", "pdf:Producer"),
+    ] {
+        if let Some(value) = text(key) {
+            properties += &format!("<{0}>{1}</{0}>\n", property, value);
+        }
+    }
+    for (key, property) in [(b"CreationDate".as_slice(), "xmp:CreateDate"), (b"ModDate", "xmp:ModifyDate")] {
+        match text(key).map(|date| xmp_date(&date)) {
+            Some(Some(date)) => properties += &format!("<{0}>{1}</{0}>\n", property, date),
+            Some(None) => invalid.push(key),
+            None => {}
+        }
+    }
+    match info.get(b"Trapped").and_then(Object::as_name) {
+        Ok(b"True") => properties += "<pdf:Trapped>True</pdf:Trapped>\n",
+        Ok(b"False") => properties += "<pdf:Trapped>False</pdf:Trapped>\n",
+        Ok(_) => invalid.push(b"Trapped"),
+        Err(_) => {}
+    }
+
+    if let Ok(info) = doc.get_dictionary_mut(info_id) {
+        for key in invalid {
+            info.remove(key);
+        }
+    }
+    properties
+}
+
+/// Propiedades `wm:` del registro de procedencia. PDF/A solo admite
+/// espacios de nombres propios si se describen en un esquema de extensión.
+fn provenance_properties(provenance: &Provenance, pdfa: bool) -> String {
+    let mut out = String::new();
+    for (name, _, _, value) in provenance.properties() {
+        out += &format!("<wm:{0}>{1}</wm:{0}>\n", name, escape(value));
+    }
+    if pdfa {
+        let mut schema = String::new();
+        for (name, kind, description, _) in provenance.properties() {
+            schema += &format!(
+                "<rdf:li rdf:parseType=\"Resource\"><pdfaProperty:name>{}</pdfaProperty:name>\
+                 <pdfaProperty:valueType>{}</pdfaProperty:valueType>\
+                 <pdfaProperty:category>internal</pdfaProperty:category>\
+                 <pdfaProperty:description>{}</pdfaProperty:description></rdf:li>\n",
+                name, kind, description
+            );
+        }
+        out += &format!(
+            "<pdfaExtension:schemas><rdf:Bag><rdf:li rdf:parseType=\"Resource\">\n\
+             <pdfaSchema:schema>Procedencia del watermark</pdfaSchema:schema>\n\
+             <pdfaSchema:namespaceURI>{}</pdfaSchema:namespaceURI>\n\
+             <pdfaSchema:prefix>wm</pdfaSchema:prefix>\n\
+             <pdfaSchema:property><rdf:Seq>\n{}</rdf:Seq></pdfaSchema:property>\n\
+             </rdf:li></rdf:Bag></pdfaExtension:schemas>\n",
+            PROVENANCE_NS, schema
+        );
+    }
+    out
+}
+
+fn xmp_packet(properties: &str, pdfa: bool) -> String {
+    let pdfaid = match pdfa {
+        true => "<pdfaid:part>2</pdfaid:part>\n<pdfaid:conformance>B</pdfaid:conformance>\n",
+        false => "",
+    };
+    format!(
+        "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n\
+         <x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n\
+         <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n\
+         <rdf:Description rdf:about=\"\" \
+         xmlns:pdfaid=\"http://www.aiim.org/pdfa/ns/id/\" \
+         xmlns:dc=\"http://purl.org/dc/elements/1.1/\" \
+         xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\" \
+         xmlns:pdf=\"http://ns.adobe.com/pdf/1.3/\" \
+         xmlns:pdfaExtension=\"http://www.aiim.org/pdfa/ns/extension/\" \
+         xmlns:pdfaSchema=\"http://www.aiim.org/pdfa/ns/schema#\" \
+         xmlns:pdfaProperty=\"http://www.aiim.org/pdfa/ns/property#\" \
+         xmlns:wm=\"{}\">\n\
+         {}{}\
+         </rdf:Description>\n\
+         </rdf:RDF>\n\
+         </x:xmpmeta>\n\
+         <?xpacket end=\"w\"?>",
+        PROVENANCE_NS, pdfaid, properties
+    )
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Convierte una fecha PDF (`D:AAAAMMDDHHmmSS+HH'mm'`, con los campos finales
+/// opcionales) al formato de XMP (`AAAA-MM-DDTHH:mm:SS+HH:mm`).
+fn xmp_date(date: &str) -> Option<String> {
+    let s = date.strip_prefix("D:").unwrap_or(date);
+    let digits = s.bytes().take_while(u8::is_ascii_digit).count();
+    if !(4..=14).contains(&digits) || digits % 2 != 0 {
+        return None;
+    }
+    let field = |i: usize| &s[i..i + 2];
+    let mut out = s[..4].to_string();
+    if digits >= 6 {
+        out += &format!("-{}", field(4));
+    }
+    if digits >= 8 {
+        out += &format!("-{}", field(6));
+    }
+    if digits < 10 {
+        return Some(out);
+    }
+    out += &format!("T{}:{}", field(8), if digits >= 12 { field(10) } else { "00" });
+    if digits == 14 {
+        out += &format!(":{}", field(12));
+    }
+
+    let zone = &s[digits..];
+    match zone.as_bytes().first() {
+        Some(b'Z') => out.push('Z'),
+        Some(sign @ (b'+' | b'-')) => {
+            let zone: String = zone[1..].chars().filter(char::is_ascii_digit).collect();
+            if zone.len() < 2 {
+                return None;
+            }
+            let minutes = zone.get(2..4).unwrap_or("00");
+            out += &format!("{}{}:{}", *sign as char, &zone[..2], minutes);
+        }
+        _ => {}
+    }
+    Some(out)
+}
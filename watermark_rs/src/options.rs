@@ -0,0 +1,768 @@
+use crate::builder::{self, OutputOptions};
+use crate::error::WatermarkError;
+use crate::forensic::{self, Forensic};
+use crate::metadata::{Metadata, Provenance};
+use crate::pages;
+use crate::pdf;
+use crate::pipeline::Pipeline;
+use crate::stamp;
+use crate::text::{self, Bates};
+use crate::watermark::{self, PagePlacements, Stamp};
+use wasm_bindgen::prelude::*;
+
+/// Configuración de un trabajo tal como la escribe el usuario (ej. "30%",
+/// "1-5,8", "aes-256"), con los mismos valores por defecto que la CLI. La
+/// CLI y la versión web la construyen con los `with_*` y
+/// [`pipeline`](Options::pipeline) la valida y prepara los watermarks.
+///
+/// Si no se indica `logo` ni `text` no hay watermark.
+#[wasm_bindgen]
+#[derive(Clone)]
+pub struct Options {
+    logo: Option<Vec<u8>>,
+    min_w: u32,
+    min_h: u32,
+    text: Option<String>,
+    text_position: Option<String>,
+    font_size: f32,
+    color: String,
+    font: Option<Vec<u8>>,
+    mode: String,
+    quality: String,
+    subsampling: String,
+    dpi: f32,
+    max_size: String,
+    downscale: bool,
+    max_dpi: f32,
+    max_width: u32,
+    page_size: String,
+    grayscale: bool,
+    invisible: Option<String>,
+    forensic: Option<(String, String, f32)>,
+    bates: Option<(String, String, u32, u32)>,
+    header: Option<String>,
+    footer: Option<String>,
+    running_size: f32,
+    running_align: String,
+    running_margin: u32,
+    timestamp: Option<(String, String)>,
+    timestamp_text: Option<String>,
+    timestamp_position: String,
+    password: String,
+    pages: String,
+    every_nth: usize,
+    skip_pages: String,
+    position: String,
+    pos: Option<(String, String)>,
+    page_positions: Option<String>,
+    layer: String,
+    avoid: String,
+    margin: u32,
+    spacing_x: u32,
+    spacing_y: u32,
+    opacity: Option<String>,
+    scale: Option<String>,
+    shadow: bool,
+    outline: u32,
+    adaptive: bool,
+    plate: Option<(String, String, u32)>,
+    tint: Option<String>,
+    gray_logo: bool,
+    user_password: String,
+    owner_password: String,
+    encryption: String,
+    pdfa: bool,
+    linearize: bool,
+    incremental: bool,
+    object_streams: bool,
+    strip_metadata: bool,
+    metadata: Metadata,
+    provenance: bool,
+    /// Certificado PKCS#12 y su contraseña.
+    #[cfg(not(target_arch = "wasm32"))]
+    signature: Option<(Vec<u8>, String)>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            logo: None,
+            min_w: 107,
+            min_h: 21,
+            text: None,
+            text_position: None,
+            font_size: 32.0,
+            color: "#000000".to_string(),
+            font: None,
+            mode: "raster".to_string(),
+            quality: "lossless".to_string(),
+            subsampling: String::new(),
+            dpi: pdf::DEFAULT_DPI,
+            max_size: String::new(),
+            downscale: false,
+            max_dpi: 0.0,
+            max_width: 0,
+            page_size: String::new(),
+            grayscale: false,
+            invisible: None,
+            forensic: None,
+            bates: None,
+            header: None,
+            footer: None,
+            running_size: 16.0,
+            running_align: "center".to_string(),
+            running_margin: 20,
+            timestamp: None,
+            timestamp_text: None,
+            timestamp_position: "bl".to_string(),
+            password: String::new(),
+            pages: String::new(),
+            every_nth: 1,
+            skip_pages: String::new(),
+            position: "br".to_string(),
+            pos: None,
+            page_positions: None,
+            layer: "over".to_string(),
+            avoid: String::new(),
+            margin: 0,
+            spacing_x: 120,
+            spacing_y: 80,
+            opacity: None,
+            scale: None,
+            shadow: false,
+            outline: 0,
+            adaptive: false,
+            plate: None,
+            tint: None,
+            gray_logo: false,
+            user_password: String::new(),
+            owner_password: String::new(),
+            encryption: "aes-256".to_string(),
+            pdfa: false,
+            linearize: false,
+            incremental: false,
+            object_streams: false,
+            strip_metadata: false,
+            metadata: Metadata::default(),
+            provenance: false,
+            #[cfg(not(target_arch = "wasm32"))]
+            signature: None,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl Options {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Options {
+        Options::default()
+    }
+
+    /// Imagen del watermark (PNG, JPG o SVG).
+    pub fn with_logo(mut self, data: &[u8]) -> Options {
+        self.logo = Some(data.to_vec());
+        self
+    }
+
+    /// Tamaño mínimo del logo en píxeles.
+    pub fn with_min_size(mut self, min_w: u32, min_h: u32) -> Options {
+        self.min_w = min_w;
+        self.min_h = min_h;
+        self
+    }
+
+    /// Watermark de texto, además del logo si lo hay. Admite {page},
+    /// {total}, {date} y {filename}.
+    pub fn with_text(mut self, text: &str) -> Options {
+        self.text = Some(text.to_string());
+        self
+    }
+
+    /// Posición del texto cuando se combina con un logo (por defecto la del logo).
+    pub fn with_text_position(mut self, position: &str) -> Options {
+        self.text_position = Some(position.to_string());
+        self
+    }
+
+    pub fn with_font_size(mut self, font_size: f32) -> Options {
+        self.font_size = font_size;
+        self
+    }
+
+    /// Color del texto: #RRGGBB o #RRGGBBAA.
+    pub fn with_color(mut self, color: &str) -> Options {
+        self.color = color.to_string();
+        self
+    }
+
+    /// Color con que se tiñe el logo: #RRGGBB o #RRGGBBAA.
+    pub fn with_tint(mut self, tint: &str) -> Options {
+        self.tint = Some(tint.to_string());
+        self
+    }
+
+    /// Pasa el logo a escala de grises.
+    pub fn with_gray_logo(mut self, gray: bool) -> Options {
+        self.gray_logo = gray;
+        self
+    }
+
+    /// Fuente TTF/OTF del texto (por defecto DejaVu Sans Bold).
+    pub fn with_font(mut self, data: &[u8]) -> Options {
+        self.font = Some(data.to_vec());
+        self
+    }
+
+    /// "raster" o "stamp".
+    pub fn with_mode(mut self, mode: &str) -> Options {
+        self.mode = mode.to_string();
+        self
+    }
+
+    /// "lossless" o 1-100 (JPEG).
+    pub fn with_quality(mut self, quality: &str) -> Options {
+        self.quality = quality.to_string();
+        self
+    }
+
+    /// Submuestreo de color de JPEG: "4:4:4", "4:2:2" o "4:2:0" (vacío =
+    /// 4:4:4).
+    pub fn with_subsampling(mut self, subsampling: &str) -> Options {
+        self.subsampling = subsampling.to_string();
+        self
+    }
+
+    pub fn with_dpi(mut self, dpi: f32) -> Options {
+        self.dpi = dpi;
+        self
+    }
+
+    /// Tamaño máximo del PDF generado, ej. "10MB" (vacío = sin límite): se
+    /// baja la calidad JPEG hasta que quepa y, con `downscale`, también la
+    /// resolución de las páginas.
+    pub fn with_max_size(mut self, size: &str, downscale: bool) -> Options {
+        self.max_size = size.to_string();
+        self.downscale = downscale;
+        self
+    }
+
+    /// Reduce las imágenes de las páginas a como mucho `max_dpi` y
+    /// `max_width` píxeles de ancho (0 = sin límite).
+    pub fn with_max_resolution(mut self, max_dpi: f32, max_width: u32) -> Options {
+        self.max_dpi = max_dpi;
+        self.max_width = max_width;
+        self
+    }
+
+    /// Tamaño de las páginas de salida: "1920x1080" (píxeles, a `dpi`), a3,
+    /// a4, a5, letter o legal (vacío = el de cada página).
+    pub fn with_page_size(mut self, page_size: &str) -> Options {
+        self.page_size = page_size.to_string();
+        self
+    }
+
+    /// Páginas en escala de grises, con los watermarks incluidos.
+    pub fn with_grayscale(mut self, grayscale: bool) -> Options {
+        self.grayscale = grayscale;
+        self
+    }
+
+    /// Texto que se guarda de forma invisible en cada página, ej. el
+    /// destinatario; admite {page}, {total}, {date} y {filename}.
+    pub fn with_invisible(mut self, payload: &str) -> Options {
+        self.invisible = Some(payload.to_string());
+        self
+    }
+
+    /// Identificador corto que se guarda en cada página con un watermark
+    /// forense que resiste JPEG y cambios de tamaño; solo se lee con `key`.
+    /// `strength` 0 usa la intensidad por defecto.
+    pub fn with_forensic(mut self, payload: &str, key: &str, strength: f32) -> Options {
+        self.forensic = Some((payload.to_string(), key.to_string(), strength));
+        self
+    }
+
+    /// Línea fija arriba de cada página marcada, ej. el título del
+    /// documento; admite {page}, {total}, {date} y {filename}.
+    pub fn with_header(mut self, text: &str) -> Options {
+        self.header = Some(text.to_string());
+        self
+    }
+
+    /// Línea fija abajo de cada página marcada, como [`with_header`](Options::with_header).
+    pub fn with_footer(mut self, text: &str) -> Options {
+        self.footer = Some(text.to_string());
+        self
+    }
+
+    /// Tamaño de fuente, alineación ("left", "center" o "right") y margen
+    /// en px de la cabecera y el pie.
+    pub fn with_running_style(mut self, font_size: f32, align: &str, margin: u32) -> Options {
+        self.running_size = font_size;
+        self.running_align = align.to_string();
+        self.running_margin = margin;
+        self
+    }
+
+    /// Sella la fecha y hora de proceso en cada página marcada, con un
+    /// formato de strftime en la zona `zone` ("local", "utc" o "+02:00").
+    pub fn with_timestamp(mut self, format: &str, zone: &str) -> Options {
+        self.timestamp = Some((format.to_string(), zone.to_string()));
+        self
+    }
+
+    /// Sella este texto tal cual en lugar de la hora actual, ej. para
+    /// repetir la fecha de una exportación anterior.
+    pub fn with_timestamp_text(mut self, text: &str) -> Options {
+        self.timestamp_text = Some(text.to_string());
+        self
+    }
+
+    /// Posición del sello de fecha, como en [`with_position`](Options::with_position).
+    pub fn with_timestamp_position(mut self, position: &str) -> Options {
+        self.timestamp_position = position.to_string();
+        self
+    }
+
+    /// Numeración Bates: `prefix` seguido de un número de `digits` cifras
+    /// que empieza en `start` y sube en cada página marcada, en `position`.
+    pub fn with_bates(mut self, prefix: &str, position: &str, start: u32, digits: u32) -> Options {
+        self.bates = Some((prefix.to_string(), position.to_string(), start, digits));
+        self
+    }
+
+    /// Contraseña del PDF de entrada.
+    pub fn with_password(mut self, password: &str) -> Options {
+        self.password = password.to_string();
+        self
+    }
+
+    /// Páginas a conservar, ej. "1-5,8,12-" (vacío = todas).
+    pub fn with_pages(mut self, spec: &str) -> Options {
+        self.pages = spec.to_string();
+        self
+    }
+
+    /// Se queda con una de cada `n` de las páginas elegidas.
+    pub fn with_every_nth(mut self, n: usize) -> Options {
+        self.every_nth = n;
+        self
+    }
+
+    /// Páginas que se conservan sin watermark, con la sintaxis de `with_pages`.
+    pub fn with_skip_pages(mut self, spec: &str) -> Options {
+        self.skip_pages = spec.to_string();
+        self
+    }
+
+    /// tl,tc,tr,ml,mc,mr,bl,bc,br, tile, diagonal o auto.
+    pub fn with_position(mut self, position: &str) -> Options {
+        self.position = position.to_string();
+        self
+    }
+
+    /// Coordenadas en px o % de la página; reemplazan a la posición.
+    pub fn with_point(mut self, x: &str, y: &str) -> Options {
+        self.pos = Some((x.to_string(), y.to_string()));
+        self
+    }
+
+    /// JSON con posiciones por página, ej. {"1": "tc", "3": {"x": "85%", "y": 40}}.
+    pub fn with_page_positions(mut self, json: &str) -> Options {
+        self.page_positions = Some(json.to_string());
+        self
+    }
+
+    /// "over" o "under".
+    pub fn with_layer(mut self, layer: &str) -> Options {
+        self.layer = layer.to_string();
+        self
+    }
+
+    /// Zonas separadas por ';' que los watermarks no deben tapar, ej.
+    /// "0,90%,100%,10%;1:0,0,100%,15%".
+    pub fn with_avoid(mut self, zones: &str) -> Options {
+        self.avoid = zones.to_string();
+        self
+    }
+
+    pub fn with_margin(mut self, margin: u32) -> Options {
+        self.margin = margin;
+        self
+    }
+
+    /// Separación entre copias en modo tile.
+    pub fn with_spacing(mut self, spacing_x: u32, spacing_y: u32) -> Options {
+        self.spacing_x = spacing_x;
+        self.spacing_y = spacing_y;
+        self
+    }
+
+    /// 0-1 o porcentaje (ej. "30%"). Sin ella es 1, o
+    /// [`DIAGONAL_OPACITY`](watermark::DIAGONAL_OPACITY) en diagonal.
+    pub fn with_opacity(mut self, opacity: &str) -> Options {
+        self.opacity = Some(opacity.to_string());
+        self
+    }
+
+    /// Ancho del logo relativo a cada página, ej. "10%".
+    pub fn with_scale(mut self, scale: &str) -> Options {
+        self.scale = Some(scale.to_string());
+        self
+    }
+
+    /// Sombra y contorno de `outline` px (0 = sin contorno) alrededor de
+    /// los watermarks.
+    pub fn with_effects(mut self, shadow: bool, outline: u32) -> Options {
+        self.shadow = shadow;
+        self.outline = outline;
+        self
+    }
+
+    /// Invierte los colores de los watermarks donde contrastan poco con la
+    /// página (solo en modo raster).
+    pub fn with_adaptive(mut self, adaptive: bool) -> Options {
+        self.adaptive = adaptive;
+        self
+    }
+
+    /// Rectángulo redondeado de `color` (#RRGGBB o #RRGGBBAA) detrás de los
+    /// watermarks, con `opacity` ("0.6" o "60%") y a `padding` px de ellos.
+    pub fn with_plate(mut self, color: &str, opacity: &str, padding: u32) -> Options {
+        self.plate = Some((color.to_string(), opacity.to_string(), padding));
+        self
+    }
+
+    /// Contraseñas para cifrar el PDF generado y nivel de cifrado
+    /// ("rc4-128", "aes-128" o "aes-256").
+    pub fn with_encryption(mut self, user_password: &str, owner_password: &str, level: &str) -> Options {
+        self.user_password = user_password.to_string();
+        self.owner_password = owner_password.to_string();
+        self.encryption = level.to_string();
+        self
+    }
+
+    pub fn with_pdfa(mut self, pdfa: bool) -> Options {
+        self.pdfa = pdfa;
+        self
+    }
+
+    pub fn with_linearize(mut self, linearize: bool) -> Options {
+        self.linearize = linearize;
+        self
+    }
+
+    pub fn with_incremental(mut self, incremental: bool) -> Options {
+        self.incremental = incremental;
+        self
+    }
+
+    pub fn with_object_streams(mut self, object_streams: bool) -> Options {
+        self.object_streams = object_streams;
+        self
+    }
+
+    /// Quita del PDF generado el diccionario Info, el XMP y las miniaturas
+    /// del original, para distribuirlo sin datos del autor.
+    pub fn with_strip_metadata(mut self, strip_metadata: bool) -> Options {
+        self.strip_metadata = strip_metadata;
+        self
+    }
+
+    /// Guarda en el XMP un registro de procedencia: versión de la
+    /// herramienta, fecha, SHA-256 del logo y opciones usadas.
+    pub fn with_provenance(mut self, provenance: bool) -> Options {
+        self.provenance = provenance;
+        self
+    }
+
+    /// Título del PDF generado, en el diccionario Info y en el XMP.
+    pub fn with_title(mut self, title: &str) -> Options {
+        self.metadata.title = Some(title.to_string());
+        self
+    }
+
+    pub fn with_author(mut self, author: &str) -> Options {
+        self.metadata.author = Some(author.to_string());
+        self
+    }
+
+    pub fn with_subject(mut self, subject: &str) -> Options {
+        self.metadata.subject = Some(subject.to_string());
+        self
+    }
+
+    pub fn with_keywords(mut self, keywords: &str) -> Options {
+        self.metadata.keywords = Some(keywords.to_string());
+        self
+    }
+}
+
+impl Options {
+    /// Firma el PDF generado con la clave del certificado PKCS#12 `p12`,
+    /// protegido con `password`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_signature(mut self, p12: &[u8], password: &str) -> Options {
+        self.signature = Some((p12.to_vec(), password.to_string()));
+        self
+    }
+
+    /// Interpreta las opciones y prepara los watermarks.
+    pub fn pipeline(&self) -> Result<Pipeline, WatermarkError> {
+        let placement = match &self.pos {
+            Some((x, y)) => watermark::parse_point(x, y)?,
+            None => self.placement(&self.position)?,
+        };
+        let opacity = match (&self.opacity, &placement) {
+            (Some(opacity), _) => watermark::parse_opacity(opacity)?,
+            (None, watermark::Placement::Diagonal { .. }) => watermark::DIAGONAL_OPACITY,
+            (None, _) => 1.0,
+        };
+        let output = OutputOptions {
+            encryption: builder::Encryption::new(
+                &self.user_password,
+                &self.owner_password,
+                builder::parse_encryption_level(&self.encryption)?,
+            ),
+            pdfa: self.pdfa,
+            linearize: self.linearize,
+            incremental: self.incremental,
+            object_streams: self.object_streams,
+            strip_metadata: self.strip_metadata,
+            metadata: Metadata {
+                provenance: self.provenance.then(|| Provenance::new(self.logo.as_deref(), &self.settings(opacity))),
+                ..self.metadata.clone()
+            },
+            #[cfg(not(target_arch = "wasm32"))]
+            signer: self
+                .signature
+                .as_ref()
+                .map(|(p12, password)| crate::sign::Signer::from_pkcs12(p12, password))
+                .transpose()?,
+        };
+        let scale = self.scale.as_deref().map(watermark::parse_scale).transpose()?;
+        let layer = watermark::parse_layer(&self.layer)?;
+        if self.outline > watermark::MAX_OUTLINE {
+            return Err(WatermarkError::InvalidOption(format!(
+                "El contorno debe ser de 1 a {} px",
+                watermark::MAX_OUTLINE
+            )));
+        }
+        let avoid: Vec<_> = self
+            .avoid
+            .split(';')
+            .filter(|z| !z.trim().is_empty())
+            .map(watermark::parse_zone)
+            .collect::<Result<_, _>>()?;
+        let placements = match &self.page_positions {
+            Some(json) => watermark::parse_page_placements(json, placement, self.margin, self.spacing_x, self.spacing_y)?,
+            None => PagePlacements::uniform(placement),
+        };
+
+        let plate = match &self.plate {
+            Some((color, plate_opacity, padding)) => {
+                let mut color = watermark::parse_color(color)?;
+                let plate_opacity = watermark::parse_opacity(plate_opacity).map_err(|_| {
+                    WatermarkError::InvalidOption(
+                        "--plate-opacity debe ser un valor entre 0 y 1 o un porcentaje 0%-100%".to_string(),
+                    )
+                })?;
+                color[3] = (color[3] as f32 * plate_opacity).round() as u8;
+                Some(watermark::Plate { color, padding: *padding })
+            }
+            None => None,
+        };
+        let tint = self.tint.as_deref().map(watermark::parse_color).transpose()?;
+        let mut stamps = Vec::new();
+        if let Some(logo) = &self.logo {
+            let mark = watermark::prepare_from_bytes(logo, self.min_w, self.min_h)?
+                .with_opacity(opacity)
+                .with_scale(scale)
+                .with_tint(tint)
+                .with_desaturate(self.gray_logo)
+                .with_effects(self.shadow, self.outline)
+                .with_adaptive(self.adaptive)
+                .with_plate(plate);
+            stamps.push(Stamp::new(mark, placements.clone()).with_layer(layer).with_avoid(avoid.clone()));
+        }
+        if let Some(text) = &self.text {
+            let color = watermark::parse_color(&self.color)?;
+            let mark = watermark::prepare_text(text, self.font_size, color, self.font.as_deref())?
+                .with_opacity(opacity)
+                .with_effects(self.shadow, self.outline)
+                .with_adaptive(self.adaptive)
+                .with_plate(plate);
+            // Las posiciones del logo no se aplican al texto que lo acompaña.
+            let text_placements = if self.logo.is_some() || self.text_position.is_some() {
+                PagePlacements::uniform(self.placement(self.text_position.as_deref().unwrap_or(&self.position))?)
+            } else {
+                placements
+            };
+            stamps.push(Stamp::new(mark, text_placements).with_layer(layer).with_avoid(avoid));
+        }
+        if self.header.is_some() || self.footer.is_some() {
+            let column = match self.running_align.as_str() {
+                "left" => 'l',
+                "center" => 'c',
+                "right" => 'r',
+                other => {
+                    return Err(WatermarkError::InvalidOption(format!(
+                        "Alineación inválida: {} (usar left, center o right)",
+                        other
+                    )))
+                }
+            };
+            let color = watermark::parse_color(&self.color)?;
+            for (text, row) in [(&self.header, 't'), (&self.footer, 'b')] {
+                let Some(text) = text else {
+                    continue;
+                };
+                let mark = watermark::prepare_text(text, self.running_size, color, self.font.as_deref())?;
+                let position = format!("{}{}", row, column);
+                let placement = watermark::parse_placement(&position, self.running_margin, 0, 0)?;
+                stamps.push(Stamp::new(mark, PagePlacements::uniform(placement)).with_layer(layer));
+            }
+        }
+        let timestamp = match (&self.timestamp_text, &self.timestamp) {
+            (Some(text), _) => Some(text.clone()),
+            (None, Some((format, zone))) => Some(text::timestamp(format, zone)?),
+            (None, None) => None,
+        };
+        if let Some(timestamp) = timestamp {
+            let color = watermark::parse_color(&self.color)?;
+            let mark = watermark::prepare_text(&timestamp, self.font_size, color, self.font.as_deref())?;
+            let placement = PagePlacements::uniform(self.placement(&self.timestamp_position)?);
+            stamps.push(Stamp::new(mark, placement).with_layer(layer));
+        }
+        let bates = match &self.bates {
+            Some((prefix, position, start, digits)) => {
+                if !(1..=20).contains(digits) {
+                    return Err(WatermarkError::InvalidOption(
+                        "El número Bates debe tener de 1 a 20 cifras".to_string(),
+                    ));
+                }
+                let color = watermark::parse_color(&self.color)?;
+                let template = format!("{}{{bates}}", prefix);
+                let mark = watermark::prepare_text(&template, self.font_size, color, self.font.as_deref())?;
+                let placement = PagePlacements::uniform(self.placement(position)?);
+                stamps.push(Stamp::new(mark, placement).with_layer(layer));
+                Some(Bates::new(*start as u64, *digits as usize))
+            }
+            None => None,
+        };
+
+        if self.max_dpi < 0.0 {
+            return Err(WatermarkError::InvalidOption("La resolución máxima no puede ser negativa".to_string()));
+        }
+        let max_dpi = (self.max_dpi > 0.0).then_some(self.max_dpi);
+        let page_size = match self.page_size.as_str() {
+            "" => None,
+            size => Some(pdf::parse_target_size(size)?),
+        };
+        let mut quality = watermark::parse_quality(&self.quality)?;
+        if !self.subsampling.is_empty() {
+            quality = quality.with_subsampling(watermark::parse_subsampling(&self.subsampling)?)?;
+        }
+        let max_size = match self.max_size.as_str() {
+            "" => None,
+            size => Some(builder::parse_size(size)?),
+        };
+
+        let mut pipeline = Pipeline::new(stamps);
+        if let Some(bates) = bates {
+            pipeline = pipeline.with_bates(bates);
+        }
+        if let Some((payload, key, strength)) = &self.forensic {
+            if key.is_empty() {
+                return Err(WatermarkError::InvalidOption("El watermark forense necesita una clave".to_string()));
+            }
+            if !payload.contains('{') && payload.len() > forensic::MAX_PAYLOAD {
+                return Err(WatermarkError::InvalidOption(format!(
+                    "El watermark forense admite como mucho {} bytes",
+                    forensic::MAX_PAYLOAD
+                )));
+            }
+            let strength = if *strength > 0.0 { *strength } else { forensic::DEFAULT_STRENGTH };
+            pipeline = pipeline.with_forensic(payload, Forensic::new(key).with_strength(strength));
+        }
+        Ok(pipeline
+            .with_mode(stamp::parse_mode(&self.mode)?)
+            .with_quality(quality)
+            .with_output(output)
+            .with_pages(pages::parse_pages(&self.pages)?.with_every_nth(self.every_nth))
+            .with_skip(pages::parse_pages(&self.skip_pages)?)
+            .with_password(&self.password)
+            .with_dpi(self.dpi)
+            .with_max_size(max_size, self.downscale)
+            .with_max_resolution(max_dpi, (self.max_width > 0).then_some(self.max_width))
+            .with_page_size(page_size)
+            .with_grayscale(self.grayscale)
+            .with_invisible(self.invisible.clone()))
+    }
+
+    /// Opciones para el registro de procedencia, en JSON. No incluye
+    /// contraseñas, claves ni el contenido de los watermarks ocultos.
+    /// `opacity` es la que se aplica, que sin una explícita depende de la posición.
+    fn settings(&self, opacity: f32) -> String {
+        let mut settings = serde_json::json!({
+            "mode": self.mode,
+            "position": self.position,
+            "layer": self.layer,
+            // Redondeada: en f64 un f32 como 0.3 sale 0.30000001192092896.
+            "opacity": (opacity as f64 * 1000.0).round() / 1000.0,
+            "margin": self.margin,
+            "spacing": [self.spacing_x, self.spacing_y],
+            "quality": self.quality,
+            "dpi": self.dpi,
+        });
+        let optional = [
+            ("pages", (!self.pages.is_empty()).then(|| self.pages.clone().into())),
+            ("every_nth", (self.every_nth > 1).then(|| self.every_nth.into())),
+            ("skip_pages", (!self.skip_pages.is_empty()).then(|| self.skip_pages.clone().into())),
+            ("pos", self.pos.clone().map(|(x, y)| serde_json::json!([x, y]))),
+            ("page_positions", self.page_positions.clone().map(Into::into)),
+            ("scale", self.scale.clone().map(Into::into)),
+            ("text", self.text.clone().map(Into::into)),
+            ("text_position", self.text_position.clone().map(Into::into)),
+            ("font_size", self.text.is_some().then(|| self.font_size.into())),
+            ("color", self.text.is_some().then(|| self.color.clone().into())),
+            ("avoid", (!self.avoid.is_empty()).then(|| self.avoid.clone().into())),
+            ("tint", self.tint.clone().map(Into::into)),
+            ("gray_logo", self.gray_logo.then_some(true.into())),
+            ("shadow", self.shadow.then_some(true.into())),
+            ("outline", (self.outline > 0).then(|| self.outline.into())),
+            ("adaptive", self.adaptive.then_some(true.into())),
+            (
+                "plate",
+                self.plate.as_ref().map(|(color, opacity, padding)| serde_json::json!([color, opacity, padding])),
+            ),
+            ("invisible", self.invisible.is_some().then_some(true.into())),
+            ("forensic", self.forensic.is_some().then_some(true.into())),
+            ("bates", self.bates.as_ref().map(|(prefix, ..)| prefix.clone().into())),
+            ("header", self.header.clone().map(Into::into)),
+            ("footer", self.footer.clone().map(Into::into)),
+            ("timestamp", self.timestamp.as_ref().map(|(format, _)| format.clone().into())),
+            ("grayscale", self.grayscale.then_some(true.into())),
+            ("pdfa", self.pdfa.then_some(true.into())),
+            ("linearize", self.linearize.then_some(true.into())),
+            ("incremental", self.incremental.then_some(true.into())),
+            ("object_streams", self.object_streams.then_some(true.into())),
+            ("strip_metadata", self.strip_metadata.then_some(true.into())),
+            (
+                "encryption",
+                (!self.user_password.is_empty() || !self.owner_password.is_empty())
+                    .then(|| self.encryption.clone().into()),
+            ),
+        ];
+        for (key, value) in optional {
+            if let Some(value) = value {
+                settings[key] = value;
+            }
+        }
+        settings.to_string()
+    }
+
+    fn placement(&self, position: &str) -> Result<watermark::Placement, WatermarkError> {
+        watermark::parse_placement(position, self.margin, self.spacing_x, self.spacing_y)
+    }
+}
@@ -0,0 +1,104 @@
+use crate::error::WatermarkError;
+
+/// Páginas elegidas con una especificación como "1-5,8,12-" (numeradas desde
+/// 1; un rango sin final llega a la última) o con `first`, `last`, `odd` y
+/// `even`. Vacía selecciona todas.
+#[derive(Clone, Default)]
+pub struct PageSelection {
+    items: Vec<Item>,
+    /// Se queda con una de cada tantas páginas elegidas (0 o 1 = todas).
+    every_nth: usize,
+}
+
+#[derive(Clone, Copy)]
+enum Item {
+    Range(usize, Option<usize>),
+    Last,
+    Odd,
+    Even,
+}
+
+pub fn parse_pages(spec: &str) -> Result<PageSelection, WatermarkError> {
+    let mut items = Vec::new();
+    for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let invalid = || {
+            WatermarkError::InvalidOption(format!(
+                "Rango de páginas inválido: '{}' (usar ej. 1-5,8,12-, first, last, odd o even)",
+                part
+            ))
+        };
+        let number = |s: &str| match s.trim().parse::<usize>() {
+            Ok(n) if n > 0 => Ok(n),
+            _ => Err(invalid()),
+        };
+        let item = match part.to_ascii_lowercase().as_str() {
+            "first" => Item::Range(1, Some(1)),
+            "last" => Item::Last,
+            "odd" => Item::Odd,
+            "even" => Item::Even,
+            _ => match part.split_once('-') {
+                None => Item::Range(number(part)?, Some(number(part)?)),
+                Some((start, end)) if end.trim().is_empty() => Item::Range(number(start)?, None),
+                Some((start, end)) => Item::Range(number(start)?, Some(number(end)?)),
+            },
+        };
+        items.push(item);
+    }
+    Ok(PageSelection { items, every_nth: 1 })
+}
+
+impl PageSelection {
+    /// Selección de las páginas `indices` (desde 0), en ese orden.
+    pub fn from_indices(indices: impl IntoIterator<Item = usize>) -> Self {
+        let items = indices.into_iter().map(|i| Item::Range(i + 1, Some(i + 1))).collect();
+        PageSelection { items, every_nth: 1 }
+    }
+
+    /// Se queda con la primera de cada `n` páginas elegidas.
+    pub fn with_every_nth(mut self, n: usize) -> Self {
+        self.every_nth = n;
+        self
+    }
+
+    /// Índices (desde 0) de las páginas elegidas en un PDF de `total` páginas,
+    /// en el orden indicado y sin repetir. Los rangos se recortan a `total`.
+    pub fn indices(&self, total: usize) -> Result<Vec<usize>, WatermarkError> {
+        let indices = if self.items.is_empty() {
+            (0..total).collect()
+        } else {
+            self.listed(total)?
+        };
+        Ok(indices.into_iter().step_by(self.every_nth.max(1)).collect())
+    }
+
+    /// Como [`indices`](Self::indices), pero una selección vacía no elige
+    /// ninguna página (para exclusiones como `--skip-pages`).
+    pub fn listed(&self, total: usize) -> Result<Vec<usize>, WatermarkError> {
+        let mut seen = vec![false; total];
+        let mut indices = Vec::new();
+        for &item in &self.items {
+            let numbers: Vec<usize> = match item {
+                Item::Range(start, _) if start > total => {
+                    return Err(WatermarkError::PageNotFound { page: start, total });
+                }
+                Item::Range(start, end) => {
+                    let end = end.unwrap_or(total).min(total);
+                    if start <= end {
+                        (start..=end).collect()
+                    } else {
+                        (end..=start).rev().collect()
+                    }
+                }
+                Item::Last => (1..=total).last().into_iter().collect(),
+                Item::Odd => (1..=total).step_by(2).collect(),
+                Item::Even => (2..=total).step_by(2).collect(),
+            };
+            for n in numbers {
+                if !std::mem::replace(&mut seen[n - 1], true) {
+                    indices.push(n - 1);
+                }
+            }
+        }
+        Ok(indices)
+    }
+}
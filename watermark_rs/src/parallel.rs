@@ -0,0 +1,12 @@
+/// `items.iter().map(f).collect()`, repartido entre núcleos y conservando el
+/// orden. En wasm no hay hilos y se hace en secuencia.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn map<T: Sync, R: Send>(items: &[T], f: impl Fn(&T) -> R + Sync + Send) -> Vec<R> {
+    use rayon::prelude::*;
+    items.par_iter().map(f).collect()
+}
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn map<T, R>(items: &[T], f: impl Fn(&T) -> R) -> Vec<R> {
+    items.iter().map(f).collect()
+}
@@ -1,128 +1,1297 @@
+use crate::error::WatermarkError;
+use crate::forensic::Forensic;
+use crate::progress::{ProgressSink, Silent, Tally};
+use crate::text::PageContext;
+use crate::watermark::{self, Layout, Stamp};
+use crate::{ccitt, parallel, render, stego};
 use anyhow::{anyhow, Context, Result};
 use flate2::read::ZlibDecoder;
-use image::{DynamicImage, RgbImage};
-use lopdf::{Document, Object};
+use image::{DynamicImage, GrayImage, Rgb, RgbImage, RgbaImage};
+use lopdf::{Document, LoadOptions, Object};
+use std::borrow::Cow;
+use std::collections::HashSet;
 use std::io::{Cursor, Read};
 
-pub fn extract_pages_from_bytes(data: &[u8]) -> Result<Vec<DynamicImage>> {
-    let doc = Document::load_mem(data).context("No se pudo parsear el PDF")?;
-    extract_from_doc(&doc)
+/// Fracción de la página que puede ocupar la zona cambiada por los
+/// watermarks de fondo para conservar la imagen original (ver
+/// [`Page::watermarked`]): por encima sale más a cuenta recodificarla entera.
+const MAX_PATCH_AREA: f64 = 0.25;
+
+/// Página extraída, ya girada según `/Rotate`: su imagen, el tamaño de la
+/// zona visible (CropBox) en puntos tal como se muestra y su número en el
+/// PDF de entrada.
+#[derive(Clone)]
+pub struct Page {
+    pub image: DynamicImage,
+    pub width: f64,
+    pub height: f64,
+    pub number: u32,
+    /// Watermarks que se dibujan sobre la imagen, en píxeles de `image`.
+    pub overlays: Vec<Layout<'static>>,
+    /// XObject de imagen que cubre la página en el PDF de entrada, si `image`
+    /// sale de él sin cambios: se copia tal cual en vez de recodificarlo.
+    pub original: Option<lopdf::ObjectId>,
+    /// El primero de `overlays` es la zona de la imagen que cambia respecto a
+    /// `original` (ver [`Page::watermarked`]), y no un watermark.
+    pub patched: bool,
 }
 
-#[cfg(not(target_arch = "wasm32"))]
-pub fn extract_pages(path: &str) -> Result<Vec<DynamicImage>> {
-    let doc = Document::load(path).context("No se pudo abrir el PDF")?;
-    extract_from_doc(&doc)
+impl Page {
+    /// Aplica los `stamps` (ver [`watermark::apply`]). Si la imagen no cambia
+    /// se conserva `original`, y también si solo cambia una zona pequeña: esa
+    /// zona se dibuja encima, con la calidad JPEG de la original si lo es, y
+    /// el resto de la página se copia sin recodificar.
+    pub fn watermarked(&self, ctx: &PageContext, stamps: &[Stamp]) -> Page {
+        let (image, mut overlays) = watermark::apply(&self.image, ctx, stamps);
+        let (image, original, patched) = match image {
+            Cow::Borrowed(image) => (image.clone(), self.original, self.patched),
+            Cow::Owned(changed) => match self.original.and(self.patch(&changed)) {
+                Some(patch) => {
+                    // Debajo de los demás watermarks, como el resto de la imagen.
+                    let patched = !patch.is_empty();
+                    overlays.splice(0..0, patch);
+                    (self.image.clone(), self.original, patched)
+                }
+                None => (changed, None, false),
+            },
+        };
+        Page {
+            image,
+            overlays,
+            original,
+            patched,
+            ..*self
+        }
+    }
+
+    /// Zona de `changed` que difiere de la imagen de la página (vacío si no
+    /// hay ninguna), o `None` si ocupa más de [`MAX_PATCH_AREA`] de la página.
+    fn patch(&self, changed: &DynamicImage) -> Option<Vec<Layout<'static>>> {
+        let (before, after) = (self.image.to_rgba8(), changed.to_rgba8());
+        let (mut left, mut top, mut right, mut bottom) = (after.width(), after.height(), 0, 0);
+        for (x, y, pixel) in after.enumerate_pixels() {
+            if before.get_pixel(x, y) != pixel {
+                (left, top) = (left.min(x), top.min(y));
+                (right, bottom) = (right.max(x + 1), bottom.max(y + 1));
+            }
+        }
+        if right <= left {
+            return Some(Vec::new());
+        }
+        let (w, h) = (right - left, bottom - top);
+        if w as f64 * h as f64 > MAX_PATCH_AREA * after.width() as f64 * after.height() as f64 {
+            return None;
+        }
+        let image = image::imageops::crop_imm(&after, left, top, w, h).to_image();
+        Some(vec![Layout {
+            image: Cow::Owned(image),
+            origins: vec![(left as i64, top as i64)],
+        }])
+    }
+
+    /// Copia con la imagen y los watermarks reducidos a `scale` (0-1), que
+    /// ya no se corresponde con la imagen original del PDF.
+    pub fn downscaled(&self, scale: f64) -> Page {
+        use image::imageops::FilterType;
+        let length = |n: u32| (n as f64 * scale).round().max(1.0) as u32;
+        let (w, h) = (length(self.image.width()), length(self.image.height()));
+        let image = self.image.resize_exact(w, h, FilterType::Triangle);
+        // Misma proporción que la imagen, para que los watermarks no se desplacen.
+        let (sx, sy) = (w as f64 / self.image.width() as f64, h as f64 / self.image.height() as f64);
+        let overlays = self
+            .overlays
+            .iter()
+            .map(|overlay| {
+                let (ow, oh) = (length(overlay.image.width()), length(overlay.image.height()));
+                Layout {
+                    image: Cow::Owned(image::imageops::resize(overlay.image.as_ref(), ow, oh, FilterType::Triangle)),
+                    origins: overlay
+                        .origins
+                        .iter()
+                        .map(|&(x, y)| ((x as f64 * sx).round() as i64, (y as f64 * sy).round() as i64))
+                        .collect(),
+                }
+            })
+            .collect();
+        Page { image, overlays, original: None, ..*self }
+    }
+
+    /// Copia que mide `width`x`height` puntos, con una imagen de `px_w`x`px_h`
+    /// píxeles en la que la original se escala sin deformarla y se centra
+    /// sobre blanco. Se aplica antes que los watermarks, que no se copian.
+    pub fn fitted(&self, px_w: u32, px_h: u32, width: f64, height: f64) -> Page {
+        let resized = self.image.resize(px_w, px_h, image::imageops::FilterType::Triangle).to_rgb8();
+        let mut canvas = RgbImage::from_pixel(px_w, px_h, Rgb([255, 255, 255]));
+        let x = (px_w - resized.width()) / 2;
+        let y = (px_h - resized.height()) / 2;
+        image::imageops::overlay(&mut canvas, &resized, x as i64, y as i64);
+        Page {
+            image: DynamicImage::ImageRgb8(canvas),
+            width,
+            height,
+            number: self.number,
+            overlays: Vec::new(),
+            original: None,
+            patched: false,
+        }
+    }
+
+    /// Copia en escala de grises, con los `overlays` ya dibujados en la
+    /// imagen para que también queden en gris.
+    pub fn grayscale(&self) -> Page {
+        let image = DynamicImage::ImageRgba8(self.flattened()).to_luma8();
+        Page {
+            image: DynamicImage::ImageLuma8(image),
+            overlays: Vec::new(),
+            original: None,
+            ..*self
+        }
+    }
+
+    /// Copia con el watermark forense `payload` en la imagen (ver
+    /// [`Forensic::embed`]), con los `overlays` ya dibujados. Falla si
+    /// `payload` es demasiado largo.
+    pub fn fingerprinted(&self, forensic: &Forensic, payload: &[u8]) -> Result<Page, WatermarkError> {
+        let mut image = if self.overlays.is_empty() {
+            self.image.clone()
+        } else {
+            DynamicImage::ImageRgba8(self.flattened())
+        };
+        forensic.embed(&mut image, payload)?;
+        Ok(Page {
+            image,
+            overlays: Vec::new(),
+            original: None,
+            ..*self
+        })
+    }
+
+    /// Copia con `payload` guardado de forma invisible en la imagen (ver
+    /// [`stego::embed`]), con los `overlays` ya dibujados para que no lo
+    /// tapen. Se queda como está si la imagen es demasiado pequeña.
+    pub fn embedded(&self, payload: &[u8]) -> Page {
+        let mut image = if self.overlays.is_empty() {
+            self.image.clone()
+        } else {
+            DynamicImage::ImageRgba8(self.flattened())
+        };
+        if !stego::embed(&mut image, payload) {
+            log::warn!("Página {}: el watermark invisible no cabe en la imagen", self.number);
+            return self.clone();
+        }
+        Page {
+            image,
+            overlays: Vec::new(),
+            original: None,
+            ..*self
+        }
+    }
+
+    /// Imagen de la página con los `overlays` ya dibujados encima, como se
+    /// ve en el PDF generado.
+    pub fn flattened(&self) -> RgbaImage {
+        let mut canvas = self.image.to_rgba8();
+        for overlay in &self.overlays {
+            for &(x, y) in &overlay.origins {
+                image::imageops::overlay(&mut canvas, overlay.image.as_ref(), x, y);
+            }
+        }
+        canvas
+    }
 }
 
-fn extract_from_doc(doc: &Document) -> Result<Vec<DynamicImage>> {
-    let mut images = Vec::new();
-    let mut page_ids: Vec<_> = doc.get_pages().into_iter().collect();
-    page_ids.sort_by_key(|(num, _)| *num);
+/// PDF de entrada: sus páginas extraídas y el documento original (ya
+/// descifrado), del que se copian los metadatos al reconstruirlo.
+pub struct Source {
+    pub pages: Vec<Page>,
+    pub doc: Document,
+}
+
+/// Tamaño al que se fuerzan las páginas de salida (ver [`Page::fitted`]).
+#[derive(Clone, Copy, PartialEq)]
+pub enum TargetSize {
+    /// En píxeles; la página mide eso a la resolución configurada.
+    Pixels(u32, u32),
+    /// Papel en puntos, girado según la orientación de cada página.
+    Paper(f64, f64),
+}
+
+/// "1920x1080" (píxeles) o un tamaño de papel: a3, a4, a5, letter o legal.
+pub fn parse_target_size(s: &str) -> Result<TargetSize, WatermarkError> {
+    let lower = s.trim().to_lowercase();
+    let paper = match lower.as_str() {
+        "a3" => Some((841.89, 1190.55)),
+        "a4" => Some((595.28, 841.89)),
+        "a5" => Some((419.53, 595.28)),
+        "letter" => Some((612.0, 792.0)),
+        "legal" => Some((612.0, 1008.0)),
+        _ => None,
+    };
+    if let Some((w, h)) = paper {
+        return Ok(TargetSize::Paper(w, h));
+    }
+    let pixels = lower
+        .split_once(['x', '×'])
+        .and_then(|(w, h)| Some((w.trim().parse::<u32>().ok()?, h.trim().parse::<u32>().ok()?)));
+    match pixels {
+        Some((w, h)) if (1..=MAX_TARGET_PX).contains(&w) && (1..=MAX_TARGET_PX).contains(&h) => {
+            Ok(TargetSize::Pixels(w, h))
+        }
+        _ => Err(WatermarkError::InvalidOption(format!(
+            "Tamaño de página inválido: {} (usar ANCHOxALTO en píxeles, a3, a4, a5, letter o legal)",
+            s
+        ))),
+    }
+}
+
+/// Mayor ancho o alto en píxeles de las páginas con tamaño forzado.
+const MAX_TARGET_PX: u32 = 20_000;
+
+/// Resolución por defecto al rasterizar páginas sin imágenes.
+pub const DEFAULT_DPI: f32 = 150.0;
+
+/// Parsea el PDF y lo descifra con `password` si está protegido (RC4 o AES).
+/// Los PDFs cifrados sin contraseña de usuario se abren con `password` vacío.
+pub fn load_document(data: &[u8], password: &str) -> Result<Document, WatermarkError> {
+    let doc = Document::load_mem_with_options(data, LoadOptions::with_password(password))
+        .map_err(|e| match e {
+            lopdf::Error::InvalidPassword if password.is_empty() => WatermarkError::EncryptedPdf,
+            lopdf::Error::InvalidPassword => WatermarkError::WrongPassword,
+            e => WatermarkError::InvalidPdf(format!("{:#}", anyhow::Error::new(e))),
+        })?;
+    if doc.is_encrypted() {
+        return Err(WatermarkError::EncryptedPdf);
+    }
+    log::debug!(
+        "PDF {}: {} objetos, {} páginas{}",
+        doc.version,
+        doc.objects.len(),
+        doc.get_pages().len(),
+        if doc.was_encrypted() { ", cifrado" } else { "" }
+    );
+    Ok(doc)
+}
+
+/// Extrae la imagen de cada página. Las páginas sin imágenes se rasterizan a
+/// `dpi` si el crate se compiló con la feature `render`.
+pub fn extract_pages_from_bytes(data: &[u8], password: &str, dpi: f32) -> Result<Source, WatermarkError> {
+    let mut reader = PageReader::new(data.to_vec(), password, dpi)?;
+    let indices: Vec<usize> = (0..reader.len()).collect();
+    let pages = reader.pages(&indices)?;
+    Ok(Source { pages, doc: reader.doc })
+}
+
+/// Extrae las páginas de un PDF bajo demanda, para repartir el trabajo en
+/// varias llamadas (ver [`extract_pages_from_bytes`]).
+pub struct PageReader {
+    data: Vec<u8>,
+    doc: Document,
+    page_ids: Vec<(u32, lopdf::ObjectId)>,
+    rasterizer: render::Rasterizer,
+}
+
+impl PageReader {
+    pub fn new(data: Vec<u8>, password: &str, dpi: f32) -> Result<Self, WatermarkError> {
+        if !(36.0..=600.0).contains(&dpi) {
+            return Err(WatermarkError::InvalidOption(format!("DPI inválido: {} (usar entre 36 y 600)", dpi)));
+        }
+        let doc = load_document(&data, password)?;
+        let mut page_ids: Vec<_> = doc.get_pages().into_iter().collect();
+        page_ids.sort_by_key(|(num, _)| *num);
+        Ok(Self {
+            data,
+            doc,
+            page_ids,
+            rasterizer: render::Rasterizer::new(password, dpi),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.page_ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.page_ids.is_empty()
+    }
+
+    /// Documento original, ya descifrado.
+    pub fn document(&self) -> &Document {
+        &self.doc
+    }
+
+    /// Extrae las páginas `indices` (desde 0, dentro de rango).
+    pub fn pages(&mut self, indices: &[usize]) -> Result<Vec<Page>, WatermarkError> {
+        self.pages_with_progress(indices, &Silent)
+    }
+
+    /// Como [`pages`](Self::pages), reportando cada página extraída como
+    /// [`Progress::Advance`](crate::progress::Progress::Advance).
+    pub fn pages_with_progress(
+        &mut self,
+        indices: &[usize],
+        progress: &dyn ProgressSink,
+    ) -> Result<Vec<Page>, WatermarkError> {
+        let doc = &self.doc;
+        let page_ids: Vec<_> = indices.iter().map(|&i| self.page_ids[i]).collect();
+        let tally = Tally::new(progress, page_ids.len());
+        // Decodificar las imágenes es lo más costoso; rasterizar va en secuencia.
+        let extracted = parallel::map(&page_ids, |&(_, page_id)| {
+            let extracted = extract_page_image(doc, page_id);
+            if !matches!(extracted, Ok(None)) {
+                tally.tick();
+            }
+            extracted
+        });
+        let mut pages = Vec::new();
+        for ((&index, &(page_num, page_id)), extracted) in indices.iter().zip(&page_ids).zip(extracted) {
+            let rotation = rotation(doc, page_id);
+            let (image, original, points_per_px) = match extracted {
+                Ok(Some((image, original))) => (Ok(rotate(image, rotation)), original, 1.0),
+                // El rasterizador ya aplica el giro de la página.
+                Ok(None) => {
+                    log::debug!("Página {}: sin imágenes que se puedan decodificar, se rasteriza", page_num);
+                    let image = self.rasterizer.page(&self.data, index).map_err(|e| {
+                        match (e.downcast_ref(), unsupported_color_space(doc, page_id)) {
+                            (Some(WatermarkError::RenderUnavailable), Some(name)) => {
+                                WatermarkError::UnsupportedColorSpace(name).into()
+                            }
+                            _ => e,
+                        }
+                    });
+                    tally.tick();
+                    (image, None, 72.0 / self.rasterizer.dpi() as f64)
+                }
+                Err(e) => (Err(e), None, 1.0),
+            };
+            let image = image.map_err(|e| WatermarkError::page(page_num, e))?;
+            let (width, height) = displayed_size(doc, page_id).unwrap_or((
+                image.width() as f64 * points_per_px,
+                image.height() as f64 * points_per_px,
+            ));
+            pages.push(Page {
+                image,
+                width,
+                height,
+                number: page_num,
+                overlays: Vec::new(),
+                original,
+                patched: false,
+            });
+        }
+        Ok(pages)
+    }
+
+    /// Tamaño en puntos de la zona visible de la página `index` (desde 0)
+    /// tal como se muestra, sin extraerla; `None` si no tiene un MediaBox
+    /// válido.
+    pub fn page_size(&self, index: usize) -> Option<(f64, f64)> {
+        displayed_size(&self.doc, self.page_ids[index].1)
+    }
 
-    for (page_num, page_id) in &page_ids {
-        let image = extract_page_image(doc, *page_id)
-            .with_context(|| format!("Error en página {}", page_num))?;
-        images.push(image);
+    /// Tamaño en píxeles de la imagen de la página `index` (desde 0), que es
+    /// sobre el que se colocan los watermarks. Hay que extraerla.
+    pub fn dimensions(&mut self, index: usize) -> Result<(u32, u32), WatermarkError> {
+        let page = self.pages(&[index])?.remove(0);
+        Ok((page.image.width(), page.image.height()))
     }
-    Ok(images)
+
+    /// Imagen de la página `index` (desde 0) reducida a como mucho
+    /// `max_width` píxeles de ancho, para mostrarla en miniatura.
+    pub fn thumbnail(&mut self, index: usize, max_width: u32) -> Result<RgbaImage, WatermarkError> {
+        let page = self.pages(&[index])?.remove(0);
+        let (width, height) = (page.image.width(), page.image.height());
+        if width <= max_width {
+            return Ok(page.image.into_rgba8());
+        }
+        let thumb_height = ((height as u64 * max_width as u64) / width as u64).max(1) as u32;
+        Ok(page.image.thumbnail_exact(max_width, thumb_height).into_rgba8())
+    }
+}
+
+pub fn page_count(data: &[u8], password: &str) -> Result<usize, WatermarkError> {
+    Ok(load_document(data, password)?.get_pages().len())
 }
 
-fn extract_page_image(doc: &Document, page_id: lopdf::ObjectId) -> Result<DynamicImage> {
-    let page_dict = doc
-        .get_object(page_id)?
-        .as_dict()
-        .map_err(|_| anyhow!("Página no es un diccionario"))?;
+/// Une los PDFs `inputs` (con la misma `password` si están protegidos) en
+/// uno solo con todas sus páginas en orden, para procesarlos como un único
+/// documento. Se conservan los metadatos del primero; los marcadores y
+/// formularios se pierden.
+pub fn merge(inputs: &[Vec<u8>], password: &str) -> Result<Vec<u8>, WatermarkError> {
+    let mut merged = Document::with_version("1.4");
+    let pages_id = merged.new_object_id();
+    let mut kids = Vec::new();
+    let mut info = None;
+    for data in inputs {
+        let mut doc = load_document(data, password)?;
+        doc.renumber_objects_with(merged.max_id + 1);
+        let mut page_ids: Vec<_> = doc.get_pages().into_iter().collect();
+        page_ids.sort_by_key(|(num, _)| *num);
+        for (num, page_id) in page_ids {
+            // El árbol de páginas se rehace: los atributos heredados pasan a la página.
+            let inherited: Vec<_> = [b"Resources".as_slice(), b"MediaBox", b"CropBox", b"Rotate"]
+                .into_iter()
+                .filter_map(|key| Some((key, inherited_attr(&doc, page_id, key)?)))
+                .collect();
+            let page = doc
+                .get_dictionary_mut(page_id)
+                .map_err(|e| WatermarkError::page(num, anyhow!("Página inválida: {}", e)))?;
+            for (key, value) in inherited {
+                if !page.has(key) {
+                    page.set(key, value);
+                }
+            }
+            page.set("Parent", pages_id);
+            kids.push(Object::Reference(page_id));
+        }
+        if info.is_none() {
+            info = doc.trailer.get(b"Info").ok().cloned();
+        }
+        if doc.version > merged.version {
+            merged.version = doc.version.clone();
+        }
+        merged.max_id = doc.max_id;
+        merged.objects.extend(doc.objects);
+    }
+
+    let count = kids.len() as i64;
+    merged.objects.insert(
+        pages_id,
+        Object::Dictionary(lopdf::dictionary! { "Type" => "Pages", "Kids" => kids, "Count" => count }),
+    );
+    let catalog_id = merged.add_object(lopdf::dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+    merged.trailer.set("Root", catalog_id);
+    if let Some(info) = info {
+        merged.trailer.set("Info", info);
+    }
+    merged.prune_objects();
+    let mut out = Vec::new();
+    merged
+        .save_to(&mut out)
+        .map_err(|e| WatermarkError::InvalidPdf(format!("No se pudo unir los PDFs: {}", e)))?;
+    Ok(out)
+}
 
-    let resources = resolve_to_dict(doc, page_dict.get(b"Resources")?)?;
-    let xobjects = resolve_to_dict(doc, resources.get(b"XObject")?)?;
+/// Arma un PDF con una página por imagen de `images` (PNG, JPEG o WebP), en
+/// orden, para procesarlas como un PDF más. Cada página mide lo que la
+/// imagen a `dpi`; la transparencia se compone sobre blanco.
+pub fn from_images(images: &[Vec<u8>], dpi: f32) -> Result<Vec<u8>, WatermarkError> {
+    if images.is_empty() {
+        return Err(WatermarkError::InvalidOption("No hay imágenes con las que armar el PDF".to_string()));
+    }
+    let points_per_px = 72.0 / dpi as f64;
+    let pages = parallel::map(images, |data| image::load_from_memory(data));
+    let pages = pages
+        .into_iter()
+        .enumerate()
+        .map(|(i, image)| {
+            let number = i as u32 + 1;
+            let image = image.map_err(|e| WatermarkError::page(number, anyhow!("No se pudo leer la imagen: {}", e)))?;
+            let image = if image.color().has_alpha() {
+                let mut canvas = RgbaImage::from_pixel(image.width(), image.height(), image::Rgba([255, 255, 255, 255]));
+                image::imageops::overlay(&mut canvas, &image.to_rgba8(), 0, 0);
+                DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(canvas).to_rgb8())
+            } else {
+                image
+            };
+            Ok(Page {
+                width: image.width() as f64 * points_per_px,
+                height: image.height() as f64 * points_per_px,
+                number,
+                overlays: Vec::new(),
+                original: None,
+                patched: false,
+                image,
+            })
+        })
+        .collect::<Result<Vec<_>, WatermarkError>>()?;
+    crate::builder::build_pdf_bytes(&pages, None, &watermark::Quality::Lossless, &Default::default())
+}
+
+/// Resumen de un PDF para saber antes de procesarlo si se podrá: versión,
+/// cifrado y, por página, su tamaño y las imágenes de sus recursos.
+pub struct PdfInfo {
+    pub version: String,
+    pub encrypted: bool,
+    /// Cifrado y `password` no lo abre: no hay datos de las páginas.
+    pub needs_password: bool,
+    pub pages: Vec<PageInfo>,
+}
+
+pub struct PageInfo {
+    pub number: u32,
+    /// Tamaño en puntos de la zona visible tal como se muestra (`None` sin
+    /// MediaBox válido).
+    pub size: Option<(f64, f64)>,
+    pub rotation: u32,
+    pub images: Vec<ImageInfo>,
+}
+
+/// XObject de imagen, sin decodificarlo. `supported` indica si sus filtros,
+/// espacio de color y bits por componente se saben leer; las páginas sin
+/// imágenes soportadas solo se procesan con la feature `render`.
+pub struct ImageInfo {
+    pub width: u32,
+    pub height: u32,
+    pub filters: Vec<String>,
+    pub color_space: Option<String>,
+    pub supported: bool,
+}
+
+impl PdfInfo {
+    pub fn to_json(&self) -> String {
+        let pages: Vec<_> = self
+            .pages
+            .iter()
+            .map(|page| {
+                let images: Vec<_> = page
+                    .images
+                    .iter()
+                    .map(|image| {
+                        serde_json::json!({
+                            "width": image.width,
+                            "height": image.height,
+                            "filters": image.filters,
+                            "color_space": image.color_space,
+                            "supported": image.supported,
+                        })
+                    })
+                    .collect();
+                serde_json::json!({
+                    "number": page.number,
+                    "width_pt": page.size.map(|(w, _)| w),
+                    "height_pt": page.size.map(|(_, h)| h),
+                    "rotation": page.rotation,
+                    "images": images,
+                })
+            })
+            .collect();
+        serde_json::json!({
+            "version": self.version,
+            "encrypted": self.encrypted,
+            "needs_password": self.needs_password,
+            "page_count": if self.needs_password { None } else { Some(self.pages.len()) },
+            "render_available": cfg!(feature = "render"),
+            "pages": pages,
+        })
+        .to_string()
+    }
+}
+
+/// Lee la estructura del PDF sin extraer las páginas (ver [`PdfInfo`]).
+pub fn pdf_info(data: &[u8], password: &str) -> Result<PdfInfo, WatermarkError> {
+    let doc = match load_document(data, password) {
+        Ok(doc) => doc,
+        Err(WatermarkError::EncryptedPdf | WatermarkError::WrongPassword) => {
+            return Ok(PdfInfo {
+                version: header_version(data).unwrap_or_default(),
+                encrypted: true,
+                needs_password: true,
+                pages: Vec::new(),
+            });
+        }
+        Err(e) => return Err(e),
+    };
+    let mut page_ids: Vec<_> = doc.get_pages().into_iter().collect();
+    page_ids.sort_by_key(|(num, _)| *num);
+    let pages = page_ids
+        .into_iter()
+        .map(|(number, page_id)| PageInfo {
+            number,
+            size: displayed_size(&doc, page_id),
+            rotation: rotation(&doc, page_id),
+            images: page_images(&doc, page_id),
+        })
+        .collect();
+    Ok(PdfInfo {
+        version: doc.version.clone(),
+        encrypted: doc.was_encrypted(),
+        needs_password: false,
+        pages,
+    })
+}
 
-    for (_name, obj_ref) in xobjects.iter() {
-        let object = resolve(doc, obj_ref)?;
+/// Versión de la cabecera `%PDF-x.y`, para los PDFs que no se pueden abrir.
+fn header_version(data: &[u8]) -> Option<String> {
+    let start = data.windows(5).take(1024).position(|w| w == b"%PDF-")? + 5;
+    let version: Vec<u8> = data[start..]
+        .iter()
+        .take_while(|b| b.is_ascii_digit() || **b == b'.')
+        .copied()
+        .collect();
+    String::from_utf8(version).ok().filter(|v| !v.is_empty())
+}
 
-        if let Object::Stream(ref stream) = object {
+fn page_images(doc: &Document, page_id: lopdf::ObjectId) -> Vec<ImageInfo> {
+    image_xobjects(doc, page_id)
+        .into_iter()
+        .map(|(_, stream)| {
             let dict = &stream.dict;
+            let filters: Vec<String> = filter_chain(dict).into_iter().map(|(f, _)| f.to_string()).collect();
+            let jpx = filters.last().is_some_and(|f| f == "JPXDecode");
+            let stencil = dict.get(b"ImageMask").and_then(Object::as_bool).unwrap_or(false);
+            let bpc = bits_per_component(dict);
+            let supported = (ColorSpace::from_dict(doc, dict).is_some() || jpx || stencil)
+                && [1, 2, 4, 8, 16].contains(&bpc)
+                && filters.iter().enumerate().all(|(i, f)| filter_supported(f, i + 1 == filters.len()));
+            ImageInfo {
+                width: get_uint(dict, b"Width").unwrap_or(0),
+                height: get_uint(dict, b"Height").unwrap_or(0),
+                filters,
+                color_space: color_space_name(doc, dict),
+                supported,
+            }
+        })
+        .collect()
+}
 
-            if !is_name(dict, b"Subtype", "Image") {
-                continue;
+/// Profundidad máxima de Form XObjects anidados en [`image_xobjects`].
+const MAX_FORM_DEPTH: usize = 8;
+
+/// XObjects de imagen de los recursos de la página con su nombre, incluidos
+/// los de los Form XObjects (y sus propios `Resources`), en los que muchos
+/// generadores envuelven la imagen de la página. Primero van los de la
+/// página y cada imagen aparece una sola vez.
+fn image_xobjects(doc: &Document, page_id: lopdf::ObjectId) -> Vec<(Vec<u8>, lopdf::Stream)> {
+    let resources = inherited_attr(doc, page_id, b"Resources").and_then(|r| resolve_to_dict(doc, &r).ok());
+    let mut pending: Vec<_> = resources.into_iter().map(|r| (r, 0)).collect();
+    let mut visited = HashSet::new();
+    let mut images = Vec::new();
+    while !pending.is_empty() {
+        let (resources, depth) = pending.remove(0);
+        let Some(xobjects) = resources.get(b"XObject").ok().and_then(|x| resolve_to_dict(doc, x).ok()) else {
+            continue;
+        };
+        for (name, obj) in xobjects.iter() {
+            if let Object::Reference(id) = obj {
+                if !visited.insert(*id) {
+                    continue;
+                }
             }
-            if !is_name(dict, b"ColorSpace", "DeviceRGB") {
+            let Ok(Object::Stream(stream)) = resolve(doc, obj) else {
                 continue;
+            };
+            if is_name(&stream.dict, b"Subtype", "Image") {
+                images.push((name.clone(), stream));
+            } else if is_name(&stream.dict, b"Subtype", "Form") && depth < MAX_FORM_DEPTH {
+                let form_resources = stream.dict.get(b"Resources").ok().and_then(|r| resolve_to_dict(doc, r).ok());
+                pending.extend(form_resources.map(|r| (r, depth + 1)));
             }
+        }
+    }
+    images
+}
+
+/// Si [`decode_stream`] sabe aplicar el filtro (los de imagen solo al final).
+fn filter_supported(filter: &str, last: bool) -> bool {
+    match filter {
+        "FlateDecode" | "LZWDecode" | "RunLengthDecode" | "ASCII85Decode" | "ASCIIHexDecode" | "CCITTFaxDecode" => true,
+        "DCTDecode" => last,
+        "JPXDecode" => last && cfg!(feature = "jpeg2000"),
+        _ => false,
+    }
+}
 
-            let width = get_uint(dict, b"Width")?;
-            let height = get_uint(dict, b"Height")?;
-            return decode_stream(stream, width, height);
+#[cfg(not(target_arch = "wasm32"))]
+pub fn extract_pages(path: &str, password: &str, dpi: f32) -> Result<Source, WatermarkError> {
+    let data = std::fs::read(path)?;
+    extract_pages_from_bytes(&data, password, dpi)
+}
+
+/// Gira la imagen como se muestra la página, para reconstruirla derecha.
+fn rotate(image: DynamicImage, rotation: u32) -> DynamicImage {
+    match rotation {
+        90 => image.rotate90(),
+        180 => image.rotate180(),
+        270 => image.rotate270(),
+        _ => image,
+    }
+}
+
+/// Imagen de la página compuesta a partir de sus XObjects, con el id de la
+/// imagen que la cubre si es solo una; `None` si no tiene ninguna imagen
+/// decodificable.
+fn extract_page_image(
+    doc: &Document,
+    page_id: lopdf::ObjectId,
+) -> Result<Option<(DynamicImage, Option<lopdf::ObjectId>)>> {
+    if let Some(page) = render::compose_page(doc, page_id)? {
+        return Ok(Some(page));
+    }
+
+    // Sin dibujos de imágenes en el contenido: se usa la primera imagen de los recursos.
+    for (name, stream) in image_xobjects(doc, page_id) {
+        if let Some(image) = decode_image(doc, &stream)? {
+            log::debug!("Sin imágenes dibujadas en el contenido: se usa /{}", String::from_utf8_lossy(&name));
+            return Ok(Some((DynamicImage::ImageRgb8(flatten(&image.pixels)), None)));
         }
     }
+    Ok(None)
+}
 
-    Err(anyhow!("No se encontró imagen RGB en la página"))
+/// Imagen de un XObject con el alfa de su SMask. Las máscaras de imagen
+/// (`ImageMask`) son stencils: el alfa indica dónde se pinta con el color de
+/// relleno actual.
+pub(crate) struct DecodedImage {
+    pub pixels: RgbaImage,
+    pub stencil: bool,
 }
 
-fn decode_stream(stream: &lopdf::Stream, w: u32, h: u32) -> Result<DynamicImage> {
-    let filter = stream
-        .dict
-        .get(b"Filter")
-        .ok()
-        .and_then(|f| f.as_name_str().ok())
-        .unwrap_or("");
+/// Decodifica un XObject de imagen; `None` si no es una imagen o su espacio
+/// de color no está soportado.
+pub(crate) fn decode_image(doc: &Document, stream: &lopdf::Stream) -> Result<Option<DecodedImage>> {
+    let dict = &stream.dict;
+    if !is_name(dict, b"Subtype", "Image") {
+        return Ok(None);
+    }
+    let stencil = dict.get(b"ImageMask").and_then(Object::as_bool).unwrap_or(false);
+    let color_space = match ColorSpace::from_dict(doc, dict) {
+        Some(color_space) => color_space,
+        // JPEG 2000 trae su propio espacio de color y el PDF puede omitirlo.
+        None if is_name(dict, b"Filter", "JPXDecode") => ColorSpace::Rgb,
+        None if stencil => ColorSpace::Gray,
+        None => {
+            log::debug!("Imagen con espacio de color no soportado: {}", color_space_name(doc, dict).unwrap_or_default());
+            return Ok(None);
+        }
+    };
 
-    match filter {
-        "FlateDecode" => {
-            let mut decoder = ZlibDecoder::new(&stream.content[..]);
-            let mut data = Vec::new();
-            decoder
-                .read_to_end(&mut data)
-                .context("Error descomprimiendo FlateDecode")?;
-
-            let components: u32 = 3;
-            let expected_raw = (w * h * components) as usize;
-            let expected_png = ((w * components + 1) * h) as usize;
-
-            let data = if data.len() == expected_raw {
-                data
-            } else if data.len() == expected_png {
-                remove_png_predictor(&data, w, components)
-            } else {
-                data
-            };
+    let width = get_uint(dict, b"Width")?;
+    let height = get_uint(dict, b"Height")?;
+    log::trace!(
+        "Imagen {}x{}, filtros {:?}",
+        width,
+        height,
+        filter_chain(dict).iter().map(|(f, _)| *f).collect::<Vec<_>>()
+    );
+    let image = decode_stream(stream, width, height, color_space).map_err(invalid_image)?;
 
-            if data.len() != expected_raw {
-                return Err(anyhow!(
-                    "Tamaño inesperado: {} bytes (esperados {})",
-                    data.len(),
-                    expected_raw
-                ));
+    let mut pixels = image.to_rgba8();
+    if stencil {
+        // Las muestras a 0 se pintan; con /Decode [1 0] ya vienen invertidas.
+        for px in pixels.pixels_mut() {
+            let painted = px.0[0] < 128;
+            px.0 = [0, 0, 0, if painted { 255 } else { 0 }];
+        }
+    } else if let Ok(mask) = dict.get(b"SMask") {
+        apply_soft_mask(doc, &mut pixels, mask)
+            .context("Error en SMask")
+            .map_err(invalid_image)?;
+    }
+    Ok(Some(DecodedImage { pixels, stencil }))
+}
+
+/// Los errores al decodificar una imagen que no tengan ya un motivo concreto
+/// pasan a ser [`WatermarkError::InvalidImage`].
+fn invalid_image(err: anyhow::Error) -> anyhow::Error {
+    if err.is::<WatermarkError>() {
+        return err;
+    }
+    WatermarkError::InvalidImage(format!("{:#}", err)).into()
+}
+
+/// Nombre del espacio de color de la primera imagen de la página que no se
+/// sabe convertir, para explicar por qué no se pudo extraer.
+fn unsupported_color_space(doc: &Document, page_id: lopdf::ObjectId) -> Option<String> {
+    image_xobjects(doc, page_id).into_iter().find_map(|(_, stream)| {
+        let dict = &stream.dict;
+        if ColorSpace::from_dict(doc, dict).is_some() {
+            return None;
+        }
+        color_space_name(doc, dict)
+    })
+}
+
+/// Nombre del espacio de color de una imagen (ej. "DeviceRGB", "ICCBased").
+fn color_space_name(doc: &Document, dict: &lopdf::Dictionary) -> Option<String> {
+    let name = match resolve(doc, dict.get(b"ColorSpace").ok()?).ok()? {
+        Object::Name(name) => name,
+        Object::Array(array) => array.first()?.as_name().ok()?.to_vec(),
+        _ => return None,
+    };
+    Some(String::from_utf8_lossy(&name).into_owned())
+}
+
+/// Compone la imagen sobre blanco.
+pub(crate) fn flatten(image: &RgbaImage) -> RgbImage {
+    RgbImage::from_fn(image.width(), image.height(), |x, y| {
+        let [r, g, b, a] = image.get_pixel(x, y).0;
+        let a = a as u32;
+        Rgb([r, g, b].map(|c| ((c as u32 * a + 255 * (255 - a)) / 255) as u8))
+    })
+}
+
+/// Usa la máscara suave como canal alfa de la imagen.
+fn apply_soft_mask(doc: &Document, image: &mut RgbaImage, mask: &Object) -> Result<()> {
+    let Object::Stream(mask) = resolve(doc, mask)? else {
+        return Err(anyhow!("SMask no es un stream"));
+    };
+    let mw = get_uint(&mask.dict, b"Width")?;
+    let mh = get_uint(&mask.dict, b"Height")?;
+    let mut alpha = decode_stream(&mask, mw, mh, ColorSpace::Gray)?.to_luma8();
+
+    if alpha.dimensions() != image.dimensions() {
+        alpha = image::imageops::resize(&alpha, image.width(), image.height(), image::imageops::FilterType::Triangle);
+    }
+    for (px, a) in image.pixels_mut().zip(alpha.pixels()) {
+        px.0[3] = a.0[0];
+    }
+    Ok(())
+}
+
+#[derive(Clone)]
+enum ColorSpace {
+    Gray,
+    Rgb,
+    Cmyk,
+    /// Paleta de colores en el espacio `base`, un byte por componente y entrada.
+    Indexed { base: Box<ColorSpace>, palette: Vec<u8> },
+}
+
+impl ColorSpace {
+    fn from_dict(doc: &Document, dict: &lopdf::Dictionary) -> Option<Self> {
+        Self::from_object(doc, dict.get(b"ColorSpace").ok()?)
+    }
+
+    fn from_object(doc: &Document, obj: &Object) -> Option<Self> {
+        match resolve(doc, obj).ok()? {
+            Object::Name(name) => match name.as_slice() {
+                b"DeviceGray" => Some(Self::Gray),
+                b"DeviceRGB" => Some(Self::Rgb),
+                b"DeviceCMYK" => Some(Self::Cmyk),
+                _ => None,
+            },
+            // [/ICCBased perfil]: se decodifica según el número de componentes
+            // del perfil, sin aplicarlo.
+            Object::Array(array) if array.first()?.as_name().ok()? == b"ICCBased" => {
+                let profile = resolve_to_dict(doc, array.get(1)?).ok()?;
+                match profile.get(b"N").and_then(Object::as_i64) {
+                    Ok(1) => Some(Self::Gray),
+                    Ok(3) => Some(Self::Rgb),
+                    Ok(4) => Some(Self::Cmyk),
+                    _ => Self::from_object(doc, profile.get(b"Alternate").ok()?),
+                }
+            }
+            // [/Indexed base hival lookup]
+            Object::Array(array) if array.first()?.as_name().ok()? == b"Indexed" => {
+                let base = Self::from_object(doc, array.get(1)?)?;
+                if matches!(base, Self::Indexed { .. }) {
+                    return None;
+                }
+                let palette = match resolve(doc, array.get(3)?).ok()? {
+                    Object::String(bytes, _) => bytes,
+                    Object::Stream(stream) => stream
+                        .decompressed_content()
+                        .unwrap_or(stream.content),
+                    _ => return None,
+                };
+                Some(Self::Indexed {
+                    base: Box::new(base),
+                    palette,
+                })
             }
-            let rgb = RgbImage::from_raw(w, h, data)
-                .ok_or_else(|| anyhow!("Datos de imagen inválidos"))?;
-            Ok(DynamicImage::ImageRgb8(rgb))
+            _ => None,
         }
-        "DCTDecode" => {
-            let cursor = Cursor::new(&stream.content);
-            let img = image::load(cursor, image::ImageFormat::Jpeg)?;
-            Ok(img)
+    }
+
+    fn components(&self) -> u32 {
+        match self {
+            Self::Gray | Self::Indexed { .. } => 1,
+            Self::Rgb => 3,
+            Self::Cmyk => 4,
+        }
+    }
+
+    /// Convierte muestras de 8 bits en este espacio de color a RGB.
+    fn to_rgb(&self, data: Vec<u8>, w: u32, h: u32) -> Result<DynamicImage> {
+        let image = match self {
+            Self::Indexed { base, palette } => {
+                let n = base.components() as usize;
+                let expanded = data
+                    .iter()
+                    .flat_map(|&i| {
+                        let start = i as usize * n;
+                        let entry = palette.get(start..start + n).unwrap_or(&[0; 4][..n]);
+                        entry.iter().copied()
+                    })
+                    .collect();
+                return base.to_rgb(expanded, w, h);
+            }
+            Self::Gray => GrayImage::from_raw(w, h, data)
+                .map(|gray| DynamicImage::ImageLuma8(gray).to_rgb8()),
+            Self::Rgb => RgbImage::from_raw(w, h, data),
+            Self::Cmyk => RgbImage::from_raw(w, h, data.chunks_exact(4).flat_map(cmyk_to_rgb).collect()),
+        };
+        image
+            .map(DynamicImage::ImageRgb8)
+            .ok_or_else(|| anyhow!("Datos de imagen inválidos"))
+    }
+}
+
+/// Conversión CMYK→RGB sin perfil de color (la que aplican los visores
+/// cuando el PDF no incluye un perfil de salida).
+fn cmyk_to_rgb(px: &[u8]) -> [u8; 3] {
+    let k = 255 - px[3] as u32;
+    [0, 1, 2].map(|i| ((255 - px[i] as u32) * k / 255) as u8)
+}
+
+fn decode_stream(stream: &lopdf::Stream, w: u32, h: u32, color_space: ColorSpace) -> Result<DynamicImage> {
+    let bpc = bits_per_component(&stream.dict);
+    if ![1, 2, 4, 8, 16].contains(&bpc) {
+        return Err(anyhow!("BitsPerComponent no soportado: {}", bpc));
+    }
+    let components = color_space.components();
+    let stride = ((w * components * bpc).div_ceil(8)) as usize;
+    let decode = decode_tables(&stream.dict, &color_space, bpc);
+
+    let chain = filter_chain(&stream.dict);
+    let mut data = stream.content.clone();
+    for (i, &(filter, parms)) in chain.iter().enumerate() {
+        let last = i + 1 == chain.len();
+        data = match filter {
+            "FlateDecode" => {
+                let mut decoder = ZlibDecoder::new(&data[..]);
+                let mut out = Vec::new();
+                decoder
+                    .read_to_end(&mut out)
+                    .context("Error descomprimiendo FlateDecode")?;
+                unpredict(out, parms)?
+            }
+            "LZWDecode" => unpredict(lzw_decode(&data, early_change(parms))?, parms)?,
+            "RunLengthDecode" => run_length_decode(&data),
+            "ASCII85Decode" => ascii85_decode(&data)?,
+            "ASCIIHexDecode" => ascii_hex_decode(&data)?,
+            "CCITTFaxDecode" => ccitt::decode(&data, &ccitt_params(parms, w, h))?,
+            "DCTDecode" if last => {
+                let img = image::load(Cursor::new(&data), image::ImageFormat::Jpeg)?;
+                return Ok(DynamicImage::ImageRgb8(decode_rgb(img.to_rgb8(), decode.as_deref(), &color_space)));
+            }
+            "JPXDecode" if last => {
+                let img = decode_jpx(&data)?.to_rgb8();
+                return Ok(DynamicImage::ImageRgb8(decode_rgb(img, decode.as_deref(), &color_space)));
+            }
+            other => return Err(WatermarkError::UnsupportedFilter(other.to_string()).into()),
+        };
+    }
+
+    let expected = stride * h as usize;
+    if data.len() < expected {
+        return Err(anyhow!(
+            "Tamaño inesperado: {} bytes (esperados {})",
+            data.len(),
+            expected
+        ));
+    }
+    data.truncate(expected);
+    let mut samples = unpack_samples(data, w, h, &color_space, bpc);
+    if let Some(tables) = &decode {
+        for (i, sample) in samples.iter_mut().enumerate() {
+            *sample = tables[i % tables.len()][*sample as usize];
         }
-        "" => {
-            let rgb = RgbImage::from_raw(w, h, stream.content.clone())
-                .ok_or_else(|| anyhow!("Datos de imagen inválidos (sin filtro)"))?;
-            Ok(DynamicImage::ImageRgb8(rgb))
+    }
+    color_space.to_rgb(samples, w, h)
+}
+
+/// Tablas por componente que aplican el array `/Decode` a las muestras de un
+/// byte de [`unpack_samples`]: cada una pasa de [0, máximo] a [Dmin, Dmax].
+/// En las paletas el máximo es el último índice con `bpc` bits; en el resto
+/// de espacios de color el rango es [0, 1]. `None` si no hay `/Decode`, no
+/// tiene un par por componente o no cambia nada.
+fn decode_tables(dict: &lopdf::Dictionary, color_space: &ColorSpace, bpc: u32) -> Option<Vec<[u8; 256]>> {
+    let decode: Vec<f64> = dict
+        .get(b"Decode")
+        .and_then(Object::as_array)
+        .ok()?
+        .iter()
+        .map(|v| v.as_float().ok().map(f64::from))
+        .collect::<Option<_>>()?;
+    if decode.len() != 2 * color_space.components() as usize {
+        return None;
+    }
+    let indexed = matches!(color_space, ColorSpace::Indexed { .. });
+    let max = if indexed { ((1u32 << bpc.min(8)) - 1) as f64 } else { 1.0 };
+    if decode.chunks(2).all(|pair| pair == [0.0, max]) {
+        return None;
+    }
+    let tables = decode
+        .chunks(2)
+        .map(|pair| {
+            std::array::from_fn(|sample| {
+                let value = if indexed { sample as f64 / max } else { sample as f64 / 255.0 };
+                let mapped = (pair[0] + value * (pair[1] - pair[0])).clamp(0.0, max);
+                if indexed { mapped.round() as u8 } else { (mapped * 255.0).round() as u8 }
+            })
+        })
+        .collect();
+    Some(tables)
+}
+
+/// Aplica [`decode_tables`] a una imagen ya convertida a RGB por el filtro
+/// (JPEG o JPEG 2000), solo si sus componentes son los del espacio de color:
+/// gris o RGB.
+fn decode_rgb(mut image: RgbImage, tables: Option<&[[u8; 256]]>, color_space: &ColorSpace) -> RgbImage {
+    let tables: [&[u8; 256]; 3] = match (tables, color_space) {
+        (Some([gray]), ColorSpace::Gray) => [gray; 3],
+        (Some([r, g, b]), ColorSpace::Rgb) => [r, g, b],
+        _ => return image,
+    };
+    for px in image.pixels_mut() {
+        px.0 = [0, 1, 2].map(|c| tables[c][px.0[c] as usize]);
+    }
+    image
+}
+
+#[cfg(feature = "jpeg2000")]
+fn decode_jpx(data: &[u8]) -> Result<DynamicImage> {
+    let settings = hayro_jpeg2000::DecodeSettings::default();
+    let image = hayro_jpeg2000::Image::new(data, &settings)
+        .map_err(|e| anyhow!("Error decodificando JPXDecode: {:?}", e))?;
+    let img = DynamicImage::from_decoder(image)?;
+    Ok(DynamicImage::ImageRgb8(img.to_rgb8()))
+}
+
+#[cfg(not(feature = "jpeg2000"))]
+fn decode_jpx(_data: &[u8]) -> Result<DynamicImage> {
+    Err(WatermarkError::UnsupportedFilter("JPXDecode".to_string()).into())
+}
+
+/// Descomprime LZW (bits más significativos primero, como TIFF).
+fn lzw_decode(data: &[u8], early_change: bool) -> Result<Vec<u8>> {
+    let mut decoder = if early_change {
+        weezl::decode::Decoder::with_tiff_size_switch(weezl::BitOrder::Msb, 8)
+    } else {
+        weezl::decode::Decoder::new(weezl::BitOrder::Msb, 8)
+    };
+    let mut out = Vec::new();
+    decoder
+        .into_vec(&mut out)
+        .decode(data)
+        .status
+        .map_err(|e| anyhow!("Error descomprimiendo LZWDecode: {}", e))?;
+    Ok(out)
+}
+
+/// Filtros de `/Filter` (nombre o array) con su entrada de `/DecodeParms`.
+fn filter_chain(dict: &lopdf::Dictionary) -> Vec<(&str, Option<&lopdf::Dictionary>)> {
+    let filters = match dict.get(b"Filter") {
+        Ok(Object::Array(filters)) => filters.iter().collect(),
+        Ok(filter) => vec![filter],
+        Err(_) => Vec::new(),
+    };
+    let parms = match dict.get(b"DecodeParms") {
+        Ok(Object::Array(parms)) => parms.iter().map(|p| p.as_dict().ok()).collect(),
+        Ok(parms) => vec![parms.as_dict().ok()],
+        Err(_) => Vec::new(),
+    };
+    filters
+        .into_iter()
+        .enumerate()
+        .map(|(i, f)| (f.as_name().ok().and_then(|n| std::str::from_utf8(n).ok()).unwrap_or(""), parms.get(i).copied().flatten()))
+        .collect()
+}
+
+fn ascii85_decode(data: &[u8]) -> Result<Vec<u8>> {
+    let data = data.strip_prefix(b"<~").unwrap_or(data);
+    let mut out = Vec::with_capacity(data.len() * 4 / 5);
+    let mut group = [0u8; 5];
+    let mut n = 0;
+    for &c in data {
+        match c {
+            b'~' => break,
+            b'z' if n == 0 => out.extend_from_slice(&[0; 4]),
+            b'!'..=b'u' => {
+                group[n] = c - b'!';
+                n += 1;
+                if n == 5 {
+                    out.extend_from_slice(&base85_value(&group).to_be_bytes());
+                    n = 0;
+                }
+            }
+            c if c.is_ascii_whitespace() => {}
+            other => return Err(anyhow!("Carácter inválido en ASCII85Decode: {:?}", other as char)),
         }
-        other => Err(anyhow!("Filtro no soportado: {}", other)),
     }
+    if n > 0 {
+        // Grupo final incompleto: se rellena con 'u' y se descartan los bytes sobrantes.
+        group[n..].fill(b'u' - b'!');
+        out.extend_from_slice(&base85_value(&group).to_be_bytes()[..n - 1]);
+    }
+    Ok(out)
 }
 
-fn resolve(doc: &Document, obj: &Object) -> Result<Object> {
+fn base85_value(group: &[u8; 5]) -> u32 {
+    group
+        .iter()
+        .fold(0u32, |acc, &d| acc.wrapping_mul(85).wrapping_add(d as u32))
+}
+
+fn ascii_hex_decode(data: &[u8]) -> Result<Vec<u8>> {
+    let mut digits = Vec::with_capacity(data.len());
+    for &c in data {
+        match c {
+            b'>' => break,
+            c if c.is_ascii_hexdigit() => digits.push((c as char).to_digit(16).unwrap_or(0) as u8),
+            c if c.is_ascii_whitespace() => {}
+            other => return Err(anyhow!("Carácter inválido en ASCIIHexDecode: {:?}", other as char)),
+        }
+    }
+    Ok(digits
+        .chunks(2)
+        .map(|pair| pair[0] << 4 | pair.get(1).copied().unwrap_or(0))
+        .collect())
+}
+
+fn ccitt_params(parms: Option<&lopdf::Dictionary>, w: u32, h: u32) -> ccitt::Params {
+    let flag = |key: &[u8]| {
+        parms
+            .and_then(|p| p.get(key).and_then(Object::as_bool).ok())
+            .unwrap_or(false)
+    };
+    ccitt::Params {
+        k: parm_int(parms, b"K", 0),
+        columns: parm_int(parms, b"Columns", w as i64) as u32,
+        rows: h,
+        black_is_1: flag(b"BlackIs1"),
+        byte_align: flag(b"EncodedByteAlign"),
+    }
+}
+
+/// Bytes de longitud n: 0-127 copian n+1 bytes literales, 129-255 repiten el
+/// siguiente byte 257-n veces y 128 marca el final.
+fn run_length_decode(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while let Some(&len) = data.get(i) {
+        match len {
+            0..=127 => {
+                let end = (i + 2 + len as usize).min(data.len());
+                out.extend_from_slice(&data[i + 1..end]);
+                i = end;
+            }
+            128 => break,
+            _ => {
+                if let Some(&byte) = data.get(i + 1) {
+                    out.extend(std::iter::repeat_n(byte, 257 - len as usize));
+                }
+                i += 2;
+            }
+        }
+    }
+    out
+}
+
+/// `EarlyChange` de `DecodeParms` (1 por defecto).
+fn early_change(parms: Option<&lopdf::Dictionary>) -> bool {
+    parm_int(parms, b"EarlyChange", 1) != 0
+}
+
+fn parm_int(parms: Option<&lopdf::Dictionary>, key: &[u8], default: i64) -> i64 {
+    parms
+        .and_then(|p| p.get(key).and_then(Object::as_i64).ok())
+        .unwrap_or(default)
+}
+
+/// Deshace el `/Predictor` de `DecodeParms` (2 = TIFF, ≥10 = PNG).
+fn unpredict(data: Vec<u8>, parms: Option<&lopdf::Dictionary>) -> Result<Vec<u8>> {
+    let predictor = parm_int(parms, b"Predictor", 1);
+    if predictor == 1 {
+        return Ok(data);
+    }
+    let colors = parm_int(parms, b"Colors", 1).max(1) as usize;
+    let bpc = parm_int(parms, b"BitsPerComponent", 8) as usize;
+    let columns = parm_int(parms, b"Columns", 1).max(1) as usize;
+    if ![1, 2, 4, 8, 16].contains(&bpc) {
+        return Err(anyhow!("BitsPerComponent no soportado en el predictor: {}", bpc));
+    }
+    let stride = (colors * bpc * columns).div_ceil(8);
+
+    match predictor {
+        2 => Ok(remove_tiff_predictor(data, stride, colors, bpc)),
+        10..=15 => Ok(remove_png_predictor(&data, stride, (colors * bpc).div_ceil(8))),
+        other => Err(anyhow!("Predictor no soportado: {}", other)),
+    }
+}
+
+/// Predictor TIFF 2: cada muestra se guarda como diferencia con la misma
+/// componente del píxel anterior.
+fn remove_tiff_predictor(mut data: Vec<u8>, stride: usize, colors: usize, bpc: usize) -> Vec<u8> {
+    let samples = stride * 8 / bpc;
+    let mask = if bpc == 16 { 0xFFFF } else { (1u32 << bpc) - 1 };
+    for row in data.chunks_mut(stride) {
+        let count = samples.min(row.len() * 8 / bpc);
+        for i in colors..count {
+            let value = (get_sample(row, i, bpc) + get_sample(row, i - colors, bpc)) & mask;
+            set_sample(row, i, bpc, value);
+        }
+    }
+    data
+}
+
+fn get_sample(row: &[u8], i: usize, bpc: usize) -> u32 {
+    match bpc {
+        16 => u16::from_be_bytes([row[i * 2], row[i * 2 + 1]]) as u32,
+        8 => row[i] as u32,
+        _ => {
+            let bit = i * bpc;
+            (row[bit / 8] as u32 >> (8 - bpc - bit % 8)) & ((1 << bpc) - 1)
+        }
+    }
+}
+
+fn set_sample(row: &mut [u8], i: usize, bpc: usize, value: u32) {
+    match bpc {
+        16 => row[i * 2..i * 2 + 2].copy_from_slice(&(value as u16).to_be_bytes()),
+        8 => row[i] = value as u8,
+        _ => {
+            let bit = i * bpc;
+            let shift = 8 - bpc - bit % 8;
+            let mask = (((1u32 << bpc) - 1) << shift) as u8;
+            row[bit / 8] = (row[bit / 8] & !mask) | ((value << shift) as u8 & mask);
+        }
+    }
+}
+
+/// Expande muestras de 1, 2 o 4 bits a un byte por muestra y reduce las de
+/// 16 bits a 8. Los índices de paleta se conservan; el resto se escala a
+/// 0-255.
+fn unpack_samples(data: Vec<u8>, w: u32, h: u32, color_space: &ColorSpace, bpc: u32) -> Vec<u8> {
+    if bpc == 8 {
+        return data;
+    }
+    if bpc == 16 {
+        // Muestras big-endian sin relleno al final de las filas.
+        return data
+            .chunks_exact(2)
+            .map(|s| ((u16::from_be_bytes([s[0], s[1]]) as u32 * 255 + 32767) / 65535) as u8)
+            .collect();
+    }
+    let per_row = (w * color_space.components()) as usize;
+    let stride = (per_row * bpc as usize).div_ceil(8);
+    let max = (1u32 << bpc) - 1;
+    let indexed = matches!(color_space, ColorSpace::Indexed { .. });
+
+    let mut out = Vec::with_capacity(per_row * h as usize);
+    for row in data.chunks(stride).take(h as usize) {
+        for i in 0..per_row {
+            let bit = i * bpc as usize;
+            let byte = row.get(bit / 8).copied().unwrap_or(0) as u32;
+            let value = (byte >> (8 - bpc as usize - bit % 8)) & max;
+            out.push(if indexed { value } else { value * 255 / max } as u8);
+        }
+    }
+    out
+}
+
+pub(crate) fn resolve(doc: &Document, obj: &Object) -> Result<Object> {
     match obj {
         Object::Reference(id) => doc
             .get_object(*id)
-            .map(|o| o.clone())
+            .cloned()
             .map_err(|e| anyhow!("Referencia {:?} no encontrada: {}", id, e)),
         other => Ok(other.clone()),
     }
 }
 
-fn resolve_to_dict(doc: &Document, obj: &Object) -> Result<lopdf::Dictionary> {
+pub(crate) fn resolve_to_dict(doc: &Document, obj: &Object) -> Result<lopdf::Dictionary> {
     let resolved = resolve(doc, obj)?;
     match resolved {
         Object::Dictionary(d) => Ok(d),
@@ -131,14 +1300,91 @@ fn resolve_to_dict(doc: &Document, obj: &Object) -> Result<lopdf::Dictionary> {
     }
 }
 
+/// Busca `key` en la página o, si no está, en sus `Pages` ancestros
+/// (atributos heredables como `Resources` o `MediaBox`).
+pub(crate) fn inherited_attr(doc: &Document, page_id: lopdf::ObjectId, key: &[u8]) -> Option<Object> {
+    let mut node = doc.get_dictionary(page_id).ok();
+    while let Some(dict) = node {
+        if let Ok(value) = dict.get(key) {
+            return resolve(doc, value).ok();
+        }
+        node = dict
+            .get(b"Parent")
+            .and_then(Object::as_reference)
+            .and_then(|id| doc.get_dictionary(id))
+            .ok();
+    }
+    None
+}
+
+/// `MediaBox` de la página como `[llx, lly, urx, ury]`.
+pub(crate) fn media_box(doc: &Document, page_id: lopdf::ObjectId) -> Result<[f64; 4]> {
+    let media_box = inherited_attr(doc, page_id, b"MediaBox")
+        .ok_or_else(|| anyhow!("Página sin MediaBox"))?;
+    page_box(doc, &media_box).ok_or_else(|| anyhow!("MediaBox inválido"))
+}
+
+/// Zona visible de la página: el `CropBox` recortado al `MediaBox`, o el
+/// `MediaBox` si no hay `CropBox` o no es válido. Es la que se extrae y
+/// sobre la que se colocan los watermarks.
+pub(crate) fn crop_box(doc: &Document, page_id: lopdf::ObjectId) -> Result<[f64; 4]> {
+    let [mx0, my0, mx1, my1] = media_box(doc, page_id)?;
+    let crop = inherited_attr(doc, page_id, b"CropBox").and_then(|crop| page_box(doc, &crop));
+    match crop.map(|[x0, y0, x1, y1]| [x0.max(mx0), y0.max(my0), x1.min(mx1), y1.min(my1)]) {
+        Some([x0, y0, x1, y1]) if x0 < x1 && y0 < y1 => Ok([x0, y0, x1, y1]),
+        _ => Ok([mx0, my0, mx1, my1]),
+    }
+}
+
+/// Rectángulo `[llx, lly, urx, ury]` normalizado de un array de 4 números.
+fn page_box(doc: &Document, rect: &Object) -> Option<[f64; 4]> {
+    let values = rect
+        .as_array()
+        .ok()?
+        .iter()
+        .map(|v| resolve(doc, v).ok()?.as_float().ok().map(f64::from))
+        .collect::<Option<Vec<_>>>()?;
+    match values[..] {
+        [x0, y0, x1, y1] => Some([x0.min(x1), y0.min(y1), x0.max(x1), y0.max(y1)]),
+        _ => None,
+    }
+}
+
+/// Ancho y alto de la zona visible en puntos, intercambiados si la página
+/// está girada.
+fn displayed_size(doc: &Document, page_id: lopdf::ObjectId) -> Option<(f64, f64)> {
+    let [llx, lly, urx, ury] = crop_box(doc, page_id).ok()?;
+    if rotation(doc, page_id).is_multiple_of(180) {
+        Some((urx - llx, ury - lly))
+    } else {
+        Some((ury - lly, urx - llx))
+    }
+}
+
+/// Giro de la página en grados en sentido horario (0, 90, 180 o 270).
+pub(crate) fn rotation(doc: &Document, page_id: lopdf::ObjectId) -> u32 {
+    let degrees = inherited_attr(doc, page_id, b"Rotate")
+        .and_then(|r| r.as_i64().ok())
+        .unwrap_or(0)
+        .rem_euclid(360) as u32;
+    if degrees.is_multiple_of(90) { degrees } else { 0 }
+}
+
 fn is_name(dict: &lopdf::Dictionary, key: &[u8], expected: &str) -> bool {
     dict.get(key)
         .ok()
-        .and_then(|v| v.as_name_str().ok())
-        .map(|s| s == expected)
+        .and_then(|v| v.as_name().ok())
+        .map(|n| n == expected.as_bytes())
         .unwrap_or(false)
 }
 
+/// `/BitsPerComponent` de una imagen: es opcional en las máscaras de imagen
+/// (`ImageMask`), que siempre tienen 1 bit, y se supone 8 en el resto.
+fn bits_per_component(dict: &lopdf::Dictionary) -> u32 {
+    let stencil = dict.get(b"ImageMask").and_then(Object::as_bool).unwrap_or(false);
+    get_uint(dict, b"BitsPerComponent").unwrap_or(if stencil { 1 } else { 8 })
+}
+
 fn get_uint(dict: &lopdf::Dictionary, key: &[u8]) -> Result<u32> {
     let val = dict.get(key)?;
     val.as_i64()
@@ -146,11 +1392,9 @@ fn get_uint(dict: &lopdf::Dictionary, key: &[u8]) -> Result<u32> {
         .map_err(|_| anyhow!("Se esperaba entero para {:?}", std::str::from_utf8(key)))
 }
 
-fn remove_png_predictor(data: &[u8], width: u32, components: u32) -> Vec<u8> {
-    let stride = (width * components) as usize;
+fn remove_png_predictor(data: &[u8], stride: usize, comp: usize) -> Vec<u8> {
     let row_len = stride + 1;
     let rows = data.len() / row_len;
-    let comp = components as usize;
 
     let mut result = Vec::with_capacity(stride * rows);
     let mut prev_row = vec![0u8; stride];
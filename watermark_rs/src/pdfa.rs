@@ -0,0 +1,220 @@
+use crate::builder::ensure_id;
+use crate::metadata;
+use anyhow::Result;
+use lopdf::{dictionary, Dictionary, Document, Object, Stream};
+
+/// Subtipos de anotación admitidos por PDF/A-2.
+const ANNOTATIONS: &[&[u8]] = &[
+    b"Text", b"Link", b"FreeText", b"Line", b"Square", b"Circle", b"Polygon", b"PolyLine",
+    b"Highlight", b"Underline", b"Squiggly", b"StrikeOut", b"Stamp", b"Caret", b"Ink",
+    b"Popup", b"Watermark", b"Redact",
+];
+
+/// Acciones admitidas por PDF/A-2 (sin JavaScript, Launch, multimedia...).
+const ACTIONS: &[&[u8]] = &[b"GoTo", b"GoToR", b"URI", b"Named", b"Thread", b"SubmitForm"];
+
+/// Banderas `/F` de anotación: Invisible, Hidden, Print, NoZoom, NoRotate,
+/// NoView y ToggleNoView.
+const INVISIBLE: i64 = 1;
+const HIDDEN: i64 = 2;
+const PRINT: i64 = 4;
+const NO_ZOOM: i64 = 8;
+const NO_ROTATE: i64 = 16;
+const NO_VIEW: i64 = 32;
+const TOGGLE_NO_VIEW: i64 = 256;
+
+const SRGB: &str = "sRGB IEC61966-2.1";
+
+/// Convierte el documento reconstruido en PDF/A-2b: añade el perfil sRGB
+/// como OutputIntent, un XMP con la identificación PDF/A coherente con el
+/// diccionario Info y quita acciones y anotaciones no permitidas.
+pub(crate) fn convert(doc: &mut Document) -> Result<()> {
+    ensure_id(doc)?;
+    let catalog_id = doc.trailer.get(b"Root")?.as_reference()?;
+
+    let profile = Stream::new(dictionary! { "N" => 3 }, srgb_profile());
+    let profile_id = doc.add_object(profile);
+    let intent = dictionary! {
+        "Type" => "OutputIntent",
+        "S" => "GTS_PDFA1",
+        "OutputConditionIdentifier" => Object::string_literal(SRGB),
+        "Info" => Object::string_literal(SRGB),
+        "DestOutputProfile" => Object::Reference(profile_id),
+    };
+
+    metadata::write_xmp(doc, true, None)?;
+    doc.get_dictionary_mut(catalog_id)?.set("OutputIntents", vec![Object::Dictionary(intent)]);
+
+    remove_actions(doc);
+    clean_annotations(doc)?;
+    Ok(())
+}
+
+/// Quita las acciones adicionales (`/AA`) y las acciones `/A` de tipos no
+/// permitidos de todos los objetos del documento.
+fn remove_actions(doc: &mut Document) {
+    let forbidden: Vec<_> = doc
+        .objects
+        .iter()
+        .filter_map(|(id, obj)| {
+            let action = obj.as_dict().ok()?.get(b"A").ok()?;
+            let action = match action {
+                Object::Reference(r) => doc.get_dictionary(*r).ok()?,
+                action => action.as_dict().ok()?,
+            };
+            let kind = action.get(b"S").and_then(Object::as_name).ok()?;
+            (!ACTIONS.contains(&kind)).then_some(*id)
+        })
+        .collect();
+
+    for (id, obj) in doc.objects.iter_mut() {
+        let dict = match obj {
+            Object::Dictionary(dict) => dict,
+            Object::Stream(stream) => &mut stream.dict,
+            _ => continue,
+        };
+        dict.remove(b"AA");
+        if forbidden.contains(id) {
+            dict.remove(b"A");
+        }
+    }
+}
+
+/// Deja solo anotaciones admitidas, imprimibles y visibles.
+fn clean_annotations(doc: &mut Document) -> Result<()> {
+    for page_id in doc.get_pages().into_values() {
+        let Ok(Object::Array(annots)) = doc.get_dictionary(page_id)?.get(b"Annots").cloned() else {
+            continue;
+        };
+        let mut kept = Vec::new();
+        for annot in annots {
+            match annot {
+                Object::Reference(id) => {
+                    let Some(dict) = doc.get_dictionary(id).ok().and_then(|d| clean_annotation(doc, d.clone())) else {
+                        continue;
+                    };
+                    doc.objects.insert(id, Object::Dictionary(dict));
+                    kept.push(annot);
+                }
+                Object::Dictionary(dict) => kept.extend(clean_annotation(doc, dict).map(Object::Dictionary)),
+                _ => {}
+            }
+        }
+        doc.get_dictionary_mut(page_id)?.set("Annots", kept);
+    }
+    Ok(())
+}
+
+/// Ajusta las banderas de una anotación, o `None` si PDF/A no la admite. Las
+/// que no son enlaces ni popups necesitan una apariencia normal (`/AP /N`)
+/// en un stream.
+fn clean_annotation(doc: &Document, mut annot: Dictionary) -> Option<Dictionary> {
+    let subtype = annot.get(b"Subtype").and_then(Object::as_name).ok()?.to_vec();
+    if !ANNOTATIONS.contains(&subtype.as_slice()) {
+        return None;
+    }
+    let normal = annot
+        .get(b"AP")
+        .ok()
+        .and_then(|ap| match ap {
+            Object::Reference(r) => doc.get_dictionary(*r).ok(),
+            ap => ap.as_dict().ok(),
+        })
+        .and_then(|ap| ap.get(b"N").ok())
+        .filter(|n| matches!(n, Object::Reference(r) if matches!(doc.get_object(*r), Ok(Object::Stream(_)))))
+        .cloned();
+    match normal {
+        Some(normal) => annot.set("AP", dictionary! { "N" => normal }),
+        None if subtype == b"Link" || subtype == b"Popup" => {
+            annot.remove(b"AP");
+        }
+        None => return None,
+    }
+
+    if subtype != b"Popup" {
+        let flags = annot.get(b"F").and_then(Object::as_i64).unwrap_or(0);
+        let mut flags = (flags | PRINT) & !(INVISIBLE | HIDDEN | NO_VIEW | TOGGLE_NO_VIEW);
+        if subtype == b"Text" {
+            flags |= NO_ZOOM | NO_ROTATE;
+        }
+        annot.set("F", flags);
+    }
+    annot.remove(b"AA");
+    if let Ok(Object::Dictionary(action)) = annot.get(b"A") {
+        if !action.get(b"S").and_then(Object::as_name).is_ok_and(|s| ACTIONS.contains(&s)) {
+            annot.remove(b"A");
+        }
+    }
+    Some(annot)
+}
+
+/// Perfil ICC v2 de sRGB (primarios adaptados a D50 y curva sRGB tabulada),
+/// generado al vuelo para no depender de un archivo externo.
+fn srgb_profile() -> Vec<u8> {
+    fn s15(v: f64) -> [u8; 4] {
+        ((v * 65536.0).round() as i32).to_be_bytes()
+    }
+    fn xyz(x: f64, y: f64, z: f64) -> Vec<u8> {
+        [b"XYZ ".as_slice(), &[0; 4], &s15(x), &s15(y), &s15(z)].concat()
+    }
+    fn text_description(text: &str) -> Vec<u8> {
+        let mut tag = [b"desc".as_slice(), &[0; 4]].concat();
+        tag.extend(((text.len() + 1) as u32).to_be_bytes());
+        tag.extend(text.as_bytes());
+        tag.push(0);
+        // Sin descripción Unicode ni ScriptCode.
+        tag.extend([0; 4 + 4 + 2 + 1 + 67]);
+        tag
+    }
+
+    let curve: Vec<u8> = {
+        const POINTS: u32 = 1024;
+        let mut tag = [b"curv".as_slice(), &[0; 4]].concat();
+        tag.extend(POINTS.to_be_bytes());
+        for i in 0..POINTS {
+            let v = i as f64 / (POINTS - 1) as f64;
+            let linear = if v <= 0.04045 { v / 12.92 } else { ((v + 0.055) / 1.055).powf(2.4) };
+            tag.extend(((linear * 65535.0).round() as u16).to_be_bytes());
+        }
+        tag
+    };
+    let tags: Vec<(&[u8; 4], Vec<u8>)> = vec![
+        (b"desc", text_description(SRGB)),
+        (b"cprt", [b"text".as_slice(), &[0; 4], b"No copyright, use freely\0"].concat()),
+        (b"wtpt", xyz(0.9642, 1.0, 0.8249)),
+        (b"rXYZ", xyz(0.4361, 0.2225, 0.0139)),
+        (b"gXYZ", xyz(0.3851, 0.7169, 0.0971)),
+        (b"bXYZ", xyz(0.1431, 0.0606, 0.7141)),
+        (b"rTRC", curve.clone()),
+        (b"gTRC", curve.clone()),
+        (b"bTRC", curve),
+    ];
+
+    let mut table = (tags.len() as u32).to_be_bytes().to_vec();
+    let mut data = Vec::new();
+    let mut offset = 128 + 4 + 12 * tags.len();
+    for (signature, tag) in &tags {
+        table.extend(*signature);
+        table.extend((offset as u32).to_be_bytes());
+        table.extend((tag.len() as u32).to_be_bytes());
+        data.extend(tag);
+        while data.len() % 4 != 0 {
+            data.push(0);
+        }
+        offset = 128 + 4 + 12 * tags.len() + data.len();
+    }
+
+    let size = 128 + table.len() + data.len();
+    let mut header = Vec::with_capacity(128);
+    header.extend((size as u32).to_be_bytes());
+    header.extend([0; 4]); // CMM
+    header.extend([2, 0x10, 0, 0]); // versión 2.1
+    header.extend(b"mntrRGB XYZ ");
+    header.extend([0x07, 0xe8, 0, 1, 0, 1, 0, 0, 0, 0, 0, 0]); // 2024-01-01
+    header.extend(b"acsp");
+    header.extend([0; 24]); // plataforma, flags, fabricante, modelo, atributos
+    header.extend([0; 4]); // intención perceptual
+    header.extend(&xyz(0.9642, 1.0, 0.8249)[8..]);
+    header.resize(128, 0);
+    [header, table, data].concat()
+}
@@ -0,0 +1,692 @@
+use crate::builder::{self, ImageFormat, OutputOptions};
+use crate::error::WatermarkError;
+use crate::forensic::Forensic;
+use crate::pages::PageSelection;
+use crate::pdf::{self, Page, PageReader, TargetSize};
+use crate::progress::{Progress, ProgressSink, Silent, Stage, Tally};
+use crate::stamp::{self, Mode};
+use crate::text::{self, Bates, PageContext};
+use crate::watermark::{Quality, Stamp, Subsampling};
+use crate::parallel;
+use image::RgbaImage;
+use lopdf::{Document, Object};
+use std::collections::HashSet;
+use std::io::Write;
+
+/// Aplica watermarks a PDFs completos con una configuración fija, que se
+/// puede reutilizar para muchos PDFs (también desde varios hilos).
+///
+/// Por defecto procesa todas las páginas en modo raster, sin pérdida, a
+/// [`pdf::DEFAULT_DPI`] y sin cifrar la salida.
+pub struct Pipeline {
+    stamps: Vec<Stamp>,
+    mode: Mode,
+    quality: Quality,
+    output: OutputOptions,
+    pages: PageSelection,
+    skip: PageSelection,
+    password: String,
+    dpi: f32,
+    max_size: Option<usize>,
+    downscale: bool,
+    max_dpi: Option<f32>,
+    max_width: Option<u32>,
+    page_size: Option<TargetSize>,
+    grayscale: bool,
+    invisible: Option<String>,
+    forensic: Option<(Forensic, String)>,
+    bates: Option<Bates>,
+}
+
+impl Pipeline {
+    /// Dibuja los `stamps` en orden sobre cada página.
+    pub fn new(stamps: Vec<Stamp>) -> Self {
+        Pipeline {
+            stamps,
+            mode: Mode::Raster,
+            quality: Quality::Lossless,
+            output: OutputOptions::default(),
+            pages: PageSelection::default(),
+            skip: PageSelection::default(),
+            password: String::new(),
+            dpi: pdf::DEFAULT_DPI,
+            max_size: None,
+            downscale: false,
+            max_dpi: None,
+            max_width: None,
+            page_size: None,
+            grayscale: false,
+            invisible: None,
+            forensic: None,
+            bates: None,
+        }
+    }
+
+    pub fn with_mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Codificación de las páginas en modo raster.
+    pub fn with_quality(mut self, quality: Quality) -> Self {
+        self.quality = quality;
+        self
+    }
+
+    /// Cifrado, PDF/A, linealización... del PDF generado.
+    pub fn with_output(mut self, output: OutputOptions) -> Self {
+        self.output = output;
+        self
+    }
+
+    /// Páginas que se conservan; el resto se descarta.
+    pub fn with_pages(mut self, pages: PageSelection) -> Self {
+        self.pages = pages;
+        self
+    }
+
+    /// Páginas que se conservan sin watermark.
+    pub fn with_skip(mut self, skip: PageSelection) -> Self {
+        self.skip = skip;
+        self
+    }
+
+    /// Contraseña para abrir los PDFs de entrada protegidos.
+    pub fn with_password(mut self, password: &str) -> Self {
+        self.password = password.to_string();
+        self
+    }
+
+    /// Resolución al rasterizar páginas sin imágenes.
+    pub fn with_dpi(mut self, dpi: f32) -> Self {
+        self.dpi = dpi;
+        self
+    }
+
+    /// Tamaño máximo del PDF generado en modo raster: si no cabe con la
+    /// calidad indicada se busca la mayor calidad JPEG con la que cabe y, con
+    /// `downscale`, se reduce además la resolución de las páginas.
+    pub fn with_max_size(mut self, max_bytes: Option<usize>, downscale: bool) -> Self {
+        self.max_size = max_bytes;
+        self.downscale = downscale;
+        self
+    }
+
+    /// Reduce las imágenes de las páginas que pasan de `max_dpi` o de
+    /// `max_width` píxeles de ancho antes de aplicar los watermarks.
+    pub fn with_max_resolution(mut self, max_dpi: Option<f32>, max_width: Option<u32>) -> Self {
+        self.max_dpi = max_dpi;
+        self.max_width = max_width;
+        self
+    }
+
+    /// Fuerza el tamaño de las páginas de salida (ver [`Page::fitted`]); los
+    /// tamaños en píxeles miden en el PDF lo que indica
+    /// [`with_dpi`](Self::with_dpi).
+    pub fn with_page_size(mut self, page_size: Option<TargetSize>) -> Self {
+        self.page_size = page_size;
+        self
+    }
+
+    /// Convierte las páginas, con sus watermarks, a escala de grises.
+    pub fn with_grayscale(mut self, grayscale: bool) -> Self {
+        self.grayscale = grayscale;
+        self
+    }
+
+    /// Guarda `payload` de forma invisible en cada página marcada (ver
+    /// [`stego`](crate::stego)), con `{page}`, `{filename}`, etc. ya
+    /// sustituidos. Necesita salida sin pérdida y modo raster.
+    pub fn with_invisible(mut self, payload: Option<String>) -> Self {
+        self.invisible = payload;
+        self
+    }
+
+    /// Añade a cada página marcada el watermark forense `payload` (ver
+    /// [`Forensic`]), con `{page}`, `{filename}`, etc. ya sustituidos. Solo
+    /// en modo raster.
+    pub fn with_forensic(mut self, payload: &str, forensic: Forensic) -> Self {
+        self.forensic = Some((forensic, payload.to_string()));
+        self
+    }
+
+    /// Numera las páginas marcadas con `bates` para `{bates}`, en el orden
+    /// en que se procesan los PDFs.
+    pub fn with_bates(mut self, bates: Bates) -> Self {
+        self.bates = Some(bates);
+        self
+    }
+
+    pub fn bates(&self) -> Option<&Bates> {
+        self.bates.as_ref()
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn quality(&self) -> &Quality {
+        &self.quality
+    }
+
+    /// Procesa el PDF `data`. `filename` sustituye a `{filename}` en los
+    /// watermarks de texto.
+    pub fn process(&self, data: &[u8], filename: &str, progress: &dyn ProgressSink) -> Result<Vec<u8>, WatermarkError> {
+        let mut buf = Vec::new();
+        self.process_to(data, filename, &mut buf, progress)?;
+        Ok(buf)
+    }
+
+    /// Como [`process`](Self::process), escribiendo el PDF en `out`.
+    pub fn process_to(
+        &self,
+        data: &[u8],
+        filename: &str,
+        out: &mut dyn Write,
+        progress: &dyn ProgressSink,
+    ) -> Result<(), WatermarkError> {
+        if self.mode == Mode::Stamp {
+            self.check_stamp()?;
+            let doc = pdf::load_document(data, &self.password)?;
+            let (indices, skip) = self.selection(doc.get_pages().len())?;
+            progress.report(Progress::Stage(Stage::Watermarking));
+            let bates = self.bates();
+            return stamp::stamp_loaded(
+                data, doc, &indices, &skip, filename, &self.stamps, bates, &self.output, progress, out,
+            );
+        }
+
+        let (reader, pages) = self.marked_pages(data, filename, progress)?;
+        progress.report(Progress::Stage(Stage::Building));
+        self.build(&pages, reader.document(), out, progress)
+    }
+
+    /// Como [`process`](Self::process), pero genera un PDF por página y se
+    /// lo pasa a `each` junto al número de la página en el PDF de entrada.
+    pub fn process_split(
+        &self,
+        data: &[u8],
+        filename: &str,
+        progress: &dyn ProgressSink,
+        each: &mut dyn FnMut(u32, Vec<u8>) -> Result<(), WatermarkError>,
+    ) -> Result<(), WatermarkError> {
+        if self.mode == Mode::Stamp {
+            self.check_stamp()?;
+            let doc = pdf::load_document(data, &self.password)?;
+            let (indices, skip) = self.selection(doc.get_pages().len())?;
+            progress.report(Progress::Stage(Stage::Watermarking));
+            let bates = self.bates();
+            return stamp::stamp_split(
+                doc, &indices, &skip, filename, &self.stamps, bates, &self.output, progress, each,
+            );
+        }
+
+        let (reader, pages) = self.marked_pages(data, filename, progress)?;
+        progress.report(Progress::Stage(Stage::Building));
+        let tally = Tally::new(progress, pages.len());
+        for page in &pages {
+            let mut buf = Vec::new();
+            self.build(std::slice::from_ref(page), reader.document(), &mut buf, &Silent)?;
+            each(page.number, buf)?;
+            tally.tick();
+        }
+        Ok(())
+    }
+
+    /// Como [`process_split`](Self::process_split), pero en vez de PDFs pasa
+    /// a `each` la imagen de cada página codificada en `format`. En modo
+    /// stamp las páginas se rasterizan igualmente, como en
+    /// [`preview`](Self::preview).
+    pub fn export_images(
+        &self,
+        data: &[u8],
+        filename: &str,
+        format: ImageFormat,
+        progress: &dyn ProgressSink,
+        each: &mut dyn FnMut(u32, Vec<u8>) -> Result<(), WatermarkError>,
+    ) -> Result<(), WatermarkError> {
+        if self.invisible.is_some() && format == ImageFormat::Jpeg {
+            return Err(WatermarkError::InvalidOption(
+                "El watermark invisible necesita salida sin pérdida: JPEG lo borra".to_string(),
+            ));
+        }
+        let (_, pages) = self.marked_pages(data, filename, progress)?;
+        progress.report(Progress::Stage(Stage::Building));
+        let tally = Tally::new(progress, pages.len());
+        let images = parallel::map(&pages, |page| {
+            let image = builder::encode_page_image(&page.flattened(), format, &self.quality);
+            tally.tick();
+            image
+        });
+        for (page, image) in pages.iter().zip(images) {
+            each(page.number, image?)?;
+        }
+        Ok(())
+    }
+
+    /// Como [`process_to`](Self::process_to), pero escribe en `out` un TIFF
+    /// multipágina en vez de un PDF. En modo stamp las páginas se rasterizan
+    /// igualmente; las imágenes van siempre sin pérdida.
+    pub fn process_tiff(
+        &self,
+        data: &[u8],
+        filename: &str,
+        out: &mut dyn Write,
+        progress: &dyn ProgressSink,
+    ) -> Result<(), WatermarkError> {
+        if matches!(self.quality, Quality::Jpeg(..)) {
+            return Err(WatermarkError::InvalidOption("TIFF se genera sin pérdida: no admite --quality".to_string()));
+        }
+        if !self.output.describe().is_empty() {
+            return Err(WatermarkError::InvalidOption(
+                "El cifrado, PDF/A, la linealización y demás opciones del PDF no se aplican a TIFF".to_string(),
+            ));
+        }
+        if self.max_size.is_some() {
+            return Err(WatermarkError::InvalidOption("El tamaño máximo no está disponible con TIFF".to_string()));
+        }
+        let (_, pages) = self.marked_pages(data, filename, progress)?;
+        progress.report(Progress::Stage(Stage::Building));
+        builder::write_tiff(&pages, out, progress)
+    }
+
+    /// Como [`process`](Self::process), leyendo `input` y escribiendo
+    /// `output`; "-" es la entrada o la salida estándar.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn process_file(&self, input: &str, output: &str, progress: &dyn ProgressSink) -> Result<(), WatermarkError> {
+        let (data, filename) = if input == "-" {
+            let mut data = Vec::new();
+            std::io::Read::read_to_end(&mut std::io::stdin().lock(), &mut data)?;
+            (data, String::new())
+        } else {
+            let filename = std::path::Path::new(input)
+                .file_name()
+                .map(|f| f.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            (std::fs::read(input)?, filename)
+        };
+        self.process_to_file(&data, &filename, output, progress)
+    }
+
+    /// Como [`process`](Self::process), escribiendo el PDF en el archivo
+    /// `output` ("-" es la salida estándar).
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn process_to_file(
+        &self,
+        data: &[u8],
+        filename: &str,
+        output: &str,
+        progress: &dyn ProgressSink,
+    ) -> Result<(), WatermarkError> {
+        let bytes = if output == "-" {
+            let mut out = Counter { inner: std::io::BufWriter::new(std::io::stdout().lock()), bytes: 0 };
+            self.process_to(data, filename, &mut out, progress)?;
+            out.flush()?;
+            out.bytes
+        } else {
+            // En memoria, para no dejar un archivo a medias si falla.
+            let buf = self.process(data, filename, progress)?;
+            std::fs::write(output, &buf)?;
+            buf.len()
+        };
+
+        progress.report(Progress::Written { path: output, bytes, summary: &self.describe() });
+        Ok(())
+    }
+
+    /// Calcula sin generarlo el tamaño aproximado del PDF que daría
+    /// [`process`](Self::process). En modo raster se extraen y marcan todas
+    /// las páginas elegidas, pero de las que hay que recodificar solo se
+    /// codifican unas pocas de muestra; en modo stamp el tamaño es exacto.
+    pub fn estimate(&self, data: &[u8], filename: &str) -> Result<Estimate, WatermarkError> {
+        if self.mode == Mode::Stamp {
+            let doc = pdf::load_document(data, &self.password)?;
+            let total = doc.get_pages().len();
+            let (indices, skip) = self.selection(total)?;
+            let mut out = Counter { inner: std::io::sink(), bytes: 0 };
+            // La estimación no gasta números Bates.
+            stamp::stamp_loaded(
+                data, doc, &indices, &skip, filename, &self.stamps, None, &self.output, &Silent, &mut out,
+            )?;
+            return Ok(Estimate { total, selected: indices.len(), pages: Vec::new(), bytes: out.bytes });
+        }
+
+        let mut reader = self.reader(data.to_vec())?;
+        let total = reader.len();
+        let (indices, skip) = self.selection(total)?;
+        if indices.is_empty() {
+            return Err(WatermarkError::NoPagesSelected);
+        }
+        let mut samples = vec![indices[0], indices[indices.len() / 2], indices[indices.len() - 1]];
+        samples.dedup();
+
+        let mut pages = Vec::new();
+        let mut copied = HashSet::new();
+        let mut bytes = 0;
+        let (mut pixels, mut sample_pixels, mut sample_bytes) = (0u64, 0u64, 0u64);
+        // Por tandas, para no tener todas las imágenes en memoria a la vez, y
+        // sin gastar números Bates, como en modo stamp.
+        for chunk in indices.chunks(ESTIMATE_CHUNK) {
+            let extracted = reader.pages(chunk)?;
+            let marked = self.watermark(extracted, chunk, total, &skip, filename, None, &Silent)?;
+            for (&index, page) in chunk.iter().zip(marked) {
+                let (width, height) = (page.image.width(), page.image.height());
+                pages.push(PageSize { index, width, height });
+                // Igual que al generar el PDF: las imágenes sin cambios se copian una vez.
+                match page.original.filter(|_| !self.output.pdfa) {
+                    Some(id) if copied.insert(id) => {
+                        bytes += reader.document().get_object(id).and_then(Object::as_stream).map_or(0, |s| s.content.len());
+                    }
+                    Some(_) => {}
+                    None => {
+                        let area = width as u64 * height as u64;
+                        pixels += area;
+                        if samples.contains(&index) || sample_pixels == 0 {
+                            sample_bytes += builder::encoded_size(&page.image, &self.quality)? as u64;
+                            sample_pixels += area;
+                        }
+                    }
+                }
+            }
+        }
+        let bytes = bytes + (pixels * sample_bytes / sample_pixels.max(1)) as usize;
+        Ok(Estimate { total, selected: indices.len(), pages, bytes })
+    }
+
+    /// Imagen de la página `index` (desde 0) del PDF `data` con los
+    /// watermarks, salvo si está en las páginas sin watermark. En modo stamp
+    /// la página se rasteriza igualmente, así que el resultado es aproximado.
+    pub fn preview(&self, data: &[u8], filename: &str, index: usize) -> Result<RgbaImage, WatermarkError> {
+        let mut reader = self.reader(data.to_vec())?;
+        let total = reader.len();
+        if index >= total {
+            return Err(WatermarkError::PageNotFound { page: index + 1, total });
+        }
+        let skip = self.skip.listed(total)?;
+        let pages = self.watermark(reader.pages(&[index])?, &[index], total, &skip, filename, None, &Silent)?;
+        Ok(pages[0].flattened())
+    }
+
+    /// Codificación de la salida, ej. "JPEG q=80, AES-256".
+    pub fn describe(&self) -> String {
+        let mode = match (self.mode, &self.quality) {
+            (Mode::Stamp, _) => "stamp".to_string(),
+            (Mode::Raster, Quality::Lossless) => "Flate lossless".to_string(),
+            (Mode::Raster, Quality::Jpeg(q, subsampling)) => format!("JPEG q={}{}", q, subsampling.suffix()),
+        };
+        let mode = if self.grayscale { format!("{} en gris", mode) } else { mode };
+        let mode = match self.max_size {
+            Some(max_bytes) if max_bytes < 1_048_576 => format!("{}, máx. {:.0} KB", mode, max_bytes as f64 / 1024.0),
+            Some(max_bytes) => format!("{}, máx. {:.1} MB", mode, max_bytes as f64 / 1_048_576.0),
+            None => mode,
+        };
+        format!("{}{}", mode, self.output.describe())
+    }
+
+    /// Aplica los watermarks a `pages`, que son las páginas `indices` de un
+    /// PDF de `total`, salvo a las de `skip`, numeradas con `bates` si lo hay.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn watermark(
+        &self,
+        pages: Vec<Page>,
+        indices: &[usize],
+        total: usize,
+        skip: &[usize],
+        filename: &str,
+        bates: Option<&Bates>,
+        progress: &dyn ProgressSink,
+    ) -> Result<Vec<Page>, WatermarkError> {
+        let date = text::today();
+        let mut next = match bates {
+            Some(bates) => bates.reserve(indices.iter().filter(|index| !skip.contains(index)).count()),
+            None => 0,
+        };
+        let pages: Vec<_> = indices
+            .iter()
+            .copied()
+            .zip(pages)
+            .map(|(index, page)| {
+                let bates = match bates {
+                    Some(bates) if !skip.contains(&index) => {
+                        next += 1;
+                        bates.format(next - 1)
+                    }
+                    _ => String::new(),
+                };
+                (index, page, bates)
+            })
+            .collect();
+        let tally = Tally::new(progress, pages.len());
+        parallel::map(&pages, |(index, page, bates)| {
+            let stamped = !skip.contains(index);
+            let fitted = self.fitted(page);
+            let page = fitted.as_ref().unwrap_or(page);
+            let reduced = self.resolution_scale(page).map(|scale| {
+                log::debug!("Página {}: imagen reducida al {:.0}%", page.number, scale * 100.0);
+                page.downscaled(scale)
+            });
+            let page = reduced.as_ref().unwrap_or(page);
+            let ctx = PageContext::new(*index, total, filename, &date).with_bates(bates.clone());
+            let page = if stamped { page.watermarked(&ctx, &self.stamps) } else { page.clone() };
+            let page = if self.grayscale { page.grayscale() } else { page };
+            let page = match &self.forensic {
+                Some((forensic, payload)) if stamped => page.fingerprinted(forensic, ctx.expand(payload).as_bytes())?,
+                _ => page,
+            };
+            let page = match &self.invisible {
+                Some(payload) if stamped => page.embedded(ctx.expand(payload).as_bytes()),
+                _ => page,
+            };
+            progress.report(Progress::Page { index: *index, total, stamped });
+            tally.tick();
+            Ok(page)
+        })
+        .into_iter()
+        .collect()
+    }
+
+    /// Extrae las páginas elegidas de `data` y les aplica los watermarks
+    /// (modo raster).
+    fn marked_pages(
+        &self,
+        data: &[u8],
+        filename: &str,
+        progress: &dyn ProgressSink,
+    ) -> Result<(PageReader, Vec<Page>), WatermarkError> {
+        if self.invisible.is_some() && (matches!(self.quality, Quality::Jpeg(..)) || self.max_size.is_some()) {
+            return Err(WatermarkError::InvalidOption(
+                "El watermark invisible necesita salida sin pérdida: JPEG lo borra".to_string(),
+            ));
+        }
+        progress.report(Progress::Stage(Stage::Extracting));
+        let mut reader = self.reader(data.to_vec())?;
+        let total = reader.len();
+        let (indices, skip) = self.selection(total)?;
+        if indices.is_empty() {
+            return Err(WatermarkError::NoPagesSelected);
+        }
+        let pages = reader.pages_with_progress(&indices, progress)?;
+
+        progress.report(Progress::Stage(Stage::Watermarking));
+        let pages = self.watermark(pages, &indices, total, &skip, filename, self.bates(), progress)?;
+        Ok((reader, pages))
+    }
+
+    /// Abre el PDF `data` con la contraseña y resolución configuradas.
+    pub(crate) fn reader(&self, data: Vec<u8>) -> Result<PageReader, WatermarkError> {
+        PageReader::new(data, &self.password, self.dpi)
+    }
+
+    /// Escribe en `out` el PDF con las `pages` ya marcadas de `source`,
+    /// ajustando la calidad si hay tamaño máximo.
+    pub(crate) fn build(
+        &self,
+        pages: &[Page],
+        source: &Document,
+        out: &mut dyn Write,
+        progress: &dyn ProgressSink,
+    ) -> Result<(), WatermarkError> {
+        let Some(max_bytes) = self.max_size else {
+            return builder::write_pdf(pages, Some(source), &self.quality, &self.output, out, progress);
+        };
+        let build = |pages: &[Page], quality: &Quality| -> Result<Vec<u8>, WatermarkError> {
+            let mut buf = Vec::new();
+            builder::write_pdf(pages, Some(source), quality, &self.output, &mut buf, &Silent)?;
+            Ok(buf)
+        };
+
+        // Puede que ya quepa con la calidad indicada.
+        let pdf = build(pages, &self.quality)?;
+        if pdf.len() <= max_bytes {
+            out.write_all(&pdf)?;
+            return Ok(());
+        }
+        let (top, subsampling) = match self.quality {
+            Quality::Jpeg(q, subsampling) => (q.saturating_sub(1).max(MIN_FIT_QUALITY), subsampling),
+            Quality::Lossless => (100, Subsampling::default()),
+        };
+        let scales = if self.downscale { &FIT_SCALES[..] } else { &FIT_SCALES[..1] };
+        let mut smallest = pdf.len();
+        for &scale in scales {
+            // Las imágenes que se copiarían sin cambios también se recodifican.
+            let pages: Vec<Page> = if scale < 1.0 {
+                pages.iter().map(|page| page.downscaled(scale)).collect()
+            } else {
+                pages.iter().map(|page| Page { original: None, ..page.clone() }).collect()
+            };
+            // Búsqueda binaria de la mayor calidad con la que cabe.
+            let (mut low, mut high) = (MIN_FIT_QUALITY, top);
+            let mut best = None;
+            while low <= high {
+                let quality = low + (high - low) / 2;
+                let pdf = build(&pages, &Quality::Jpeg(quality, subsampling))?;
+                log::debug!("JPEG q={} al {:.0}%: {} bytes", quality, scale * 100.0, pdf.len());
+                smallest = smallest.min(pdf.len());
+                if pdf.len() <= max_bytes {
+                    best = Some((quality, pdf));
+                    low = quality + 1;
+                } else {
+                    high = quality - 1;
+                }
+            }
+            if let Some((quality, pdf)) = best {
+                progress.report(Progress::Fitted { quality, scale });
+                out.write_all(&pdf)?;
+                return Ok(());
+            }
+        }
+        Err(WatermarkError::TooLarge { max_bytes, smallest })
+    }
+
+    /// `page` con el tamaño de página forzado, si lo hay.
+    fn fitted(&self, page: &Page) -> Option<Page> {
+        let points_per_px = 72.0 / self.dpi as f64;
+        let (px_w, px_h, width, height) = match self.page_size? {
+            TargetSize::Pixels(w, h) => (w, h, w as f64 * points_per_px, h as f64 * points_per_px),
+            TargetSize::Paper(w, h) => {
+                let (w, h) = if (page.width > page.height) == (w > h) { (w, h) } else { (h, w) };
+                let px = |points: f64| (points / points_per_px).round().max(1.0) as u32;
+                (px(w), px(h), w, h)
+            }
+        };
+        Some(page.fitted(px_w, px_h, width, height))
+    }
+
+    /// Escala (menor que 1) a la que hay que reducir la imagen de `page` para
+    /// no pasar de la resolución máxima, si pasa.
+    fn resolution_scale(&self, page: &Page) -> Option<f64> {
+        let width = page.image.width().max(1) as f64;
+        let mut scale = 1.0f64;
+        if let Some(max_dpi) = self.max_dpi {
+            scale = scale.min(max_dpi as f64 * page.width / 72.0 / width);
+        }
+        if let Some(max_width) = self.max_width {
+            scale = scale.min(max_width as f64 / width);
+        }
+        (scale < 1.0).then_some(scale)
+    }
+
+    /// El modo stamp no recodifica las imágenes del PDF.
+    fn check_stamp(&self) -> Result<(), WatermarkError> {
+        if self.max_size.is_some() || self.max_dpi.is_some() || self.max_width.is_some() || self.page_size.is_some() {
+            return Err(WatermarkError::InvalidOption(
+                "El tamaño de página y el tamaño y la resolución máximos solo están disponibles en modo raster"
+                    .to_string(),
+            ));
+        }
+        if self.grayscale {
+            return Err(WatermarkError::InvalidOption(
+                "La escala de grises solo está disponible en modo raster".to_string(),
+            ));
+        }
+        if self.stamps.iter().any(|stamp| stamp.placements.has_auto()) {
+            return Err(WatermarkError::InvalidOption(
+                "La posición automática solo está disponible en modo raster".to_string(),
+            ));
+        }
+        if self.invisible.is_some() || self.forensic.is_some() {
+            return Err(WatermarkError::InvalidOption(
+                "Los watermarks invisible y forense solo están disponibles en modo raster".to_string(),
+            ));
+        }
+        if self.stamps.iter().any(|stamp| stamp.mark.adaptive) {
+            return Err(WatermarkError::InvalidOption(
+                "El watermark adaptativo solo está disponible en modo raster".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Índices de las páginas a conservar y de las que van sin watermark en
+    /// un PDF de `total` páginas.
+    pub(crate) fn selection(&self, total: usize) -> Result<(Vec<usize>, Vec<usize>), WatermarkError> {
+        Ok((self.pages.indices(total)?, self.skip.listed(total)?))
+    }
+}
+
+/// Resultado de [`Pipeline::estimate`].
+pub struct Estimate {
+    /// Páginas del PDF de entrada.
+    pub total: usize,
+    /// Páginas elegidas.
+    pub selected: usize,
+    /// Tamaño de la imagen de cada página elegida (vacío en modo stamp).
+    pub pages: Vec<PageSize>,
+    /// Tamaño aproximado del PDF generado, sin contar metadatos ni marcadores.
+    pub bytes: usize,
+}
+
+/// Tamaño en píxeles de la imagen de la página `index` (desde 0).
+pub struct PageSize {
+    pub index: usize,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Menor calidad JPEG que se prueba para no pasar del tamaño máximo.
+const MIN_FIT_QUALITY: u8 = 10;
+
+/// Escalas de las páginas que se prueban, en orden, si no basta con bajar la
+/// calidad y se permite reducir la resolución.
+const FIT_SCALES: [f64; 5] = [1.0, 0.75, 0.5, 0.35, 0.25];
+
+/// Páginas que se extraen a la vez al estimar el tamaño.
+const ESTIMATE_CHUNK: usize = 8;
+
+/// Cuenta los bytes escritos en `inner`.
+struct Counter<W> {
+    inner: W,
+    bytes: usize,
+}
+
+impl<W: Write> Write for Counter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.bytes += n;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,69 @@
+/// Avance del procesamiento de un PDF, para que la aplicación que usa la
+/// librería lo muestre a su manera.
+pub enum Progress<'a> {
+    /// Empieza una fase del procesamiento.
+    Stage(Stage),
+    /// Terminó la página `index` (desde 0) de las `total` del PDF de entrada;
+    /// `stamped` es false si se conservó sin marca de agua.
+    Page { index: usize, total: usize, stamped: bool },
+    /// Terminó otra página de la fase actual: van `done` de las `total`
+    /// elegidas. Permite mostrar el avance de todas las fases, no solo del
+    /// watermark.
+    Advance { done: usize, total: usize },
+    /// Para no pasar del tamaño máximo las páginas se codificaron en JPEG con
+    /// calidad `quality` y reducidas a `scale` (1 = tamaño original).
+    Fitted { quality: u8, scale: f64 },
+    /// Se escribió el PDF de salida en `path`, con `bytes` de tamaño y
+    /// `summary` describiendo su codificación (ej. "Flate lossless, AES-256").
+    Written { path: &'a str, bytes: usize, summary: &'a str },
+}
+
+/// Fases de [`Pipeline::process`](crate::Pipeline::process), en orden.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Extracción de las imágenes de las páginas (solo modo raster).
+    Extracting,
+    /// Watermark de cada página.
+    Watermarking,
+    /// Generación del PDF de salida (solo modo raster o al separar las páginas).
+    Building,
+}
+
+/// Recibe el [`Progress`]. Las páginas pueden procesarse en paralelo, así
+/// que debe poder llamarse desde varios hilos. Cualquier closure
+/// `Fn(Progress)` sirve.
+pub trait ProgressSink: Sync {
+    fn report(&self, progress: Progress<'_>);
+}
+
+impl<F: Fn(Progress<'_>) + Sync> ProgressSink for F {
+    fn report(&self, progress: Progress<'_>) {
+        self(progress)
+    }
+}
+
+/// Cuenta las páginas terminadas de una fase y reporta cada una como
+/// [`Progress::Advance`]; puede usarse desde varios hilos.
+pub(crate) struct Tally<'a> {
+    progress: &'a dyn ProgressSink,
+    done: std::sync::atomic::AtomicUsize,
+    total: usize,
+}
+
+impl<'a> Tally<'a> {
+    pub(crate) fn new(progress: &'a dyn ProgressSink, total: usize) -> Self {
+        Tally { progress, done: Default::default(), total }
+    }
+
+    pub(crate) fn tick(&self) {
+        let done = self.done.fetch_add(1, std::sync::atomic::Ordering::Relaxed) + 1;
+        self.progress.report(Progress::Advance { done, total: self.total });
+    }
+}
+
+/// Descarta el avance.
+pub struct Silent;
+
+impl ProgressSink for Silent {
+    fn report(&self, _: Progress<'_>) {}
+}
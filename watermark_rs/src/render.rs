@@ -0,0 +1,331 @@
+//! Composición de las imágenes de una página según su contenido.
+
+use crate::pdf::{self, DecodedImage};
+use anyhow::Result;
+use image::{DynamicImage, Rgba, RgbaImage};
+use lopdf::content::Content;
+use lopdf::{Dictionary, Document, Object, ObjectId};
+use std::collections::HashMap;
+
+/// Resolución máxima del lienzo (píxeles por punto, 432 dpi).
+const MAX_SCALE: f64 = 6.0;
+/// Profundidad máxima de Form XObjects anidados.
+const MAX_DEPTH: usize = 8;
+
+/// Matriz afín de PDF `[a b c d e f]`.
+pub(crate) type Matrix = [f64; 6];
+
+pub(crate) const IDENTITY: Matrix = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+/// `m` seguida de `n` (convención de PDF: `cm` hace CTM' = m × CTM).
+pub(crate) fn multiply(m: &Matrix, n: &Matrix) -> Matrix {
+    [
+        m[0] * n[0] + m[1] * n[2],
+        m[0] * n[1] + m[1] * n[3],
+        m[2] * n[0] + m[3] * n[2],
+        m[2] * n[1] + m[3] * n[3],
+        m[4] * n[0] + m[5] * n[2] + n[4],
+        m[4] * n[1] + m[5] * n[3] + n[5],
+    ]
+}
+
+/// Un `Do` de imagen: el XObject, la CTM y el color de relleno vigente.
+struct Draw {
+    id: ObjectId,
+    ctm: Matrix,
+    fill: [u8; 3],
+}
+
+/// Compone las imágenes dibujadas en la página sobre un lienzo blanco del
+/// tamaño de la zona visible (ver [`pdf::crop_box`]). `None` si el contenido
+/// no dibuja ninguna imagen. Si una sola imagen cubre la página se devuelve
+/// también su id.
+pub(crate) fn compose_page(doc: &Document, page_id: ObjectId) -> Result<Option<(DynamicImage, Option<ObjectId>)>> {
+    let Ok([llx, lly, urx, ury]) = pdf::crop_box(doc, page_id) else {
+        return Ok(None);
+    };
+    let resources = match pdf::inherited_attr(doc, page_id, b"Resources") {
+        Some(obj) => pdf::resolve_to_dict(doc, &obj)?,
+        None => Dictionary::new(),
+    };
+    let content = doc.get_page_content(page_id);
+
+    let mut draws = Vec::new();
+    collect_draws(doc, &content, &resources, IDENTITY, 0, &mut draws);
+
+    let mut images: HashMap<ObjectId, DecodedImage> = HashMap::new();
+    for draw in &draws {
+        if images.contains_key(&draw.id) {
+            continue;
+        }
+        let Ok(Object::Stream(stream)) = doc.get_object(draw.id) else {
+            continue;
+        };
+        if let Some(image) = pdf::decode_image(doc, stream)? {
+            images.insert(draw.id, image);
+        }
+    }
+    draws.retain(|d| images.contains_key(&d.id));
+    if draws.is_empty() {
+        return Ok(None);
+    }
+
+    let (pw, ph) = (urx - llx, ury - lly);
+
+    // Una sola imagen que cubre la página: se usa tal cual, a su resolución.
+    if let [draw] = &draws[..] {
+        let image = &images[&draw.id];
+        let [a, b, c, d, e, f] = draw.ctm;
+        let covers = b == 0.0
+            && c == 0.0
+            && (a - pw).abs() < 1.0
+            && (d - ph).abs() < 1.0
+            && (e - llx).abs() < 1.0
+            && (f - lly).abs() < 1.0;
+        if covers && !image.stencil {
+            log::debug!("La imagen {:?} cubre la página: se usa a su resolución", draw.id);
+            return Ok(Some((DynamicImage::ImageRgb8(pdf::flatten(&image.pixels)), Some(draw.id))));
+        }
+    }
+
+    // Resolución del lienzo: la de la imagen que ocupa más superficie.
+    let scale = draws
+        .iter()
+        .max_by(|x, y| area(&x.ctm).total_cmp(&area(&y.ctm)))
+        .map(|draw| {
+            let image = &images[&draw.id].pixels;
+            let [a, b, c, d, ..] = draw.ctm;
+            let sx = image.width() as f64 / a.hypot(b).max(f64::EPSILON);
+            let sy = image.height() as f64 / c.hypot(d).max(f64::EPSILON);
+            sx.max(sy).clamp(1.0, MAX_SCALE)
+        })
+        .unwrap_or(1.0);
+
+    let width = (pw * scale).round().max(1.0) as u32;
+    let height = (ph * scale).round().max(1.0) as u32;
+    log::debug!("Imágenes dibujadas: {}; página compuesta a {}x{} px", draws.len(), width, height);
+    let mut canvas = RgbaImage::from_pixel(width, height, Rgba([255, 255, 255, 255]));
+    for draw in &draws {
+        draw_image(&mut canvas, &images[&draw.id], draw, scale, (llx, ury));
+    }
+    Ok(Some((DynamicImage::ImageRgb8(pdf::flatten(&canvas)), None)))
+}
+
+fn area(m: &Matrix) -> f64 {
+    (m[0] * m[3] - m[1] * m[2]).abs()
+}
+
+fn collect_draws(
+    doc: &Document,
+    content: &[u8],
+    resources: &Dictionary,
+    ctm: Matrix,
+    depth: usize,
+    draws: &mut Vec<Draw>,
+) {
+    let Ok(content) = Content::decode(content) else {
+        return;
+    };
+    let xobjects = resources
+        .get(b"XObject")
+        .ok()
+        .and_then(|x| pdf::resolve_to_dict(doc, x).ok());
+
+    let mut stack = Vec::new();
+    let mut ctm = ctm;
+    let mut fill = [0u8; 3];
+    for op in &content.operations {
+        let nums: Vec<f64> = op
+            .operands
+            .iter()
+            .filter_map(|o| o.as_float().ok().map(f64::from))
+            .collect();
+        match op.operator.as_str() {
+            "q" => stack.push((ctm, fill)),
+            "Q" => {
+                if let Some((saved_ctm, saved_fill)) = stack.pop() {
+                    ctm = saved_ctm;
+                    fill = saved_fill;
+                }
+            }
+            "cm" if nums.len() == 6 => {
+                let m = [nums[0], nums[1], nums[2], nums[3], nums[4], nums[5]];
+                ctm = multiply(&m, &ctm);
+            }
+            "g" if nums.len() == 1 => fill = [to_byte(nums[0]); 3],
+            "rg" if nums.len() == 3 => fill = [nums[0], nums[1], nums[2]].map(to_byte),
+            "k" if nums.len() == 4 => {
+                let k = 1.0 - nums[3];
+                fill = [nums[0], nums[1], nums[2]].map(|c| to_byte((1.0 - c) * k));
+            }
+            "Do" => {
+                let Some(name) = op.operands.first().and_then(|o| o.as_name().ok()) else {
+                    continue;
+                };
+                let Some(Object::Reference(id)) = xobjects.as_ref().and_then(|x| x.get(name).ok()) else {
+                    continue;
+                };
+                let Ok(Object::Stream(stream)) = doc.get_object(*id) else {
+                    continue;
+                };
+                match stream.dict.get(b"Subtype").and_then(Object::as_name) {
+                    Ok(b"Image") => draws.push(Draw { id: *id, ctm, fill }),
+                    Ok(b"Form") if depth < MAX_DEPTH => {
+                        let matrix = stream
+                            .dict
+                            .get(b"Matrix")
+                            .and_then(Object::as_array)
+                            .ok()
+                            .and_then(|m| {
+                                let m: Vec<f64> = m.iter().filter_map(|v| v.as_float().ok().map(f64::from)).collect();
+                                <[f64; 6]>::try_from(m).ok()
+                            })
+                            .unwrap_or(IDENTITY);
+                        let form_resources = stream
+                            .dict
+                            .get(b"Resources")
+                            .ok()
+                            .and_then(|r| pdf::resolve_to_dict(doc, r).ok());
+                        let form_content = stream
+                            .decompressed_content()
+                            .unwrap_or_else(|_| stream.content.clone());
+                        collect_draws(
+                            doc,
+                            &form_content,
+                            form_resources.as_ref().unwrap_or(resources),
+                            multiply(&matrix, &ctm),
+                            depth + 1,
+                            draws,
+                        );
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn to_byte(v: f64) -> u8 {
+    (v.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+/// Dibuja la imagen (el cuadrado unidad transformado por su CTM) en el lienzo
+/// muestreando por vecino más cercano. `(llx, ury)` es la esquina de la página
+/// que corresponde al píxel (0, 0) y `scale` los píxeles por punto.
+fn draw_image(
+    canvas: &mut RgbaImage,
+    image: &DecodedImage,
+    draw: &Draw,
+    scale: f64,
+    (llx, ury): (f64, f64),
+) {
+    let [a, b, c, d, e, f] = draw.ctm;
+    let det = a * d - b * c;
+    if det.abs() < f64::EPSILON {
+        return;
+    }
+
+    // Caja del cuadrado unidad en píxeles del lienzo.
+    let corners = [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)].map(|(u, v)| {
+        let x = u * a + v * c + e;
+        let y = u * b + v * d + f;
+        ((x - llx) * scale, (ury - y) * scale)
+    });
+    let (cw, ch) = (canvas.width() as f64, canvas.height() as f64);
+    let x0 = corners.iter().map(|p| p.0).fold(f64::INFINITY, f64::min).floor().max(0.0) as u32;
+    let x1 = corners.iter().map(|p| p.0).fold(f64::NEG_INFINITY, f64::max).ceil().min(cw) as u32;
+    let y0 = corners.iter().map(|p| p.1).fold(f64::INFINITY, f64::min).floor().max(0.0) as u32;
+    let y1 = corners.iter().map(|p| p.1).fold(f64::NEG_INFINITY, f64::max).ceil().min(ch) as u32;
+
+    let pixels = &image.pixels;
+    let (iw, ih) = (pixels.width() as f64, pixels.height() as f64);
+    for cy in y0..y1 {
+        for cx in x0..x1 {
+            let x = llx + (cx as f64 + 0.5) / scale - e;
+            let y = ury - (cy as f64 + 0.5) / scale - f;
+            let u = (x * d - y * c) / det;
+            let v = (y * a - x * b) / det;
+            if !(0.0..1.0).contains(&u) || !(0.0..1.0).contains(&v) {
+                continue;
+            }
+            let px = (u * iw) as u32;
+            let py = ((1.0 - v) * ih) as u32;
+            let src = pixels.get_pixel(px.min(pixels.width() - 1), py.min(pixels.height() - 1));
+            let rgb = if image.stencil { draw.fill } else { [src[0], src[1], src[2]] };
+            let alpha = src[3] as u32;
+            if alpha == 0 {
+                continue;
+            }
+            let dst = canvas.get_pixel_mut(cx, cy);
+            for i in 0..3 {
+                dst[i] = ((rgb[i] as u32 * alpha + dst[i] as u32 * (255 - alpha)) / 255) as u8;
+            }
+        }
+    }
+}
+
+/// Rasteriza páginas que no dibujan imágenes (texto y vectores) a `dpi`.
+/// El documento se parsea una sola vez, en la primera página que lo necesite,
+/// a partir de los mismos `data` en cada llamada.
+pub(crate) struct Rasterizer {
+    password: String,
+    dpi: f32,
+    #[cfg(feature = "render")]
+    pdf: Option<hayro::hayro_syntax::Pdf>,
+}
+
+impl Rasterizer {
+    pub fn new(password: &str, dpi: f32) -> Self {
+        Self {
+            password: password.to_string(),
+            dpi,
+            #[cfg(feature = "render")]
+            pdf: None,
+        }
+    }
+
+    pub fn dpi(&self) -> f32 {
+        self.dpi
+    }
+
+    #[cfg(feature = "render")]
+    pub fn page(&mut self, data: &[u8], index: usize) -> Result<DynamicImage> {
+        use anyhow::anyhow;
+        use hayro::hayro_interpret::InterpreterSettings;
+        use hayro::vello_cpu::color::palette::css::WHITE;
+        use hayro::{PixmapSettings, RenderCache, RenderSettings};
+
+        if self.pdf.is_none() {
+            let pdf = hayro::hayro_syntax::Pdf::new_with_password(data.to_vec(), &self.password)
+                .map_err(|e| anyhow!("No se pudo rasterizar el PDF: {:?}", e))?;
+            self.pdf = Some(pdf);
+        }
+        let pdf = self.pdf.as_ref().expect("PDF recién parseado");
+        let page = pdf
+            .pages()
+            .get(index)
+            .ok_or_else(|| anyhow!("Página {} fuera de rango", index + 1))?;
+
+        let scale = self.dpi / 72.0;
+        log::debug!("Rasterizando la página {} a {} dpi", index + 1, self.dpi);
+        let pixmap = hayro::render(
+            page,
+            &RenderCache::new(),
+            &InterpreterSettings::default(),
+            &RenderSettings::default(),
+            &PixmapSettings { x_scale: scale, y_scale: scale, bg_color: WHITE },
+        );
+        // Fondo opaco: el RGBA premultiplicado coincide con el normal.
+        let (w, h) = (pixmap.width() as u32, pixmap.height() as u32);
+        let rgba = RgbaImage::from_raw(w, h, pixmap.data_as_u8_slice().to_vec())
+            .ok_or_else(|| anyhow!("Tamaño de página inválido"))?;
+        Ok(DynamicImage::ImageRgb8(pdf::flatten(&rgba)))
+    }
+
+    #[cfg(not(feature = "render"))]
+    pub fn page(&mut self, _data: &[u8], _index: usize) -> Result<DynamicImage> {
+        let _ = &self.password;
+        Err(crate::error::WatermarkError::RenderUnavailable.into())
+    }
+}
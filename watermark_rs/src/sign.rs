@@ -0,0 +1,287 @@
+//! Firma digital del PDF generado con un certificado PKCS#12.
+//!
+//! La firma se añade como actualización incremental: un campo de firma
+//! invisible en la primera página cuyo diccionario `/Sig` lleva en
+//! `/Contents` una firma CMS separada (adbe.pkcs7.detached) de todos los
+//! bytes del archivo salvo ese mismo `/Contents`, que indica `/ByteRange`.
+//! El contenido del PDF no cambia, así que la firma va después de cualquier
+//! otro paso (linealizado, actualización incremental...).
+
+use crate::error::WatermarkError;
+use anyhow::{anyhow, Result};
+use lopdf::{dictionary, Document, IncrementalDocument, Object, StringFormat};
+use p12_keystore::{KeyStore, Pkcs12ImportPolicy};
+use ring::rand::SystemRandom;
+use ring::signature::{EcdsaKeyPair, RsaKeyPair, ECDSA_P256_SHA256_ASN1_SIGNING, RSA_PKCS1_SHA256};
+
+/// Bytes reservados para la firma CMS en `/Contents`: caben el certificado,
+/// una cadena de un par de intermedios y una firma RSA de 4096 bits.
+const CONTENTS_LEN: usize = 16 * 1024;
+
+/// Valor provisional de `/ByteRange`, con tantas cifras como el definitivo
+/// puede necesitar.
+const PLACEHOLDER: i64 = 9_999_999_999;
+
+const OID_DATA: &[u64] = &[1, 2, 840, 113549, 1, 7, 1];
+const OID_SIGNED_DATA: &[u64] = &[1, 2, 840, 113549, 1, 7, 2];
+const OID_CONTENT_TYPE: &[u64] = &[1, 2, 840, 113549, 1, 9, 3];
+const OID_MESSAGE_DIGEST: &[u64] = &[1, 2, 840, 113549, 1, 9, 4];
+const OID_SIGNING_TIME: &[u64] = &[1, 2, 840, 113549, 1, 9, 5];
+const OID_SHA256: &[u64] = &[2, 16, 840, 1, 101, 3, 4, 2, 1];
+const OID_RSA: &[u64] = &[1, 2, 840, 113549, 1, 1, 1];
+const OID_ECDSA_SHA256: &[u64] = &[1, 2, 840, 10045, 4, 3, 2];
+
+/// Clave privada y certificados con los que se firma el PDF.
+#[derive(Clone)]
+pub struct Signer {
+    /// Clave privada RSA o EC P-256 en PKCS#8.
+    key: Vec<u8>,
+    /// Certificado del firmante seguido de los de su cadena, en DER.
+    chain: Vec<Vec<u8>>,
+}
+
+impl Signer {
+    /// Lee la clave y los certificados del archivo PKCS#12 (.p12 o .pfx)
+    /// `data`, protegido con `password`.
+    pub fn from_pkcs12(data: &[u8], password: &str) -> Result<Signer, WatermarkError> {
+        let invalid = |message: String| WatermarkError::InvalidOption(message);
+        let store = KeyStore::from_pkcs12(data, password, Pkcs12ImportPolicy::Strict)
+            .map_err(|e| invalid(format!("No se pudo leer el certificado PKCS#12: {}", e)))?;
+        let (_, chain) = store
+            .private_key_chain()
+            .ok_or_else(|| invalid("El archivo PKCS#12 no tiene una clave privada con su certificado".to_string()))?;
+        let signer = Signer {
+            key: chain.key().as_der().to_vec(),
+            chain: chain.certs().iter().map(|cert| cert.as_der().to_vec()).collect(),
+        };
+        // Se comprueba ya que la clave sirve para firmar, no al final del trabajo.
+        signer.sign_bytes(b"").map_err(|e| invalid(e.to_string()))?;
+        Ok(signer)
+    }
+
+    /// Firma el PDF `data` añadiéndole una actualización incremental.
+    pub(crate) fn sign(&self, data: Vec<u8>) -> Result<Vec<u8>> {
+        let doc = Document::load_mem(&data)?;
+        let page_id = *doc.get_pages().values().next().ok_or_else(|| anyhow!("El PDF no tiene páginas"))?;
+        let catalog_id = doc.trailer.get(b"Root")?.as_reference()?;
+        let resolve = |object: &Object| match object {
+            Object::Reference(id) => doc.get_object(*id).cloned().unwrap_or(Object::Null),
+            object => object.clone(),
+        };
+        let mut page = doc.get_dictionary(page_id)?.clone();
+        let mut catalog = doc.get_dictionary(catalog_id)?.clone();
+        let mut acroform = match catalog.get(b"AcroForm").map(resolve) {
+            Ok(Object::Dictionary(acroform)) => acroform,
+            _ => dictionary! {},
+        };
+        let mut fields = match acroform.get(b"Fields").map(resolve) {
+            Ok(Object::Array(fields)) => fields,
+            _ => Vec::new(),
+        };
+        let mut annots = match page.get(b"Annots").map(resolve) {
+            Ok(Object::Array(annots)) => annots,
+            _ => Vec::new(),
+        };
+        let names: Vec<Vec<u8>> = fields
+            .iter()
+            .filter_map(|field| resolve(field).as_dict().ok()?.get(b"T").ok()?.as_str().ok().map(<[u8]>::to_vec))
+            .collect();
+        let name = (1..).map(|n| format!("Firma{}", n)).find(|n| !names.contains(&n.as_bytes().to_vec()));
+
+        let mut update = IncrementalDocument::create_from(data, doc);
+        let new = &mut update.new_document;
+        // `/XRefStm` solo vale para la sección en la que aparece.
+        new.trailer.remove(b"XRefStm");
+        let now = chrono::Local::now();
+        let zone = now.format("%z").to_string();
+        let date = format!("D:{}{}'{}'", now.format("%Y%m%d%H%M%S"), &zone[..3], &zone[3..]);
+        let signature_id = new.add_object(dictionary! {
+            "Type" => "Sig",
+            "Filter" => "Adobe.PPKLite",
+            "SubFilter" => "adbe.pkcs7.detached",
+            "ByteRange" => vec![0.into(), PLACEHOLDER.into(), PLACEHOLDER.into(), PLACEHOLDER.into()],
+            "Contents" => Object::String(vec![0; CONTENTS_LEN], StringFormat::Hexadecimal),
+            "M" => Object::string_literal(date),
+        });
+        let field_id = new.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Widget",
+            "FT" => "Sig",
+            "T" => Object::string_literal(name.unwrap_or_default()),
+            "V" => signature_id,
+            "Rect" => vec![0.into(), 0.into(), 0.into(), 0.into()],
+            // Oculta y bloqueada.
+            "F" => 132,
+            "P" => page_id,
+        });
+        fields.push(field_id.into());
+        annots.push(field_id.into());
+        acroform.set("Fields", fields);
+        // Hay firmas y el PDF solo admite cambios incrementales.
+        acroform.set("SigFlags", 3);
+        catalog.set("AcroForm", acroform);
+        page.set("Annots", annots);
+        new.objects.insert(catalog_id, Object::Dictionary(catalog));
+        new.objects.insert(page_id, Object::Dictionary(page));
+        let start = update.get_prev_documents_bytes().len();
+        let mut buf = Vec::new();
+        update.save_to(&mut buf)?;
+
+        // Solo se busca en la actualización: el PDF puede tener otras firmas.
+        let find = |pattern: &[u8]| {
+            buf[start..].windows(pattern.len()).position(|w| w == pattern).map(|i| start + i + pattern.len())
+        };
+        let range = find(b"/ByteRange").ok_or_else(|| anyhow!("No se encontró /ByteRange"))?;
+        let range_open = range + buf[range..].iter().position(|&b| b == b'[').unwrap_or_default();
+        let range_close = range + buf[range..].iter().position(|&b| b == b']').unwrap_or_default() + 1;
+        let placeholder = format!("<{}>", "0".repeat(2 * CONTENTS_LEN));
+        let open = find(placeholder.as_bytes()).ok_or_else(|| anyhow!("No se encontró /Contents"))? - placeholder.len();
+        let close = open + placeholder.len();
+
+        let byte_range = format!("[0 {} {} {}]", open, close, buf.len() - close);
+        if range_open >= range_close || byte_range.len() > range_close - range_open {
+            return Err(anyhow!("No se pudo escribir /ByteRange"));
+        }
+        buf[range_open..range_close].copy_from_slice(format!("{:<1$}", byte_range, range_close - range_open).as_bytes());
+
+        let signed: Vec<u8> = [&buf[..open], &buf[close..]].concat();
+        let cms = self.signed_data(&signed)?;
+        if cms.len() > CONTENTS_LEN {
+            return Err(anyhow!("La firma ocupa {} bytes y solo hay sitio para {}", cms.len(), CONTENTS_LEN));
+        }
+        let hex: String = cms.iter().map(|byte| format!("{:02X}", byte)).collect();
+        buf[open + 1..open + 1 + hex.len()].copy_from_slice(hex.as_bytes());
+        Ok(buf)
+    }
+
+    /// Firma CMS SignedData separada de `content`, con los atributos
+    /// firmados que piden los lectores de PDF (tipo, resumen SHA-256 y
+    /// fecha).
+    fn signed_data(&self, content: &[u8]) -> Result<Vec<u8>> {
+        let certificate = self.chain.first().ok_or_else(|| anyhow!("Falta el certificado del firmante"))?;
+        let digest = ring::digest::digest(&ring::digest::SHA256, content);
+        let time = chrono::Utc::now().format("%y%m%d%H%M%SZ").to_string();
+        let mut attributes = [
+            attribute(OID_CONTENT_TYPE, &der(0x06, &oid(OID_DATA))),
+            attribute(OID_SIGNING_TIME, &der(0x17, time.as_bytes())),
+            attribute(OID_MESSAGE_DIGEST, &der(0x04, digest.as_ref())),
+        ];
+        // Un SET OF de DER va ordenado por su codificación.
+        attributes.sort();
+        let attributes = attributes.concat();
+        // Se firma el SET con su etiqueta, no la [0] con la que se guarda.
+        let (signature, algorithm) = self.sign_bytes(&der(0x31, &attributes))?;
+        let sha256 = sequence(&[&der(0x06, &oid(OID_SHA256)), &der(0x05, &[])]);
+        let signer_info = sequence(&[
+            &der(0x02, &[1]),
+            &issuer_and_serial(certificate).ok_or_else(|| anyhow!("El certificado del firmante no es válido"))?,
+            &sha256,
+            &der(0xA0, &attributes),
+            &algorithm,
+            &der(0x04, &signature),
+        ]);
+        let signed_data = sequence(&[
+            &der(0x02, &[1]),
+            &der(0x31, &sha256),
+            &sequence(&[&der(0x06, &oid(OID_DATA))]),
+            &der(0xA0, &self.chain.concat()),
+            &der(0x31, &signer_info),
+        ]);
+        Ok(sequence(&[&der(0x06, &oid(OID_SIGNED_DATA)), &der(0xA0, &signed_data)]))
+    }
+
+    /// Firma `message` con SHA-256 y devuelve la firma con el algoritmo que
+    /// la identifica en CMS.
+    fn sign_bytes(&self, message: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+        let rng = SystemRandom::new();
+        if let Ok(key) = RsaKeyPair::from_pkcs8(&self.key) {
+            let mut signature = vec![0; key.public().modulus_len()];
+            key.sign(&RSA_PKCS1_SHA256, &rng, message, &mut signature)
+                .map_err(|_| anyhow!("No se pudo firmar con la clave RSA"))?;
+            return Ok((signature, sequence(&[&der(0x06, &oid(OID_RSA)), &der(0x05, &[])])));
+        }
+        if let Ok(key) = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &self.key, &rng) {
+            let signature = key.sign(&rng, message).map_err(|_| anyhow!("No se pudo firmar con la clave EC"))?;
+            return Ok((signature.as_ref().to_vec(), sequence(&[&der(0x06, &oid(OID_ECDSA_SHA256))])));
+        }
+        Err(anyhow!("La clave del certificado debe ser RSA (2048 bits o más) o EC P-256"))
+    }
+}
+
+/// Elemento DER con la etiqueta `tag` y el contenido `content`.
+fn der(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    let len = content.len();
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let bytes: Vec<u8> = len.to_be_bytes().into_iter().skip_while(|&b| b == 0).collect();
+        out.push(0x80 | bytes.len() as u8);
+        out.extend(bytes);
+    }
+    out.extend_from_slice(content);
+    out
+}
+
+fn sequence(parts: &[&[u8]]) -> Vec<u8> {
+    der(0x30, &parts.concat())
+}
+
+/// Contenido DER del identificador de objeto `arcs`.
+fn oid(arcs: &[u64]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for (n, &arc) in arcs.iter().enumerate().skip(1) {
+        let arc = if n == 1 { arcs[0] * 40 + arc } else { arc };
+        let mut bytes = vec![(arc & 0x7F) as u8];
+        let mut rest = arc >> 7;
+        while rest > 0 {
+            bytes.push((rest & 0x7F) as u8 | 0x80);
+            rest >>= 7;
+        }
+        out.extend(bytes.iter().rev());
+    }
+    out
+}
+
+fn attribute(id: &[u64], value: &[u8]) -> Vec<u8> {
+    sequence(&[&der(0x06, &oid(id)), &der(0x31, value)])
+}
+
+/// Emisor y número de serie del certificado DER `certificate`, que
+/// identifican al firmante en CMS.
+fn issuer_and_serial(certificate: &[u8]) -> Option<Vec<u8>> {
+    let (_, certificate, _) = read_der(certificate)?;
+    let (_, mut tbs, _) = read_der(certificate)?;
+    let mut next = || {
+        let (tag, _, rest) = read_der(tbs)?;
+        let element = &tbs[..tbs.len() - rest.len()];
+        tbs = rest;
+        Some((tag, element))
+    };
+    // La versión es opcional y va en [0].
+    let (mut tag, mut serial) = next()?;
+    if tag == 0xA0 {
+        (tag, serial) = next()?;
+    }
+    let _algorithm = next()?;
+    let (_, issuer) = next()?;
+    (tag == 0x02).then(|| sequence(&[issuer, serial]))
+}
+
+/// Etiqueta y contenido del primer elemento DER de `data`, con lo que sigue.
+fn read_der(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = data.split_first()?;
+    let (&first, mut rest) = rest.split_first()?;
+    let len = if first < 0x80 {
+        first as usize
+    } else {
+        let count = (first & 0x7F) as usize;
+        if count > 4 || rest.len() < count {
+            return None;
+        }
+        let (bytes, tail) = rest.split_at(count);
+        rest = tail;
+        bytes.iter().fold(0, |len, &b| (len << 8) | b as usize)
+    };
+    (rest.len() >= len).then(|| (tag, &rest[..len], &rest[len..]))
+}
@@ -0,0 +1,271 @@
+use crate::builder::{self, ImageCache, OutputOptions};
+use crate::error::WatermarkError;
+use crate::pdf;
+use crate::progress::{Progress, ProgressSink, Stage, Tally};
+use crate::text::{Bates, PageContext};
+use crate::watermark::{Layer, Stamp};
+use anyhow::{anyhow, Result};
+use lopdf::{Dictionary, Document, Object, ObjectId, Stream};
+use std::io::Write;
+
+/// Cómo se aplica el watermark al PDF.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Mode {
+    /// Rasteriza cada página y reconstruye el PDF.
+    Raster,
+    /// Conserva las páginas originales y añade el watermark como XObject.
+    Stamp,
+}
+
+pub fn parse_mode(s: &str) -> Result<Mode, WatermarkError> {
+    match s {
+        "raster" => Ok(Mode::Raster),
+        "stamp" => Ok(Mode::Stamp),
+        other => Err(WatermarkError::InvalidOption(format!("Modo inválido: {} (usar raster o stamp)", other))),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn stamp_pdf_bytes(
+    data: &[u8],
+    password: &str,
+    page_indices: &[usize],
+    skip: &[usize],
+    filename: &str,
+    stamps: &[Stamp],
+    options: &OutputOptions,
+    progress: &dyn ProgressSink,
+) -> Result<Vec<u8>, WatermarkError> {
+    let doc = pdf::load_document(data, password)?;
+    let mut buf = Vec::new();
+    stamp_loaded(data, doc, page_indices, skip, filename, stamps, None, options, progress, &mut buf)?;
+    Ok(buf)
+}
+
+/// [`stamp_pdf_bytes`] con el documento ya cargado de `data`, escribiendo el
+/// PDF en `out`.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn stamp_loaded(
+    data: &[u8],
+    mut doc: Document,
+    page_indices: &[usize],
+    skip: &[usize],
+    filename: &str,
+    stamps: &[Stamp],
+    bates: Option<&Bates>,
+    options: &OutputOptions,
+    progress: &dyn ProgressSink,
+    out: &mut dyn Write,
+) -> Result<(), WatermarkError> {
+    if options.pdfa {
+        return Err(WatermarkError::InvalidOption("PDF/A solo está disponible en modo raster".to_string()));
+    }
+    let original = options.incremental.then(|| doc.clone());
+    stamp_document(&mut doc, page_indices, skip, filename, stamps, bates, progress)?;
+    match original {
+        Some(original) => builder::save_incremental(data, original, &doc, options, out)?,
+        None => builder::save(&mut doc, options, out)?,
+    }
+    Ok(())
+}
+
+/// Como [`stamp_loaded`], pero pasa a `each` cada página conservada como un
+/// PDF de una sola página, junto a su número en el PDF de entrada.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn stamp_split(
+    mut doc: Document,
+    page_indices: &[usize],
+    skip: &[usize],
+    filename: &str,
+    stamps: &[Stamp],
+    bates: Option<&Bates>,
+    options: &OutputOptions,
+    progress: &dyn ProgressSink,
+    each: &mut dyn FnMut(u32, Vec<u8>) -> Result<(), WatermarkError>,
+) -> Result<(), WatermarkError> {
+    if options.pdfa {
+        return Err(WatermarkError::InvalidOption("PDF/A solo está disponible en modo raster".to_string()));
+    }
+    if options.incremental {
+        return Err(WatermarkError::InvalidOption(
+            "La actualización incremental no admite separar las páginas".to_string(),
+        ));
+    }
+    let total = doc.get_pages().len();
+    // Tras eliminar las no elegidas quedan en el orden del PDF de entrada.
+    let kept: Vec<usize> = (0..total).filter(|i| page_indices.is_empty() || page_indices.contains(i)).collect();
+    stamp_document(&mut doc, page_indices, skip, filename, stamps, bates, progress)?;
+
+    progress.report(Progress::Stage(Stage::Building));
+    let tally = Tally::new(progress, kept.len());
+    for (n, &index) in kept.iter().enumerate() {
+        let mut page = doc.clone();
+        let others: Vec<u32> = (1..=kept.len() as u32).filter(|&p| p as usize != n + 1).collect();
+        page.delete_pages(&others);
+        page.prune_objects();
+        let mut buf = Vec::new();
+        builder::save(&mut page, options, &mut buf)?;
+        each(index as u32 + 1, buf)?;
+        tally.tick();
+    }
+    Ok(())
+}
+
+/// Dibuja los stamps sobre las páginas de `page_indices` (todas si está
+/// vacío) salvo las de `skip`, que se dejan como están, y elimina el resto,
+/// igual que el modo raster. Las páginas marcadas toman sus números de
+/// `bates`. Informa de cada página conservada a `progress`.
+pub fn stamp_document(
+    doc: &mut Document,
+    page_indices: &[usize],
+    skip: &[usize],
+    filename: &str,
+    stamps: &[Stamp],
+    bates: Option<&Bates>,
+    progress: &dyn ProgressSink,
+) -> Result<(), WatermarkError> {
+    let pages: Vec<_> = doc.get_pages().into_iter().collect();
+    let total = pages.len();
+    let selected = |i: usize| page_indices.is_empty() || page_indices.contains(&i);
+    if !(0..total).any(selected) {
+        return Err(WatermarkError::NoPagesSelected);
+    }
+
+    let date = crate::text::today();
+    let mut cache = Vec::new();
+    let tally = Tally::new(progress, (0..total).filter(|&i| selected(i)).count());
+    let mut next = bates.map(|bates| bates.reserve((0..total).filter(|&i| selected(i) && !skip.contains(&i)).count()));
+    for (i, &(num, page_id)) in pages.iter().enumerate() {
+        if !selected(i) {
+            continue;
+        }
+        let stamped = !skip.contains(&i);
+        if stamped {
+            let mut ctx = PageContext::new(i, total, filename, &date);
+            if let (Some(bates), Some(next)) = (bates, next.as_mut()) {
+                ctx = ctx.with_bates(bates.format(*next));
+                *next += 1;
+            }
+            stamp_page(doc, page_id, &ctx, stamps, &mut cache).map_err(|e| WatermarkError::page(num, e))?;
+        }
+        progress.report(Progress::Page { index: i, total, stamped });
+        tally.tick();
+    }
+
+    let unselected: Vec<u32> = pages
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !selected(*i))
+        .map(|(_, &(num, _))| num)
+        .collect();
+    if !unselected.is_empty() {
+        doc.delete_pages(&unselected);
+        doc.prune_objects();
+    }
+    Ok(())
+}
+
+fn stamp_page(
+    doc: &mut Document,
+    page_id: ObjectId,
+    ctx: &PageContext,
+    stamps: &[Stamp],
+    cache: &mut ImageCache,
+) -> Result<()> {
+    let [llx, lly, urx, ury] = pdf::crop_box(doc, page_id)?;
+    let rotation = pdf::rotation(doc, page_id);
+    // El watermark se coloca sobre la zona visible de la página tal como se muestra.
+    let (mut pw, mut ph) = ((urx - llx).round().max(1.0) as u32, (ury - lly).round().max(1.0) as u32);
+    if !rotation.is_multiple_of(180) {
+        std::mem::swap(&mut pw, &mut ph);
+    }
+    let to_user = |x: f64, y: f64| {
+        let (u, v) = match rotation {
+            90 => (y, x),
+            180 => (urx - llx - x, y),
+            270 => (urx - llx - y, ury - lly - x),
+            _ => (x, ury - lly - y),
+        };
+        (llx + u, lly + v)
+    };
+
+    let mut resources = match pdf::inherited_attr(doc, page_id, b"Resources") {
+        Some(obj) => pdf::resolve_to_dict(doc, &obj)?,
+        None => Dictionary::new(),
+    };
+    let mut xobjects = match resources.get(b"XObject") {
+        Ok(obj) => pdf::resolve_to_dict(doc, obj)?,
+        Err(_) => Dictionary::new(),
+    };
+
+    let mut under = String::new();
+    let mut over = String::new();
+    for stamp in stamps {
+        let layout = stamp.layout(pw, ph, ctx);
+        let (iw, ih) = layout.image.dimensions();
+        if iw == 0 || ih == 0 {
+            continue;
+        }
+
+        let image_id = builder::cached_image(doc, cache, &layout.image)?;
+        let name = (0..)
+            .map(|n| format!("Wm{}", n))
+            .find(|n| !xobjects.has(n.as_bytes()))
+            .unwrap_or_default();
+        xobjects.set(name.as_bytes(), Object::Reference(image_id));
+
+        let ops = if stamp.layer == Layer::Under { &mut under } else { &mut over };
+        for &(x, y) in &layout.origins {
+            // Esquinas inferior izquierda, inferior derecha y superior
+            // izquierda de la imagen, en coordenadas de la página sin girar.
+            let (x, y) = (x as f64, y as f64);
+            let (e, f) = to_user(x, y + ih as f64);
+            let (rx, ry) = to_user(x + iw as f64, y + ih as f64);
+            let (tx, ty) = to_user(x, y);
+            ops.push_str(&format!(
+                "q {} {} {} {} {} {} cm /{} Do Q\n",
+                rx - e,
+                ry - f,
+                tx - e,
+                ty - f,
+                e,
+                f,
+                name
+            ));
+        }
+    }
+    resources.set("XObject", Object::Dictionary(xobjects));
+
+    // El contenido original va entre q/Q para que su estado gráfico no
+    // afecte a los watermarks dibujados encima.
+    let before = doc.add_object(Stream::new(Dictionary::new(), format!("{}q\n", under).into_bytes()));
+    let after = doc.add_object(Stream::new(Dictionary::new(), format!("\nQ\n{}", over).into_bytes()));
+
+    let mut contents = vec![Object::Reference(before)];
+    contents.extend(page_contents(doc, page_id));
+    contents.push(Object::Reference(after));
+
+    let page = doc
+        .get_object_mut(page_id)?
+        .as_dict_mut()
+        .map_err(|_| anyhow!("Página no es un diccionario"))?;
+    page.set("Contents", contents);
+    page.set("Resources", Object::Dictionary(resources));
+    Ok(())
+}
+
+/// Streams de contenido de la página, aplanando `Contents` si es un array.
+fn page_contents(doc: &Document, page_id: ObjectId) -> Vec<Object> {
+    let Ok(contents) = doc.get_dictionary(page_id).and_then(|d| d.get(b"Contents")) else {
+        return Vec::new();
+    };
+    let array = match contents {
+        Object::Array(streams) => Some(streams),
+        Object::Reference(id) => doc.get_object(*id).and_then(Object::as_array).ok(),
+        _ => None,
+    };
+    match array {
+        Some(streams) => streams.clone(),
+        None => vec![contents.clone()],
+    }
+}
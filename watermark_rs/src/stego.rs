@@ -0,0 +1,72 @@
+//! Watermark invisible: un texto (ej. el destinatario del documento) guardado
+//! en el bit menos significativo de cada canal de color de las páginas, para
+//! saber de dónde sale una copia filtrada sin que se note en la página.
+//!
+//! Solo sobrevive a una codificación sin pérdida: JPEG lo borra.
+
+use crate::error::WatermarkError;
+use crate::pdf::{self, PageReader};
+use image::DynamicImage;
+
+/// Marca el principio del texto guardado, seguida de su longitud (u16).
+const MAGIC: &[u8; 4] = b"WMID";
+
+/// Guarda `payload` en los bits bajos de `image`, que pasa a RGB si no es
+/// RGB ni gris. Devuelve `false` sin cambiarla si no cabe.
+pub fn embed(image: &mut DynamicImage, payload: &[u8]) -> bool {
+    let Ok(len) = u16::try_from(payload.len()) else {
+        return false;
+    };
+    let record: Vec<u8> = MAGIC.iter().copied().chain(len.to_be_bytes()).chain(payload.iter().copied()).collect();
+    let samples = samples_mut(image);
+    if record.len() * 8 > samples.len() {
+        return false;
+    }
+    let bits = record.iter().flat_map(|byte| (0..8).rev().map(move |i| (byte >> i) & 1));
+    for (sample, bit) in samples.iter_mut().zip(bits) {
+        *sample = (*sample & !1) | bit;
+    }
+    true
+}
+
+/// Texto guardado con [`embed`] en `image`, si lo hay.
+pub fn extract(image: &DynamicImage) -> Option<Vec<u8>> {
+    if let DynamicImage::ImageLuma8(gray) = image {
+        return read(gray);
+    }
+    // Las páginas en gris se leen del PDF como RGB con los tres canales
+    // iguales: el texto está en cada uno de ellos.
+    let rgb = image.to_rgb8();
+    read(&rgb).or_else(|| read(&rgb.chunks_exact(3).map(|pixel| pixel[0]).collect::<Vec<_>>()))
+}
+
+fn read(samples: &[u8]) -> Option<Vec<u8>> {
+    let mut bytes = samples.chunks_exact(8).map(|bits| bits.iter().fold(0, |byte, sample| (byte << 1) | (sample & 1)));
+    if !MAGIC.iter().all(|&m| bytes.next() == Some(m)) {
+        return None;
+    }
+    let len = u16::from_be_bytes([bytes.next()?, bytes.next()?]) as usize;
+    let payload: Vec<u8> = bytes.take(len).collect();
+    (payload.len() == len).then_some(payload)
+}
+
+/// Textos guardados en las páginas del PDF `data`, con el número de cada
+/// página que tiene uno.
+pub fn extract_pdf(data: &[u8], password: &str) -> Result<Vec<(u32, Vec<u8>)>, WatermarkError> {
+    let mut reader = PageReader::new(data.to_vec(), password, pdf::DEFAULT_DPI)?;
+    let indices: Vec<usize> = (0..reader.len()).collect();
+    let pages = reader.pages(&indices)?;
+    Ok(pages.iter().filter_map(|page| Some((page.number, extract(&page.image)?))).collect())
+}
+
+/// Canales de color de `image`, uno por byte.
+fn samples_mut(image: &mut DynamicImage) -> &mut [u8] {
+    if !matches!(image, DynamicImage::ImageLuma8(_) | DynamicImage::ImageRgb8(_)) {
+        *image = DynamicImage::ImageRgb8(image.to_rgb8());
+    }
+    match image {
+        DynamicImage::ImageLuma8(img) => img,
+        DynamicImage::ImageRgb8(img) => img,
+        _ => unreachable!(),
+    }
+}
@@ -0,0 +1,203 @@
+use ab_glyph::{point, Font, FontArc, FontRef, PxScale, ScaleFont};
+use crate::error::WatermarkError;
+use image::{Rgba, RgbaImage};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+const DEFAULT_FONT: &[u8] = include_bytes!("../fonts/DejaVuSans-Bold.ttf");
+const VARIABLES: [&str; 5] = ["{page}", "{total}", "{date}", "{filename}", "{bates}"];
+
+/// Datos de la página disponibles en las plantillas de texto.
+pub struct PageContext<'a> {
+    /// Índice de la página en el documento (desde 0)
+    pub index: usize,
+    /// Número de páginas del documento
+    pub total: usize,
+    pub filename: &'a str,
+    pub date: &'a str,
+    /// Número Bates de la página, con sus ceros delante
+    pub bates: String,
+}
+
+impl<'a> PageContext<'a> {
+    pub fn new(index: usize, total: usize, filename: &'a str, date: &'a str) -> Self {
+        PageContext {
+            index,
+            total,
+            filename,
+            date,
+            bates: String::new(),
+        }
+    }
+
+    pub fn with_bates(mut self, bates: String) -> Self {
+        self.bates = bates;
+        self
+    }
+
+    /// Sustituye `{page}`, `{total}`, `{date}`, `{filename}` y `{bates}` en
+    /// `template`.
+    pub fn expand(&self, template: &str) -> String {
+        template
+            .replace("{page}", &(self.index + 1).to_string())
+            .replace("{total}", &self.total.to_string())
+            .replace("{date}", self.date)
+            .replace("{filename}", self.filename)
+            .replace("{bates}", &self.bates)
+    }
+}
+
+/// Numeración Bates para `{bates}`: cada página marcada recibe el siguiente
+/// número, que sigue de un PDF al siguiente mientras se use el mismo
+/// contador.
+pub struct Bates {
+    next: AtomicU64,
+    digits: usize,
+}
+
+impl Bates {
+    /// Empieza en `start` y escribe los números con al menos `digits` cifras.
+    pub fn new(start: u64, digits: usize) -> Self {
+        Bates { next: AtomicU64::new(start), digits }
+    }
+
+    /// Número de la siguiente página marcada.
+    pub fn next(&self) -> u64 {
+        self.next.load(Ordering::SeqCst)
+    }
+
+    /// Reserva `count` números seguidos y devuelve el primero.
+    pub(crate) fn reserve(&self, count: usize) -> u64 {
+        self.next.fetch_add(count as u64, Ordering::SeqCst)
+    }
+
+    pub(crate) fn format(&self, number: u64) -> String {
+        format!("{:01$}", number, self.digits)
+    }
+}
+
+/// Fecha local actual en formato AAAA-MM-DD, para `{date}`.
+pub fn today() -> String {
+    chrono::Local::now().format("%Y-%m-%d").to_string()
+}
+
+/// Fecha y hora actuales con un formato de strftime (ej. "%Y-%m-%d %H:%M")
+/// en la zona `zone`: "local", "utc" o un desfase fijo como "+02:00".
+pub fn timestamp(format: &str, zone: &str) -> Result<String, WatermarkError> {
+    use chrono::format::{Item, StrftimeItems};
+    if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+        return Err(WatermarkError::InvalidOption(format!("Formato de fecha inválido: {}", format)));
+    }
+    Ok(match zone.to_ascii_lowercase().as_str() {
+        "local" => chrono::Local::now().format(format).to_string(),
+        "utc" => chrono::Utc::now().format(format).to_string(),
+        _ => {
+            let offset: chrono::FixedOffset = zone.parse().map_err(|_| {
+                WatermarkError::InvalidOption(format!(
+                    "Zona horaria inválida: {} (usar local, utc o un desfase como +02:00)",
+                    zone
+                ))
+            })?;
+            chrono::Utc::now().with_timezone(&offset).format(format).to_string()
+        }
+    })
+}
+
+/// Texto de watermark con su estilo; puede contener variables como `{page}`.
+#[derive(Clone)]
+pub struct TextSpec {
+    pub template: String,
+    pub font_size: f32,
+    pub color: Rgba<u8>,
+    font: FontArc,
+}
+
+impl TextSpec {
+    /// `font_data` es un TTF/OTF; si es `None` se usa la fuente incluida.
+    pub fn new(
+        template: &str,
+        font_size: f32,
+        color: Rgba<u8>,
+        font_data: Option<&[u8]>,
+    ) -> Result<Self, WatermarkError> {
+        if template.is_empty() {
+            return Err(WatermarkError::InvalidOption("El texto del watermark está vacío".to_string()));
+        }
+        if font_size <= 0.0 {
+            return Err(WatermarkError::InvalidOption("El tamaño de fuente debe ser mayor que 0".to_string()));
+        }
+        let font = match font_data {
+            Some(data) => FontArc::try_from_vec(data.to_vec()),
+            None => FontRef::try_from_slice(DEFAULT_FONT).map(FontArc::new),
+        }
+        .map_err(|_| WatermarkError::InvalidOption("No se pudo leer la fuente".to_string()))?;
+
+        Ok(TextSpec {
+            template: template.to_string(),
+            font_size,
+            color,
+            font,
+        })
+    }
+
+    pub fn with_font_size(&self, font_size: f32) -> Self {
+        TextSpec {
+            font_size: font_size.max(1.0),
+            ..self.clone()
+        }
+    }
+
+    /// Indica si el texto cambia de una página a otra.
+    pub fn is_dynamic(&self) -> bool {
+        VARIABLES.iter().any(|v| self.template.contains(v))
+    }
+
+    /// Renderiza `text` (una o varias líneas) sobre un lienzo transparente.
+    pub fn render(&self, text: &str) -> RgbaImage {
+        let font = &self.font;
+        let color = self.color;
+        let scaled = font.as_scaled(PxScale::from(self.font_size));
+        let line_h = scaled.height() + scaled.line_gap();
+
+        let mut glyphs = Vec::new();
+        let mut width: f32 = 0.0;
+        let lines: Vec<&str> = text.lines().collect();
+        for (row, line) in lines.iter().enumerate() {
+            let baseline = scaled.ascent() + row as f32 * line_h;
+            let mut caret = 0.0;
+            let mut prev = None;
+            for c in line.chars() {
+                let id = scaled.glyph_id(c);
+                if let Some(prev) = prev {
+                    caret += scaled.kern(prev, id);
+                }
+                glyphs.push(id.with_scale_and_position(self.font_size, point(caret, baseline)));
+                caret += scaled.h_advance(id);
+                prev = Some(id);
+            }
+            width = width.max(caret);
+        }
+
+        let w = width.ceil().max(1.0) as u32;
+        let h = (line_h * lines.len().max(1) as f32).ceil().max(1.0) as u32;
+        let mut canvas = RgbaImage::new(w, h);
+        for glyph in glyphs {
+            let Some(outlined) = font.outline_glyph(glyph) else {
+                continue;
+            };
+            let bounds = outlined.px_bounds();
+            outlined.draw(|gx, gy, coverage| {
+                let x = bounds.min.x as i64 + gx as i64;
+                let y = bounds.min.y as i64 + gy as i64;
+                if x < 0 || y < 0 || x >= w as i64 || y >= h as i64 {
+                    return;
+                }
+                let alpha = (coverage.clamp(0.0, 1.0) * color[3] as f32).round() as u8;
+                let pixel = canvas.get_pixel_mut(x as u32, y as u32);
+                if alpha > pixel[3] {
+                    *pixel = Rgba([color[0], color[1], color[2], alpha]);
+                }
+            });
+        }
+        canvas
+    }
+}
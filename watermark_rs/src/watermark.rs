@@ -1,75 +1,1171 @@
-use anyhow::{anyhow, Result};
-use image::{DynamicImage, RgbaImage};
+use crate::text::{PageContext, TextSpec};
+use crate::error::WatermarkError;
+use crate::pages::{self, PageSelection};
+use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+use serde_json::Value;
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::io::Cursor;
 
 const WM_MAX_W: u32 = 120;
-const WM_OPACITY: f32 = 1.0;
-const WM_MARGIN: u32 = 0;
+const DIAGONAL_ANGLE: f32 = 45.0;
+const DIAGONAL_COVERAGE: f64 = 0.9;
+/// Opacidad de la marca en diagonal si no se indica otra: cruza el texto de
+/// la página, que tiene que seguir leyéndose.
+pub const DIAGONAL_OPACITY: f32 = 0.3;
+const UNDERLAY_TOLERANCE: f32 = 48.0;
+/// Grosor máximo del contorno, en px.
+pub const MAX_OUTLINE: u32 = 4;
+/// Opacidad de la sombra respecto a la del watermark.
+const SHADOW_OPACITY: f32 = 0.5;
+/// Diferencia de luminancia (0-255) con el fondo por debajo de la cual un
+/// watermark adaptativo prueba con los colores invertidos.
+const ADAPTIVE_CONTRAST: f64 = 80.0;
+
+/// Rectángulo redondeado y semitransparente detrás del watermark, para que
+/// se lea sobre fotos.
+#[derive(Clone, Copy)]
+pub struct Plate {
+    pub color: Rgba<u8>,
+    /// Margen en px entre el watermark y el borde del rectángulo, que es
+    /// también el radio de sus esquinas
+    pub padding: u32,
+}
+const ANCHORS: [&str; 9] = ["tl", "tc", "tr", "ml", "mc", "mr", "bl", "bc", "br"];
+/// Anclas que prueba `auto`, por orden de preferencia cuando tienen el mismo detalle.
+const AUTO_ANCHORS: [&str; 9] = ["br", "bl", "tr", "tl", "bc", "tc", "mr", "ml", "mc"];
 
 pub enum Quality {
     Lossless,
-    Jpeg(u8),
+    Jpeg(u8, Subsampling),
+}
+
+impl Quality {
+    /// Cambia el submuestreo de color, que solo tiene sentido en JPEG.
+    pub fn with_subsampling(self, subsampling: Subsampling) -> Result<Quality, WatermarkError> {
+        match self {
+            Quality::Jpeg(q, _) => Ok(Quality::Jpeg(q, subsampling)),
+            Quality::Lossless => Err(WatermarkError::InvalidOption(
+                "El submuestreo de color solo se aplica con --quality JPEG".to_string(),
+            )),
+        }
+    }
+}
+
+/// Submuestreo de color de las imágenes JPEG. 4:2:0 ocupa menos, pero emborrona
+/// los bordes de color, como el del texto pequeño.
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum Subsampling {
+    #[default]
+    S444,
+    S422,
+    S420,
 }
 
-pub fn parse_quality(s: &str) -> Result<Quality> {
+impl Subsampling {
+    /// Sufijo para las descripciones de la calidad, vacío para el de por defecto.
+    pub fn suffix(self) -> &'static str {
+        match self {
+            Subsampling::S444 => "",
+            Subsampling::S422 => " 4:2:2",
+            Subsampling::S420 => " 4:2:0",
+        }
+    }
+}
+
+pub fn parse_subsampling(s: &str) -> Result<Subsampling, WatermarkError> {
+    match s {
+        "4:4:4" | "444" => Ok(Subsampling::S444),
+        "4:2:2" | "422" => Ok(Subsampling::S422),
+        "4:2:0" | "420" => Ok(Subsampling::S420),
+        other => Err(WatermarkError::InvalidOption(format!(
+            "Submuestreo inválido: {} (usar 4:4:4, 4:2:2 o 4:2:0)",
+            other
+        ))),
+    }
+}
+
+pub fn parse_quality(s: &str) -> Result<Quality, WatermarkError> {
     if s == "lossless" {
         Ok(Quality::Lossless)
     } else {
         let q: u8 = s
             .parse()
-            .map_err(|_| anyhow!("--quality debe ser 'lossless' o un número 1-100"))?;
+            .map_err(|_| WatermarkError::InvalidOption("--quality debe ser 'lossless' o un número 1-100".to_string()))?;
         if !(1..=100).contains(&q) {
-            return Err(anyhow!("--quality debe estar entre 1 y 100"));
+            return Err(WatermarkError::InvalidOption("--quality debe estar entre 1 y 100".to_string()));
+        }
+        Ok(Quality::Jpeg(q, Subsampling::default()))
+    }
+}
+
+pub struct Watermark {
+    /// Watermark al tamaño fijo calculado en `prepare`
+    pub image: RgbaImage,
+    /// Origen del watermark, usado para redimensionar cuando hay `scale`
+    source: Source,
+    /// 0.0 = invisible, 1.0 = opaco
+    pub opacity: f32,
+    /// Ancho como fracción del ancho de cada página (reemplaza el tamaño fijo)
+    pub scale: Option<f32>,
+    /// Plantilla de texto, para watermarks que se renderizan por página
+    text: Option<TextSpec>,
+    /// Sombra difuminada debajo del watermark
+    pub shadow: bool,
+    /// Grosor en px del contorno en un color que contrasta (0 = sin contorno)
+    pub outline: u32,
+    /// Invierte los colores donde el fondo de la página contrasta poco
+    pub adaptive: bool,
+    /// Color con que se tiñe el watermark según su luminosidad
+    pub tint: Option<Rgba<u8>>,
+    /// Quita el color del watermark
+    pub desaturate: bool,
+    /// Fondo detrás del watermark
+    pub plate: Option<Plate>,
+}
+
+enum Source {
+    Raster(RgbaImage),
+    #[cfg(feature = "svg")]
+    Svg(Box<resvg::usvg::Tree>),
+}
+
+impl Source {
+    fn dimensions(&self) -> (u32, u32) {
+        match self {
+            Source::Raster(img) => img.dimensions(),
+            #[cfg(feature = "svg")]
+            Source::Svg(tree) => {
+                let size = tree.size();
+                (
+                    (size.width().round() as u32).max(1),
+                    (size.height().round() as u32).max(1),
+                )
+            }
+        }
+    }
+
+    fn render(&self, w: u32, h: u32) -> RgbaImage {
+        match self {
+            Source::Raster(img) => {
+                image::imageops::resize(img, w, h, image::imageops::FilterType::Lanczos3)
+            }
+            #[cfg(feature = "svg")]
+            Source::Svg(tree) => rasterize_svg(tree, w, h),
+        }
+    }
+}
+
+#[cfg(feature = "svg")]
+fn rasterize_svg(tree: &resvg::usvg::Tree, w: u32, h: u32) -> RgbaImage {
+    use resvg::tiny_skia::{Pixmap, Transform};
+
+    let Some(mut pixmap) = Pixmap::new(w.max(1), h.max(1)) else {
+        return RgbaImage::new(w, h);
+    };
+    let size = tree.size();
+    let transform = Transform::from_scale(
+        pixmap.width() as f32 / size.width(),
+        pixmap.height() as f32 / size.height(),
+    );
+    resvg::render(tree, transform, &mut pixmap.as_mut());
+
+    let mut img = RgbaImage::new(pixmap.width(), pixmap.height());
+    for (dst, src) in img.pixels_mut().zip(pixmap.pixels()) {
+        let c = src.demultiply();
+        *dst = Rgba([c.red(), c.green(), c.blue(), c.alpha()]);
+    }
+    img
+}
+
+#[cfg(feature = "svg")]
+fn looks_like_svg(data: &[u8]) -> bool {
+    if data.starts_with(&[0x1f, 0x8b]) {
+        return true; // svgz
+    }
+    let head = &data[..data.len().min(1024)];
+    let head = String::from_utf8_lossy(head);
+    head.trim_start().starts_with('<') && head.contains("<svg")
+}
+
+#[cfg(feature = "svg")]
+fn prepare_svg(data: &[u8], min_w: u32, min_h: u32) -> Result<Watermark, WatermarkError> {
+    let tree = resvg::usvg::Tree::from_data(data, &resvg::usvg::Options::default())
+        .map_err(|e| WatermarkError::InvalidLogo(format!("SVG inválido: {}", e)))?;
+    prepare_source(Source::Svg(Box::new(tree)), min_w, min_h)
+}
+
+impl Watermark {
+    fn new(image: RgbaImage, source: Source) -> Self {
+        Watermark {
+            image,
+            source,
+            opacity: 1.0,
+            scale: None,
+            text: None,
+            shadow: false,
+            outline: 0,
+            adaptive: false,
+            tint: None,
+            desaturate: false,
+            plate: None,
+        }
+    }
+
+    pub fn with_opacity(mut self, opacity: f32) -> Self {
+        self.opacity = opacity.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn with_scale(mut self, scale: Option<f32>) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// Sombra y contorno para que el watermark se lea sobre fondos
+    /// recargados. Amplían la imagen lo justo para que no se recorten.
+    pub fn with_effects(mut self, shadow: bool, outline: u32) -> Self {
+        self.shadow = shadow;
+        self.outline = outline.min(MAX_OUTLINE);
+        self
+    }
+
+    /// Watermark a su tamaño preparado, con el texto ya expandido.
+    fn base(&self, ctx: &PageContext) -> Cow<'_, RgbaImage> {
+        match &self.text {
+            Some(spec) if spec.is_dynamic() => Cow::Owned(spec.render(&ctx.expand(&spec.template))),
+            _ => Cow::Borrowed(&self.image),
+        }
+    }
+
+    /// Renderiza el watermark con ancho `width`, sin reescalar píxeles cuando
+    /// el origen es texto o SVG.
+    fn render_width(&self, width: u32, ctx: &PageContext) -> RgbaImage {
+        let width = width.max(1);
+        if let Some(spec) = &self.text {
+            let text = ctx.expand(&spec.template);
+            let natural = spec.render(&text).width().max(1);
+            let font_size = spec.font_size * width as f32 / natural as f32;
+            return spec.with_font_size(font_size).render(&text);
+        }
+        let (sw, sh) = self.source.dimensions();
+        let height = ((width as f64 * sh as f64 / sw as f64).round() as u32).max(1);
+        self.source.render(width, height)
+    }
+
+    /// Watermark listo para superponer en una página de ancho `page_w`.
+    fn render_for(&self, page_w: u32, ctx: &PageContext) -> Cow<'_, RgbaImage> {
+        let mut img = match self.scale {
+            Some(scale) => Cow::Owned(self.render_width((page_w as f32 * scale).round() as u32, ctx)),
+            None => self.base(ctx),
+        };
+        self.finish(&mut img);
+        img
+    }
+
+    /// Invierte los colores de las copias que se pierden en el fondo de la
+    /// página, como un logo negro en una diapositiva oscura (solo en modo
+    /// raster, donde se conocen los píxeles de la página).
+    pub fn with_adaptive(mut self, adaptive: bool) -> Self {
+        self.adaptive = adaptive;
+        self
+    }
+
+    /// Tiñe el watermark de `tint`, para usar un mismo logo blanco en los
+    /// colores de cada marca.
+    pub fn with_tint(mut self, tint: Option<Rgba<u8>>) -> Self {
+        self.tint = tint;
+        self
+    }
+
+    /// Pasa el watermark a escala de grises, para documentos en los que no se
+    /// admiten sellos de color.
+    pub fn with_desaturate(mut self, desaturate: bool) -> Self {
+        self.desaturate = desaturate;
+        self
+    }
+
+    pub fn with_plate(mut self, plate: Option<Plate>) -> Self {
+        self.plate = plate;
+        self
+    }
+
+    /// Quita el color y aplica el tinte (por ese orden: con los dos, el logo
+    /// queda del tinte), añade el contorno, la sombra y el fondo y aplica la
+    /// opacidad.
+    fn finish(&self, img: &mut Cow<'_, RgbaImage>) {
+        if self.desaturate {
+            for pixel in img.to_mut().pixels_mut() {
+                let gray = luma(*pixel).round() as u8;
+                *pixel = Rgba([gray, gray, gray, pixel[3]]);
+            }
+        }
+        if let Some(tint) = self.tint {
+            // Multiplica: el blanco pasa a ser el tinte y las sombras se
+            // conservan más oscuras. El alfa del tinte atenúa el del logo.
+            for pixel in img.to_mut().pixels_mut() {
+                let shade = luma(*pixel);
+                for c in 0..3 {
+                    pixel[c] = (tint[c] as f64 * shade / 255.0).round() as u8;
+                }
+                pixel[3] = (pixel[3] as u16 * tint[3] as u16 / 255) as u8;
+            }
+        }
+        if self.outline > 0 {
+            *img = Cow::Owned(outline(img, self.outline));
+        }
+        if self.shadow {
+            *img = Cow::Owned(drop_shadow(img));
+        }
+        if let Some(plate) = self.plate {
+            *img = Cow::Owned(backing_plate(img, plate));
+        }
+        if self.opacity < 1.0 {
+            for pixel in img.to_mut().pixels_mut() {
+                pixel[3] = (pixel[3] as f32 * self.opacity).round() as u8;
+            }
+        }
+    }
+}
+
+/// Opacidad como fracción ("0.3") o porcentaje ("30%").
+pub fn parse_opacity(s: &str) -> Result<f32, WatermarkError> {
+    let err = || WatermarkError::InvalidOption("--opacity debe ser un valor entre 0 y 1 o un porcentaje 0%-100%".to_string());
+    let value = match s.trim().strip_suffix('%') {
+        Some(pct) => pct.trim().parse::<f32>().map_err(|_| err())? / 100.0,
+        None => s.trim().parse::<f32>().map_err(|_| err())?,
+    };
+    if !(0.0..=1.0).contains(&value) {
+        return Err(err());
+    }
+    Ok(value)
+}
+
+/// Escala como fracción ("0.1") o porcentaje ("10%") del ancho de página.
+pub fn parse_scale(s: &str) -> Result<f32, WatermarkError> {
+    let err = || WatermarkError::InvalidOption("--scale debe ser un porcentaje (10%) o fracción (0.1) mayor que 0".to_string());
+    let value = match s.trim().strip_suffix('%') {
+        Some(pct) => pct.trim().parse::<f32>().map_err(|_| err())? / 100.0,
+        None => s.trim().parse::<f32>().map_err(|_| err())?,
+    };
+    if !(value > 0.0 && value <= 1.0) {
+        return Err(err());
+    }
+    Ok(value)
+}
+
+#[derive(Clone)]
+pub enum Placement {
+    /// Una sola copia anclada: "tl","tc","tr","ml","mc","mr","bl","bc","br",
+    /// separada `margin` px de los bordes
+    Anchor { position: String, margin: u32 },
+    /// Esquina superior izquierda del watermark en (x, y)
+    Absolute { x: Coord, y: Coord },
+    /// Copias repetidas por toda la página, separadas por `spacing_x`/`spacing_y` px
+    Tile { spacing_x: u32, spacing_y: u32 },
+    /// Una copia grande, centrada y girada `angle` grados (sello tipo BORRADOR)
+    Diagonal { angle: f32 },
+    /// Una copia en la de las nueve anclas con menos detalle de cada página,
+    /// para no tapar gráficos ni caras. Sin los píxeles de la página (modo
+    /// stamp), abajo a la derecha
+    Auto { margin: u32 },
+}
+
+pub fn parse_placement(
+    position: &str,
+    margin: u32,
+    spacing_x: u32,
+    spacing_y: u32,
+) -> Result<Placement, WatermarkError> {
+    if position == "tile" {
+        Ok(Placement::Tile { spacing_x, spacing_y })
+    } else if position == "diagonal" {
+        Ok(Placement::Diagonal {
+            angle: DIAGONAL_ANGLE,
+        })
+    } else if position == "auto" {
+        Ok(Placement::Auto { margin })
+    } else if ANCHORS.contains(&position) {
+        Ok(Placement::Anchor {
+            position: position.to_string(),
+            margin,
+        })
+    } else {
+        Err(WatermarkError::InvalidOption(format!(
+            "Posición inválida '{}': use tl,tc,tr,ml,mc,mr,bl,bc,br, tile, diagonal o auto",
+            position
+        )))
+    }
+}
+
+#[derive(Clone)]
+pub enum Coord {
+    /// Píxeles desde el borde superior/izquierdo
+    Px(i64),
+    /// Porcentaje del ancho/alto de la página
+    Percent(f64),
+}
+
+impl Coord {
+    fn resolve(&self, extent: u32) -> i64 {
+        match self {
+            Coord::Px(v) => *v,
+            Coord::Percent(p) => (extent as f64 * p / 100.0).round() as i64,
         }
-        Ok(Quality::Jpeg(q))
     }
 }
 
-pub fn prepare_from_bytes(data: &[u8], min_w: u32, min_h: u32) -> Result<RgbaImage> {
+/// Coordenadas explícitas del origen del watermark, medidas desde la esquina
+/// superior izquierda de la página: "120" (px) o "85%" (del ancho/alto).
+pub fn parse_point(x: &str, y: &str) -> Result<Placement, WatermarkError> {
+    Ok(Placement::Absolute {
+        x: parse_coord(x, "x")?,
+        y: parse_coord(y, "y")?,
+    })
+}
+
+fn parse_coord(v: &str, axis: &str) -> Result<Coord, WatermarkError> {
+    let err = || WatermarkError::InvalidOption(format!("Coordenada {} inválida '{}': use píxeles (120) o porcentaje (85%)", axis, v));
+    match v.trim().strip_suffix('%') {
+        Some(pct) => Ok(Coord::Percent(pct.trim().parse().map_err(|_| err())?)),
+        None => Ok(Coord::Px(v.trim().parse().map_err(|_| err())?)),
+    }
+}
+
+/// Rectángulo que los watermarks no deben tapar, como un pie legal, en las
+/// páginas de `pages` (vacía = todas).
+#[derive(Clone)]
+pub struct Zone {
+    pages: PageSelection,
+    x: Coord,
+    y: Coord,
+    width: Coord,
+    height: Coord,
+}
+
+impl Zone {
+    /// Bordes izquierdo, superior, derecho e inferior en la página de `ctx`,
+    /// de `pw`×`ph`, o `None` si no es una de sus páginas.
+    fn rect(&self, ctx: &PageContext, pw: u32, ph: u32) -> Option<[i64; 4]> {
+        if !self.pages.indices(ctx.total).ok()?.contains(&ctx.index) {
+            return None;
+        }
+        let (x, y) = (self.x.resolve(pw), self.y.resolve(ph));
+        Some([x, y, x + self.width.resolve(pw), y + self.height.resolve(ph)])
+    }
+}
+
+/// "x,y,ancho,alto" en px o % de la página, ej. "0,90%,100%,10%", con las
+/// páginas delante si no son todas: "1-3:0,90%,100%,10%".
+pub fn parse_zone(s: &str) -> Result<Zone, WatermarkError> {
+    let (spec, rect) = s.split_once(':').unwrap_or(("", s));
+    let parts: Vec<&str> = rect.split(',').collect();
+    let [x, y, width, height] = parts[..] else {
+        return Err(WatermarkError::InvalidOption(format!(
+            "Zona inválida '{}': use x,y,ancho,alto en px o % y las páginas delante (1-3:0,90%,100%,10%)",
+            s
+        )));
+    };
+    Ok(Zone {
+        pages: pages::parse_pages(spec)?,
+        x: parse_coord(x, "x")?,
+        y: parse_coord(y, "y")?,
+        width: parse_coord(width, "del ancho")?,
+        height: parse_coord(height, "del alto")?,
+    })
+}
+
+/// Posición por página; las páginas sin entrada usan `default`.
+#[derive(Clone)]
+pub struct PagePlacements {
+    pub default: Placement,
+    /// Índice de página (desde 0) → posición
+    pub pages: HashMap<usize, Placement>,
+}
+
+impl PagePlacements {
+    pub fn uniform(default: Placement) -> Self {
+        PagePlacements {
+            default,
+            pages: HashMap::new(),
+        }
+    }
+
+    pub fn for_page(&self, index: usize) -> &Placement {
+        self.pages.get(&index).unwrap_or(&self.default)
+    }
+
+    /// Si alguna página usa `Placement::Auto`.
+    pub fn has_auto(&self) -> bool {
+        std::iter::once(&self.default)
+            .chain(self.pages.values())
+            .any(|placement| matches!(placement, Placement::Auto { .. }))
+    }
+}
+
+/// JSON con números de página (desde 1) como claves, p. ej.
+/// `{"1": "tc", "2": {"x": "85%", "y": 40}, "5": {"position": "br", "margin": 20}}`.
+/// `margin` y los `spacing` se aplican a las entradas que no los indiquen.
+pub fn parse_page_placements(
+    json: &str,
+    default: Placement,
+    margin: u32,
+    spacing_x: u32,
+    spacing_y: u32,
+) -> Result<PagePlacements, WatermarkError> {
+    let root: Value =
+        serde_json::from_str(json).map_err(|e| WatermarkError::InvalidOption(format!("JSON de posiciones inválido: {}", e)))?;
+    let entries = root
+        .as_object()
+        .ok_or_else(|| WatermarkError::InvalidOption("El JSON de posiciones debe ser un objeto {\"página\": posición}".to_string()))?;
+
+    let mut placements = PagePlacements::uniform(default);
+    for (key, value) in entries {
+        let page: usize = key
+            .trim()
+            .parse()
+            .ok()
+            .filter(|&p| p >= 1)
+            .ok_or_else(|| WatermarkError::InvalidOption(format!("Número de página inválido en posiciones: '{}'", key)))?;
+        let placement = placement_from_json(value, margin, spacing_x, spacing_y)
+            .map_err(|e| WatermarkError::InvalidOption(format!("Página {}: {}", page, e)))?;
+        placements.pages.insert(page - 1, placement);
+    }
+    Ok(placements)
+}
+
+fn placement_from_json(value: &Value, margin: u32, spacing_x: u32, spacing_y: u32) -> Result<Placement, WatermarkError> {
+    let as_text = |v: &Value| match v {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    };
+
+    match value {
+        Value::String(position) => parse_placement(position, margin, spacing_x, spacing_y),
+        Value::Object(obj) => {
+            if let (Some(x), Some(y)) = (obj.get("x"), obj.get("y")) {
+                let x = as_text(x).ok_or_else(|| WatermarkError::InvalidOption("'x' debe ser número o texto".to_string()))?;
+                let y = as_text(y).ok_or_else(|| WatermarkError::InvalidOption("'y' debe ser número o texto".to_string()))?;
+                return parse_point(&x, &y);
+            }
+            let position = obj
+                .get("position")
+                .and_then(Value::as_str)
+                .ok_or_else(|| WatermarkError::InvalidOption("se esperaba 'position' o 'x'/'y'".to_string()))?;
+            let margin = match obj.get("margin") {
+                Some(m) => m
+                    .as_u64()
+                    .ok_or_else(|| WatermarkError::InvalidOption("'margin' debe ser un entero positivo".to_string()))?
+                    as u32,
+                None => margin,
+            };
+            parse_placement(position, margin, spacing_x, spacing_y)
+        }
+        _ => Err(WatermarkError::InvalidOption("se esperaba texto u objeto".to_string())),
+    }
+}
+
+pub fn prepare_from_bytes(data: &[u8], min_w: u32, min_h: u32) -> Result<Watermark, WatermarkError> {
+    #[cfg(feature = "svg")]
+    if looks_like_svg(data) {
+        return prepare_svg(data, min_w, min_h);
+    }
+
     let cursor = Cursor::new(data);
     let logo = image::load(cursor, image::ImageFormat::Png)
         .or_else(|_| {
             let cursor = Cursor::new(data);
             image::load(cursor, image::ImageFormat::Jpeg)
         })
-        .or_else(|_| image::load_from_memory(data))?
+        .or_else(|_| image::load_from_memory(data))
+        .map_err(|e| WatermarkError::InvalidLogo(e.to_string()))?
         .into_rgba8();
     prepare_logo(logo, min_w, min_h)
 }
 
 #[cfg(not(target_arch = "wasm32"))]
-pub fn prepare(logo_path: &str, min_w: u32, min_h: u32) -> Result<RgbaImage> {
-    let logo = image::open(logo_path)?.into_rgba8();
+pub fn prepare(logo_path: &str, min_w: u32, min_h: u32) -> Result<Watermark, WatermarkError> {
+    #[cfg(feature = "svg")]
+    {
+        let lower = logo_path.to_ascii_lowercase();
+        if lower.ends_with(".svg") || lower.ends_with(".svgz") {
+            let data = std::fs::read(logo_path).map_err(|e| WatermarkError::InvalidLogo(e.to_string()))?;
+            return prepare_svg(&data, min_w, min_h);
+        }
+    }
+
+    let logo = image::open(logo_path)
+        .map_err(|e| WatermarkError::InvalidLogo(e.to_string()))?
+        .into_rgba8();
     prepare_logo(logo, min_w, min_h)
 }
 
-fn prepare_logo(logo: RgbaImage, min_w: u32, min_h: u32) -> Result<RgbaImage> {
-    let (orig_w, orig_h) = logo.dimensions();
+/// Color en formato "#RRGGBB" o "#RRGGBBAA".
+pub fn parse_color(s: &str) -> Result<Rgba<u8>, WatermarkError> {
+    let hex = s.trim().trim_start_matches('#');
+    if hex.len() != 6 && hex.len() != 8 {
+        return Err(WatermarkError::InvalidOption(format!("Color inválido '{}': use #RRGGBB o #RRGGBBAA", s)));
+    }
+    let channel = |i: usize| {
+        u8::from_str_radix(&hex[i..i + 2], 16)
+            .map_err(|_| WatermarkError::InvalidOption(format!("Color inválido '{}': use #RRGGBB o #RRGGBBAA", s)))
+    };
+    let alpha = if hex.len() == 8 { channel(6)? } else { 255 };
+    Ok(Rgba([channel(0)?, channel(2)?, channel(4)?, alpha]))
+}
+
+/// Watermark de texto; `text` puede incluir `{page}`, `{total}`, `{date}` y
+/// `{filename}`, que se sustituyen en cada página.
+/// `font_data` es un TTF/OTF; si es `None` se usa la fuente incluida.
+pub fn prepare_text(
+    text: &str,
+    font_size: f32,
+    color: Rgba<u8>,
+    font_data: Option<&[u8]>,
+) -> Result<Watermark, WatermarkError> {
+    let spec = TextSpec::new(text, font_size, color, font_data)?;
+    let image = spec.render(text);
+    let mut mark = Watermark::new(image.clone(), Source::Raster(image));
+    mark.text = Some(spec);
+    Ok(mark)
+}
+
+fn prepare_logo(logo: RgbaImage, min_w: u32, min_h: u32) -> Result<Watermark, WatermarkError> {
+    prepare_source(Source::Raster(logo), min_w, min_h)
+}
+
+fn prepare_source(source: Source, min_w: u32, min_h: u32) -> Result<Watermark, WatermarkError> {
+    let (orig_w, orig_h) = source.dimensions();
     let (new_w, new_h) = calc_size(orig_w, orig_h, min_w, min_h);
 
-    let resized = image::imageops::resize(
-        &logo,
-        new_w,
-        new_h,
-        image::imageops::FilterType::Lanczos3,
-    );
+    let resized = source.render(new_w, new_h);
+
+    Ok(Watermark::new(resized, source))
+}
+
+/// Un watermark preparado junto con su posición en cada página.
+pub struct Stamp {
+    pub mark: Watermark,
+    pub placements: PagePlacements,
+    pub layer: Layer,
+    /// Zonas que no debe tapar
+    pub avoid: Vec<Zone>,
+}
+
+impl Stamp {
+    pub fn new(mark: Watermark, placements: PagePlacements) -> Self {
+        Stamp {
+            mark,
+            placements,
+            layer: Layer::Over,
+            avoid: Vec::new(),
+        }
+    }
+
+    pub fn with_layer(mut self, layer: Layer) -> Self {
+        self.layer = layer;
+        self
+    }
+
+    /// Si una copia suelta toca alguna de las `zones` se mueve al ancla
+    /// libre más cercana y las de un mosaico que tocan alguna se quitan. La
+    /// diagonal no se mueve.
+    pub fn with_avoid(mut self, zones: Vec<Zone>) -> Self {
+        self.avoid = zones;
+        self
+    }
 
-    let mut result = resized;
-    if WM_OPACITY < 1.0 {
-        for pixel in result.pixels_mut() {
-            pixel[3] = (pixel[3] as f32 * WM_OPACITY) as u8;
+    /// Cómo se dibuja en una página de `pw`×`ph`, fuera de las zonas a evitar.
+    pub fn layout(&self, pw: u32, ph: u32, ctx: &PageContext) -> Layout<'_> {
+        let placement = self.placements.for_page(ctx.index);
+        let mut layout = layout(&self.mark, placement, pw, ph, ctx);
+        let zones = self.zones(ctx, pw, ph);
+        let (ww, wh) = layout.image.dimensions();
+        let hits = |&(x, y): &(i64, i64)| zones.iter().any(|&zone| overlaps(zone, x, y, ww, wh));
+        match placement {
+            Placement::Tile { .. } => layout.origins.retain(|origin| !hits(origin)),
+            Placement::Diagonal { .. } => {}
+            Placement::Anchor { margin, .. } | Placement::Auto { margin } if layout.origins.iter().any(hits) => {
+                layout.origins = vec![nearest_free(layout.origins[0], *margin, pw, ph, ww, wh, hits, ctx)];
+            }
+            Placement::Absolute { .. } if layout.origins.iter().any(hits) => {
+                layout.origins = vec![nearest_free(layout.origins[0], 0, pw, ph, ww, wh, hits, ctx)];
+            }
+            _ => {}
         }
+        layout
+    }
+
+    /// Rectángulos de las zonas a evitar en la página de `ctx`.
+    fn zones(&self, ctx: &PageContext, pw: u32, ph: u32) -> Vec<[i64; 4]> {
+        self.avoid.iter().filter_map(|zone| zone.rect(ctx, pw, ph)).collect()
     }
+}
 
-    Ok(result)
+/// Si un watermark de `w`×`h` en (`x`, `y`) se solapa con `zone`.
+fn overlaps(zone: [i64; 4], x: i64, y: i64, w: u32, h: u32) -> bool {
+    let [left, top, right, bottom] = zone;
+    x < right && x + w as i64 > left && y < bottom && y + h as i64 > top
 }
 
-/// position: "tl","tc","tr","ml","mc","mr","bl","bc","br"
-pub fn apply(page: &DynamicImage, wm: &RgbaImage, position: &str) -> DynamicImage {
+/// El origen de ancla más cercano a `origin` en el que un watermark de
+/// `ww`×`wh` no toca ninguna zona, o `origin` si no queda ninguno.
+#[allow(clippy::too_many_arguments)]
+fn nearest_free(
+    origin: (i64, i64),
+    margin: u32,
+    pw: u32,
+    ph: u32,
+    ww: u32,
+    wh: u32,
+    hits: impl Fn(&(i64, i64)) -> bool,
+    ctx: &PageContext,
+) -> (i64, i64) {
+    let distance = |&(x, y): &(i64, i64)| (x - origin.0).pow(2) + (y - origin.1).pow(2);
+    let free = ANCHORS
+        .iter()
+        .map(|position| anchor_origin(position, margin, pw, ph, ww, wh))
+        .filter(|candidate| !hits(candidate))
+        .min_by_key(distance);
+    if free.is_none() {
+        log::warn!("Página {}: el watermark tapa una zona a evitar porque no cabe en otro sitio", ctx.index + 1);
+    }
+    free.unwrap_or(origin)
+}
+
+/// Orden de composición del watermark respecto al contenido de la página.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Layer {
+    /// Encima del contenido
+    Over,
+    /// Debajo del contenido: sólo se ve sobre el color de fondo de la página
+    Under,
+}
+
+pub fn parse_layer(s: &str) -> Result<Layer, WatermarkError> {
+    match s {
+        "over" => Ok(Layer::Over),
+        "under" => Ok(Layer::Under),
+        other => Err(WatermarkError::InvalidOption(format!("Capa inválida '{}': use over o under", other))),
+    }
+}
+
+/// Aplica los `stamps`, en orden, sobre la página descrita por `ctx`. Los que
+/// van debajo del contenido se funden con la imagen; los de encima se
+/// devuelven aparte para dibujarlos sobre ella como XObjects, sin tocar sus
+/// píxeles.
+pub fn apply<'a>(
+    page: &'a DynamicImage,
+    ctx: &PageContext,
+    stamps: &[Stamp],
+) -> (Cow<'a, DynamicImage>, Vec<Layout<'static>>) {
+    let overlays = stamps
+        .iter()
+        .filter(|s| s.layer == Layer::Over)
+        .flat_map(|s| {
+            let layout = layout_on(s, page, ctx);
+            match s.mark.adaptive {
+                true => adapt(layout, page),
+                false => vec![layout.into_owned()],
+            }
+        })
+        .filter(|l| l.image.width() > 0 && l.image.height() > 0)
+        .collect();
+    if !stamps.iter().any(|s| s.layer == Layer::Under) {
+        return (Cow::Borrowed(page), overlays);
+    }
+
     let mut canvas = page.to_rgba8();
+    let background = background_color(&canvas);
+    for stamp in stamps.iter().filter(|s| s.layer == Layer::Under) {
+        underlay_stamp(&mut canvas, stamp, ctx, background);
+    }
+    (Cow::Owned(DynamicImage::ImageRgba8(canvas)), overlays)
+}
+
+/// Separa las copias de `layout` que contrastan poco con la zona de `page`
+/// que tapan y las dibuja con los colores invertidos.
+fn adapt(layout: Layout<'_>, page: &DynamicImage) -> Vec<Layout<'static>> {
+    let Some(luma) = mean_luma(layout.image.pixels().copied()) else {
+        return vec![layout.into_owned()];
+    };
+    let (w, h) = layout.image.dimensions();
+    let (inverted, kept): (Vec<_>, Vec<_>) = layout.origins.iter().partition(|&&(x, y)| {
+        region_luma(page, x, y, w, h).is_some_and(|background| needs_inversion(luma, background))
+    });
+    let mut layouts = Vec::new();
+    if !inverted.is_empty() {
+        layouts.push(Layout { image: Cow::Owned(invert(&layout.image)), origins: inverted });
+    }
+    if !kept.is_empty() {
+        layouts.push(Layout { image: Cow::Owned(layout.image.into_owned()), origins: kept });
+    }
+    layouts
+}
+
+/// Luminancia media de `pixels`, ponderada por su alfa (`None` si son todos
+/// transparentes).
+fn mean_luma(pixels: impl Iterator<Item = Rgba<u8>>) -> Option<f64> {
+    let (mut sum, mut weight) = (0.0, 0.0);
+    for p in pixels {
+        let a = p[3] as f64;
+        sum += luma(p) * a;
+        weight += a;
+    }
+    (weight > 0.0).then(|| sum / weight)
+}
+
+fn luma(p: Rgba<u8>) -> f64 {
+    0.299 * p[0] as f64 + 0.587 * p[1] as f64 + 0.114 * p[2] as f64
+}
+
+/// Luminancia media de la zona de `page` de `w`×`h` con la esquina superior
+/// izquierda en (`x`, `y`), muestreada en una rejilla de como mucho 32×32.
+fn region_luma(page: &DynamicImage, x: i64, y: i64, w: u32, h: u32) -> Option<f64> {
+    let (pw, ph) = page.dimensions();
+    let (left, top) = (x.max(0) as u32, y.max(0) as u32);
+    let right = (x + w as i64).clamp(0, pw as i64) as u32;
+    let bottom = (y + h as i64).clamp(0, ph as i64) as u32;
+    let step_x = ((right.saturating_sub(left)) / 32).max(1) as usize;
+    let step_y = ((bottom.saturating_sub(top)) / 32).max(1) as usize;
+    let samples = (top..bottom)
+        .step_by(step_y)
+        .flat_map(|py| (left..right).step_by(step_x).map(move |px| (px, py)))
+        .map(|(px, py)| {
+            let mut p = page.get_pixel(px, py);
+            p[3] = 255;
+            p
+        });
+    mean_luma(samples)
+}
+
+/// Si un watermark de luminancia `mark` se ve mejor invertido sobre un
+/// fondo de luminancia `background`.
+fn needs_inversion(mark: f64, background: f64) -> bool {
+    let contrast = (mark - background).abs();
+    contrast < ADAPTIVE_CONTRAST && (255.0 - mark - background).abs() > contrast
+}
+
+fn invert(img: &RgbaImage) -> RgbaImage {
+    let mut out = img.clone();
+    for p in out.pixels_mut() {
+        p[0] = 255 - p[0];
+        p[1] = 255 - p[1];
+        p[2] = 255 - p[2];
+    }
+    out
+}
+
+/// Color más frecuente en el borde de la página, tomado como fondo.
+fn background_color(canvas: &RgbaImage) -> [u8; 3] {
+    let (w, h) = canvas.dimensions();
+    let mut counts: HashMap<[u8; 3], usize> = HashMap::new();
+    let mut count = |x: u32, y: u32| {
+        let p = canvas.get_pixel(x, y);
+        // Cuantizado a 16 niveles para tolerar ruido de compresión
+        *counts.entry([p[0] >> 4, p[1] >> 4, p[2] >> 4]).or_default() += 1;
+    };
+    for x in 0..w {
+        count(x, 0);
+        count(x, h - 1);
+    }
+    for y in 0..h {
+        count(0, y);
+        count(w - 1, y);
+    }
+    let bucket = counts
+        .into_iter()
+        .max_by_key(|(_, n)| *n)
+        .map(|(c, _)| c)
+        .unwrap_or([15, 15, 15]);
+    [bucket[0] << 4 | 8, bucket[1] << 4 | 8, bucket[2] << 4 | 8]
+}
+
+/// Funde `wm` debajo del contenido de `canvas`, cuyo color de fondo es `bg`.
+fn underlay(canvas: &mut RgbaImage, wm: &RgbaImage, x: i64, y: i64, bg: [u8; 3]) {
     let (pw, ph) = canvas.dimensions();
-    let (ww, wh) = wm.dimensions();
-    let m = WM_MARGIN as i64;
+    for (wx, wy, src) in wm.enumerate_pixels() {
+        let (cx, cy) = (x + wx as i64, y + wy as i64);
+        if src[3] == 0 || cx < 0 || cy < 0 || cx >= pw as i64 || cy >= ph as i64 {
+            continue;
+        }
+        let dst = canvas.get_pixel_mut(cx as u32, cy as u32);
+        let dist = (0..3)
+            .map(|c| (dst[c] as i32 - bg[c] as i32).abs())
+            .max()
+            .unwrap_or(0);
+        // Píxeles cercanos al fondo dejan ver el watermark; el contenido lo tapa
+        let visibility = 1.0 - (dist as f32 / UNDERLAY_TOLERANCE).min(1.0);
+        let a = src[3] as f32 / 255.0 * visibility;
+        for c in 0..3 {
+            dst[c] = (dst[c] as f32 * (1.0 - a) + src[c] as f32 * a).round() as u8;
+        }
+    }
+}
+
+fn underlay_stamp(canvas: &mut RgbaImage, stamp: &Stamp, ctx: &PageContext, background: [u8; 3]) {
+    let mark = &stamp.mark;
+    let mut layout = layout_on(stamp, &*canvas, ctx);
+    // Debajo del contenido solo se ve sobre el fondo, así que basta con su color.
+    if mark.adaptive {
+        let background = luma(Rgba([background[0], background[1], background[2], 255]));
+        if mean_luma(layout.image.pixels().copied()).is_some_and(|mark| needs_inversion(mark, background)) {
+            layout.image = Cow::Owned(invert(&layout.image));
+        }
+    }
+    for &(x, y) in &layout.origins {
+        underlay(canvas, &layout.image, x, y, background);
+    }
+}
+
+/// Watermark renderizado para una página y las posiciones (esquina superior
+/// izquierda, en unidades de página) donde se dibuja.
+#[derive(Clone)]
+pub struct Layout<'a> {
+    pub image: Cow<'a, RgbaImage>,
+    pub origins: Vec<(i64, i64)>,
+}
+
+impl Layout<'_> {
+    pub fn into_owned(self) -> Layout<'static> {
+        Layout {
+            image: Cow::Owned(self.image.into_owned()),
+            origins: self.origins,
+        }
+    }
+}
+
+/// Calcula cómo se dibuja `mark` en una página de `pw`×`ph`.
+pub fn layout<'a>(
+    mark: &'a Watermark,
+    placement: &Placement,
+    pw: u32,
+    ph: u32,
+    ctx: &PageContext,
+) -> Layout<'a> {
+    let image = match placement {
+        Placement::Diagonal { angle } => Cow::Owned(render_diagonal(mark, *angle, pw, ph, ctx)),
+        _ => mark.render_for(pw, ctx),
+    };
+    let (ww, wh) = image.dimensions();
+
+    let origins = match placement {
+        Placement::Anchor { position, margin } => {
+            vec![anchor_origin(position, *margin, pw, ph, ww, wh)]
+        }
+        Placement::Absolute { x, y } => vec![(x.resolve(pw), y.resolve(ph))],
+        Placement::Tile { spacing_x, spacing_y } => {
+            let step_x = (ww + spacing_x).max(1) as usize;
+            let step_y = (wh + spacing_y).max(1) as usize;
+            let mut origins = Vec::new();
+            for y in (*spacing_y / 2..ph).step_by(step_y) {
+                for x in (*spacing_x / 2..pw).step_by(step_x) {
+                    origins.push((x as i64, y as i64));
+                }
+            }
+            origins
+        }
+        Placement::Auto { margin } => vec![anchor_origin("br", *margin, pw, ph, ww, wh)],
+        Placement::Diagonal { .. } => vec![((pw as i64 - ww as i64) / 2, (ph as i64 - wh as i64) / 2)],
+    };
+    Layout { image, origins }
+}
+
+/// Como [`Stamp::layout`], pero con `Placement::Auto` elige el ancla
+/// mirando los píxeles de `page`.
+fn layout_on<'a, I: GenericImageView<Pixel = Rgba<u8>>>(stamp: &'a Stamp, page: &I, ctx: &PageContext) -> Layout<'a> {
+    let (pw, ph) = page.dimensions();
+    let mut layout = stamp.layout(pw, ph, ctx);
+    if let Placement::Auto { margin } = stamp.placements.for_page(ctx.index) {
+        let (ww, wh) = layout.image.dimensions();
+        let origin = |position: &str| anchor_origin(position, *margin, pw, ph, ww, wh);
+        let zones = stamp.zones(ctx, pw, ph);
+        let mut best = (AUTO_ANCHORS[0], f64::INFINITY);
+        for position in AUTO_ANCHORS {
+            let (x, y) = origin(position);
+            if zones.iter().any(|&zone| overlaps(zone, x, y, ww, wh)) {
+                continue;
+            }
+            let score = detail(page, x, y, ww, wh);
+            // Con un margen, para que el ruido no gane a las preferidas.
+            if score < best.1 - 0.5 {
+                best = (position, score);
+            }
+        }
+        log::debug!("Página {}: posición automática {}", ctx.index + 1, best.0);
+        layout.origins = vec![origin(best.0)];
+    }
+    layout
+}
+
+/// Cuánto detalle hay en la zona de `page` de `w`×`h` con la esquina
+/// superior izquierda en (`x`, `y`): la diferencia media de luminancia
+/// entre puntos vecinos de una rejilla de como mucho 48×48.
+fn detail<I: GenericImageView<Pixel = Rgba<u8>>>(page: &I, x: i64, y: i64, w: u32, h: u32) -> f64 {
+    let (pw, ph) = page.dimensions();
+    let (left, top) = (x.clamp(0, pw as i64) as u32, y.clamp(0, ph as i64) as u32);
+    let right = (x + w as i64).clamp(0, pw as i64) as u32;
+    let bottom = (y + h as i64).clamp(0, ph as i64) as u32;
+    let step_x = ((right - left) / 48).max(1);
+    let step_y = ((bottom - top) / 48).max(1);
+    let (mut sum, mut count) = (0.0, 0u32);
+    for py in (top..bottom).step_by(step_y as usize) {
+        for px in (left..right).step_by(step_x as usize) {
+            let here = luma(page.get_pixel(px, py));
+            if px + step_x < right {
+                sum += (here - luma(page.get_pixel(px + step_x, py))).abs();
+                count += 1;
+            }
+            if py + step_y < bottom {
+                sum += (here - luma(page.get_pixel(px, py + step_y))).abs();
+                count += 1;
+            }
+        }
+    }
+    if count == 0 { f64::INFINITY } else { sum / count as f64 }
+}
+
+/// El watermark girado y escalado para que ocupe la mayor parte de la página.
+fn render_diagonal(mark: &Watermark, angle: f32, pw: u32, ph: u32, ctx: &PageContext) -> RgbaImage {
+    let (bw, bh) = mark.base(ctx).dimensions();
+    let (sin, cos) = (angle as f64).to_radians().sin_cos();
+    let (sin, cos) = (sin.abs(), cos.abs());
+    let rot_w = bw as f64 * cos + bh as f64 * sin;
+    let rot_h = bw as f64 * sin + bh as f64 * cos;
+    let factor = (pw as f64 * DIAGONAL_COVERAGE / rot_w).min(ph as f64 * DIAGONAL_COVERAGE / rot_h);
+
+    let mut img = Cow::Owned(mark.render_width((bw as f64 * factor).round() as u32, ctx));
+    mark.finish(&mut img);
+    rotate(&img, angle)
+}
+
+/// `img` con un contorno de `width` px en negro o blanco, el que más
+/// contraste con su color medio.
+fn outline(img: &RgbaImage, width: u32) -> RgbaImage {
+    let dark = mean_luma(img.pixels().copied()).is_some_and(|luma| luma < 128.0);
+    let color = if dark { [255, 255, 255] } else { [0, 0, 0] };
+
+    let (w, h) = img.dimensions();
+    let r = width as i64;
+    let mut out = RgbaImage::new(w + 2 * width, h + 2 * width);
+    for (x, y, pixel) in out.enumerate_pixels_mut() {
+        // Máximo alfa del watermark en un círculo de radio `width`
+        let mut alpha = 0;
+        for dy in -r..=r {
+            for dx in -r..=r {
+                let (sx, sy) = (x as i64 - r + dx, y as i64 - r + dy);
+                if dx * dx + dy * dy <= r * r && sx >= 0 && sy >= 0 && sx < w as i64 && sy < h as i64 {
+                    alpha = alpha.max(img.get_pixel(sx as u32, sy as u32)[3]);
+                }
+            }
+        }
+        *pixel = Rgba([color[0], color[1], color[2], alpha]);
+    }
+    image::imageops::overlay(&mut out, img, r, r);
+    out
+}
+
+/// `img` sobre una sombra negra difuminada, desplazada hacia abajo a la
+/// derecha en proporción a su alto.
+fn drop_shadow(img: &RgbaImage) -> RgbaImage {
+    let (w, h) = img.dimensions();
+    let sigma = (h as f32 * 0.06).max(1.5);
+    let offset = sigma.ceil() as u32;
+    let pad = (3.0 * sigma).ceil() as u32 + offset;
+
+    let mut alpha = image::GrayImage::new(w + 2 * pad, h + 2 * pad);
+    for (x, y, p) in img.enumerate_pixels() {
+        let a = (p[3] as f32 * SHADOW_OPACITY).round() as u8;
+        alpha.put_pixel(x + pad + offset, y + pad + offset, image::Luma([a]));
+    }
+    let alpha = image::imageops::fast_blur(&alpha, sigma);
+    let mut out = RgbaImage::from_fn(alpha.width(), alpha.height(), |x, y| Rgba([0, 0, 0, alpha.get_pixel(x, y)[0]]));
+    image::imageops::overlay(&mut out, img, pad as i64, pad as i64);
+    out
+}
+
+/// `img` sobre `plate`, ampliado en `plate.padding` por cada lado.
+fn backing_plate(img: &RgbaImage, plate: Plate) -> RgbaImage {
+    let (w, h) = img.dimensions();
+    let pad = plate.padding;
+    let (out_w, out_h) = (w + 2 * pad, h + 2 * pad);
+    let radius = (pad as f64).min(out_w.min(out_h) as f64 / 2.0);
+    let mut out = RgbaImage::from_fn(out_w, out_h, |x, y| {
+        // Distancia al centro de la esquina redondeada más cercana (0 fuera
+        // de las esquinas)
+        let (cx, cy) = (x as f64 + 0.5, y as f64 + 0.5);
+        let dx = (radius - cx).max(cx - (out_w as f64 - radius)).max(0.0);
+        let dy = (radius - cy).max(cy - (out_h as f64 - radius)).max(0.0);
+        let coverage = (radius + 0.5 - (dx * dx + dy * dy).sqrt()).clamp(0.0, 1.0);
+        let alpha = (plate.color[3] as f64 * coverage).round() as u8;
+        Rgba([plate.color[0], plate.color[1], plate.color[2], alpha])
+    });
+    image::imageops::overlay(&mut out, img, pad as i64, pad as i64);
+    out
+}
+
+/// Gira `img` `angle` grados en sentido antihorario (interpolación bilineal),
+/// ampliando el lienzo para que no se recorte.
+fn rotate(img: &RgbaImage, angle: f32) -> RgbaImage {
+    let (w, h) = img.dimensions();
+    let (sin, cos) = (angle as f64).to_radians().sin_cos();
+    let out_w = (w as f64 * cos.abs() + h as f64 * sin.abs()).ceil() as u32;
+    let out_h = (w as f64 * sin.abs() + h as f64 * cos.abs()).ceil() as u32;
+    let (cx, cy) = (w as f64 / 2.0, h as f64 / 2.0);
+    let (ocx, ocy) = (out_w as f64 / 2.0, out_h as f64 / 2.0);
+
+    let mut out = RgbaImage::new(out_w, out_h);
+    for (ox, oy, pixel) in out.enumerate_pixels_mut() {
+        let dx = ox as f64 + 0.5 - ocx;
+        let dy = oy as f64 + 0.5 - ocy;
+        // En coordenadas de imagen (y hacia abajo) el giro antihorario invierte el signo
+        let sx = dx * cos - dy * sin + cx - 0.5;
+        let sy = dx * sin + dy * cos + cy - 0.5;
+        *pixel = sample_bilinear(img, sx, sy);
+    }
+    out
+}
+
+fn sample_bilinear(img: &RgbaImage, x: f64, y: f64) -> Rgba<u8> {
+    let (w, h) = img.dimensions();
+    let x0 = x.floor();
+    let y0 = y.floor();
+    let (fx, fy) = (x - x0, y - y0);
+    let fetch = |px: f64, py: f64| -> [f64; 4] {
+        if px < 0.0 || py < 0.0 || px >= w as f64 || py >= h as f64 {
+            return [0.0; 4];
+        }
+        let p = img.get_pixel(px as u32, py as u32);
+        let a = p[3] as f64;
+        // Premultiplicado para no arrastrar color de píxeles transparentes
+        [p[0] as f64 * a, p[1] as f64 * a, p[2] as f64 * a, a]
+    };
+    let samples = [
+        (fetch(x0, y0), (1.0 - fx) * (1.0 - fy)),
+        (fetch(x0 + 1.0, y0), fx * (1.0 - fy)),
+        (fetch(x0, y0 + 1.0), (1.0 - fx) * fy),
+        (fetch(x0 + 1.0, y0 + 1.0), fx * fy),
+    ];
+    let mut acc = [0.0; 4];
+    for (s, weight) in samples {
+        for c in 0..4 {
+            acc[c] += s[c] * weight;
+        }
+    }
+    if acc[3] <= 0.0 {
+        return Rgba([0, 0, 0, 0]);
+    }
+    Rgba([
+        (acc[0] / acc[3]).round().clamp(0.0, 255.0) as u8,
+        (acc[1] / acc[3]).round().clamp(0.0, 255.0) as u8,
+        (acc[2] / acc[3]).round().clamp(0.0, 255.0) as u8,
+        acc[3].round().clamp(0.0, 255.0) as u8,
+    ])
+}
+
+fn anchor_origin(position: &str, margin: u32, pw: u32, ph: u32, ww: u32, wh: u32) -> (i64, i64) {
+    let m = margin as i64;
 
     let x = match &position[1..2] {
         "l" => m,
@@ -81,9 +1177,7 @@ pub fn apply(page: &DynamicImage, wm: &RgbaImage, position: &str) -> DynamicImag
         "m" => (ph as i64 - wh as i64) / 2,
         _ => ph as i64 - wh as i64 - m, // "b"
     };
-
-    image::imageops::overlay(&mut canvas, wm, x, y);
-    DynamicImage::ImageRgba8(canvas)
+    (x, y)
 }
 
 fn calc_size(orig_w: u32, orig_h: u32, min_w: u32, min_h: u32) -> (u32, u32) {
@@ -1,20 +1,457 @@
 /* tslint:disable */
 /* eslint-disable */
 
-export function get_page_count(pdf_bytes: Uint8Array): number;
+/**
+ * Configuración de un trabajo tal como la escribe el usuario (ej. "30%",
+ * "1-5,8", "aes-256"), con los mismos valores por defecto que la CLI. La
+ * CLI y la versión web la construyen con los `with_*` y
+ * [`pipeline`](Options::pipeline) la valida y prepara los watermarks.
+ *
+ * Si no se indica `logo` ni `text` no hay watermark.
+ */
+export class Options {
+    free(): void;
+    [Symbol.dispose](): void;
+    constructor();
+    /**
+     * Invierte los colores de los watermarks donde contrastan poco con la
+     * página (solo en modo raster).
+     */
+    with_adaptive(adaptive: boolean): Options;
+    with_author(author: string): Options;
+    /**
+     * Zonas separadas por ';' que los watermarks no deben tapar, ej.
+     * "0,90%,100%,10%;1:0,0,100%,15%".
+     */
+    with_avoid(zones: string): Options;
+    /**
+     * Numeración Bates: `prefix` seguido de un número de `digits` cifras
+     * que empieza en `start` y sube en cada página marcada, en `position`.
+     */
+    with_bates(prefix: string, position: string, start: number, digits: number): Options;
+    /**
+     * Color del texto: #RRGGBB o #RRGGBBAA.
+     */
+    with_color(color: string): Options;
+    with_dpi(dpi: number): Options;
+    /**
+     * Sombra y contorno de `outline` px (0 = sin contorno) alrededor de
+     * los watermarks.
+     */
+    with_effects(shadow: boolean, outline: number): Options;
+    /**
+     * Contraseñas para cifrar el PDF generado y nivel de cifrado
+     * ("rc4-128", "aes-128" o "aes-256").
+     */
+    with_encryption(user_password: string, owner_password: string, level: string): Options;
+    /**
+     * Se queda con una de cada `n` de las páginas elegidas.
+     */
+    with_every_nth(n: number): Options;
+    /**
+     * Fuente TTF/OTF del texto (por defecto DejaVu Sans Bold).
+     */
+    with_font(data: Uint8Array): Options;
+    with_font_size(font_size: number): Options;
+    /**
+     * Línea fija abajo de cada página marcada, como [`with_header`](Options::with_header).
+     */
+    with_footer(text: string): Options;
+    /**
+     * Identificador corto que se guarda en cada página con un watermark
+     * forense que resiste JPEG y cambios de tamaño; solo se lee con `key`.
+     * `strength` 0 usa la intensidad por defecto.
+     */
+    with_forensic(payload: string, key: string, strength: number): Options;
+    /**
+     * Pasa el logo a escala de grises.
+     */
+    with_gray_logo(gray: boolean): Options;
+    /**
+     * Páginas en escala de grises, con los watermarks incluidos.
+     */
+    with_grayscale(grayscale: boolean): Options;
+    /**
+     * Línea fija arriba de cada página marcada, ej. el título del
+     * documento; admite {page}, {total}, {date} y {filename}.
+     */
+    with_header(text: string): Options;
+    with_incremental(incremental: boolean): Options;
+    /**
+     * Texto que se guarda de forma invisible en cada página, ej. el
+     * destinatario; admite {page}, {total}, {date} y {filename}.
+     */
+    with_invisible(payload: string): Options;
+    with_keywords(keywords: string): Options;
+    /**
+     * "over" o "under".
+     */
+    with_layer(layer: string): Options;
+    with_linearize(linearize: boolean): Options;
+    /**
+     * Imagen del watermark (PNG, JPG o SVG).
+     */
+    with_logo(data: Uint8Array): Options;
+    with_margin(margin: number): Options;
+    /**
+     * Reduce las imágenes de las páginas a como mucho `max_dpi` y
+     * `max_width` píxeles de ancho (0 = sin límite).
+     */
+    with_max_resolution(max_dpi: number, max_width: number): Options;
+    /**
+     * Tamaño máximo del PDF generado, ej. "10MB" (vacío = sin límite): se
+     * baja la calidad JPEG hasta que quepa y, con `downscale`, también la
+     * resolución de las páginas.
+     */
+    with_max_size(size: string, downscale: boolean): Options;
+    /**
+     * Tamaño mínimo del logo en píxeles.
+     */
+    with_min_size(min_w: number, min_h: number): Options;
+    /**
+     * "raster" o "stamp".
+     */
+    with_mode(mode: string): Options;
+    with_object_streams(object_streams: boolean): Options;
+    /**
+     * 0-1 o porcentaje (ej. "30%"). Sin ella es 1, o
+     * [`DIAGONAL_OPACITY`](watermark::DIAGONAL_OPACITY) en diagonal.
+     */
+    with_opacity(opacity: string): Options;
+    /**
+     * JSON con posiciones por página, ej. {"1": "tc", "3": {"x": "85%", "y": 40}}.
+     */
+    with_page_positions(json: string): Options;
+    /**
+     * Tamaño de las páginas de salida: "1920x1080" (píxeles, a `dpi`), a3,
+     * a4, a5, letter o legal (vacío = el de cada página).
+     */
+    with_page_size(page_size: string): Options;
+    /**
+     * Páginas a conservar, ej. "1-5,8,12-" (vacío = todas).
+     */
+    with_pages(spec: string): Options;
+    /**
+     * Contraseña del PDF de entrada.
+     */
+    with_password(password: string): Options;
+    with_pdfa(pdfa: boolean): Options;
+    /**
+     * Rectángulo redondeado de `color` (#RRGGBB o #RRGGBBAA) detrás de los
+     * watermarks, con `opacity` ("0.6" o "60%") y a `padding` px de ellos.
+     */
+    with_plate(color: string, opacity: string, padding: number): Options;
+    /**
+     * Coordenadas en px o % de la página; reemplazan a la posición.
+     */
+    with_point(x: string, y: string): Options;
+    /**
+     * tl,tc,tr,ml,mc,mr,bl,bc,br, tile, diagonal o auto.
+     */
+    with_position(position: string): Options;
+    /**
+     * Guarda en el XMP un registro de procedencia: versión de la
+     * herramienta, fecha, SHA-256 del logo y opciones usadas.
+     */
+    with_provenance(provenance: boolean): Options;
+    /**
+     * "lossless" o 1-100 (JPEG).
+     */
+    with_quality(quality: string): Options;
+    /**
+     * Tamaño de fuente, alineación ("left", "center" o "right") y margen
+     * en px de la cabecera y el pie.
+     */
+    with_running_style(font_size: number, align: string, margin: number): Options;
+    /**
+     * Ancho del logo relativo a cada página, ej. "10%".
+     */
+    with_scale(scale: string): Options;
+    /**
+     * Páginas que se conservan sin watermark, con la sintaxis de `with_pages`.
+     */
+    with_skip_pages(spec: string): Options;
+    /**
+     * Separación entre copias en modo tile.
+     */
+    with_spacing(spacing_x: number, spacing_y: number): Options;
+    /**
+     * Quita del PDF generado el diccionario Info, el XMP y las miniaturas
+     * del original, para distribuirlo sin datos del autor.
+     */
+    with_strip_metadata(strip_metadata: boolean): Options;
+    with_subject(subject: string): Options;
+    /**
+     * Submuestreo de color de JPEG: "4:4:4", "4:2:2" o "4:2:0" (vacío =
+     * 4:4:4).
+     */
+    with_subsampling(subsampling: string): Options;
+    /**
+     * Watermark de texto, además del logo si lo hay. Admite {page},
+     * {total}, {date} y {filename}.
+     */
+    with_text(text: string): Options;
+    /**
+     * Posición del texto cuando se combina con un logo (por defecto la del logo).
+     */
+    with_text_position(position: string): Options;
+    /**
+     * Sella la fecha y hora de proceso en cada página marcada, con un
+     * formato de strftime en la zona `zone` ("local", "utc" o "+02:00").
+     */
+    with_timestamp(format: string, zone: string): Options;
+    /**
+     * Posición del sello de fecha, como en [`with_position`](Options::with_position).
+     */
+    with_timestamp_position(position: string): Options;
+    /**
+     * Sella este texto tal cual en lugar de la hora actual, ej. para
+     * repetir la fecha de una exportación anterior.
+     */
+    with_timestamp_text(text: string): Options;
+    /**
+     * Color con que se tiñe el logo: #RRGGBB o #RRGGBBAA.
+     */
+    with_tint(tint: string): Options;
+    /**
+     * Título del PDF generado, en el diccionario Info y en el XMP.
+     */
+    with_title(title: string): Options;
+}
+
+/**
+ * Tamaño de una página (ver [`get_page_dimensions`]).
+ */
+export class PageDimensions {
+    private constructor();
+    free(): void;
+    [Symbol.dispose](): void;
+    /**
+     * Ancho entre alto, de los píxeles o, si no hay, de los puntos.
+     */
+    readonly aspect_ratio: number;
+    /**
+     * Motivo por el que no se pudo extraer la página, si es el caso.
+     */
+    readonly error: string | undefined;
+    /**
+     * Alto en píxeles de la imagen de la página; 0 si no se pudo extraer.
+     */
+    readonly height: number;
+    /**
+     * Alto en puntos, ya girada según /Rotate.
+     */
+    readonly height_pt: number;
+    /**
+     * Ancho en píxeles de la imagen de la página; 0 si no se pudo extraer.
+     */
+    readonly width: number;
+    /**
+     * Ancho en puntos, ya girada según /Rotate.
+     */
+    readonly width_pt: number;
+}
+
+/**
+ * Error devuelto a JavaScript, para distinguir el motivo sin interpretar el
+ * mensaje (ver [`WatermarkError`]).
+ */
+export class ProcessError {
+    private constructor();
+    free(): void;
+    [Symbol.dispose](): void;
+    toString(): string;
+    /**
+     * Motivo, ej. "unsupported_filter" o "wrong_password".
+     */
+    readonly code: string;
+    /**
+     * Filtro o espacio de color no soportado, si es el motivo.
+     */
+    readonly detail: string | undefined;
+    /**
+     * Mensaje en español, el mismo que muestra la CLI.
+     */
+    readonly message: string;
+    /**
+     * Índice (desde 0) de la página que falló, si la hay.
+     */
+    readonly page: number | undefined;
+}
 
-export function process_pdf(pdf_bytes: Uint8Array, logo_bytes: Uint8Array, quality_str: string, page_indices: Uint32Array, position: string, min_w: number, min_h: number): Uint8Array;
+/**
+ * Procesa un PDF por partes para no bloquear el navegador: cada llamada a
+ * `step` extrae y marca unas pocas páginas, devolviendo el control al bucle
+ * de eventos entre llamadas, y `finish` genera el PDF. En modo stamp todo el
+ * trabajo se hace en `finish`, que es rápido.
+ */
+export class Processor {
+    free(): void;
+    [Symbol.dispose](): void;
+    /**
+     * Procesa las páginas que falten y genera el PDF.
+     */
+    finish(): Uint8Array;
+    constructor(pdf_bytes: Uint8Array, options: Options);
+    /**
+     * Páginas que faltan por procesar.
+     */
+    remaining(): number;
+    /**
+     * Procesa hasta `count` páginas y devuelve cuántas faltan.
+     */
+    step(count: number): number;
+}
+
+/**
+ * Miniatura PNG de una página (ver [`render_thumbnails`]).
+ */
+export class Thumbnail {
+    private constructor();
+    free(): void;
+    [Symbol.dispose](): void;
+    /**
+     * Motivo por el que no hay miniatura, si es el caso.
+     */
+    readonly error: string | undefined;
+    readonly height: number;
+    /**
+     * PNG de la página; vacío si no se pudo extraer.
+     */
+    readonly png: Uint8Array;
+    readonly width: number;
+}
+
+export function get_page_count(pdf_bytes: Uint8Array, password: string): number;
+
+/**
+ * Tamaño de cada página del PDF, para avisar de tamaños mezclados o
+ * calcular la escala del watermark antes de procesarlo. Extrae todas las
+ * páginas; las que fallan llevan el motivo en `error` y solo su tamaño en
+ * puntos.
+ */
+export function get_page_dimensions(pdf_bytes: Uint8Array, password: string): PageDimensions[];
+
+/**
+ * Informe del PDF en JSON (ver [`pdf::PdfInfo`]): versión, cifrado, número
+ * de páginas y, por página, tamaño en puntos, giro y las imágenes con sus
+ * filtros, espacio de color y si se saben decodificar. No extrae las
+ * páginas, así que es rápido. Si el PDF está cifrado y `password` no lo
+ * abre devuelve `needs_password: true` sin páginas en vez de un error.
+ */
+export function get_pdf_info(pdf_bytes: Uint8Array, password: string): string;
+
+/**
+ * Índices (desde 0) de las páginas de `spec` (ej. "1-5,8,12-") en un PDF de
+ * `total` páginas; vacío si `spec` lo está.
+ */
+export function parse_page_spec(spec: string, total: number): Uint32Array;
+
+/**
+ * Aplica el watermark descrito en `options` al PDF `pdf_bytes`.
+ */
+export function process_pdf(pdf_bytes: Uint8Array, options: Options): Uint8Array;
+
+/**
+ * Miniaturas de las páginas del PDF, de como mucho `max_width` píxeles de
+ * ancho, para elegir páginas antes de procesarlo. Las páginas que no se
+ * pueden extraer devuelven una miniatura vacía con el motivo en `error`.
+ */
+export function render_thumbnails(pdf_bytes: Uint8Array, password: string, max_width: number): Thumbnail[];
 
 export type InitInput = RequestInfo | URL | Response | BufferSource | WebAssembly.Module;
 
 export interface InitOutput {
     readonly memory: WebAssembly.Memory;
-    readonly process_pdf: (a: number, b: number, c: number, d: number, e: number, f: number, g: number, h: number, i: number, j: number, k: number, l: number, m: number) => void;
-    readonly get_page_count: (a: number, b: number, c: number) => void;
+    readonly __wbg_options_free: (a: number, b: number) => void;
+    readonly options_new: () => number;
+    readonly options_with_adaptive: (a: number, b: number) => number;
+    readonly options_with_author: (a: number, b: number, c: number) => number;
+    readonly options_with_avoid: (a: number, b: number, c: number) => number;
+    readonly options_with_bates: (a: number, b: number, c: number, d: number, e: number, f: number, g: number) => number;
+    readonly options_with_color: (a: number, b: number, c: number) => number;
+    readonly options_with_dpi: (a: number, b: number) => number;
+    readonly options_with_effects: (a: number, b: number, c: number) => number;
+    readonly options_with_encryption: (a: number, b: number, c: number, d: number, e: number, f: number, g: number) => number;
+    readonly options_with_every_nth: (a: number, b: number) => number;
+    readonly options_with_font: (a: number, b: number, c: number) => number;
+    readonly options_with_font_size: (a: number, b: number) => number;
+    readonly options_with_footer: (a: number, b: number, c: number) => number;
+    readonly options_with_forensic: (a: number, b: number, c: number, d: number, e: number, f: number) => number;
+    readonly options_with_gray_logo: (a: number, b: number) => number;
+    readonly options_with_grayscale: (a: number, b: number) => number;
+    readonly options_with_header: (a: number, b: number, c: number) => number;
+    readonly options_with_incremental: (a: number, b: number) => number;
+    readonly options_with_invisible: (a: number, b: number, c: number) => number;
+    readonly options_with_keywords: (a: number, b: number, c: number) => number;
+    readonly options_with_layer: (a: number, b: number, c: number) => number;
+    readonly options_with_linearize: (a: number, b: number) => number;
+    readonly options_with_logo: (a: number, b: number, c: number) => number;
+    readonly options_with_margin: (a: number, b: number) => number;
+    readonly options_with_max_resolution: (a: number, b: number, c: number) => number;
+    readonly options_with_max_size: (a: number, b: number, c: number, d: number) => number;
+    readonly options_with_min_size: (a: number, b: number, c: number) => number;
+    readonly options_with_mode: (a: number, b: number, c: number) => number;
+    readonly options_with_object_streams: (a: number, b: number) => number;
+    readonly options_with_opacity: (a: number, b: number, c: number) => number;
+    readonly options_with_page_positions: (a: number, b: number, c: number) => number;
+    readonly options_with_page_size: (a: number, b: number, c: number) => number;
+    readonly options_with_pages: (a: number, b: number, c: number) => number;
+    readonly options_with_password: (a: number, b: number, c: number) => number;
+    readonly options_with_pdfa: (a: number, b: number) => number;
+    readonly options_with_plate: (a: number, b: number, c: number, d: number, e: number, f: number) => number;
+    readonly options_with_point: (a: number, b: number, c: number, d: number, e: number) => number;
+    readonly options_with_position: (a: number, b: number, c: number) => number;
+    readonly options_with_provenance: (a: number, b: number) => number;
+    readonly options_with_quality: (a: number, b: number, c: number) => number;
+    readonly options_with_running_style: (a: number, b: number, c: number, d: number, e: number) => number;
+    readonly options_with_scale: (a: number, b: number, c: number) => number;
+    readonly options_with_skip_pages: (a: number, b: number, c: number) => number;
+    readonly options_with_spacing: (a: number, b: number, c: number) => number;
+    readonly options_with_strip_metadata: (a: number, b: number) => number;
+    readonly options_with_subject: (a: number, b: number, c: number) => number;
+    readonly options_with_subsampling: (a: number, b: number, c: number) => number;
+    readonly options_with_text: (a: number, b: number, c: number) => number;
+    readonly options_with_text_position: (a: number, b: number, c: number) => number;
+    readonly options_with_timestamp: (a: number, b: number, c: number, d: number, e: number) => number;
+    readonly options_with_timestamp_position: (a: number, b: number, c: number) => number;
+    readonly options_with_timestamp_text: (a: number, b: number, c: number) => number;
+    readonly options_with_tint: (a: number, b: number, c: number) => number;
+    readonly options_with_title: (a: number, b: number, c: number) => number;
+    readonly __wbg_pagedimensions_free: (a: number, b: number) => void;
+    readonly __wbg_processerror_free: (a: number, b: number) => void;
+    readonly __wbg_processor_free: (a: number, b: number) => void;
+    readonly __wbg_thumbnail_free: (a: number, b: number) => void;
+    readonly get_page_count: (a: number, b: number, c: number, d: number, e: number) => void;
+    readonly get_page_dimensions: (a: number, b: number, c: number, d: number, e: number) => void;
+    readonly get_pdf_info: (a: number, b: number, c: number, d: number, e: number) => void;
+    readonly pagedimensions_aspect_ratio: (a: number) => number;
+    readonly pagedimensions_error: (a: number, b: number) => void;
+    readonly pagedimensions_height: (a: number) => number;
+    readonly pagedimensions_height_pt: (a: number) => number;
+    readonly pagedimensions_width: (a: number) => number;
+    readonly pagedimensions_width_pt: (a: number) => number;
+    readonly parse_page_spec: (a: number, b: number, c: number, d: number) => void;
+    readonly process_pdf: (a: number, b: number, c: number, d: number) => void;
+    readonly processerror_code: (a: number, b: number) => void;
+    readonly processerror_detail: (a: number, b: number) => void;
+    readonly processerror_message: (a: number, b: number) => void;
+    readonly processerror_page: (a: number) => number;
+    readonly processor_finish: (a: number, b: number) => void;
+    readonly processor_new: (a: number, b: number, c: number, d: number) => void;
+    readonly processor_remaining: (a: number) => number;
+    readonly processor_step: (a: number, b: number, c: number) => void;
+    readonly render_thumbnails: (a: number, b: number, c: number, d: number, e: number, f: number) => void;
+    readonly thumbnail_error: (a: number, b: number) => void;
+    readonly thumbnail_height: (a: number) => number;
+    readonly thumbnail_png: (a: number, b: number) => void;
+    readonly thumbnail_width: (a: number) => number;
+    readonly processerror_toString: (a: number, b: number) => void;
+    readonly __wbindgen_export: (a: number) => void;
     readonly __wbindgen_add_to_stack_pointer: (a: number) => number;
-    readonly __wbindgen_export: (a: number, b: number) => number;
-    readonly __wbindgen_export2: (a: number, b: number, c: number, d: number) => number;
-    readonly __wbindgen_export3: (a: number, b: number, c: number) => void;
+    readonly __wbindgen_export2: (a: number, b: number) => number;
+    readonly __wbindgen_export3: (a: number, b: number, c: number, d: number) => number;
+    readonly __wbindgen_export4: (a: number, b: number, c: number) => void;
 }
 
 export type SyncInitInput = BufferSource | WebAssembly.Module;
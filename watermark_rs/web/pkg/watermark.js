@@ -1,15 +1,1065 @@
 /* @ts-self-types="./watermark.d.ts" */
 
+/**
+ * Configuración de un trabajo tal como la escribe el usuario (ej. "30%",
+ * "1-5,8", "aes-256"), con los mismos valores por defecto que la CLI. La
+ * CLI y la versión web la construyen con los `with_*` y
+ * [`pipeline`](Options::pipeline) la valida y prepara los watermarks.
+ *
+ * Si no se indica `logo` ni `text` no hay watermark.
+ */
+export class Options {
+    static __wrap(ptr) {
+        ptr = ptr >>> 0;
+        const obj = Object.create(Options.prototype);
+        obj.__wbg_ptr = ptr;
+        OptionsFinalization.register(obj, obj.__wbg_ptr, obj);
+        return obj;
+    }
+    __destroy_into_raw() {
+        const ptr = this.__wbg_ptr;
+        this.__wbg_ptr = 0;
+        OptionsFinalization.unregister(this);
+        return ptr;
+    }
+    free() {
+        const ptr = this.__destroy_into_raw();
+        wasm.__wbg_options_free(ptr, 0);
+    }
+    constructor() {
+        const ret = wasm.options_new();
+        this.__wbg_ptr = ret >>> 0;
+        OptionsFinalization.register(this, this.__wbg_ptr, this);
+        return this;
+    }
+    /**
+     * Invierte los colores de los watermarks donde contrastan poco con la
+     * página (solo en modo raster).
+     * @param {boolean} adaptive
+     * @returns {Options}
+     */
+    with_adaptive(adaptive) {
+        const ptr = this.__destroy_into_raw();
+        const ret = wasm.options_with_adaptive(ptr, adaptive);
+        return Options.__wrap(ret);
+    }
+    /**
+     * @param {string} author
+     * @returns {Options}
+     */
+    with_author(author) {
+        const ptr = this.__destroy_into_raw();
+        const ptr0 = passStringToWasm0(author, wasm.__wbindgen_export2, wasm.__wbindgen_export3);
+        const len0 = WASM_VECTOR_LEN;
+        const ret = wasm.options_with_author(ptr, ptr0, len0);
+        return Options.__wrap(ret);
+    }
+    /**
+     * Zonas separadas por ';' que los watermarks no deben tapar, ej.
+     * "0,90%,100%,10%;1:0,0,100%,15%".
+     * @param {string} zones
+     * @returns {Options}
+     */
+    with_avoid(zones) {
+        const ptr = this.__destroy_into_raw();
+        const ptr0 = passStringToWasm0(zones, wasm.__wbindgen_export2, wasm.__wbindgen_export3);
+        const len0 = WASM_VECTOR_LEN;
+        const ret = wasm.options_with_avoid(ptr, ptr0, len0);
+        return Options.__wrap(ret);
+    }
+    /**
+     * Numeración Bates: `prefix` seguido de un número de `digits` cifras
+     * que empieza en `start` y sube en cada página marcada, en `position`.
+     * @param {string} prefix
+     * @param {string} position
+     * @param {number} start
+     * @param {number} digits
+     * @returns {Options}
+     */
+    with_bates(prefix, position, start, digits) {
+        const ptr = this.__destroy_into_raw();
+        const ptr0 = passStringToWasm0(prefix, wasm.__wbindgen_export2, wasm.__wbindgen_export3);
+        const len0 = WASM_VECTOR_LEN;
+        const ptr1 = passStringToWasm0(position, wasm.__wbindgen_export2, wasm.__wbindgen_export3);
+        const len1 = WASM_VECTOR_LEN;
+        const ret = wasm.options_with_bates(ptr, ptr0, len0, ptr1, len1, start, digits);
+        return Options.__wrap(ret);
+    }
+    /**
+     * Color del texto: #RRGGBB o #RRGGBBAA.
+     * @param {string} color
+     * @returns {Options}
+     */
+    with_color(color) {
+        const ptr = this.__destroy_into_raw();
+        const ptr0 = passStringToWasm0(color, wasm.__wbindgen_export2, wasm.__wbindgen_export3);
+        const len0 = WASM_VECTOR_LEN;
+        const ret = wasm.options_with_color(ptr, ptr0, len0);
+        return Options.__wrap(ret);
+    }
+    /**
+     * @param {number} dpi
+     * @returns {Options}
+     */
+    with_dpi(dpi) {
+        const ptr = this.__destroy_into_raw();
+        const ret = wasm.options_with_dpi(ptr, dpi);
+        return Options.__wrap(ret);
+    }
+    /**
+     * Sombra y contorno de `outline` px (0 = sin contorno) alrededor de
+     * los watermarks.
+     * @param {boolean} shadow
+     * @param {number} outline
+     * @returns {Options}
+     */
+    with_effects(shadow, outline) {
+        const ptr = this.__destroy_into_raw();
+        const ret = wasm.options_with_effects(ptr, shadow, outline);
+        return Options.__wrap(ret);
+    }
+    /**
+     * Contraseñas para cifrar el PDF generado y nivel de cifrado
+     * ("rc4-128", "aes-128" o "aes-256").
+     * @param {string} user_password
+     * @param {string} owner_password
+     * @param {string} level
+     * @returns {Options}
+     */
+    with_encryption(user_password, owner_password, level) {
+        const ptr = this.__destroy_into_raw();
+        const ptr0 = passStringToWasm0(user_password, wasm.__wbindgen_export2, wasm.__wbindgen_export3);
+        const len0 = WASM_VECTOR_LEN;
+        const ptr1 = passStringToWasm0(owner_password, wasm.__wbindgen_export2, wasm.__wbindgen_export3);
+        const len1 = WASM_VECTOR_LEN;
+        const ptr2 = passStringToWasm0(level, wasm.__wbindgen_export2, wasm.__wbindgen_export3);
+        const len2 = WASM_VECTOR_LEN;
+        const ret = wasm.options_with_encryption(ptr, ptr0, len0, ptr1, len1, ptr2, len2);
+        return Options.__wrap(ret);
+    }
+    /**
+     * Se queda con una de cada `n` de las páginas elegidas.
+     * @param {number} n
+     * @returns {Options}
+     */
+    with_every_nth(n) {
+        const ptr = this.__destroy_into_raw();
+        const ret = wasm.options_with_every_nth(ptr, n);
+        return Options.__wrap(ret);
+    }
+    /**
+     * Fuente TTF/OTF del texto (por defecto DejaVu Sans Bold).
+     * @param {Uint8Array} data
+     * @returns {Options}
+     */
+    with_font(data) {
+        const ptr = this.__destroy_into_raw();
+        const ptr0 = passArray8ToWasm0(data, wasm.__wbindgen_export2);
+        const len0 = WASM_VECTOR_LEN;
+        const ret = wasm.options_with_font(ptr, ptr0, len0);
+        return Options.__wrap(ret);
+    }
+    /**
+     * @param {number} font_size
+     * @returns {Options}
+     */
+    with_font_size(font_size) {
+        const ptr = this.__destroy_into_raw();
+        const ret = wasm.options_with_font_size(ptr, font_size);
+        return Options.__wrap(ret);
+    }
+    /**
+     * Línea fija abajo de cada página marcada, como [`with_header`](Options::with_header).
+     * @param {string} text
+     * @returns {Options}
+     */
+    with_footer(text) {
+        const ptr = this.__destroy_into_raw();
+        const ptr0 = passStringToWasm0(text, wasm.__wbindgen_export2, wasm.__wbindgen_export3);
+        const len0 = WASM_VECTOR_LEN;
+        const ret = wasm.options_with_footer(ptr, ptr0, len0);
+        return Options.__wrap(ret);
+    }
+    /**
+     * Identificador corto que se guarda en cada página con un watermark
+     * forense que resiste JPEG y cambios de tamaño; solo se lee con `key`.
+     * `strength` 0 usa la intensidad por defecto.
+     * @param {string} payload
+     * @param {string} key
+     * @param {number} strength
+     * @returns {Options}
+     */
+    with_forensic(payload, key, strength) {
+        const ptr = this.__destroy_into_raw();
+        const ptr0 = passStringToWasm0(payload, wasm.__wbindgen_export2, wasm.__wbindgen_export3);
+        const len0 = WASM_VECTOR_LEN;
+        const ptr1 = passStringToWasm0(key, wasm.__wbindgen_export2, wasm.__wbindgen_export3);
+        const len1 = WASM_VECTOR_LEN;
+        const ret = wasm.options_with_forensic(ptr, ptr0, len0, ptr1, len1, strength);
+        return Options.__wrap(ret);
+    }
+    /**
+     * Pasa el logo a escala de grises.
+     * @param {boolean} gray
+     * @returns {Options}
+     */
+    with_gray_logo(gray) {
+        const ptr = this.__destroy_into_raw();
+        const ret = wasm.options_with_gray_logo(ptr, gray);
+        return Options.__wrap(ret);
+    }
+    /**
+     * Páginas en escala de grises, con los watermarks incluidos.
+     * @param {boolean} grayscale
+     * @returns {Options}
+     */
+    with_grayscale(grayscale) {
+        const ptr = this.__destroy_into_raw();
+        const ret = wasm.options_with_grayscale(ptr, grayscale);
+        return Options.__wrap(ret);
+    }
+    /**
+     * Línea fija arriba de cada página marcada, ej. el título del
+     * documento; admite {page}, {total}, {date} y {filename}.
+     * @param {string} text
+     * @returns {Options}
+     */
+    with_header(text) {
+        const ptr = this.__destroy_into_raw();
+        const ptr0 = passStringToWasm0(text, wasm.__wbindgen_export2, wasm.__wbindgen_export3);
+        const len0 = WASM_VECTOR_LEN;
+        const ret = wasm.options_with_header(ptr, ptr0, len0);
+        return Options.__wrap(ret);
+    }
+    /**
+     * @param {boolean} incremental
+     * @returns {Options}
+     */
+    with_incremental(incremental) {
+        const ptr = this.__destroy_into_raw();
+        const ret = wasm.options_with_incremental(ptr, incremental);
+        return Options.__wrap(ret);
+    }
+    /**
+     * Texto que se guarda de forma invisible en cada página, ej. el
+     * destinatario; admite {page}, {total}, {date} y {filename}.
+     * @param {string} payload
+     * @returns {Options}
+     */
+    with_invisible(payload) {
+        const ptr = this.__destroy_into_raw();
+        const ptr0 = passStringToWasm0(payload, wasm.__wbindgen_export2, wasm.__wbindgen_export3);
+        const len0 = WASM_VECTOR_LEN;
+        const ret = wasm.options_with_invisible(ptr, ptr0, len0);
+        return Options.__wrap(ret);
+    }
+    /**
+     * @param {string} keywords
+     * @returns {Options}
+     */
+    with_keywords(keywords) {
+        const ptr = this.__destroy_into_raw();
+        const ptr0 = passStringToWasm0(keywords, wasm.__wbindgen_export2, wasm.__wbindgen_export3);
+        const len0 = WASM_VECTOR_LEN;
+        const ret = wasm.options_with_keywords(ptr, ptr0, len0);
+        return Options.__wrap(ret);
+    }
+    /**
+     * "over" o "under".
+     * @param {string} layer
+     * @returns {Options}
+     */
+    with_layer(layer) {
+        const ptr = this.__destroy_into_raw();
+        const ptr0 = passStringToWasm0(layer, wasm.__wbindgen_export2, wasm.__wbindgen_export3);
+        const len0 = WASM_VECTOR_LEN;
+        const ret = wasm.options_with_layer(ptr, ptr0, len0);
+        return Options.__wrap(ret);
+    }
+    /**
+     * @param {boolean} linearize
+     * @returns {Options}
+     */
+    with_linearize(linearize) {
+        const ptr = this.__destroy_into_raw();
+        const ret = wasm.options_with_linearize(ptr, linearize);
+        return Options.__wrap(ret);
+    }
+    /**
+     * Imagen del watermark (PNG, JPG o SVG).
+     * @param {Uint8Array} data
+     * @returns {Options}
+     */
+    with_logo(data) {
+        const ptr = this.__destroy_into_raw();
+        const ptr0 = passArray8ToWasm0(data, wasm.__wbindgen_export2);
+        const len0 = WASM_VECTOR_LEN;
+        const ret = wasm.options_with_logo(ptr, ptr0, len0);
+        return Options.__wrap(ret);
+    }
+    /**
+     * @param {number} margin
+     * @returns {Options}
+     */
+    with_margin(margin) {
+        const ptr = this.__destroy_into_raw();
+        const ret = wasm.options_with_margin(ptr, margin);
+        return Options.__wrap(ret);
+    }
+    /**
+     * Reduce las imágenes de las páginas a como mucho `max_dpi` y
+     * `max_width` píxeles de ancho (0 = sin límite).
+     * @param {number} max_dpi
+     * @param {number} max_width
+     * @returns {Options}
+     */
+    with_max_resolution(max_dpi, max_width) {
+        const ptr = this.__destroy_into_raw();
+        const ret = wasm.options_with_max_resolution(ptr, max_dpi, max_width);
+        return Options.__wrap(ret);
+    }
+    /**
+     * Tamaño máximo del PDF generado, ej. "10MB" (vacío = sin límite): se
+     * baja la calidad JPEG hasta que quepa y, con `downscale`, también la
+     * resolución de las páginas.
+     * @param {string} size
+     * @param {boolean} downscale
+     * @returns {Options}
+     */
+    with_max_size(size, downscale) {
+        const ptr = this.__destroy_into_raw();
+        const ptr0 = passStringToWasm0(size, wasm.__wbindgen_export2, wasm.__wbindgen_export3);
+        const len0 = WASM_VECTOR_LEN;
+        const ret = wasm.options_with_max_size(ptr, ptr0, len0, downscale);
+        return Options.__wrap(ret);
+    }
+    /**
+     * Tamaño mínimo del logo en píxeles.
+     * @param {number} min_w
+     * @param {number} min_h
+     * @returns {Options}
+     */
+    with_min_size(min_w, min_h) {
+        const ptr = this.__destroy_into_raw();
+        const ret = wasm.options_with_min_size(ptr, min_w, min_h);
+        return Options.__wrap(ret);
+    }
+    /**
+     * "raster" o "stamp".
+     * @param {string} mode
+     * @returns {Options}
+     */
+    with_mode(mode) {
+        const ptr = this.__destroy_into_raw();
+        const ptr0 = passStringToWasm0(mode, wasm.__wbindgen_export2, wasm.__wbindgen_export3);
+        const len0 = WASM_VECTOR_LEN;
+        const ret = wasm.options_with_mode(ptr, ptr0, len0);
+        return Options.__wrap(ret);
+    }
+    /**
+     * @param {boolean} object_streams
+     * @returns {Options}
+     */
+    with_object_streams(object_streams) {
+        const ptr = this.__destroy_into_raw();
+        const ret = wasm.options_with_object_streams(ptr, object_streams);
+        return Options.__wrap(ret);
+    }
+    /**
+     * 0-1 o porcentaje (ej. "30%"). Sin ella es 1, o
+     * [`DIAGONAL_OPACITY`](watermark::DIAGONAL_OPACITY) en diagonal.
+     * @param {string} opacity
+     * @returns {Options}
+     */
+    with_opacity(opacity) {
+        const ptr = this.__destroy_into_raw();
+        const ptr0 = passStringToWasm0(opacity, wasm.__wbindgen_export2, wasm.__wbindgen_export3);
+        const len0 = WASM_VECTOR_LEN;
+        const ret = wasm.options_with_opacity(ptr, ptr0, len0);
+        return Options.__wrap(ret);
+    }
+    /**
+     * JSON con posiciones por página, ej. {"1": "tc", "3": {"x": "85%", "y": 40}}.
+     * @param {string} json
+     * @returns {Options}
+     */
+    with_page_positions(json) {
+        const ptr = this.__destroy_into_raw();
+        const ptr0 = passStringToWasm0(json, wasm.__wbindgen_export2, wasm.__wbindgen_export3);
+        const len0 = WASM_VECTOR_LEN;
+        const ret = wasm.options_with_page_positions(ptr, ptr0, len0);
+        return Options.__wrap(ret);
+    }
+    /**
+     * Tamaño de las páginas de salida: "1920x1080" (píxeles, a `dpi`), a3,
+     * a4, a5, letter o legal (vacío = el de cada página).
+     * @param {string} page_size
+     * @returns {Options}
+     */
+    with_page_size(page_size) {
+        const ptr = this.__destroy_into_raw();
+        const ptr0 = passStringToWasm0(page_size, wasm.__wbindgen_export2, wasm.__wbindgen_export3);
+        const len0 = WASM_VECTOR_LEN;
+        const ret = wasm.options_with_page_size(ptr, ptr0, len0);
+        return Options.__wrap(ret);
+    }
+    /**
+     * Páginas a conservar, ej. "1-5,8,12-" (vacío = todas).
+     * @param {string} spec
+     * @returns {Options}
+     */
+    with_pages(spec) {
+        const ptr = this.__destroy_into_raw();
+        const ptr0 = passStringToWasm0(spec, wasm.__wbindgen_export2, wasm.__wbindgen_export3);
+        const len0 = WASM_VECTOR_LEN;
+        const ret = wasm.options_with_pages(ptr, ptr0, len0);
+        return Options.__wrap(ret);
+    }
+    /**
+     * Contraseña del PDF de entrada.
+     * @param {string} password
+     * @returns {Options}
+     */
+    with_password(password) {
+        const ptr = this.__destroy_into_raw();
+        const ptr0 = passStringToWasm0(password, wasm.__wbindgen_export2, wasm.__wbindgen_export3);
+        const len0 = WASM_VECTOR_LEN;
+        const ret = wasm.options_with_password(ptr, ptr0, len0);
+        return Options.__wrap(ret);
+    }
+    /**
+     * @param {boolean} pdfa
+     * @returns {Options}
+     */
+    with_pdfa(pdfa) {
+        const ptr = this.__destroy_into_raw();
+        const ret = wasm.options_with_pdfa(ptr, pdfa);
+        return Options.__wrap(ret);
+    }
+    /**
+     * Rectángulo redondeado de `color` (#RRGGBB o #RRGGBBAA) detrás de los
+     * watermarks, con `opacity` ("0.6" o "60%") y a `padding` px de ellos.
+     * @param {string} color
+     * @param {string} opacity
+     * @param {number} padding
+     * @returns {Options}
+     */
+    with_plate(color, opacity, padding) {
+        const ptr = this.__destroy_into_raw();
+        const ptr0 = passStringToWasm0(color, wasm.__wbindgen_export2, wasm.__wbindgen_export3);
+        const len0 = WASM_VECTOR_LEN;
+        const ptr1 = passStringToWasm0(opacity, wasm.__wbindgen_export2, wasm.__wbindgen_export3);
+        const len1 = WASM_VECTOR_LEN;
+        const ret = wasm.options_with_plate(ptr, ptr0, len0, ptr1, len1, padding);
+        return Options.__wrap(ret);
+    }
+    /**
+     * Coordenadas en px o % de la página; reemplazan a la posición.
+     * @param {string} x
+     * @param {string} y
+     * @returns {Options}
+     */
+    with_point(x, y) {
+        const ptr = this.__destroy_into_raw();
+        const ptr0 = passStringToWasm0(x, wasm.__wbindgen_export2, wasm.__wbindgen_export3);
+        const len0 = WASM_VECTOR_LEN;
+        const ptr1 = passStringToWasm0(y, wasm.__wbindgen_export2, wasm.__wbindgen_export3);
+        const len1 = WASM_VECTOR_LEN;
+        const ret = wasm.options_with_point(ptr, ptr0, len0, ptr1, len1);
+        return Options.__wrap(ret);
+    }
+    /**
+     * tl,tc,tr,ml,mc,mr,bl,bc,br, tile, diagonal o auto.
+     * @param {string} position
+     * @returns {Options}
+     */
+    with_position(position) {
+        const ptr = this.__destroy_into_raw();
+        const ptr0 = passStringToWasm0(position, wasm.__wbindgen_export2, wasm.__wbindgen_export3);
+        const len0 = WASM_VECTOR_LEN;
+        const ret = wasm.options_with_position(ptr, ptr0, len0);
+        return Options.__wrap(ret);
+    }
+    /**
+     * Guarda en el XMP un registro de procedencia: versión de la
+     * herramienta, fecha, SHA-256 del logo y opciones usadas.
+     * @param {boolean} provenance
+     * @returns {Options}
+     */
+    with_provenance(provenance) {
+        const ptr = this.__destroy_into_raw();
+        const ret = wasm.options_with_provenance(ptr, provenance);
+        return Options.__wrap(ret);
+    }
+    /**
+     * "lossless" o 1-100 (JPEG).
+     * @param {string} quality
+     * @returns {Options}
+     */
+    with_quality(quality) {
+        const ptr = this.__destroy_into_raw();
+        const ptr0 = passStringToWasm0(quality, wasm.__wbindgen_export2, wasm.__wbindgen_export3);
+        const len0 = WASM_VECTOR_LEN;
+        const ret = wasm.options_with_quality(ptr, ptr0, len0);
+        return Options.__wrap(ret);
+    }
+    /**
+     * Tamaño de fuente, alineación ("left", "center" o "right") y margen
+     * en px de la cabecera y el pie.
+     * @param {number} font_size
+     * @param {string} align
+     * @param {number} margin
+     * @returns {Options}
+     */
+    with_running_style(font_size, align, margin) {
+        const ptr = this.__destroy_into_raw();
+        const ptr0 = passStringToWasm0(align, wasm.__wbindgen_export2, wasm.__wbindgen_export3);
+        const len0 = WASM_VECTOR_LEN;
+        const ret = wasm.options_with_running_style(ptr, font_size, ptr0, len0, margin);
+        return Options.__wrap(ret);
+    }
+    /**
+     * Ancho del logo relativo a cada página, ej. "10%".
+     * @param {string} scale
+     * @returns {Options}
+     */
+    with_scale(scale) {
+        const ptr = this.__destroy_into_raw();
+        const ptr0 = passStringToWasm0(scale, wasm.__wbindgen_export2, wasm.__wbindgen_export3);
+        const len0 = WASM_VECTOR_LEN;
+        const ret = wasm.options_with_scale(ptr, ptr0, len0);
+        return Options.__wrap(ret);
+    }
+    /**
+     * Páginas que se conservan sin watermark, con la sintaxis de `with_pages`.
+     * @param {string} spec
+     * @returns {Options}
+     */
+    with_skip_pages(spec) {
+        const ptr = this.__destroy_into_raw();
+        const ptr0 = passStringToWasm0(spec, wasm.__wbindgen_export2, wasm.__wbindgen_export3);
+        const len0 = WASM_VECTOR_LEN;
+        const ret = wasm.options_with_skip_pages(ptr, ptr0, len0);
+        return Options.__wrap(ret);
+    }
+    /**
+     * Separación entre copias en modo tile.
+     * @param {number} spacing_x
+     * @param {number} spacing_y
+     * @returns {Options}
+     */
+    with_spacing(spacing_x, spacing_y) {
+        const ptr = this.__destroy_into_raw();
+        const ret = wasm.options_with_spacing(ptr, spacing_x, spacing_y);
+        return Options.__wrap(ret);
+    }
+    /**
+     * Quita del PDF generado el diccionario Info, el XMP y las miniaturas
+     * del original, para distribuirlo sin datos del autor.
+     * @param {boolean} strip_metadata
+     * @returns {Options}
+     */
+    with_strip_metadata(strip_metadata) {
+        const ptr = this.__destroy_into_raw();
+        const ret = wasm.options_with_strip_metadata(ptr, strip_metadata);
+        return Options.__wrap(ret);
+    }
+    /**
+     * @param {string} subject
+     * @returns {Options}
+     */
+    with_subject(subject) {
+        const ptr = this.__destroy_into_raw();
+        const ptr0 = passStringToWasm0(subject, wasm.__wbindgen_export2, wasm.__wbindgen_export3);
+        const len0 = WASM_VECTOR_LEN;
+        const ret = wasm.options_with_subject(ptr, ptr0, len0);
+        return Options.__wrap(ret);
+    }
+    /**
+     * Submuestreo de color de JPEG: "4:4:4", "4:2:2" o "4:2:0" (vacío =
+     * 4:4:4).
+     * @param {string} subsampling
+     * @returns {Options}
+     */
+    with_subsampling(subsampling) {
+        const ptr = this.__destroy_into_raw();
+        const ptr0 = passStringToWasm0(subsampling, wasm.__wbindgen_export2, wasm.__wbindgen_export3);
+        const len0 = WASM_VECTOR_LEN;
+        const ret = wasm.options_with_subsampling(ptr, ptr0, len0);
+        return Options.__wrap(ret);
+    }
+    /**
+     * Watermark de texto, además del logo si lo hay. Admite {page},
+     * {total}, {date} y {filename}.
+     * @param {string} text
+     * @returns {Options}
+     */
+    with_text(text) {
+        const ptr = this.__destroy_into_raw();
+        const ptr0 = passStringToWasm0(text, wasm.__wbindgen_export2, wasm.__wbindgen_export3);
+        const len0 = WASM_VECTOR_LEN;
+        const ret = wasm.options_with_text(ptr, ptr0, len0);
+        return Options.__wrap(ret);
+    }
+    /**
+     * Posición del texto cuando se combina con un logo (por defecto la del logo).
+     * @param {string} position
+     * @returns {Options}
+     */
+    with_text_position(position) {
+        const ptr = this.__destroy_into_raw();
+        const ptr0 = passStringToWasm0(position, wasm.__wbindgen_export2, wasm.__wbindgen_export3);
+        const len0 = WASM_VECTOR_LEN;
+        const ret = wasm.options_with_text_position(ptr, ptr0, len0);
+        return Options.__wrap(ret);
+    }
+    /**
+     * Sella la fecha y hora de proceso en cada página marcada, con un
+     * formato de strftime en la zona `zone` ("local", "utc" o "+02:00").
+     * @param {string} format
+     * @param {string} zone
+     * @returns {Options}
+     */
+    with_timestamp(format, zone) {
+        const ptr = this.__destroy_into_raw();
+        const ptr0 = passStringToWasm0(format, wasm.__wbindgen_export2, wasm.__wbindgen_export3);
+        const len0 = WASM_VECTOR_LEN;
+        const ptr1 = passStringToWasm0(zone, wasm.__wbindgen_export2, wasm.__wbindgen_export3);
+        const len1 = WASM_VECTOR_LEN;
+        const ret = wasm.options_with_timestamp(ptr, ptr0, len0, ptr1, len1);
+        return Options.__wrap(ret);
+    }
+    /**
+     * Posición del sello de fecha, como en [`with_position`](Options::with_position).
+     * @param {string} position
+     * @returns {Options}
+     */
+    with_timestamp_position(position) {
+        const ptr = this.__destroy_into_raw();
+        const ptr0 = passStringToWasm0(position, wasm.__wbindgen_export2, wasm.__wbindgen_export3);
+        const len0 = WASM_VECTOR_LEN;
+        const ret = wasm.options_with_timestamp_position(ptr, ptr0, len0);
+        return Options.__wrap(ret);
+    }
+    /**
+     * Sella este texto tal cual en lugar de la hora actual, ej. para
+     * repetir la fecha de una exportación anterior.
+     * @param {string} text
+     * @returns {Options}
+     */
+    with_timestamp_text(text) {
+        const ptr = this.__destroy_into_raw();
+        const ptr0 = passStringToWasm0(text, wasm.__wbindgen_export2, wasm.__wbindgen_export3);
+        const len0 = WASM_VECTOR_LEN;
+        const ret = wasm.options_with_timestamp_text(ptr, ptr0, len0);
+        return Options.__wrap(ret);
+    }
+    /**
+     * Color con que se tiñe el logo: #RRGGBB o #RRGGBBAA.
+     * @param {string} tint
+     * @returns {Options}
+     */
+    with_tint(tint) {
+        const ptr = this.__destroy_into_raw();
+        const ptr0 = passStringToWasm0(tint, wasm.__wbindgen_export2, wasm.__wbindgen_export3);
+        const len0 = WASM_VECTOR_LEN;
+        const ret = wasm.options_with_tint(ptr, ptr0, len0);
+        return Options.__wrap(ret);
+    }
+    /**
+     * Título del PDF generado, en el diccionario Info y en el XMP.
+     * @param {string} title
+     * @returns {Options}
+     */
+    with_title(title) {
+        const ptr = this.__destroy_into_raw();
+        const ptr0 = passStringToWasm0(title, wasm.__wbindgen_export2, wasm.__wbindgen_export3);
+        const len0 = WASM_VECTOR_LEN;
+        const ret = wasm.options_with_title(ptr, ptr0, len0);
+        return Options.__wrap(ret);
+    }
+}
+if (Symbol.dispose) Options.prototype[Symbol.dispose] = Options.prototype.free;
+
+/**
+ * Tamaño de una página (ver [`get_page_dimensions`]).
+ */
+export class PageDimensions {
+    static __wrap(ptr) {
+        ptr = ptr >>> 0;
+        const obj = Object.create(PageDimensions.prototype);
+        obj.__wbg_ptr = ptr;
+        PageDimensionsFinalization.register(obj, obj.__wbg_ptr, obj);
+        return obj;
+    }
+    __destroy_into_raw() {
+        const ptr = this.__wbg_ptr;
+        this.__wbg_ptr = 0;
+        PageDimensionsFinalization.unregister(this);
+        return ptr;
+    }
+    free() {
+        const ptr = this.__destroy_into_raw();
+        wasm.__wbg_pagedimensions_free(ptr, 0);
+    }
+    /**
+     * Ancho entre alto, de los píxeles o, si no hay, de los puntos.
+     * @returns {number}
+     */
+    get aspect_ratio() {
+        const ret = wasm.pagedimensions_aspect_ratio(this.__wbg_ptr);
+        return ret;
+    }
+    /**
+     * Motivo por el que no se pudo extraer la página, si es el caso.
+     * @returns {string | undefined}
+     */
+    get error() {
+        try {
+            const retptr = wasm.__wbindgen_add_to_stack_pointer(-16);
+            wasm.pagedimensions_error(retptr, this.__wbg_ptr);
+            var r0 = getDataViewMemory0().getInt32(retptr + 4 * 0, true);
+            var r1 = getDataViewMemory0().getInt32(retptr + 4 * 1, true);
+            let v1;
+            if (r0 !== 0) {
+                v1 = getStringFromWasm0(r0, r1).slice();
+                wasm.__wbindgen_export4(r0, r1 * 1, 1);
+            }
+            return v1;
+        } finally {
+            wasm.__wbindgen_add_to_stack_pointer(16);
+        }
+    }
+    /**
+     * Alto en píxeles de la imagen de la página; 0 si no se pudo extraer.
+     * @returns {number}
+     */
+    get height() {
+        const ret = wasm.pagedimensions_height(this.__wbg_ptr);
+        return ret >>> 0;
+    }
+    /**
+     * Alto en puntos, ya girada según /Rotate.
+     * @returns {number}
+     */
+    get height_pt() {
+        const ret = wasm.pagedimensions_height_pt(this.__wbg_ptr);
+        return ret;
+    }
+    /**
+     * Ancho en píxeles de la imagen de la página; 0 si no se pudo extraer.
+     * @returns {number}
+     */
+    get width() {
+        const ret = wasm.pagedimensions_width(this.__wbg_ptr);
+        return ret >>> 0;
+    }
+    /**
+     * Ancho en puntos, ya girada según /Rotate.
+     * @returns {number}
+     */
+    get width_pt() {
+        const ret = wasm.pagedimensions_width_pt(this.__wbg_ptr);
+        return ret;
+    }
+}
+if (Symbol.dispose) PageDimensions.prototype[Symbol.dispose] = PageDimensions.prototype.free;
+
+/**
+ * Error devuelto a JavaScript, para distinguir el motivo sin interpretar el
+ * mensaje (ver [`WatermarkError`]).
+ */
+export class ProcessError {
+    static __wrap(ptr) {
+        ptr = ptr >>> 0;
+        const obj = Object.create(ProcessError.prototype);
+        obj.__wbg_ptr = ptr;
+        ProcessErrorFinalization.register(obj, obj.__wbg_ptr, obj);
+        return obj;
+    }
+    __destroy_into_raw() {
+        const ptr = this.__wbg_ptr;
+        this.__wbg_ptr = 0;
+        ProcessErrorFinalization.unregister(this);
+        return ptr;
+    }
+    free() {
+        const ptr = this.__destroy_into_raw();
+        wasm.__wbg_processerror_free(ptr, 0);
+    }
+    /**
+     * Motivo, ej. "unsupported_filter" o "wrong_password".
+     * @returns {string}
+     */
+    get code() {
+        let deferred1_0;
+        let deferred1_1;
+        try {
+            const retptr = wasm.__wbindgen_add_to_stack_pointer(-16);
+            wasm.processerror_code(retptr, this.__wbg_ptr);
+            var r0 = getDataViewMemory0().getInt32(retptr + 4 * 0, true);
+            var r1 = getDataViewMemory0().getInt32(retptr + 4 * 1, true);
+            deferred1_0 = r0;
+            deferred1_1 = r1;
+            return getStringFromWasm0(r0, r1);
+        } finally {
+            wasm.__wbindgen_add_to_stack_pointer(16);
+            wasm.__wbindgen_export4(deferred1_0, deferred1_1, 1);
+        }
+    }
+    /**
+     * Filtro o espacio de color no soportado, si es el motivo.
+     * @returns {string | undefined}
+     */
+    get detail() {
+        try {
+            const retptr = wasm.__wbindgen_add_to_stack_pointer(-16);
+            wasm.processerror_detail(retptr, this.__wbg_ptr);
+            var r0 = getDataViewMemory0().getInt32(retptr + 4 * 0, true);
+            var r1 = getDataViewMemory0().getInt32(retptr + 4 * 1, true);
+            let v1;
+            if (r0 !== 0) {
+                v1 = getStringFromWasm0(r0, r1).slice();
+                wasm.__wbindgen_export4(r0, r1 * 1, 1);
+            }
+            return v1;
+        } finally {
+            wasm.__wbindgen_add_to_stack_pointer(16);
+        }
+    }
+    /**
+     * Mensaje en español, el mismo que muestra la CLI.
+     * @returns {string}
+     */
+    get message() {
+        let deferred1_0;
+        let deferred1_1;
+        try {
+            const retptr = wasm.__wbindgen_add_to_stack_pointer(-16);
+            wasm.processerror_message(retptr, this.__wbg_ptr);
+            var r0 = getDataViewMemory0().getInt32(retptr + 4 * 0, true);
+            var r1 = getDataViewMemory0().getInt32(retptr + 4 * 1, true);
+            deferred1_0 = r0;
+            deferred1_1 = r1;
+            return getStringFromWasm0(r0, r1);
+        } finally {
+            wasm.__wbindgen_add_to_stack_pointer(16);
+            wasm.__wbindgen_export4(deferred1_0, deferred1_1, 1);
+        }
+    }
+    /**
+     * Índice (desde 0) de la página que falló, si la hay.
+     * @returns {number | undefined}
+     */
+    get page() {
+        const ret = wasm.processerror_page(this.__wbg_ptr);
+        return ret === 0x100000001 ? undefined : ret;
+    }
+    /**
+     * @returns {string}
+     */
+    toString() {
+        let deferred1_0;
+        let deferred1_1;
+        try {
+            const retptr = wasm.__wbindgen_add_to_stack_pointer(-16);
+            wasm.processerror_message(retptr, this.__wbg_ptr);
+            var r0 = getDataViewMemory0().getInt32(retptr + 4 * 0, true);
+            var r1 = getDataViewMemory0().getInt32(retptr + 4 * 1, true);
+            deferred1_0 = r0;
+            deferred1_1 = r1;
+            return getStringFromWasm0(r0, r1);
+        } finally {
+            wasm.__wbindgen_add_to_stack_pointer(16);
+            wasm.__wbindgen_export4(deferred1_0, deferred1_1, 1);
+        }
+    }
+}
+if (Symbol.dispose) ProcessError.prototype[Symbol.dispose] = ProcessError.prototype.free;
+
+/**
+ * Procesa un PDF por partes para no bloquear el navegador: cada llamada a
+ * `step` extrae y marca unas pocas páginas, devolviendo el control al bucle
+ * de eventos entre llamadas, y `finish` genera el PDF. En modo stamp todo el
+ * trabajo se hace en `finish`, que es rápido.
+ */
+export class Processor {
+    __destroy_into_raw() {
+        const ptr = this.__wbg_ptr;
+        this.__wbg_ptr = 0;
+        ProcessorFinalization.unregister(this);
+        return ptr;
+    }
+    free() {
+        const ptr = this.__destroy_into_raw();
+        wasm.__wbg_processor_free(ptr, 0);
+    }
+    /**
+     * Procesa las páginas que falten y genera el PDF.
+     * @returns {Uint8Array}
+     */
+    finish() {
+        try {
+            const ptr = this.__destroy_into_raw();
+            const retptr = wasm.__wbindgen_add_to_stack_pointer(-16);
+            wasm.processor_finish(retptr, ptr);
+            var r0 = getDataViewMemory0().getInt32(retptr + 4 * 0, true);
+            var r1 = getDataViewMemory0().getInt32(retptr + 4 * 1, true);
+            var r2 = getDataViewMemory0().getInt32(retptr + 4 * 2, true);
+            var r3 = getDataViewMemory0().getInt32(retptr + 4 * 3, true);
+            if (r3) {
+                throw takeObject(r2);
+            }
+            var v1 = getArrayU8FromWasm0(r0, r1).slice();
+            wasm.__wbindgen_export4(r0, r1 * 1, 1);
+            return v1;
+        } finally {
+            wasm.__wbindgen_add_to_stack_pointer(16);
+        }
+    }
+    /**
+     * @param {Uint8Array} pdf_bytes
+     * @param {Options} options
+     */
+    constructor(pdf_bytes, options) {
+        try {
+            const retptr = wasm.__wbindgen_add_to_stack_pointer(-16);
+            const ptr0 = passArray8ToWasm0(pdf_bytes, wasm.__wbindgen_export2);
+            const len0 = WASM_VECTOR_LEN;
+            _assertClass(options, Options);
+            wasm.processor_new(retptr, ptr0, len0, options.__wbg_ptr);
+            var r0 = getDataViewMemory0().getInt32(retptr + 4 * 0, true);
+            var r1 = getDataViewMemory0().getInt32(retptr + 4 * 1, true);
+            var r2 = getDataViewMemory0().getInt32(retptr + 4 * 2, true);
+            if (r2) {
+                throw takeObject(r1);
+            }
+            this.__wbg_ptr = r0 >>> 0;
+            ProcessorFinalization.register(this, this.__wbg_ptr, this);
+            return this;
+        } finally {
+            wasm.__wbindgen_add_to_stack_pointer(16);
+        }
+    }
+    /**
+     * Páginas que faltan por procesar.
+     * @returns {number}
+     */
+    remaining() {
+        const ret = wasm.processor_remaining(this.__wbg_ptr);
+        return ret >>> 0;
+    }
+    /**
+     * Procesa hasta `count` páginas y devuelve cuántas faltan.
+     * @param {number} count
+     * @returns {number}
+     */
+    step(count) {
+        try {
+            const retptr = wasm.__wbindgen_add_to_stack_pointer(-16);
+            wasm.processor_step(retptr, this.__wbg_ptr, count);
+            var r0 = getDataViewMemory0().getInt32(retptr + 4 * 0, true);
+            var r1 = getDataViewMemory0().getInt32(retptr + 4 * 1, true);
+            var r2 = getDataViewMemory0().getInt32(retptr + 4 * 2, true);
+            if (r2) {
+                throw takeObject(r1);
+            }
+            return r0 >>> 0;
+        } finally {
+            wasm.__wbindgen_add_to_stack_pointer(16);
+        }
+    }
+}
+if (Symbol.dispose) Processor.prototype[Symbol.dispose] = Processor.prototype.free;
+
+/**
+ * Miniatura PNG de una página (ver [`render_thumbnails`]).
+ */
+export class Thumbnail {
+    static __wrap(ptr) {
+        ptr = ptr >>> 0;
+        const obj = Object.create(Thumbnail.prototype);
+        obj.__wbg_ptr = ptr;
+        ThumbnailFinalization.register(obj, obj.__wbg_ptr, obj);
+        return obj;
+    }
+    __destroy_into_raw() {
+        const ptr = this.__wbg_ptr;
+        this.__wbg_ptr = 0;
+        ThumbnailFinalization.unregister(this);
+        return ptr;
+    }
+    free() {
+        const ptr = this.__destroy_into_raw();
+        wasm.__wbg_thumbnail_free(ptr, 0);
+    }
+    /**
+     * Motivo por el que no hay miniatura, si es el caso.
+     * @returns {string | undefined}
+     */
+    get error() {
+        try {
+            const retptr = wasm.__wbindgen_add_to_stack_pointer(-16);
+            wasm.thumbnail_error(retptr, this.__wbg_ptr);
+            var r0 = getDataViewMemory0().getInt32(retptr + 4 * 0, true);
+            var r1 = getDataViewMemory0().getInt32(retptr + 4 * 1, true);
+            let v1;
+            if (r0 !== 0) {
+                v1 = getStringFromWasm0(r0, r1).slice();
+                wasm.__wbindgen_export4(r0, r1 * 1, 1);
+            }
+            return v1;
+        } finally {
+            wasm.__wbindgen_add_to_stack_pointer(16);
+        }
+    }
+    /**
+     * @returns {number}
+     */
+    get height() {
+        const ret = wasm.thumbnail_height(this.__wbg_ptr);
+        return ret >>> 0;
+    }
+    /**
+     * PNG de la página; vacío si no se pudo extraer.
+     * @returns {Uint8Array}
+     */
+    get png() {
+        try {
+            const retptr = wasm.__wbindgen_add_to_stack_pointer(-16);
+            wasm.thumbnail_png(retptr, this.__wbg_ptr);
+            var r0 = getDataViewMemory0().getInt32(retptr + 4 * 0, true);
+            var r1 = getDataViewMemory0().getInt32(retptr + 4 * 1, true);
+            var v1 = getArrayU8FromWasm0(r0, r1).slice();
+            wasm.__wbindgen_export4(r0, r1 * 1, 1);
+            return v1;
+        } finally {
+            wasm.__wbindgen_add_to_stack_pointer(16);
+        }
+    }
+    /**
+     * @returns {number}
+     */
+    get width() {
+        const ret = wasm.thumbnail_width(this.__wbg_ptr);
+        return ret >>> 0;
+    }
+}
+if (Symbol.dispose) Thumbnail.prototype[Symbol.dispose] = Thumbnail.prototype.free;
+
 /**
  * @param {Uint8Array} pdf_bytes
+ * @param {string} password
  * @returns {number}
  */
-export function get_page_count(pdf_bytes) {
+export function get_page_count(pdf_bytes, password) {
     try {
         const retptr = wasm.__wbindgen_add_to_stack_pointer(-16);
-        const ptr0 = passArray8ToWasm0(pdf_bytes, wasm.__wbindgen_export);
+        const ptr0 = passArray8ToWasm0(pdf_bytes, wasm.__wbindgen_export2);
         const len0 = WASM_VECTOR_LEN;
-        wasm.get_page_count(retptr, ptr0, len0);
+        const ptr1 = passStringToWasm0(password, wasm.__wbindgen_export2, wasm.__wbindgen_export3);
+        const len1 = WASM_VECTOR_LEN;
+        wasm.get_page_count(retptr, ptr0, len0, ptr1, len1);
         var r0 = getDataViewMemory0().getInt32(retptr + 4 * 0, true);
         var r1 = getDataViewMemory0().getInt32(retptr + 4 * 1, true);
         var r2 = getDataViewMemory0().getInt32(retptr + 4 * 2, true);
@@ -23,29 +1073,149 @@ export function get_page_count(pdf_bytes) {
 }
 
 /**
+ * Tamaño de cada página del PDF, para avisar de tamaños mezclados o
+ * calcular la escala del watermark antes de procesarlo. Extrae todas las
+ * páginas; las que fallan llevan el motivo en `error` y solo su tamaño en
+ * puntos.
+ * @param {Uint8Array} pdf_bytes
+ * @param {string} password
+ * @returns {PageDimensions[]}
+ */
+export function get_page_dimensions(pdf_bytes, password) {
+    try {
+        const retptr = wasm.__wbindgen_add_to_stack_pointer(-16);
+        const ptr0 = passArray8ToWasm0(pdf_bytes, wasm.__wbindgen_export2);
+        const len0 = WASM_VECTOR_LEN;
+        const ptr1 = passStringToWasm0(password, wasm.__wbindgen_export2, wasm.__wbindgen_export3);
+        const len1 = WASM_VECTOR_LEN;
+        wasm.get_page_dimensions(retptr, ptr0, len0, ptr1, len1);
+        var r0 = getDataViewMemory0().getInt32(retptr + 4 * 0, true);
+        var r1 = getDataViewMemory0().getInt32(retptr + 4 * 1, true);
+        var r2 = getDataViewMemory0().getInt32(retptr + 4 * 2, true);
+        var r3 = getDataViewMemory0().getInt32(retptr + 4 * 3, true);
+        if (r3) {
+            throw takeObject(r2);
+        }
+        var v3 = getArrayJsValueFromWasm0(r0, r1).slice();
+        wasm.__wbindgen_export4(r0, r1 * 4, 4);
+        return v3;
+    } finally {
+        wasm.__wbindgen_add_to_stack_pointer(16);
+    }
+}
+
+/**
+ * Informe del PDF en JSON (ver [`pdf::PdfInfo`]): versión, cifrado, número
+ * de páginas y, por página, tamaño en puntos, giro y las imágenes con sus
+ * filtros, espacio de color y si se saben decodificar. No extrae las
+ * páginas, así que es rápido. Si el PDF está cifrado y `password` no lo
+ * abre devuelve `needs_password: true` sin páginas en vez de un error.
  * @param {Uint8Array} pdf_bytes
- * @param {Uint8Array} logo_bytes
- * @param {string} quality_str
- * @param {Uint32Array} page_indices
- * @param {string} position
- * @param {number} min_w
- * @param {number} min_h
+ * @param {string} password
+ * @returns {string}
+ */
+export function get_pdf_info(pdf_bytes, password) {
+    let deferred4_0;
+    let deferred4_1;
+    try {
+        const retptr = wasm.__wbindgen_add_to_stack_pointer(-16);
+        const ptr0 = passArray8ToWasm0(pdf_bytes, wasm.__wbindgen_export2);
+        const len0 = WASM_VECTOR_LEN;
+        const ptr1 = passStringToWasm0(password, wasm.__wbindgen_export2, wasm.__wbindgen_export3);
+        const len1 = WASM_VECTOR_LEN;
+        wasm.get_pdf_info(retptr, ptr0, len0, ptr1, len1);
+        var r0 = getDataViewMemory0().getInt32(retptr + 4 * 0, true);
+        var r1 = getDataViewMemory0().getInt32(retptr + 4 * 1, true);
+        var r2 = getDataViewMemory0().getInt32(retptr + 4 * 2, true);
+        var r3 = getDataViewMemory0().getInt32(retptr + 4 * 3, true);
+        var ptr3 = r0;
+        var len3 = r1;
+        if (r3) {
+            ptr3 = 0; len3 = 0;
+            throw takeObject(r2);
+        }
+        deferred4_0 = ptr3;
+        deferred4_1 = len3;
+        return getStringFromWasm0(ptr3, len3);
+    } finally {
+        wasm.__wbindgen_add_to_stack_pointer(16);
+        wasm.__wbindgen_export4(deferred4_0, deferred4_1, 1);
+    }
+}
+
+/**
+ * Índices (desde 0) de las páginas de `spec` (ej. "1-5,8,12-") en un PDF de
+ * `total` páginas; vacío si `spec` lo está.
+ * @param {string} spec
+ * @param {number} total
+ * @returns {Uint32Array}
+ */
+export function parse_page_spec(spec, total) {
+    try {
+        const retptr = wasm.__wbindgen_add_to_stack_pointer(-16);
+        const ptr0 = passStringToWasm0(spec, wasm.__wbindgen_export2, wasm.__wbindgen_export3);
+        const len0 = WASM_VECTOR_LEN;
+        wasm.parse_page_spec(retptr, ptr0, len0, total);
+        var r0 = getDataViewMemory0().getInt32(retptr + 4 * 0, true);
+        var r1 = getDataViewMemory0().getInt32(retptr + 4 * 1, true);
+        var r2 = getDataViewMemory0().getInt32(retptr + 4 * 2, true);
+        var r3 = getDataViewMemory0().getInt32(retptr + 4 * 3, true);
+        if (r3) {
+            throw takeObject(r2);
+        }
+        var v2 = getArrayU32FromWasm0(r0, r1).slice();
+        wasm.__wbindgen_export4(r0, r1 * 4, 4);
+        return v2;
+    } finally {
+        wasm.__wbindgen_add_to_stack_pointer(16);
+    }
+}
+
+/**
+ * Aplica el watermark descrito en `options` al PDF `pdf_bytes`.
+ * @param {Uint8Array} pdf_bytes
+ * @param {Options} options
  * @returns {Uint8Array}
  */
-export function process_pdf(pdf_bytes, logo_bytes, quality_str, page_indices, position, min_w, min_h) {
+export function process_pdf(pdf_bytes, options) {
+    try {
+        const retptr = wasm.__wbindgen_add_to_stack_pointer(-16);
+        const ptr0 = passArray8ToWasm0(pdf_bytes, wasm.__wbindgen_export2);
+        const len0 = WASM_VECTOR_LEN;
+        _assertClass(options, Options);
+        wasm.process_pdf(retptr, ptr0, len0, options.__wbg_ptr);
+        var r0 = getDataViewMemory0().getInt32(retptr + 4 * 0, true);
+        var r1 = getDataViewMemory0().getInt32(retptr + 4 * 1, true);
+        var r2 = getDataViewMemory0().getInt32(retptr + 4 * 2, true);
+        var r3 = getDataViewMemory0().getInt32(retptr + 4 * 3, true);
+        if (r3) {
+            throw takeObject(r2);
+        }
+        var v2 = getArrayU8FromWasm0(r0, r1).slice();
+        wasm.__wbindgen_export4(r0, r1 * 1, 1);
+        return v2;
+    } finally {
+        wasm.__wbindgen_add_to_stack_pointer(16);
+    }
+}
+
+/**
+ * Miniaturas de las páginas del PDF, de como mucho `max_width` píxeles de
+ * ancho, para elegir páginas antes de procesarlo. Las páginas que no se
+ * pueden extraer devuelven una miniatura vacía con el motivo en `error`.
+ * @param {Uint8Array} pdf_bytes
+ * @param {string} password
+ * @param {number} max_width
+ * @returns {Thumbnail[]}
+ */
+export function render_thumbnails(pdf_bytes, password, max_width) {
     try {
         const retptr = wasm.__wbindgen_add_to_stack_pointer(-16);
-        const ptr0 = passArray8ToWasm0(pdf_bytes, wasm.__wbindgen_export);
+        const ptr0 = passArray8ToWasm0(pdf_bytes, wasm.__wbindgen_export2);
         const len0 = WASM_VECTOR_LEN;
-        const ptr1 = passArray8ToWasm0(logo_bytes, wasm.__wbindgen_export);
+        const ptr1 = passStringToWasm0(password, wasm.__wbindgen_export2, wasm.__wbindgen_export3);
         const len1 = WASM_VECTOR_LEN;
-        const ptr2 = passStringToWasm0(quality_str, wasm.__wbindgen_export, wasm.__wbindgen_export2);
-        const len2 = WASM_VECTOR_LEN;
-        const ptr3 = passArray32ToWasm0(page_indices, wasm.__wbindgen_export);
-        const len3 = WASM_VECTOR_LEN;
-        const ptr4 = passStringToWasm0(position, wasm.__wbindgen_export, wasm.__wbindgen_export2);
-        const len4 = WASM_VECTOR_LEN;
-        wasm.process_pdf(retptr, ptr0, len0, ptr1, len1, ptr2, len2, ptr3, len3, ptr4, len4, min_w, min_h);
+        wasm.render_thumbnails(retptr, ptr0, len0, ptr1, len1, max_width);
         var r0 = getDataViewMemory0().getInt32(retptr + 4 * 0, true);
         var r1 = getDataViewMemory0().getInt32(retptr + 4 * 1, true);
         var r2 = getDataViewMemory0().getInt32(retptr + 4 * 2, true);
@@ -53,9 +1223,9 @@ export function process_pdf(pdf_bytes, logo_bytes, quality_str, page_indices, po
         if (r3) {
             throw takeObject(r2);
         }
-        var v6 = getArrayU8FromWasm0(r0, r1).slice();
-        wasm.__wbindgen_export3(r0, r1 * 1, 1);
-        return v6;
+        var v3 = getArrayJsValueFromWasm0(r0, r1).slice();
+        wasm.__wbindgen_export4(r0, r1 * 4, 4);
+        return v3;
     } finally {
         wasm.__wbindgen_add_to_stack_pointer(16);
     }
@@ -64,11 +1234,48 @@ export function process_pdf(pdf_bytes, logo_bytes, quality_str, page_indices, po
 function __wbg_get_imports() {
     const import0 = {
         __proto__: null,
-        __wbindgen_cast_0000000000000001: function(arg0, arg1) {
-            // Cast intrinsic for `Ref(String) -> Externref`.
-            const ret = getStringFromWasm0(arg0, arg1);
+        __wbg___wbindgen_throw_be289d5034ed271b: function(arg0, arg1) {
+            throw new Error(getStringFromWasm0(arg0, arg1));
+        },
+        __wbg_getRandomValues_a8ddca022803a145: function() { return handleError(function (arg0, arg1) {
+            globalThis.crypto.getRandomValues(getArrayU8FromWasm0(arg0, arg1));
+        }, arguments); },
+        __wbg_getTime_1e3cd1391c5c3995: function(arg0) {
+            const ret = getObject(arg0).getTime();
+            return ret;
+        },
+        __wbg_getTimezoneOffset_81776d10a4ec18a8: function(arg0) {
+            const ret = getObject(arg0).getTimezoneOffset();
+            return ret;
+        },
+        __wbg_new_0_73afc35eb544e539: function() {
+            const ret = new Date();
             return addHeapObject(ret);
         },
+        __wbg_new_245cd5c49157e602: function(arg0) {
+            const ret = new Date(getObject(arg0));
+            return addHeapObject(ret);
+        },
+        __wbg_pagedimensions_new: function(arg0) {
+            const ret = PageDimensions.__wrap(arg0);
+            return addHeapObject(ret);
+        },
+        __wbg_processerror_new: function(arg0) {
+            const ret = ProcessError.__wrap(arg0);
+            return addHeapObject(ret);
+        },
+        __wbg_thumbnail_new: function(arg0) {
+            const ret = Thumbnail.__wrap(arg0);
+            return addHeapObject(ret);
+        },
+        __wbindgen_cast_0000000000000001: function(arg0) {
+            // Cast intrinsic for `F64 -> Externref`.
+            const ret = arg0;
+            return addHeapObject(ret);
+        },
+        __wbindgen_object_drop_ref: function(arg0) {
+            takeObject(arg0);
+        },
     };
     return {
         __proto__: null,
@@ -76,6 +1283,22 @@ function __wbg_get_imports() {
     };
 }
 
+const OptionsFinalization = (typeof FinalizationRegistry === 'undefined')
+    ? { register: () => {}, unregister: () => {} }
+    : new FinalizationRegistry(ptr => wasm.__wbg_options_free(ptr >>> 0, 1));
+const PageDimensionsFinalization = (typeof FinalizationRegistry === 'undefined')
+    ? { register: () => {}, unregister: () => {} }
+    : new FinalizationRegistry(ptr => wasm.__wbg_pagedimensions_free(ptr >>> 0, 1));
+const ProcessErrorFinalization = (typeof FinalizationRegistry === 'undefined')
+    ? { register: () => {}, unregister: () => {} }
+    : new FinalizationRegistry(ptr => wasm.__wbg_processerror_free(ptr >>> 0, 1));
+const ProcessorFinalization = (typeof FinalizationRegistry === 'undefined')
+    ? { register: () => {}, unregister: () => {} }
+    : new FinalizationRegistry(ptr => wasm.__wbg_processor_free(ptr >>> 0, 1));
+const ThumbnailFinalization = (typeof FinalizationRegistry === 'undefined')
+    ? { register: () => {}, unregister: () => {} }
+    : new FinalizationRegistry(ptr => wasm.__wbg_thumbnail_free(ptr >>> 0, 1));
+
 function addHeapObject(obj) {
     if (heap_next === heap.length) heap.push(heap.length + 1);
     const idx = heap_next;
@@ -85,12 +1308,33 @@ function addHeapObject(obj) {
     return idx;
 }
 
+function _assertClass(instance, klass) {
+    if (!(instance instanceof klass)) {
+        throw new Error(`expected instance of ${klass.name}`);
+    }
+}
+
 function dropObject(idx) {
     if (idx < 132) return;
     heap[idx] = heap_next;
     heap_next = idx;
 }
 
+function getArrayJsValueFromWasm0(ptr, len) {
+    ptr = ptr >>> 0;
+    const mem = getDataViewMemory0();
+    const result = [];
+    for (let i = ptr; i < ptr + 4 * len; i += 4) {
+        result.push(takeObject(mem.getUint32(i, true)));
+    }
+    return result;
+}
+
+function getArrayU32FromWasm0(ptr, len) {
+    ptr = ptr >>> 0;
+    return getUint32ArrayMemory0().subarray(ptr / 4, ptr / 4 + len);
+}
+
 function getArrayU8FromWasm0(ptr, len) {
     ptr = ptr >>> 0;
     return getUint8ArrayMemory0().subarray(ptr / 1, ptr / 1 + len);
@@ -127,18 +1371,19 @@ function getUint8ArrayMemory0() {
 
 function getObject(idx) { return heap[idx]; }
 
+function handleError(f, args) {
+    try {
+        return f.apply(this, args);
+    } catch (e) {
+        wasm.__wbindgen_export(addHeapObject(e));
+    }
+}
+
 let heap = new Array(128).fill(undefined);
 heap.push(undefined, null, true, false);
 
 let heap_next = heap.length;
 
-function passArray32ToWasm0(arg, malloc) {
-    const ptr = malloc(arg.length * 4, 4) >>> 0;
-    getUint32ArrayMemory0().set(arg, ptr / 4);
-    WASM_VECTOR_LEN = arg.length;
-    return ptr;
-}
-
 function passArray8ToWasm0(arg, malloc) {
     const ptr = malloc(arg.length * 1, 1) >>> 0;
     getUint8ArrayMemory0().set(arg, ptr / 1);
@@ -1,9 +1,92 @@
 /* tslint:disable */
 /* eslint-disable */
 export const memory: WebAssembly.Memory;
-export const process_pdf: (a: number, b: number, c: number, d: number, e: number, f: number, g: number, h: number, i: number, j: number, k: number, l: number, m: number) => void;
-export const get_page_count: (a: number, b: number, c: number) => void;
+export const __wbg_options_free: (a: number, b: number) => void;
+export const options_new: () => number;
+export const options_with_adaptive: (a: number, b: number) => number;
+export const options_with_author: (a: number, b: number, c: number) => number;
+export const options_with_avoid: (a: number, b: number, c: number) => number;
+export const options_with_bates: (a: number, b: number, c: number, d: number, e: number, f: number, g: number) => number;
+export const options_with_color: (a: number, b: number, c: number) => number;
+export const options_with_dpi: (a: number, b: number) => number;
+export const options_with_effects: (a: number, b: number, c: number) => number;
+export const options_with_encryption: (a: number, b: number, c: number, d: number, e: number, f: number, g: number) => number;
+export const options_with_every_nth: (a: number, b: number) => number;
+export const options_with_font: (a: number, b: number, c: number) => number;
+export const options_with_font_size: (a: number, b: number) => number;
+export const options_with_footer: (a: number, b: number, c: number) => number;
+export const options_with_forensic: (a: number, b: number, c: number, d: number, e: number, f: number) => number;
+export const options_with_gray_logo: (a: number, b: number) => number;
+export const options_with_grayscale: (a: number, b: number) => number;
+export const options_with_header: (a: number, b: number, c: number) => number;
+export const options_with_incremental: (a: number, b: number) => number;
+export const options_with_invisible: (a: number, b: number, c: number) => number;
+export const options_with_keywords: (a: number, b: number, c: number) => number;
+export const options_with_layer: (a: number, b: number, c: number) => number;
+export const options_with_linearize: (a: number, b: number) => number;
+export const options_with_logo: (a: number, b: number, c: number) => number;
+export const options_with_margin: (a: number, b: number) => number;
+export const options_with_max_resolution: (a: number, b: number, c: number) => number;
+export const options_with_max_size: (a: number, b: number, c: number, d: number) => number;
+export const options_with_min_size: (a: number, b: number, c: number) => number;
+export const options_with_mode: (a: number, b: number, c: number) => number;
+export const options_with_object_streams: (a: number, b: number) => number;
+export const options_with_opacity: (a: number, b: number, c: number) => number;
+export const options_with_page_positions: (a: number, b: number, c: number) => number;
+export const options_with_page_size: (a: number, b: number, c: number) => number;
+export const options_with_pages: (a: number, b: number, c: number) => number;
+export const options_with_password: (a: number, b: number, c: number) => number;
+export const options_with_pdfa: (a: number, b: number) => number;
+export const options_with_plate: (a: number, b: number, c: number, d: number, e: number, f: number) => number;
+export const options_with_point: (a: number, b: number, c: number, d: number, e: number) => number;
+export const options_with_position: (a: number, b: number, c: number) => number;
+export const options_with_provenance: (a: number, b: number) => number;
+export const options_with_quality: (a: number, b: number, c: number) => number;
+export const options_with_running_style: (a: number, b: number, c: number, d: number, e: number) => number;
+export const options_with_scale: (a: number, b: number, c: number) => number;
+export const options_with_skip_pages: (a: number, b: number, c: number) => number;
+export const options_with_spacing: (a: number, b: number, c: number) => number;
+export const options_with_strip_metadata: (a: number, b: number) => number;
+export const options_with_subject: (a: number, b: number, c: number) => number;
+export const options_with_subsampling: (a: number, b: number, c: number) => number;
+export const options_with_text: (a: number, b: number, c: number) => number;
+export const options_with_text_position: (a: number, b: number, c: number) => number;
+export const options_with_timestamp: (a: number, b: number, c: number, d: number, e: number) => number;
+export const options_with_timestamp_position: (a: number, b: number, c: number) => number;
+export const options_with_timestamp_text: (a: number, b: number, c: number) => number;
+export const options_with_tint: (a: number, b: number, c: number) => number;
+export const options_with_title: (a: number, b: number, c: number) => number;
+export const __wbg_pagedimensions_free: (a: number, b: number) => void;
+export const __wbg_processerror_free: (a: number, b: number) => void;
+export const __wbg_processor_free: (a: number, b: number) => void;
+export const __wbg_thumbnail_free: (a: number, b: number) => void;
+export const get_page_count: (a: number, b: number, c: number, d: number, e: number) => void;
+export const get_page_dimensions: (a: number, b: number, c: number, d: number, e: number) => void;
+export const get_pdf_info: (a: number, b: number, c: number, d: number, e: number) => void;
+export const pagedimensions_aspect_ratio: (a: number) => number;
+export const pagedimensions_error: (a: number, b: number) => void;
+export const pagedimensions_height: (a: number) => number;
+export const pagedimensions_height_pt: (a: number) => number;
+export const pagedimensions_width: (a: number) => number;
+export const pagedimensions_width_pt: (a: number) => number;
+export const parse_page_spec: (a: number, b: number, c: number, d: number) => void;
+export const process_pdf: (a: number, b: number, c: number, d: number) => void;
+export const processerror_code: (a: number, b: number) => void;
+export const processerror_detail: (a: number, b: number) => void;
+export const processerror_message: (a: number, b: number) => void;
+export const processerror_page: (a: number) => number;
+export const processor_finish: (a: number, b: number) => void;
+export const processor_new: (a: number, b: number, c: number, d: number) => void;
+export const processor_remaining: (a: number) => number;
+export const processor_step: (a: number, b: number, c: number) => void;
+export const render_thumbnails: (a: number, b: number, c: number, d: number, e: number, f: number) => void;
+export const thumbnail_error: (a: number, b: number) => void;
+export const thumbnail_height: (a: number) => number;
+export const thumbnail_png: (a: number, b: number) => void;
+export const thumbnail_width: (a: number) => number;
+export const processerror_toString: (a: number, b: number) => void;
+export const __wbindgen_export: (a: number) => void;
 export const __wbindgen_add_to_stack_pointer: (a: number) => number;
-export const __wbindgen_export: (a: number, b: number) => number;
-export const __wbindgen_export2: (a: number, b: number, c: number, d: number) => number;
-export const __wbindgen_export3: (a: number, b: number, c: number) => void;
+export const __wbindgen_export2: (a: number, b: number) => number;
+export const __wbindgen_export3: (a: number, b: number, c: number, d: number) => number;
+export const __wbindgen_export4: (a: number, b: number, c: number) => void;